@@ -0,0 +1,88 @@
+use std::env;
+
+use common::server::ServerTuning;
+
+/// Where the HTTP server listens and how it is tuned. Defaults to `127.0.0.1:8080`;
+/// containers set `BACKEND_HOST=0.0.0.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub server: ServerTuning,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|name| env::var(name).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let config = Self {
+            host: lookup("BACKEND_HOST")
+                .unwrap_or_else(|| "127.0.0.1".to_string()),
+
+            port: lookup("BACKEND_PORT")
+                .unwrap_or_else(|| "8080".to_string())
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid BACKEND_PORT: {}", e))?,
+
+            server: ServerTuning::from_lookup(&lookup)?,
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.host.trim().is_empty() {
+            return Err("BACKEND_HOST cannot be empty".to_string());
+        }
+
+        if self.port == 0 {
+            return Err("BACKEND_PORT must be between 1 and 65535".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn bind_address(&self) -> (String, u16) {
+        (self.host.clone(), self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults_to_localhost_8080() {
+        assert_eq!(config(&[]).unwrap().bind_address(), ("127.0.0.1".to_string(), 8080));
+        assert_eq!(
+            config(&[("BACKEND_HOST", "0.0.0.0"), ("BACKEND_PORT", "9000")]).unwrap().bind_address(),
+            ("0.0.0.0".to_string(), 9000)
+        );
+    }
+
+    #[test]
+    fn test_invalid_port_is_rejected() {
+        assert!(config(&[("BACKEND_PORT", "eighty")]).unwrap_err().contains("Invalid BACKEND_PORT"));
+        assert!(config(&[("BACKEND_PORT", "70000")]).is_err());
+        assert!(config(&[("BACKEND_PORT", "0")]).is_err());
+        assert!(config(&[("BACKEND_HOST", " ")]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_server_tuning_is_rejected() {
+        assert_eq!(config(&[("SERVER_WORKERS", "2")]).unwrap().server.workers, 2);
+        assert!(config(&[("SERVER_WORKERS", "0")]).unwrap_err().contains("SERVER_WORKERS"));
+        assert!(config(&[("SERVER_KEEP_ALIVE_SECS", "soon")]).is_err());
+    }
+}
@@ -1,65 +1,164 @@
-use actix_web::{web, App, HttpResponse, HttpServer, middleware::Logger};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use actix_web::{web, App, HttpResponse, HttpServer, middleware::{from_fn, Logger}};
 
+mod config;
 mod routes;
+use config::Config;
 use routes::*;
 use store::Store;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
 	dotenv::dotenv().ok();
-	println!("🚀 Backend Server starting on http://127.0.0.1:8080");
+	let config = match Config::from_env() {
+		Ok(config) => config,
+		Err(e) => {
+			println!("❌ Invalid configuration: {}", e);
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+		}
+	};
+	println!("🚀 Backend Server starting on http://{}:{}", config.host, config.port);
 
 	// Connect to database
 	let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 	let store = match Store::connect(&database_url).await {
 		Ok(s) => {
 			println!("✅ Connected to database");
-			Arc::new(Mutex::new(s))
+			web::Data::new(s)
 		}
 		Err(e) => {
-			println!("❌ Failed to connect to database: {}", e);
-			return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Database connection failed: {}", e)));
+			println!("❌ Failed to open store: {}", e);
+			return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Store initialization failed: {}", e)));
 		}
 	};
 
+	// Periodically purge inactive quotes past the retention period
+	let quote_retention_hours: i64 = std::env::var("QUOTE_RETENTION_HOURS")
+		.ok()
+		.and_then(|v| v.parse().ok())
+		.unwrap_or(24);
+	let quote_cleanup_interval_secs: u64 = std::env::var("QUOTE_CLEANUP_INTERVAL_SECS")
+		.ok()
+		.and_then(|v| v.parse().ok())
+		.unwrap_or(3600)
+		.max(1);
+	let cleanup_store = store.clone();
+	actix_web::rt::spawn(async move {
+		let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(quote_cleanup_interval_secs));
+		loop {
+			interval.tick().await;
+			match cleanup_store.delete_quotes_older_than(chrono::Duration::hours(quote_retention_hours)).await {
+				Ok(deleted) => println!("🧹 Deleted {} stale quotes older than {}h", deleted, quote_retention_hours),
+				Err(e) => println!("❌ Failed to delete stale quotes: {}", e),
+			}
+		}
+	});
+
+	// Optionally sweep zero-amount balance rows for all users
+	if let Some(prune_interval_secs) = std::env::var("BALANCE_PRUNE_INTERVAL_SECS")
+		.ok()
+		.and_then(|v| v.parse::<u64>().ok())
+		.filter(|secs| *secs > 0)
+	{
+		let prune_store = store.clone();
+		actix_web::rt::spawn(async move {
+			let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(prune_interval_secs));
+			loop {
+				interval.tick().await;
+				match prune_store.prune_all_zero_balances().await {
+					Ok(pruned) => println!("🧹 Pruned {} zero balances", pruned),
+					Err(e) => println!("❌ Failed to prune zero balances: {}", e),
+				}
+			}
+		});
+	}
+
+	// Finish indexer key removals for deactivated users that failed at deactivation time
+	if routes::indexer_client::indexer_url().is_some() {
+		let removal_store = store.clone();
+		let removal_interval_secs: u64 = std::env::var("INDEXER_KEY_REMOVAL_RETRY_SECS")
+			.ok()
+			.and_then(|v| v.parse().ok())
+			.filter(|secs| *secs > 0)
+			.unwrap_or(300);
+		actix_web::rt::spawn(async move {
+			let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(removal_interval_secs));
+			loop {
+				interval.tick().await;
+				let cleared = routes::indexer_client::reconcile_key_removals(&removal_store).await;
+				if cleared > 0 {
+					println!("🧹 Removed indexer keys for {} deactivated users", cleared);
+				}
+			}
+		});
+	}
+
 	HttpServer::new(move || {
 		App::new()
-			.app_data(web::Data::new(store.clone()))
+			.app_data(store.clone())
+			.app_data(routes::extractor_errors::json_config())
+			.app_data(routes::extractor_errors::query_config())
+			.wrap(from_fn(routes::request_id::propagate_request_id))
 			.wrap(Logger::default())
 			.service(
 				web::scope("/api")
 					// User routes
 					.service(sign_up)
 					.service(sign_in)
+					.service(validate_token)
+					.service(refresh_token)
+					.service(revoke_token)
+					// Registered before get_user so "settings" isn't taken as a user id
+					.service(get_user_settings)
+					.service(update_user_settings)
 					.service(get_user)
+					.service(deactivate_user)
+					.service(rotate_keys)
+					.service(delete_user)
 					// Solana routes
 					.service(sol_balance)
 					.service(token_balance)
 					.service(send_sol)
 					.service(add_sol_balance)
+					.service(transaction_status_batch)
+					.service(transaction_status)
 					// Jupiter routes
 					.service(quote)
 					.service(swap)
 					// Asset routes
 					.service(create_asset)
 					.service(list_assets)
+					.service(get_assets_by_symbol)
+					.service(get_asset_by_mint)
 					.service(get_asset)
 					.service(update_asset)
+					.service(upsert_asset_by_mint)
 					.service(delete_asset)
 					// Balance routes
 					.service(create_balance)
 					.service(get_user_balances)
+					.service(get_portfolio)
 					.service(get_balance)
+					.service(get_balance_history)
 					.service(update_balance)
 					.service(transfer_balance)
+					.service(prune_balances)
+					.service(reconcile_user)
+					.service(capabilities)
+					.service(backfill_pubkeys)
+					.service(login_attempts)
+					.service(liabilities)
+					.service(admin_config)
+					.service(list_unresolved_operations)
+					.service(resolve_unresolved_operation)
 					// Health check
 					.route("/health", web::get().to(health_check))
 			)
 			.route("/", web::get().to(index))
 	})
-	.bind("127.0.0.1:8080")?
+	.workers(config.server.workers)
+	.keep_alive(config.server.keep_alive())
+	.client_request_timeout(config.server.client_timeout())
+	.bind(config.bind_address())?
 	.run()
 	.await
 }
@@ -70,25 +169,49 @@ async fn index() -> HttpResponse {
 		"version": "1.0.0",
 		"status": "running",
 		"endpoints": [
-			"POST /api/signup - User signup",
-			"POST /api/signin - User signin",
+			"POST /api/signup - User signup (rate limited per IP)",
+			"POST /api/signin - User signin (rate limited per IP)",
+			"POST /api/validate-token - Check a signin token and return its user id",
+			"POST /api/token/refresh - Exchange a refresh token for a new access and refresh token",
+			"POST /api/token/revoke - Revoke a refresh token and its family (sign out)",
+			"GET /api/user/settings - Get own settings (bearer token)",
+			"PUT /api/user/settings - Update own settings (bearer token)",
 			"GET /api/user/{id} - Get user info",
+			"POST /api/user/{id}/deactivate - Deactivate user and stop monitoring their keys (self or admin)",
+			"DELETE /api/user/{id} - Delete user with their balances, quotes and key shares (self or admin)",
+			"POST /api/rotate-keys - Replace an empty wallet's key with a fresh one and move monitoring to it (self or admin)",
 			"GET /api/sol-balance/{pubkey} - Get SOL balance",
 			"GET /api/token-balance/{pubkey}/{mint} - Get token balance",
-			"POST /api/send-sol - Send SOL transaction",
+			"POST /api/send-sol - Send SOL transaction (honours Idempotency-Key)",
 			"POST /api/add-sol-balance - Add SOL balance",
+			"GET /api/transaction-status/{signature} - Transaction status",
+			"POST /api/transaction-status/batch - Statuses for up to 256 signatures",
 			"POST /api/quote - Get Jupiter quote",
-			"POST /api/swap - Jupiter swap",
+			"POST /api/swap - Jupiter swap (honours Idempotency-Key)",
 			"POST /api/assets - Create asset",
-			"GET /api/assets - List assets",
+			"GET /api/assets?limit=&offset=&symbol=&sort=&order=&search= - List assets (paginated, total in X-Total-Count)",
 			"GET /api/assets/{asset_id} - Get asset",
+			"GET /api/assets/by-symbol/{symbol} - List assets sharing a symbol",
+			"GET /api/assets/by-mint/{mint} - Get asset by mint address",
 			"PUT /api/assets/{asset_id} - Update asset",
+			"PUT /api/assets/by-mint/{mint} - Create or update asset by mint",
 			"DELETE /api/assets/{asset_id} - Delete asset",
 			"POST /api/balances - Create balance",
 			"GET /api/users/{user_id}/balances - Get user balances",
+			"GET /api/users/{user_id}/portfolio - Balances valued in USD with a total (unpriced assets excluded)",
 			"GET /api/users/{user_id}/balances/{asset_id} - Get balance",
+			"GET /api/users/{user_id}/balances/{asset_id}/history?limit=&offset= - Balance change history",
 			"PUT /api/users/{user_id}/balances/{asset_id} - Update balance",
 			"POST /api/balances/transfer - Transfer balance",
+			"POST /api/users/{user_id}/prune-balances - Delete zero-amount balances (admin)",
+			"POST /api/users/{user_id}/reconcile?fix=true - Compare (and with admin key, correct) balances against chain",
+			"GET /api/capabilities - Deployment network, features and limits",
+			"POST /api/admin/backfill-pubkeys - Restore missing user public keys from the MPC service (admin)",
+			"GET /api/admin/login-attempts?email=&ip_address=&success=&since=&limit= - Recent sign-in attempts (admin)",
+			"GET /api/admin/liabilities - Total user balances per asset against on-chain holdings, flagging shortfalls (admin)",
+			"GET /api/admin/config - Effective configuration with secrets redacted (admin)",
+			"GET /api/admin/unresolved-operations - Sends and swaps whose on-chain outcome is unknown (admin)",
+			"POST /api/admin/unresolved-operations/{id}/resolve - Settle one by its signature status: keep or refund the debit (admin)",
 			"GET /api/health - Health check"
 		]    
 	}))
@@ -97,6 +220,11 @@ async fn index() -> HttpResponse {
 async fn health_check() -> HttpResponse {
 	HttpResponse::Ok().json(serde_json::json!({
 		"status": "healthy",
-		"timestamp": chrono::Utc::now()
+		"timestamp": chrono::Utc::now(),
+		"circuit_breakers": [
+			JUPITER_QUOTE_BREAKER.status(),
+			JUPITER_SWAP_BREAKER.status(),
+			routes::mpc_client::MPC_BREAKER.status(),
+		]
 	}))
 }
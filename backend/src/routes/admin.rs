@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use store::{asset::Asset, login_attempt::LoginAttemptFilter, Store};
+use subtle::ConstantTimeEq;
+
+use super::rpc;
+
+const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+pub const ADMIN_KEY_HEADER: &str = "X-Admin-Key";
+
+/// True when the request carries the key configured in `ADMIN_API_KEY`. With no key
+/// configured, admin-only operations are disabled entirely.
+pub fn is_admin(req: &HttpRequest) -> bool {
+    let expected = match std::env::var("ADMIN_API_KEY") {
+        Ok(key) if !key.is_empty() => key,
+        _ => return false,
+    };
+
+    req.headers()
+        .get(ADMIN_KEY_HEADER)
+        .is_some_and(|provided| keys_match(provided.as_bytes(), expected.as_bytes()))
+}
+
+/// Compared in constant time, so response timing doesn't reveal how much of a guess was right.
+fn keys_match(provided: &[u8], expected: &[u8]) -> bool {
+    provided.ct_eq(expected).into()
+}
+
+#[derive(Serialize)]
+pub struct BackfillFailure {
+    pub user_id: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct BackfillPubkeysReport {
+    pub users_missing_key: usize,
+    pub backfilled: usize,
+    pub without_shares: Vec<String>,
+    pub failed: Vec<BackfillFailure>,
+}
+
+/// Fill in `public_key` for users that have shares in the MPC service but no key recorded
+/// in the store. The key is read from the share records, nothing is regenerated.
+#[actix_web::post("/admin/backfill-pubkeys")]
+pub async fn backfill_pubkeys(
+    http_req: HttpRequest,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    let user_ids = match store.users_missing_public_key().await {
+        Ok(user_ids) => user_ids,
+        Err(e) => {
+            eprintln!("Failed to list users missing a public key: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to list users"
+            })));
+        }
+    };
+
+    let mut report = BackfillPubkeysReport {
+        users_missing_key: user_ids.len(),
+        ..Default::default()
+    };
+
+    for user_id in user_ids {
+        let outcome = match store.fetch_public_key_via_mpc(&user_id).await {
+            Ok(Some(public_key)) => store.set_missing_public_key(&user_id, &public_key).await.map(Some),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+
+        match outcome {
+            Ok(Some(true)) => report.backfilled += 1,
+            Ok(Some(false)) => {}
+            Ok(None) => report.without_shares.push(user_id),
+            Err(e) => report.failed.push(BackfillFailure { user_id, error: e.to_string() }),
+        }
+    }
+
+    println!(
+        "Public key backfill: {} of {} users backfilled, {} without shares, {} failed",
+        report.backfilled, report.users_missing_key, report.without_shares.len(), report.failed.len()
+    );
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Recent sign-in attempts for audit and anomaly detection, newest first.
+/// Filters: `email`, `ip_address`, `success`, `since` (RFC 3339) and `limit` (max 500).
+#[actix_web::get("/admin/login-attempts")]
+pub async fn login_attempts(
+    http_req: HttpRequest,
+    query: web::Query<LoginAttemptFilter>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    match store.get_recent_login_attempts(query.into_inner()).await {
+        Ok(attempts) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "count": attempts.len(),
+            "attempts": attempts
+        }))),
+        Err(e) => {
+            eprintln!("Failed to load login attempts: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load login attempts"
+            })))
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AssetLiability {
+    pub asset_id: String,
+    /// `None` when balances reference an asset that no longer exists.
+    pub mint_address: Option<String>,
+    pub symbol: Option<String>,
+    pub liabilities: Decimal,
+    pub holdings: Decimal,
+    /// Holdings less liabilities; negative when the asset is undercollateralized.
+    pub surplus: Decimal,
+    pub undercollateralized: bool,
+}
+
+#[derive(Serialize)]
+pub struct LiabilitiesReport {
+    pub solvent: bool,
+    pub wallets_checked: usize,
+    pub assets: Vec<AssetLiability>,
+}
+
+/// Match each asset's total user balance against what the custodied wallets hold of its mint.
+fn liability_report(
+    totals: Vec<(String, Decimal)>,
+    assets: &HashMap<String, Asset>,
+    holdings: &HashMap<String, Decimal>,
+) -> Vec<AssetLiability> {
+    totals.into_iter().map(|(asset_id, liabilities)| {
+        let asset = assets.get(&asset_id);
+        let held = asset
+            .and_then(|asset| holdings.get(&asset.mint_address))
+            .copied()
+            .unwrap_or(Decimal::ZERO);
+
+        AssetLiability {
+            mint_address: asset.map(|asset| asset.mint_address.clone()),
+            symbol: asset.map(|asset| asset.symbol.clone()),
+            asset_id,
+            liabilities,
+            holdings: held,
+            surplus: held - liabilities,
+            undercollateralized: liabilities > held,
+        }
+    }).collect()
+}
+
+/// Solvency check: total internal balances per asset against the on-chain holdings of every
+/// user wallet. Fails with 502 rather than report partial holdings if any wallet can't be read.
+#[actix_web::get("/admin/liabilities")]
+pub async fn liabilities(
+    http_req: HttpRequest,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    let loaded = async {
+        let totals = store.total_balance_by_asset().await?;
+        let assets = store.list_assets().await?;
+        let wallets = store.wallet_public_keys().await?;
+        Ok::<_, store::error::UserError>((totals, assets, wallets))
+    }.await;
+
+    let (totals, assets, wallets) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Failed to load liabilities: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load liabilities"
+            })));
+        }
+    };
+
+    let client = rpc::rpc_client();
+    let mut holdings: HashMap<String, Decimal> = HashMap::new();
+    for wallet in &wallets {
+        let lamports = rpc::get_sol_balance(&client, wallet).await;
+        let tokens = rpc::get_token_balances(&client, wallet).await;
+        let (lamports, tokens) = match (lamports, tokens) {
+            (Ok(lamports), Ok(tokens)) => (lamports, tokens),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Failed to fetch holdings of {}: {}", wallet, e);
+                return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": "Failed to fetch on-chain holdings",
+                    "public_key": wallet
+                })));
+            }
+        };
+
+        // Native SOL is tracked under the wrapped SOL mint
+        *holdings.entry(SOL_MINT.to_string()).or_insert(Decimal::ZERO) +=
+            Decimal::from(lamports) / Decimal::from(1_000_000_000u64);
+        for (mint, amount) in tokens {
+            *holdings.entry(mint).or_insert(Decimal::ZERO) += amount;
+        }
+    }
+
+    let assets: HashMap<String, Asset> = assets.into_iter().map(|asset| (asset.id.clone(), asset)).collect();
+    let report = liability_report(totals, &assets, &holdings);
+    let undercollateralized: Vec<&str> = report.iter()
+        .filter(|liability| liability.undercollateralized)
+        .map(|liability| liability.asset_id.as_str())
+        .collect();
+
+    if !undercollateralized.is_empty() {
+        println!("Liabilities exceed on-chain holdings for: {}", undercollateralized.join(", "));
+    }
+
+    Ok(HttpResponse::Ok().json(LiabilitiesReport {
+        solvent: undercollateralized.is_empty(),
+        wallets_checked: wallets.len(),
+        assets: report,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(id: &str, mint_address: &str, symbol: &str) -> Asset {
+        Asset {
+            id: id.to_string(),
+            mint_address: mint_address.to_string(),
+            decimals: 9,
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            logo_url: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_keys_match() {
+        assert!(keys_match(b"secret-key", b"secret-key"));
+        assert!(!keys_match(b"secret-kez", b"secret-key"));
+        assert!(!keys_match(b"secret", b"secret-key"));
+        assert!(!keys_match(b"", b"secret-key"));
+    }
+
+    #[test]
+    fn test_liability_report_flags_undercollateralized_assets() {
+        let assets = HashMap::from([
+            ("sol-native".to_string(), asset("sol-native", SOL_MINT, "SOL")),
+            ("usdc".to_string(), asset("usdc", "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "USDC")),
+        ]);
+        let holdings = HashMap::from([
+            (SOL_MINT.to_string(), Decimal::new(125, 1)),
+            ("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(), Decimal::from(90)),
+        ]);
+        let totals = vec![
+            ("sol-native".to_string(), Decimal::from(12)),
+            ("usdc".to_string(), Decimal::from(100)),
+            ("deleted".to_string(), Decimal::ONE),
+        ];
+
+        let report = liability_report(totals, &assets, &holdings);
+
+        assert!(!report[0].undercollateralized);
+        assert_eq!(report[0].surplus, Decimal::new(5, 1));
+        assert!(report[1].undercollateralized);
+        assert_eq!(report[1].surplus, Decimal::from(-10));
+        assert_eq!(report[2].mint_address, None);
+        assert_eq!(report[2].holdings, Decimal::ZERO);
+        assert!(report[2].undercollateralized);
+    }
+}
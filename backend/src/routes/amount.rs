@@ -0,0 +1,48 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+use store::helper::{format_amount, to_base_units};
+
+/// Lamports per SOL is 10^9.
+pub const SOL_DECIMALS: i32 = 9;
+
+/// An amount in both representations: the integer in the asset's base units (lamports
+/// for SOL) and the decimal string a UI would show. Every amount the API returns carries
+/// both, so clients never have to guess the unit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AmountView {
+    pub amount_base_units: String,
+    pub amount_ui: String,
+}
+
+impl AmountView {
+    /// From a UI-decimal amount such as a stored balance.
+    pub fn new(amount: Decimal, decimals: i32) -> Self {
+        Self {
+            amount_base_units: to_base_units(amount, decimals),
+            amount_ui: format_amount(amount, decimals),
+        }
+    }
+
+    /// From an integer base-unit amount such as lamports.
+    pub fn from_base_units(base_units: u64, decimals: i32) -> Self {
+        let scale = decimals.clamp(0, 28) as u32;
+        Self::new(Decimal::from_i128_with_scale(base_units as i128, scale), decimals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amount_view_round_trips_units() {
+        let from_lamports = AmountView::from_base_units(1_500_000_000, SOL_DECIMALS);
+        assert_eq!(from_lamports.amount_base_units, "1500000000");
+        assert_eq!(from_lamports.amount_ui, "1.500000000");
+        assert_eq!(AmountView::new(Decimal::new(15, 1), SOL_DECIMALS), from_lamports);
+
+        let usdc = AmountView::new(Decimal::new(2_500_000, 6), 6);
+        assert_eq!(usdc.amount_base_units, "2500000");
+        assert_eq!(usdc.amount_ui, "2.500000");
+    }
+}
@@ -1,12 +1,12 @@
-use std::sync::Arc;
 use actix_web::{web, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
-use store::Store;
-use tokio::sync::Mutex;
+use store::{asset::{AssetSort, ListAssetsParams}, error::UserError, pubkey::SolanaPubkey, Store};
+
+use super::pagination::{page_offset, resolve_page, Paginated, MAX_PER_PAGE};
 
 #[derive(Deserialize)]
 pub struct CreateAssetRequest {
-    pub mint_address: String,
+    pub mint_address: SolanaPubkey,
     pub decimals: i32,
     pub name: String,
     pub symbol: String,
@@ -20,6 +20,46 @@ pub struct UpdateAssetRequest {
     pub logo_url: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct UpsertAssetRequest {
+    pub decimals: i32,
+    pub name: String,
+    pub symbol: String,
+    pub logo_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AssetListQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub sort: Option<String>,
+    pub order: Option<String>,
+    pub search: Option<String>,
+    pub symbol: Option<String>,
+}
+
+const DEFAULT_ASSET_LIMIT: i64 = 50;
+
+/// The `(limit, offset)` window to read. `limit`/`offset` take precedence; `page`/`per_page`
+/// are deprecated but still honoured for older clients, and with neither the first 50 assets
+/// are returned. `None` when the page number is too large to have an offset.
+fn resolve_window(query: &AssetListQuery) -> Option<(i64, i64)> {
+    if !uses_page_params(query) {
+        let limit = query.limit.unwrap_or(DEFAULT_ASSET_LIMIT).clamp(1, MAX_PER_PAGE);
+        return Some((limit, query.offset.unwrap_or(0).max(0)));
+    }
+
+    let (page, per_page) = resolve_page(query.page, query.per_page);
+    Some((per_page, page_offset(page, per_page)?))
+}
+
+/// Whether the window comes from the deprecated `page`/`per_page` parameters.
+fn uses_page_params(query: &AssetListQuery) -> bool {
+    query.limit.is_none() && query.offset.is_none() && (query.page.is_some() || query.per_page.is_some())
+}
+
 #[derive(Serialize)]
 pub struct AssetResponse {
     pub id: String,
@@ -35,10 +75,8 @@ pub struct AssetResponse {
 #[actix_web::post("/assets")]
 pub async fn create_asset(
     req: web::Json<CreateAssetRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
-    let store_guard = store.lock().await;
-    
     let create_request = store::asset::CreateAssetRequest {
         mint_address: req.mint_address.clone(),
         decimals: req.decimals,
@@ -47,38 +85,71 @@ pub async fn create_asset(
         logo_url: req.logo_url.clone(),
     };
 
-    match store_guard.create_asset(create_request).await {
-        Ok(asset) => {
-            let response = AssetResponse {
-                id: asset.id,
-                mint_address: asset.mint_address,
-                decimals: asset.decimals,
-                name: asset.name,
-                symbol: asset.symbol,
-                logo_url: asset.logo_url,
-                created_at: asset.created_at,
-                updated_at: asset.updated_at,
-            };
-            Ok(HttpResponse::Created().json(response))
-        }
-        Err(e) => {
-            println!("Failed to create asset: {:?}", e);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                "error": e.to_string()
-            })))
-        }
-    }
+    let asset = store.create_asset(create_request).await?;
+    let response = AssetResponse {
+        id: asset.id,
+        mint_address: asset.mint_address,
+        decimals: asset.decimals,
+        name: asset.name,
+        symbol: asset.symbol,
+        logo_url: asset.logo_url,
+        created_at: asset.created_at,
+        updated_at: asset.updated_at,
+    };
+    Ok(HttpResponse::Created().json(response))
 }
 
+/// Paged with `limit`/`offset`. Requests still using `page`/`per_page` get the same envelope
+/// plus a `Deprecation: true` header.
 #[actix_web::get("/assets")]
 pub async fn list_assets(
-    store: web::Data<Arc<Mutex<Store>>>,
+    query: web::Query<AssetListQuery>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
-    let store_guard = store.lock().await;
+    let sort = match query.sort.as_deref() {
+        None => AssetSort::CreatedAt,
+        Some(value) => match AssetSort::parse(value) {
+            Some(sort) => sort,
+            None => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "sort must be one of: symbol, name, created_at"
+                })));
+            }
+        },
+    };
+    let descending = match query.order.as_deref() {
+        // Newest first by default, alphabetical otherwise
+        None => sort == AssetSort::CreatedAt,
+        Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "order must be asc or desc"
+            })));
+        }
+    };
+    let (limit, offset) = match resolve_window(&query) {
+        Some(window) => window,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "page is out of range"
+            })));
+        }
+    };
 
-    match store_guard.list_assets().await {
-        Ok(assets) => {
-            let response: Vec<AssetResponse> = assets.into_iter().map(|asset| AssetResponse {
+    let params = ListAssetsParams {
+        limit,
+        offset,
+        sort,
+        descending,
+        search: query.search.clone(),
+        symbol: query.symbol.clone(),
+    };
+
+
+    match store.list_assets_paginated(params).await {
+        Ok((assets, total)) => {
+            let items: Vec<AssetResponse> = assets.into_iter().map(|asset| AssetResponse {
                 id: asset.id,
                 mint_address: asset.mint_address,
                 decimals: asset.decimals,
@@ -89,7 +160,12 @@ pub async fn list_assets(
                 updated_at: asset.updated_at,
             }).collect();
             
-            Ok(HttpResponse::Ok().json(response))
+            let mut response = HttpResponse::Ok();
+            response.insert_header(("X-Total-Count", total.to_string()));
+            if uses_page_params(&query) {
+                response.insert_header(("Deprecation", "true"));
+            }
+            Ok(response.json(Paginated::from_window(items, limit, offset, total)))
         }
         Err(e) => {
             println!("Failed to list assets: {:?}", e);
@@ -103,12 +179,41 @@ pub async fn list_assets(
 #[actix_web::get("/assets/{asset_id}")]
 pub async fn get_asset(
     path: web::Path<String>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
     let asset_id = path.into_inner();
-    let store_guard = store.lock().await;
 
-    match store_guard.get_asset_by_id(&asset_id).await {
+    let asset = store.get_asset_by_id(&asset_id).await?.ok_or(UserError::AssetNotFound)?;
+    let response = AssetResponse {
+        id: asset.id,
+        mint_address: asset.mint_address,
+        decimals: asset.decimals,
+        name: asset.name,
+        symbol: asset.symbol,
+        logo_url: asset.logo_url,
+        created_at: asset.created_at,
+        updated_at: asset.updated_at,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Look an asset up by its mint address, which is what the Jupiter swap flow deals in.
+#[actix_web::get("/assets/by-mint/{mint}")]
+pub async fn get_asset_by_mint(
+    path: web::Path<String>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let mint_address: SolanaPubkey = match path.into_inner().parse() {
+        Ok(mint_address) => mint_address,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+
+
+    match store.get_asset_by_mint(mint_address.as_str()).await {
         Ok(Some(asset)) => {
             let response = AssetResponse {
                 id: asset.id,
@@ -128,7 +233,7 @@ pub async fn get_asset(
             })))
         }
         Err(e) => {
-            println!("Failed to get asset: {:?}", e);
+            println!("Failed to get asset by mint {}: {:?}", mint_address, e);
             Ok(HttpResponse::InternalServerError().json(serde_json::json!({
                 "error": "Failed to retrieve asset"
             })))
@@ -136,14 +241,44 @@ pub async fn get_asset(
     }
 }
 
+/// All assets sharing a symbol, so clients can pick the right mint.
+#[actix_web::get("/assets/by-symbol/{symbol}")]
+pub async fn get_assets_by_symbol(
+    path: web::Path<String>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let symbol = path.into_inner();
+
+    match store.get_assets_by_symbol(&symbol).await {
+        Ok(assets) => {
+            let response: Vec<AssetResponse> = assets.into_iter().map(|asset| AssetResponse {
+                id: asset.id,
+                mint_address: asset.mint_address,
+                decimals: asset.decimals,
+                name: asset.name,
+                symbol: asset.symbol,
+                logo_url: asset.logo_url,
+                created_at: asset.created_at,
+                updated_at: asset.updated_at,
+            }).collect();
+            Ok(HttpResponse::Ok().json(response))
+        }
+        Err(e) => {
+            println!("Failed to get assets by symbol: {:?}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to retrieve assets"
+            })))
+        }
+    }
+}
+
 #[actix_web::put("/assets/{asset_id}")]
 pub async fn update_asset(
     path: web::Path<String>,
     req: web::Json<UpdateAssetRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
     let asset_id = path.into_inner();
-    let store_guard = store.lock().await;
 
     let update_request = store::asset::UpdateAssetRequest {
         id: asset_id,
@@ -152,46 +287,172 @@ pub async fn update_asset(
         logo_url: req.logo_url.clone(),
     };
 
-    match store_guard.update_asset(update_request).await {
-        Ok(asset) => {
-            let response = AssetResponse {
-                id: asset.id,
-                mint_address: asset.mint_address,
-                decimals: asset.decimals,
-                name: asset.name,
-                symbol: asset.symbol,
-                logo_url: asset.logo_url,
-                created_at: asset.created_at,
-                updated_at: asset.updated_at,
-            };
-            Ok(HttpResponse::Ok().json(response))
-        }
+    let asset = store.update_asset(update_request).await?;
+    let response = AssetResponse {
+        id: asset.id,
+        mint_address: asset.mint_address,
+        decimals: asset.decimals,
+        name: asset.name,
+        symbol: asset.symbol,
+        logo_url: asset.logo_url,
+        created_at: asset.created_at,
+        updated_at: asset.updated_at,
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[actix_web::put("/assets/by-mint/{mint}")]
+pub async fn upsert_asset_by_mint(
+    path: web::Path<String>,
+    req: web::Json<UpsertAssetRequest>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let mint_address: SolanaPubkey = match path.into_inner().parse() {
+        Ok(mint_address) => mint_address,
         Err(e) => {
-            println!("Failed to update asset: {:?}", e);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
                 "error": e.to_string()
-            })))
+            })));
+        }
+    };
+    if let Err(e) = store::helper::validate_asset_decimals(req.decimals) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": e.to_string()
+        })));
+    }
+
+
+    let create_request = store::asset::CreateAssetRequest {
+        mint_address: mint_address.clone(),
+        decimals: req.decimals,
+        name: req.name.clone(),
+        symbol: req.symbol.clone(),
+        logo_url: req.logo_url.clone(),
+    };
+
+    let (asset, created) = match store.get_or_create_asset(create_request).await {
+        Ok(result) => result,
+        Err(e) => {
+            println!("Failed to upsert asset {}: {:?}", mint_address, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to upsert asset"
+            })));
+        }
+    };
+
+    let asset = if created {
+        asset
+    } else {
+        // Decimals are fixed by the on-chain mint, so only metadata may change
+        if asset.decimals != req.decimals {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Asset decimals are {} and cannot be changed", asset.decimals)
+            })));
+        }
+
+        let update_request = store::asset::UpdateAssetRequest {
+            id: asset.id,
+            name: Some(req.name.clone()),
+            symbol: Some(req.symbol.clone()),
+            logo_url: req.logo_url.clone(),
+        };
+
+        match store.update_asset(update_request).await {
+            Ok(asset) => asset,
+            Err(e) => {
+                println!("Failed to update asset {}: {:?}", mint_address, e);
+                return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to upsert asset"
+                })));
+            }
         }
+    };
+
+    let response = AssetResponse {
+        id: asset.id,
+        mint_address: asset.mint_address,
+        decimals: asset.decimals,
+        name: asset.name,
+        symbol: asset.symbol,
+        logo_url: asset.logo_url,
+        created_at: asset.created_at,
+        updated_at: asset.updated_at,
+    };
+
+    if created {
+        Ok(HttpResponse::Created().json(response))
+    } else {
+        Ok(HttpResponse::Ok().json(response))
     }
 }
 
 #[actix_web::delete("/assets/{asset_id}")]
 pub async fn delete_asset(
     path: web::Path<String>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
     let asset_id = path.into_inner();
-    let store_guard = store.lock().await;
 
-    match store_guard.delete_asset(&asset_id).await {
-        Ok(()) => {
-            Ok(HttpResponse::NoContent().finish())
-        }
-        Err(e) => {
-            println!("Failed to delete asset: {:?}", e);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                "error": e.to_string()
-            })))
+    store.delete_asset(&asset_id).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(page: Option<i64>, per_page: Option<i64>, limit: Option<i64>, offset: Option<i64>) -> AssetListQuery {
+        AssetListQuery { page, per_page, limit, offset, sort: None, order: None, search: None, symbol: None }
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_get_asset_by_mint() {
+        let database_url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+        let store = web::Data::new(Store::connect(&database_url).await.unwrap());
+        let app = actix_web::test::init_service(
+            actix_web::App::new().app_data(store.clone()).service(get_asset_by_mint),
+        ).await;
+
+        // The system program id is a valid pubkey that is never a mint
+        let unknown_mint = "11111111111111111111111111111111";
+        let request = actix_web::test::TestRequest::get().uri(&format!("/assets/by-mint/{}", unknown_mint)).to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let request = actix_web::test::TestRequest::get().uri("/assets/by-mint/not-a-mint").to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let mint_address = "So11111111111111111111111111111111111111112";
+        let (asset, created) = store.get_or_create_asset(store::asset::CreateAssetRequest {
+            mint_address: mint_address.parse().unwrap(),
+            decimals: 9,
+            name: "Wrapped SOL".to_string(),
+            symbol: "SOL".to_string(),
+            logo_url: None,
+        }).await.unwrap();
+
+        let request = actix_web::test::TestRequest::get().uri(&format!("/assets/by-mint/{}", mint_address)).to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::OK);
+        let body: serde_json::Value = actix_web::test::read_body_json(response).await;
+        assert_eq!(body["id"], asset.id);
+        assert_eq!(body["mint_address"], mint_address);
+
+        if created {
+            store.delete_asset(&asset.id).await.unwrap();
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_resolve_window() {
+        assert_eq!(resolve_window(&query(None, None, None, None)), Some((50, 0)));
+        assert_eq!(resolve_window(&query(None, None, Some(10), Some(30))), Some((10, 30)));
+        assert_eq!(resolve_window(&query(None, None, Some(1_000), Some(-5))), Some((MAX_PER_PAGE, 0)));
+        // Legacy page parameters still work when no limit/offset is given
+        assert_eq!(resolve_window(&query(Some(3), Some(20), None, None)), Some((20, 40)));
+        assert_eq!(resolve_window(&query(Some(3), Some(20), Some(5), None)), Some((5, 0)));
+        assert_eq!(resolve_window(&query(Some(i64::MAX), Some(20), None, None)), None);
+    }
+}
@@ -0,0 +1,10 @@
+use actix_web::HttpRequest;
+use store::helper::validate_token;
+
+/// The user id behind the `Authorization: Bearer <token>` header, if present, correctly
+/// signed and not expired.
+pub fn authenticated_user(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get("Authorization")?.to_str().ok()?;
+    let token = header.strip_prefix("Bearer ")?.trim();
+    validate_token(token).ok()
+}
@@ -1,10 +1,13 @@
-use std::sync::Arc;
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
-use store::Store;
-use tokio::sync::Mutex;
+use std::collections::HashMap;
+use store::{balance::BalanceWithDetails, error::UserError, helper::format_amount, Store};
 use rust_decimal::Decimal;
 
+use super::admin::is_admin;
+use super::amount::AmountView;
+use super::feature_flags::{operation_disabled, Operation};
+
 #[derive(Deserialize)]
 pub struct CreateBalanceRequest {
     pub user_id: String,
@@ -29,6 +32,9 @@ pub struct TransferRequest {
 pub struct BalanceResponse {
     pub id: String,
     pub amount: Decimal,
+    /// Omitted only when the asset's decimals couldn't be loaded.
+    #[serde(flatten)]
+    pub amount_view: Option<AmountView>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
     pub user_id: String,
@@ -39,6 +45,10 @@ pub struct BalanceResponse {
 pub struct BalanceWithDetailsResponse {
     pub id: String,
     pub amount: Decimal,
+    /// Same value as `amount_ui`; kept for existing clients.
+    pub amount_formatted: String,
+    #[serde(flatten)]
+    pub amount_view: AmountView,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
     pub user_id: String,
@@ -50,117 +60,174 @@ pub struct BalanceWithDetailsResponse {
     pub asset_logo_url: Option<String>,
 }
 
+async fn asset_decimals(store: &Store, asset_id: &str) -> Option<i32> {
+    match store.get_asset_by_id(asset_id).await {
+        Ok(asset) => asset.map(|asset| asset.decimals),
+        Err(e) => {
+            println!("Failed to load asset {} for amount formatting: {:?}", asset_id, e);
+            None
+        }
+    }
+}
+
+fn balance_response(balance: store::balance::Balance, decimals: Option<i32>) -> BalanceResponse {
+    BalanceResponse {
+        id: balance.id,
+        amount: balance.amount,
+        amount_view: decimals.map(|decimals| AmountView::new(balance.amount, decimals)),
+        created_at: balance.created_at,
+        updated_at: balance.updated_at,
+        user_id: balance.user_id,
+        asset_id: balance.asset_id,
+    }
+}
+
 #[actix_web::post("/balances")]
 pub async fn create_balance(
     req: web::Json<CreateBalanceRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
-    let store_guard = store.lock().await;
-    
     let create_request = store::balance::CreateBalanceRequest {
         user_id: req.user_id.clone(),
         asset_id: req.asset_id.clone(),
         amount: req.amount,
     };
 
-    match store_guard.create_or_update_balance(create_request).await {
-        Ok(balance) => {
-            let response = BalanceResponse {
-                id: balance.id,
-                amount: balance.amount,
-                created_at: balance.created_at,
-                updated_at: balance.updated_at,
-                user_id: balance.user_id,
-                asset_id: balance.asset_id,
-            };
-            Ok(HttpResponse::Created().json(response))
-        }
-        Err(e) => {
-            println!("Failed to create balance: {:?}", e);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                "error": e.to_string()
-            })))
-        }
-    }
+    let balance = store.create_or_update_balance(create_request).await?;
+    let decimals = asset_decimals(&store, &balance.asset_id).await;
+    Ok(HttpResponse::Created().json(balance_response(balance, decimals)))
 }
 
 #[actix_web::get("/users/{user_id}/balances")]
 pub async fn get_user_balances(
     path: web::Path<String>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
     let user_id = path.into_inner();
-    let store_guard = store.lock().await;
-
-    match store_guard.get_user_balances(&user_id).await {
-        Ok(balances) => {
-            let response: Vec<BalanceWithDetailsResponse> = balances.into_iter().map(|balance| BalanceWithDetailsResponse {
-                id: balance.id,
-                amount: balance.amount,
-                created_at: balance.created_at,
-                updated_at: balance.updated_at,
-                user_id: balance.user_id,
-                asset_id: balance.asset_id,
-                asset_mint_address: balance.asset_mint_address,
-                asset_name: balance.asset_name,
-                asset_symbol: balance.asset_symbol,
-                asset_decimals: balance.asset_decimals,
-                asset_logo_url: balance.asset_logo_url,
-            }).collect();
-            
-            Ok(HttpResponse::Ok().json(response))
-        }
-        Err(e) => {
-            println!("Failed to get user balances: {:?}", e);
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to retrieve balances"
-            })))
+
+    let balances = store.get_user_balances(&user_id).await?;
+    let response: Vec<BalanceWithDetailsResponse> = balances.into_iter().map(|balance| BalanceWithDetailsResponse {
+        id: balance.id,
+        amount: balance.amount,
+        amount_formatted: format_amount(balance.amount, balance.asset_decimals),
+        amount_view: AmountView::new(balance.amount, balance.asset_decimals),
+        created_at: balance.created_at,
+        updated_at: balance.updated_at,
+        user_id: balance.user_id,
+        asset_id: balance.asset_id,
+        asset_mint_address: balance.asset_mint_address,
+        asset_name: balance.asset_name,
+        asset_symbol: balance.asset_symbol,
+        asset_decimals: balance.asset_decimals,
+        asset_logo_url: balance.asset_logo_url,
+    }).collect();
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Serialize)]
+pub struct PortfolioAsset {
+    pub asset_id: String,
+    pub asset_mint_address: String,
+    pub asset_symbol: String,
+    pub amount: Decimal,
+    #[serde(flatten)]
+    pub amount_view: AmountView,
+    /// Null when Jupiter has no price for the mint.
+    pub usd_price: Option<String>,
+    pub usd_value: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PortfolioResponse {
+    pub user_id: String,
+    /// Sum of the priced assets; unpriced assets are left out.
+    pub total_usd_value: String,
+    pub assets: Vec<PortfolioAsset>,
+}
+
+fn portfolio(user_id: String, balances: Vec<BalanceWithDetails>, prices: &HashMap<String, Decimal>) -> PortfolioResponse {
+    let mut total = Decimal::ZERO;
+    let assets = balances.into_iter().map(|balance| {
+        let price = prices.get(&balance.asset_mint_address).copied();
+        let value = price.map(|price| (balance.amount * price).round_dp(2));
+        total += value.unwrap_or(Decimal::ZERO);
+
+        PortfolioAsset {
+            asset_id: balance.asset_id,
+            asset_mint_address: balance.asset_mint_address,
+            asset_symbol: balance.asset_symbol,
+            amount: balance.amount,
+            amount_view: AmountView::new(balance.amount, balance.asset_decimals),
+            usd_price: price.map(|price| price.to_string()),
+            usd_value: value.map(|value| value.to_string()),
         }
+    }).collect();
+
+    PortfolioResponse {
+        user_id,
+        total_usd_value: total.round_dp(2).to_string(),
+        assets,
     }
 }
 
+/// Every balance valued at the Jupiter USD price, plus the total.
+#[actix_web::get("/users/{user_id}/portfolio")]
+pub async fn get_portfolio(
+    path: web::Path<String>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let user_id = path.into_inner();
+
+    let balances = store.get_user_balances(&user_id).await?;
+
+    let mints: Vec<&str> = balances.iter().map(|balance| balance.asset_mint_address.as_str()).collect();
+    let prices = super::price_oracle::get_usd_prices(&mints).await;
+
+    Ok(HttpResponse::Ok().json(portfolio(user_id, balances, &prices)))
+}
+
 #[actix_web::get("/users/{user_id}/balances/{asset_id}")]
 pub async fn get_balance(
     path: web::Path<(String, String)>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
     let (user_id, asset_id) = path.into_inner();
-    let store_guard = store.lock().await;
-
-    match store_guard.get_balance(&user_id, &asset_id).await {
-        Ok(Some(balance)) => {
-            let response = BalanceResponse {
-                id: balance.id,
-                amount: balance.amount,
-                created_at: balance.created_at,
-                updated_at: balance.updated_at,
-                user_id: balance.user_id,
-                asset_id: balance.asset_id,
-            };
-            Ok(HttpResponse::Ok().json(response))
-        }
-        Ok(None) => {
-            Ok(HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Balance not found"
-            })))
-        }
-        Err(e) => {
-            println!("Failed to get balance: {:?}", e);
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to retrieve balance"
-            })))
-        }
-    }
+
+    let balance = store.get_balance(&user_id, &asset_id).await?.ok_or(UserError::BalanceNotFound)?;
+    let decimals = asset_decimals(&store, &balance.asset_id).await;
+    Ok(HttpResponse::Ok().json(balance_response(balance, decimals)))
+}
+
+#[derive(Deserialize)]
+pub struct BalanceHistoryQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[actix_web::get("/users/{user_id}/balances/{asset_id}/history")]
+pub async fn get_balance_history(
+    path: web::Path<(String, String)>,
+    query: web::Query<BalanceHistoryQuery>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let (user_id, asset_id) = path.into_inner();
+
+    let entries = store.get_balance_history(&user_id, &asset_id, query.limit, query.offset).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "asset_id": asset_id,
+        "entries": entries
+    })))
 }
 
 #[actix_web::put("/users/{user_id}/balances/{asset_id}")]
 pub async fn update_balance(
     path: web::Path<(String, String)>,
     req: web::Json<UpdateBalanceRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
     let (user_id, asset_id) = path.into_inner();
-    let store_guard = store.lock().await;
 
     let update_request = store::balance::UpdateBalanceRequest {
         user_id,
@@ -168,33 +235,19 @@ pub async fn update_balance(
         amount: req.amount,
     };
 
-    match store_guard.update_balance(update_request).await {
-        Ok(balance) => {
-            let response = BalanceResponse {
-                id: balance.id,
-                amount: balance.amount,
-                created_at: balance.created_at,
-                updated_at: balance.updated_at,
-                user_id: balance.user_id,
-                asset_id: balance.asset_id,
-            };
-            Ok(HttpResponse::Ok().json(response))
-        }
-        Err(e) => {
-            println!("Failed to update balance: {:?}", e);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                "error": e.to_string()
-            })))
-        }
-    }
+    let balance = store.update_balance(update_request).await?;
+    let decimals = asset_decimals(&store, &balance.asset_id).await;
+    Ok(HttpResponse::Ok().json(balance_response(balance, decimals)))
 }
 
 #[actix_web::post("/balances/transfer")]
 pub async fn transfer_balance(
     req: web::Json<TransferRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
-    let store_guard = store.lock().await;
+    if let Some(response) = operation_disabled(Operation::Transfer) {
+        return Ok(response);
+    }
 
     let transfer_request = store::balance::TransferRequest {
         from_user_id: req.from_user_id.clone(),
@@ -203,31 +256,77 @@ pub async fn transfer_balance(
         amount: req.amount,
     };
 
-    match store_guard.transfer_balance(transfer_request).await {
-        Ok((sender_balance, receiver_balance)) => {
-            let response = serde_json::json!({
-                "sender_balance": {
-                    "id": sender_balance.id,
-                    "amount": sender_balance.amount,
-                    "updated_at": sender_balance.updated_at,
-                    "user_id": sender_balance.user_id,
-                    "asset_id": sender_balance.asset_id,
-                },
-                "receiver_balance": {
-                    "id": receiver_balance.id,
-                    "amount": receiver_balance.amount,
-                    "updated_at": receiver_balance.updated_at,
-                    "user_id": receiver_balance.user_id,
-                    "asset_id": receiver_balance.asset_id,
-                }
-            });
-            Ok(HttpResponse::Ok().json(response))
-        }
-        Err(e) => {
-            println!("Failed to transfer balance: {:?}", e);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                "error": e.to_string()
-            })))
+    let (sender_balance, receiver_balance) = store.transfer_balance(transfer_request).await?;
+    let decimals = asset_decimals(&store, &sender_balance.asset_id).await;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "sender_balance": balance_response(sender_balance, decimals),
+        "receiver_balance": balance_response(receiver_balance, decimals),
+    })))
+}
+
+#[actix_web::post("/users/{user_id}/prune-balances")]
+pub async fn prune_balances(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    let user_id = path.into_inner();
+
+    let pruned = store.prune_zero_balances(&user_id).await?;
+    println!("Pruned {} zero balances for user {}", pruned, user_id);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "pruned": pruned
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balance(mint: &str, symbol: &str, amount: Decimal, decimals: i32) -> BalanceWithDetails {
+        BalanceWithDetails {
+            id: uuid::Uuid::new_v4().to_string(),
+            amount,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            user_id: "user-1".to_string(),
+            asset_id: format!("{}-asset", symbol),
+            asset_mint_address: mint.to_string(),
+            asset_name: symbol.to_string(),
+            asset_symbol: symbol.to_string(),
+            asset_decimals: decimals,
+            asset_logo_url: None,
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_portfolio_values_priced_assets_only() {
+        let balances = vec![
+            balance("sol-mint", "SOL", Decimal::new(15, 1), 9),
+            balance("usdc-mint", "USDC", Decimal::new(2500, 2), 6),
+            balance("unknown-mint", "NEW", Decimal::from(1000), 6),
+        ];
+        let prices = HashMap::from([
+            ("sol-mint".to_string(), Decimal::new(15025, 2)),
+            ("usdc-mint".to_string(), Decimal::new(9998, 4)),
+        ]);
+
+        let response = portfolio("user-1".to_string(), balances, &prices);
+
+        assert_eq!(response.assets[0].usd_value.as_deref(), Some("225.38"));
+        assert_eq!(response.assets[1].usd_value.as_deref(), Some("25.00"));
+        assert_eq!(response.assets[2].usd_price, None);
+        assert_eq!(response.assets[2].usd_value, None);
+        assert_eq!(response.total_usd_value, "250.38");
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert!(json["assets"][2]["usd_value"].is_null());
+    }
+}
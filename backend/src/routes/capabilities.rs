@@ -0,0 +1,112 @@
+use actix_web::{web, HttpResponse, Result};
+use serde::Serialize;
+use store::{balance::max_balances_per_user, Store};
+
+use super::circuit_breaker::BreakerState;
+use super::feature_flags::{Operation, OperationFlags};
+use super::jupiter::{swap_confirmation_threshold, JUPITER_SWAP_BREAKER};
+use super::mpc_client::MPC_BREAKER;
+use super::priority_fee::PriorityFeeConfig;
+use super::rpc::rpc_url;
+use super::solana::min_sol_reserve;
+use super::transaction_status::MAX_BATCH_SIGNATURES;
+
+#[derive(Serialize)]
+pub struct FeatureFlags {
+    pub send_sol: bool,
+    pub token_transfers: bool,
+    pub swaps: bool,
+    pub transaction_status: bool,
+}
+
+#[derive(Serialize)]
+pub struct FeeConfiguration {
+    pub swap_priority_level: String,
+    pub swap_max_priority_fee_lamports: u64,
+}
+
+#[derive(Serialize)]
+pub struct Limits {
+    pub min_sol_reserve: String,
+    pub max_balances_per_user: i64,
+    pub max_batch_signatures: usize,
+    pub swap_confirmation_threshold: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Capabilities {
+    pub network: &'static str,
+    pub features: FeatureFlags,
+    /// Raw `ENABLE_*` kill switches, independent of breaker state.
+    pub operations: OperationFlags,
+    pub fees: FeeConfiguration,
+    pub limits: Limits,
+    pub supported_assets: Option<usize>,
+}
+
+/// Best-effort cluster name from the configured RPC endpoint.
+pub fn network_from_rpc_url(url: &str) -> &'static str {
+    let url = url.to_ascii_lowercase();
+    if url.contains("mainnet") {
+        "mainnet-beta"
+    } else if url.contains("devnet") {
+        "devnet"
+    } else if url.contains("testnet") {
+        "testnet"
+    } else if url.contains("localhost") || url.contains("127.0.0.1") {
+        "localnet"
+    } else {
+        "custom"
+    }
+}
+
+/// What this deployment supports, derived from its active configuration.
+#[actix_web::get("/capabilities")]
+pub async fn capabilities(store: web::Data<Store>) -> Result<HttpResponse> {
+    let supported_assets = match store.list_assets().await {
+        Ok(assets) => Some(assets.len()),
+        Err(e) => {
+            println!("Failed to count assets for capabilities: {}", e);
+            None
+        }
+    };
+
+    let mpc_available = MPC_BREAKER.state() != BreakerState::Open;
+    let priority_fee = PriorityFeeConfig::from_env();
+
+    Ok(HttpResponse::Ok().json(Capabilities {
+        network: network_from_rpc_url(&rpc_url()),
+        features: FeatureFlags {
+            send_sol: mpc_available && Operation::SendSol.is_enabled(),
+            // Only native SOL can be sent; SPL balances move through swaps
+            token_transfers: false,
+            swaps: mpc_available && Operation::Swap.is_enabled() && JUPITER_SWAP_BREAKER.state() != BreakerState::Open,
+            transaction_status: true,
+        },
+        operations: OperationFlags::from_env(),
+        fees: FeeConfiguration {
+            swap_priority_level: priority_fee.priority_level().to_string(),
+            swap_max_priority_fee_lamports: priority_fee.max_lamports(),
+        },
+        limits: Limits {
+            min_sol_reserve: min_sol_reserve().to_string(),
+            max_balances_per_user: max_balances_per_user(),
+            max_batch_signatures: MAX_BATCH_SIGNATURES,
+            swap_confirmation_threshold: swap_confirmation_threshold().map(|t| t.to_string()),
+        },
+        supported_assets,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_from_rpc_url() {
+        assert_eq!(network_from_rpc_url("https://api.mainnet-beta.solana.com"), "mainnet-beta");
+        assert_eq!(network_from_rpc_url("https://api.devnet.solana.com"), "devnet");
+        assert_eq!(network_from_rpc_url("http://127.0.0.1:8899"), "localnet");
+        assert_eq!(network_from_rpc_url("https://rpc.example.com/abc"), "custom");
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_COOLDOWN_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    // When the circuit opened, or when the current half-open probe was let through
+    changed_at: Instant,
+}
+
+/// Fails fast after `failure_threshold` consecutive failures of a downstream dependency.
+/// Once `cooldown` has passed a single probe request is let through (half-open); its
+/// outcome either closes the circuit again or re-opens it for another cooldown.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<BreakerInner>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreakerStatus {
+    pub name: &'static str,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            name,
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                changed_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Reads `{prefix}_BREAKER_FAILURE_THRESHOLD` and `{prefix}_BREAKER_COOLDOWN_SECS`.
+    pub fn from_env(name: &'static str, prefix: &str) -> Self {
+        let failure_threshold = std::env::var(format!("{}_BREAKER_FAILURE_THRESHOLD", prefix))
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_FAILURE_THRESHOLD);
+        let cooldown_secs = std::env::var(format!("{}_BREAKER_COOLDOWN_SECS", prefix))
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_COOLDOWN_SECS);
+
+        Self::new(name, failure_threshold, Duration::from_secs(cooldown_secs))
+    }
+
+    /// Whether a call may go ahead. When this returns false the caller should fail fast.
+    pub fn allow_request(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        match inner.state {
+            BreakerState::Closed => true,
+            // A probe that never reported back shouldn't wedge the breaker, so another
+            // probe is allowed once a further cooldown has passed.
+            BreakerState::Open | BreakerState::HalfOpen => {
+                if inner.changed_at.elapsed() >= self.cooldown {
+                    inner.state = BreakerState::HalfOpen;
+                    inner.changed_at = Instant::now();
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if inner.state != BreakerState::Closed {
+            println!("Circuit breaker '{}' closed", self.name);
+        }
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+
+        let should_open = inner.state == BreakerState::HalfOpen
+            || (inner.state == BreakerState::Closed && inner.consecutive_failures >= self.failure_threshold);
+        if should_open {
+            println!("Circuit breaker '{}' opened after {} consecutive failures", self.name, inner.consecutive_failures);
+            inner.state = BreakerState::Open;
+            inner.changed_at = Instant::now();
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).state
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        BreakerStatus {
+            name: self.name,
+            state: inner.state,
+            consecutive_failures: inner.consecutive_failures,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_and_recovers() {
+        let breaker = CircuitBreaker::new("test", 3, Duration::from_millis(20));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.allow_request());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.allow_request());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allow_request());
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        // Only the one probe is let through
+        assert!(!breaker.allow_request());
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.allow_request());
+    }
+
+    #[test]
+    fn test_failed_probe_reopens_breaker() {
+        let breaker = CircuitBreaker::new("test", 1, Duration::from_millis(20));
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(breaker.allow_request());
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.allow_request());
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use actix_web::{HttpRequest, HttpResponse, Result};
+use serde::Serialize;
+use common::redact::{redact_url, REDACTED};
+use store::balance::max_balances_per_user;
+
+use super::admin::is_admin;
+use super::capabilities::network_from_rpc_url;
+use super::feature_flags::OperationFlags;
+use super::jupiter::swap_confirmation_threshold;
+use super::mpc_client::mpc_service_url;
+use super::priority_fee::PriorityFeeConfig;
+use super::rpc::rpc_url;
+use super::solana::min_sol_reserve;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sensitivity {
+    Plain,
+    /// Never shown; only whether it is set.
+    Secret,
+    /// Shown with any password and key-like query parameters masked.
+    Url,
+}
+
+/// Every variable the backend reads, so an unset one shows up as `null` rather than missing.
+const CONFIG_VARS: &[(&str, Sensitivity)] = &[
+    ("BACKEND_HOST", Sensitivity::Plain),
+    ("BACKEND_PORT", Sensitivity::Plain),
+    ("DATABASE_URL", Sensitivity::Url),
+    ("SOLANA_RPC_URL", Sensitivity::Url),
+    ("MPC_SIMPLE_URL", Sensitivity::Url),
+    ("INDEXER_URL", Sensitivity::Url),
+    ("ADMIN_API_KEY", Sensitivity::Secret),
+    ("INDEXER_ADMIN_KEY", Sensitivity::Secret),
+    ("JWT_SECRET", Sensitivity::Secret),
+    ("QUOTE_ENCRYPTION_KEY", Sensitivity::Secret),
+    ("LOGIN_ATTEMPTS_IP_SALT", Sensitivity::Secret),
+    ("JWT_TTL_SECS", Sensitivity::Plain),
+    ("REFRESH_TOKEN_TTL_SECS", Sensitivity::Plain),
+    ("ENCRYPT_QUOTE_DATA", Sensitivity::Plain),
+    ("QUOTE_RETENTION_HOURS", Sensitivity::Plain),
+    ("QUOTE_CLEANUP_INTERVAL_SECS", Sensitivity::Plain),
+    ("MAX_QUOTE_AGE_SECS", Sensitivity::Plain),
+    ("BALANCE_PRUNE_INTERVAL_SECS", Sensitivity::Plain),
+    ("MIN_SOL_RESERVE", Sensitivity::Plain),
+    ("MAX_BALANCES_PER_USER", Sensitivity::Plain),
+    ("SWAP_CONFIRMATION_THRESHOLD", Sensitivity::Plain),
+    ("SWAP_MINT_ALLOWLIST", Sensitivity::Plain),
+    ("SWAP_MINT_DENYLIST", Sensitivity::Plain),
+    ("SWAP_MIN_INPUT_AMOUNTS", Sensitivity::Plain),
+    ("AUTO_CREATE_SWAP_ASSETS", Sensitivity::Plain),
+    ("SWAP_REQUOTE_TOLERANCE_BPS", Sensitivity::Plain),
+    ("SWAP_MAX_PRICE_IMPACT_PCT", Sensitivity::Plain),
+    ("SWAP_PRIORITY_LEVEL", Sensitivity::Plain),
+    ("SWAP_PRIORITY_MAX_LAMPORTS", Sensitivity::Plain),
+    ("SWAP_PRIORITY_FEE_CAP_LAMPORTS", Sensitivity::Plain),
+    ("JUPITER_BREAKER_FAILURE_THRESHOLD", Sensitivity::Plain),
+    ("JUPITER_BREAKER_COOLDOWN_SECS", Sensitivity::Plain),
+    ("JUPITER_MAX_RETRIES", Sensitivity::Plain),
+    ("MPC_REQUEST_TIMEOUT_SECS", Sensitivity::Plain),
+    ("MPC_BREAKER_FAILURE_THRESHOLD", Sensitivity::Plain),
+    ("MPC_BREAKER_COOLDOWN_SECS", Sensitivity::Plain),
+    ("INDEXER_KEY_REMOVAL_RETRY_SECS", Sensitivity::Plain),
+    ("LOGIN_ATTEMPTS_HASH_IP", Sensitivity::Plain),
+    ("SERVER_WORKERS", Sensitivity::Plain),
+    ("SERVER_KEEP_ALIVE_SECS", Sensitivity::Plain),
+    ("SERVER_CLIENT_TIMEOUT_MS", Sensitivity::Plain),
+    ("AUTH_RATE_LIMIT_PER_MIN", Sensitivity::Plain),
+    ("TRUST_PROXY", Sensitivity::Plain),
+];
+
+fn redact(value: &str, sensitivity: Sensitivity) -> String {
+    match sensitivity {
+        Sensitivity::Plain => value.to_string(),
+        Sensitivity::Secret => REDACTED.to_string(),
+        Sensitivity::Url => redact_url(value),
+    }
+}
+
+/// Values as the backend resolves them, defaults included.
+#[derive(Serialize)]
+pub struct ResolvedConfig {
+    pub network: &'static str,
+    pub rpc_url: String,
+    pub mpc_service_url: String,
+    pub operations: OperationFlags,
+    pub min_sol_reserve: String,
+    pub max_balances_per_user: i64,
+    pub swap_confirmation_threshold: Option<String>,
+    pub swap_priority_level: String,
+    pub swap_max_priority_fee_lamports: u64,
+}
+
+#[derive(Serialize)]
+pub struct EffectiveConfig {
+    pub resolved: ResolvedConfig,
+    /// Raw environment, secrets masked; `null` means unset.
+    pub env: BTreeMap<&'static str, Option<String>>,
+}
+
+impl EffectiveConfig {
+    pub fn from_env() -> Self {
+        let env = CONFIG_VARS.iter()
+            .map(|(name, sensitivity)| {
+                let value = std::env::var(name).ok().map(|value| redact(&value, *sensitivity));
+                (*name, value)
+            })
+            .collect();
+
+        let rpc_url = rpc_url();
+        let priority_fee = PriorityFeeConfig::from_env();
+        Self {
+            resolved: ResolvedConfig {
+                network: network_from_rpc_url(&rpc_url),
+                rpc_url: redact_url(&rpc_url),
+                mpc_service_url: redact_url(&mpc_service_url()),
+                operations: OperationFlags::from_env(),
+                min_sol_reserve: min_sol_reserve().to_string(),
+                max_balances_per_user: max_balances_per_user(),
+                swap_confirmation_threshold: swap_confirmation_threshold().map(|t| t.to_string()),
+                swap_priority_level: priority_fee.priority_level().to_string(),
+                swap_max_priority_fee_lamports: priority_fee.max_lamports(),
+            },
+            env,
+        }
+    }
+}
+
+/// The configuration this instance is running with, for checking a deployment's env without
+/// shell access. Admin only, since even redacted values describe the infrastructure.
+#[actix_web::get("/admin/config")]
+pub async fn admin_config(http_req: HttpRequest) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(EffectiveConfig::from_env()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secrets_are_never_shown() {
+        assert_eq!(redact("super-secret", Sensitivity::Secret), REDACTED);
+        assert_eq!(redact("30", Sensitivity::Plain), "30");
+    }
+}
@@ -0,0 +1,80 @@
+use actix_web::{
+    error::{InternalError, JsonPayloadError, QueryPayloadError},
+    web, HttpRequest, HttpResponse,
+};
+use common::serde_error::field_from_message;
+
+fn error_body(error: &str, detail: String) -> serde_json::Value {
+    let field = field_from_message(&detail);
+    serde_json::json!({
+        "error": error,
+        "field": field,
+        "details": detail,
+    })
+}
+
+fn json_error_response(err: &JsonPayloadError) -> HttpResponse {
+    match err {
+        JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+            HttpResponse::PayloadTooLarge().json(error_body("Request body is too large", err.to_string()))
+        }
+        JsonPayloadError::ContentType => {
+            HttpResponse::UnsupportedMediaType().json(error_body("Content-Type must be application/json", err.to_string()))
+        }
+        JsonPayloadError::Deserialize(e) => {
+            HttpResponse::BadRequest().json(error_body("Invalid request body", e.to_string()))
+        }
+        _ => HttpResponse::BadRequest().json(error_body("Invalid request body", err.to_string())),
+    }
+}
+
+/// JSON bodies that fail to extract get the API's `{"error": ...}` envelope instead of
+/// actix's plain-text 400, with the offending field when serde names one.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _req: &HttpRequest| {
+        let response = json_error_response(&err);
+        InternalError::from_response(err, response).into()
+    })
+}
+
+/// Same envelope for query strings.
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|err, _req: &HttpRequest| {
+        let detail = match &err {
+            QueryPayloadError::Deserialize(e) => e.to_string(),
+            _ => err.to_string(),
+        };
+        let response = HttpResponse::BadRequest().json(error_body("Invalid query string", detail));
+        InternalError::from_response(err, response).into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Body {
+        #[allow(dead_code)]
+        user_id: String,
+    }
+
+    #[actix_web::post("/echo")]
+    async fn echo(_body: web::Json<Body>) -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn test_missing_field_returns_json_error() {
+        let app = actix_web::test::init_service(actix_web::App::new().app_data(json_config()).service(echo)).await;
+
+        let req = actix_web::test::TestRequest::post().uri("/echo").set_json(serde_json::json!({})).to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let body: serde_json::Value = actix_web::test::read_body_json(res).await;
+        assert_eq!(body["error"], "Invalid request body");
+        assert_eq!(body["field"], "user_id");
+    }
+}
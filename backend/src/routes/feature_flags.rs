@@ -0,0 +1,99 @@
+use actix_web::HttpResponse;
+use serde::Serialize;
+
+use super::idempotency::NOT_ATTEMPTED_HEADER;
+
+/// Operations an operator can switch off with an `ENABLE_*` variable, e.g. to stop swaps
+/// during an incident. Every operation is enabled unless its variable says otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Swap,
+    SendSol,
+    Signup,
+    Transfer,
+    AddSolBalance,
+}
+
+impl Operation {
+    fn env_var(&self) -> &'static str {
+        match self {
+            Operation::Swap => "ENABLE_SWAP",
+            Operation::SendSol => "ENABLE_SEND_SOL",
+            Operation::Signup => "ENABLE_SIGNUP",
+            Operation::Transfer => "ENABLE_TRANSFER",
+            Operation::AddSolBalance => "ENABLE_ADD_SOL_BALANCE",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Swap => "swap",
+            Operation::SendSol => "send_sol",
+            Operation::Signup => "signup",
+            Operation::Transfer => "transfer",
+            Operation::AddSolBalance => "add_sol_balance",
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        flag_enabled(std::env::var(self.env_var()).ok().as_deref())
+    }
+}
+
+/// Unset or unrecognised values leave the operation on; only an explicit "off" disables it.
+fn flag_enabled(value: Option<&str>) -> bool {
+    match value.map(|v| v.trim().to_ascii_lowercase()) {
+        Some(v) => !matches!(v.as_str(), "false" | "0" | "off" | "no"),
+        None => true,
+    }
+}
+
+/// The 503 to return when `operation` is switched off, or `None` if it may proceed.
+pub fn operation_disabled(operation: Operation) -> Option<HttpResponse> {
+    if operation.is_enabled() {
+        return None;
+    }
+
+    println!("Rejected {} request: operation disabled by {}", operation.name(), operation.env_var());
+    Some(HttpResponse::ServiceUnavailable().insert_header((NOT_ATTEMPTED_HEADER, "true")).json(serde_json::json!({
+        "success": false,
+        "error": "operation temporarily disabled",
+        "operation": operation.name()
+    })))
+}
+
+#[derive(Serialize)]
+pub struct OperationFlags {
+    pub swap: bool,
+    pub send_sol: bool,
+    pub signup: bool,
+    pub transfer: bool,
+    pub add_sol_balance: bool,
+}
+
+impl OperationFlags {
+    pub fn from_env() -> Self {
+        Self {
+            swap: Operation::Swap.is_enabled(),
+            send_sol: Operation::SendSol.is_enabled(),
+            signup: Operation::Signup.is_enabled(),
+            transfer: Operation::Transfer.is_enabled(),
+            add_sol_balance: Operation::AddSolBalance.is_enabled(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_enabled() {
+        assert!(flag_enabled(None));
+        assert!(flag_enabled(Some("true")));
+        assert!(flag_enabled(Some("1")));
+        assert!(!flag_enabled(Some("false")));
+        assert!(!flag_enabled(Some(" OFF ")));
+        assert!(!flag_enabled(Some("0")));
+    }
+}
@@ -0,0 +1,190 @@
+use std::future::Future;
+use actix_web::{body::{self, BoxBody}, http::StatusCode, web, HttpRequest, HttpResponse, Result};
+use store::{idempotency::IdempotencyClaim, Store};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const IDEMPOTENT_REPLAY_HEADER: &str = "Idempotent-Replayed";
+/// Set on a 503 returned before the operation touched anything (the operation is switched off
+/// or a circuit breaker is open). Only these release the key; any other failure may have moved
+/// funds and is stored like a success.
+pub const NOT_ATTEMPTED_HEADER: &str = "X-Operation-Not-Attempted";
+const MAX_KEY_LEN: usize = 255;
+
+/// The `Idempotency-Key` header, if sent. A blank, oversized or non-ASCII key is rejected.
+fn idempotency_key(req: &HttpRequest) -> std::result::Result<Option<String>, &'static str> {
+    let value = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let key = value.to_str().map_err(|_| "Idempotency-Key must be ASCII")?.trim();
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err("Idempotency-Key must be 1 to 255 characters");
+    }
+    Ok(Some(key.to_string()))
+}
+
+/// Release the key only for an explicit refusal, so the client may retry with it.
+fn should_release(response: &HttpResponse) -> bool {
+    response.status() == StatusCode::SERVICE_UNAVAILABLE && response.headers().contains_key(NOT_ATTEMPTED_HEADER)
+}
+
+fn replay(status_code: u16, response: serde_json::Value) -> HttpResponse {
+    let status = StatusCode::from_u16(status_code).unwrap_or(StatusCode::OK);
+    HttpResponse::build(status)
+        .insert_header((IDEMPOTENT_REPLAY_HEADER, "true"))
+        .json(response)
+}
+
+/// Run `execute` at most once per `Idempotency-Key` for the user. A repeated key replays the
+/// stored response instead of running the operation again; without the header the operation
+/// simply runs.
+pub async fn idempotent<F, Fut>(
+    http_req: &HttpRequest,
+    store: &web::Data<Store>,
+    user_id: &str,
+    operation: &str,
+    execute: F,
+) -> Result<HttpResponse>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<HttpResponse>>,
+{
+    let key = match idempotency_key(http_req) {
+        Ok(Some(key)) => key,
+        Ok(None) => return execute().await,
+        Err(error) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "error": error
+            })));
+        }
+    };
+
+    let claim = store.claim_idempotency_key(user_id, &key, operation).await;
+    match claim {
+        Ok(IdempotencyClaim::Claimed) => {}
+        Ok(IdempotencyClaim::Completed { status_code, response }) => {
+            println!("Replaying {} response for idempotency key {} (user {})", operation, key, user_id);
+            return Ok(replay(status_code, response));
+        }
+        Ok(IdempotencyClaim::InProgress) => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "success": false,
+                "error": "A request with this Idempotency-Key is still in progress"
+            })));
+        }
+        Ok(IdempotencyClaim::OperationMismatch) => {
+            return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                "success": false,
+                "error": "This Idempotency-Key was already used for a different operation"
+            })));
+        }
+        Err(e) => {
+            println!("Failed to claim idempotency key {} for user {}: {:?}", key, user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "error": "Failed to check Idempotency-Key"
+            })));
+        }
+    }
+
+    let response = match execute().await {
+        Ok(response) => response,
+        Err(e) => {
+            if let Err(release_err) = store.release_idempotency_key(user_id, &key).await {
+                println!("Failed to release idempotency key {} for user {}: {:?}", key, user_id, release_err);
+            }
+            return Err(e);
+        }
+    };
+
+    let status = response.status();
+    let release = should_release(&response);
+    let (head, response_body) = response.into_parts();
+    let bytes = match body::to_bytes(response_body).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            println!("Failed to read {} response body for idempotency key {}", operation, key);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "success": false,
+                "error": "Failed to record response"
+            })));
+        }
+    };
+
+    let recorded = if release {
+        store.release_idempotency_key(user_id, &key).await
+    } else {
+        let json = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        store.complete_idempotency_key(user_id, &key, status.as_u16(), &json).await
+    };
+    if let Err(e) = recorded {
+        // The operation already ran, so its response is still returned
+        println!("Failed to record idempotency key {} for user {}: {:?}", key, user_id, e);
+    }
+
+    Ok(head.set_body(BoxBody::new(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_idempotency_key_header() {
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        assert_eq!(idempotency_key(&req), Ok(None));
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((IDEMPOTENCY_KEY_HEADER, " order-42 "))
+            .to_http_request();
+        assert_eq!(idempotency_key(&req), Ok(Some("order-42".to_string())));
+
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((IDEMPOTENCY_KEY_HEADER, "x".repeat(MAX_KEY_LEN + 1)))
+            .to_http_request();
+        assert!(idempotency_key(&req).is_err());
+    }
+
+    #[test]
+    fn test_only_marked_refusals_release_the_key() {
+        let refused = HttpResponse::ServiceUnavailable().insert_header((NOT_ATTEMPTED_HEADER, "true")).finish();
+        assert!(should_release(&refused));
+
+        // A 503 from a failure after the debit may have moved funds
+        assert!(!should_release(&HttpResponse::ServiceUnavailable().finish()));
+        assert!(!should_release(&HttpResponse::InternalServerError().insert_header((NOT_ATTEMPTED_HEADER, "true")).finish()));
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_repeated_key_executes_once() {
+        let database_url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+        let store = web::Data::new(Store::connect(&database_url).await.unwrap());
+        let user_id = uuid::Uuid::new_v4().to_string();
+        let http_req = actix_web::test::TestRequest::default()
+            .insert_header((IDEMPOTENCY_KEY_HEADER, uuid::Uuid::new_v4().to_string()))
+            .to_http_request();
+
+        let executions = AtomicUsize::new(0);
+        let execute = || async {
+            executions.fetch_add(1, Ordering::SeqCst);
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true, "transaction_signature": "sig" })))
+        };
+
+        let first = idempotent(&http_req, &store, &user_id, "send_sol", execute).await.unwrap();
+        let second = idempotent(&http_req, &store, &user_id, "send_sol", execute).await.unwrap();
+
+        assert_eq!(executions.load(Ordering::SeqCst), 1);
+        assert_eq!(second.status(), StatusCode::OK);
+        assert!(second.headers().contains_key(IDEMPOTENT_REPLAY_HEADER));
+        let first_body = body::to_bytes(first.into_body()).await.unwrap();
+        let second_body = body::to_bytes(second.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&first_body).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(&second_body).unwrap()
+        );
+    }
+}
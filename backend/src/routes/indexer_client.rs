@@ -0,0 +1,127 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+use serde::Deserialize;
+use store::Store;
+
+use super::retry::send_with_retries;
+
+const INDEXER_ATTEMPTS: u32 = 3;
+const INDEXER_TIMEOUT_SECS: u64 = 10;
+
+static INDEXER_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(INDEXER_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+});
+
+/// Base URL of the indexer (`INDEXER_URL`). Unset means key removals stay pending.
+pub fn indexer_url() -> Option<String> {
+    std::env::var("INDEXER_URL")
+        .ok()
+        .map(|url| url.trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+}
+
+#[derive(Deserialize)]
+struct BulkRemoveResponse {
+    data: BulkRemoveResult,
+}
+
+#[derive(Deserialize)]
+struct BulkRemoveResult {
+    failed: u32,
+    errors: Vec<String>,
+}
+
+/// Body of the indexer's `DELETE /api/v1/keys/bulk` for all of a user's keys.
+fn bulk_remove_body(user_id: &str, public_keys: &[String]) -> serde_json::Value {
+    let keys: Vec<serde_json::Value> = public_keys.iter()
+        .map(|public_key| serde_json::json!({ "user_id": user_id, "public_key": public_key }))
+        .collect();
+    serde_json::json!({ "keys": keys })
+}
+
+/// Send the request built by `build` with the indexer's admin key (`INDEXER_ADMIN_KEY`, the
+/// same variable the indexer reads), retrying transient failures.
+async fn send_to_indexer(build: impl Fn() -> reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+    let admin_key = std::env::var("INDEXER_ADMIN_KEY").ok();
+    let response = send_with_retries(INDEXER_ATTEMPTS, || match &admin_key {
+        Some(api_key) => build().header("X-API-Key", api_key),
+        None => build(),
+    })
+    .await
+    .map_err(|e| format!("Indexer request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Indexer returned {}", response.status()));
+    }
+    Ok(response)
+}
+
+/// Ask the indexer to stop monitoring `public_keys`, retrying transient failures.
+pub async fn remove_user_keys(user_id: &str, public_keys: &[String]) -> Result<(), String> {
+    if public_keys.is_empty() {
+        return Ok(());
+    }
+    let base_url = indexer_url().ok_or_else(|| "INDEXER_URL is not configured".to_string())?;
+
+    let body = bulk_remove_body(user_id, public_keys);
+    let response = send_to_indexer(|| {
+        INDEXER_CLIENT.delete(format!("{}/api/v1/keys/bulk", base_url)).json(&body)
+    }).await?;
+
+    let body: BulkRemoveResponse = response.json().await
+        .map_err(|e| format!("Invalid indexer response: {}", e))?;
+    if body.data.failed > 0 {
+        return Err(format!("Indexer failed to remove {} key(s): {}", body.data.failed, body.data.errors.join("; ")));
+    }
+    Ok(())
+}
+
+/// Ask the indexer to start monitoring `public_key` for `user_id`, retrying transient failures.
+pub async fn add_user_key(user_id: &str, public_key: &str) -> Result<(), String> {
+    let base_url = indexer_url().ok_or_else(|| "INDEXER_URL is not configured".to_string())?;
+
+    let body = serde_json::json!({ "user_id": user_id, "public_key": public_key, "subscription_type": "Both" });
+    send_to_indexer(|| INDEXER_CLIENT.post(format!("{}/api/v1/keys", base_url)).json(&body)).await?;
+    Ok(())
+}
+
+/// Retry key removal for deactivated users the indexer hasn't confirmed yet. Returns how
+/// many users were cleared.
+pub async fn reconcile_key_removals(store: &Store) -> usize {
+    let pending = match store.users_pending_key_removal().await {
+        Ok(pending) => pending,
+        Err(e) => {
+            println!("❌ Failed to load users pending key removal: {}", e);
+            return 0;
+        }
+    };
+
+    let mut cleared = 0;
+    for (user_id, public_keys) in pending {
+        if let Err(e) = remove_user_keys(&user_id, &public_keys).await {
+            println!("❌ Key removal for user {} still pending: {}", user_id, e);
+            continue;
+        }
+        match store.mark_keys_removed(&user_id).await {
+            Ok(()) => cleared += 1,
+            Err(e) => println!("❌ Failed to mark keys removed for user {}: {}", user_id, e),
+        }
+    }
+    cleared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_remove_body_lists_every_key() {
+        let body = bulk_remove_body("user-1", &["KeyA".to_string(), "KeyB".to_string()]);
+        assert_eq!(body["keys"].as_array().map(|keys| keys.len()), Some(2));
+        assert_eq!(body["keys"][1]["user_id"], "user-1");
+        assert_eq!(body["keys"][1]["public_key"], "KeyB");
+    }
+}
@@ -1,9 +1,37 @@
-use std::sync::Arc;
-use actix_web::{web, HttpResponse, Result};
+use std::sync::LazyLock;
+use std::time::Duration;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, HttpResponseBuilder, Result};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use store::Store;
-use tokio::sync::Mutex;
+use store::{helper::{format_amount, to_base_units}, ledger::LedgerReason, pending_operation::ConfirmationClaim, pubkey::SolanaPubkey, Store};
 
+use super::circuit_breaker::CircuitBreaker;
+use super::swap_policy::{auto_create_swap_assets, MintPolicy, QuoteProtection, SwapMinimums};
+use super::priority_fee::PriorityFeeConfig;
+use super::amount::AmountView;
+use super::feature_flags::{operation_disabled, Operation};
+use super::request_id::{request_id, with_request_id};
+use super::idempotency::{idempotent, NOT_ATTEMPTED_HEADER};
+use super::mpc_client::{mpc_client, mpc_service_url, read_mpc_reply, record_mpc_outcome, MpcReply, MPC_BREAKER};
+use super::retry::{send_with_connect_retry, send_with_retry};
+use super::rpc::{fetch_mint_metadata, rpc_client, MintMetadata, MintMetadataError};
+use super::unresolved::{record_unresolved, unresolved_response, AssetAmount, UnresolvedPayload};
+
+/// Tripped by consecutive Jupiter outages so requests fail fast instead of piling up.
+pub static JUPITER_QUOTE_BREAKER: LazyLock<CircuitBreaker> =
+    LazyLock::new(|| CircuitBreaker::from_env("jupiter_quote", "JUPITER"));
+pub static JUPITER_SWAP_BREAKER: LazyLock<CircuitBreaker> =
+    LazyLock::new(|| CircuitBreaker::from_env("jupiter_swap", "JUPITER"));
+
+/// A hung Jupiter call would otherwise hold the swap (and its debited funds) open indefinitely.
+const JUPITER_TIMEOUT_SECS: u64 = 15;
+
+static JUPITER_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(JUPITER_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+});
 
 #[derive(Deserialize)]
 pub struct QuoteRequest {
@@ -47,6 +75,34 @@ pub struct QuoteResponse {
 pub struct SwapRequest {
     pub user_id: String,
     pub user_public_key: String,
+    /// Challenge id returned by a previous call when the swap exceeded the confirmation threshold.
+    #[serde(default)]
+    pub confirmation_id: Option<String>,
+    /// Overrides `SWAP_PRIORITY_LEVEL` for this swap ("medium", "high" or "veryHigh").
+    #[serde(default)]
+    pub priority_level: Option<String>,
+    /// Overrides `SWAP_PRIORITY_MAX_LAMPORTS`; still clamped to `SWAP_PRIORITY_FEE_CAP_LAMPORTS`.
+    #[serde(default)]
+    pub max_priority_fee_lamports: Option<u64>,
+    /// Retry once on a fresh quote if the swap fails on slippage or an expired quote.
+    #[serde(default)]
+    pub auto_requote: bool,
+    /// Overrides `SWAP_MAX_PRICE_IMPACT_PCT`, in percent; quotes with a larger price impact are refused.
+    #[serde(default)]
+    pub max_price_impact_pct: Option<Decimal>,
+    /// Smallest acceptable `outAmount`, in output base units.
+    #[serde(default)]
+    pub min_out_amount: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct SwapConfirmationResponse {
+    pub success: bool,
+    pub confirmation_required: bool,
+    pub confirmation_id: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub input_amount: String,
+    pub threshold: String,
 }
 
 #[derive(Serialize)]
@@ -58,12 +114,34 @@ pub struct SwapResponse {
     pub balance_updates: Option<BalanceUpdates>,
 }
 
+/// One execution of a swap, as reported when `auto_requote` was requested.
+#[derive(Serialize)]
+pub struct SwapAttemptReport {
+    pub out_amount: String,
+    pub success: bool,
+    pub transaction_signature: Option<String>,
+    pub error: Option<String>,
+}
+
+/// `SwapResponse` plus every attempt made, for swaps sent with `auto_requote`.
+#[derive(Serialize)]
+pub struct RequotedSwapResponse {
+    #[serde(flatten)]
+    pub response: SwapResponse,
+    pub attempts: Vec<SwapAttemptReport>,
+    /// Why no retry was made after a requotable failure.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requote_error: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct SwapDetails {
     pub input_mint: String,
     pub output_mint: String,
     pub input_amount: String,
     pub output_amount: String,
+    pub input: AmountView,
+    pub output: AmountView,
     pub price_impact_pct: String,
 }
 
@@ -71,12 +149,501 @@ pub struct SwapDetails {
 pub struct BalanceUpdates {
     pub input_token_balance: String,
     pub output_token_balance: String,
+    pub input_token_balance_base_units: String,
+    pub output_token_balance_base_units: String,
     pub input_token_symbol: String,
     pub output_token_symbol: String,
+    #[serde(flatten)]
+    pub usd: SwapUsdSummary,
+}
+
+/// USD view of a completed swap. Fields are omitted when a price is unavailable.
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct SwapUsdSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_usd_value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_usd_value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net_usd_change: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net_change_pct: Option<String>,
+}
+
+fn swap_usd_summary(
+    input_amount: Decimal,
+    input_price: Option<Decimal>,
+    output_amount: Decimal,
+    output_price: Option<Decimal>,
+) -> SwapUsdSummary {
+    let input_value = input_price.map(|p| (input_amount * p).round_dp(2));
+    let output_value = output_price.map(|p| (output_amount * p).round_dp(2));
+
+    let (net_change, net_change_pct) = match (input_value, output_value) {
+        (Some(input), Some(output)) => {
+            let change = output - input;
+            let pct = if input.is_zero() { None } else { Some((change / input * Decimal::ONE_HUNDRED).round_dp(2)) };
+            (Some(change), pct)
+        }
+        _ => (None, None),
+    };
+
+    SwapUsdSummary {
+        input_usd_value: input_value.map(|v| v.to_string()),
+        output_usd_value: output_value.map(|v| v.to_string()),
+        net_usd_change: net_change.map(|v| v.to_string()),
+        net_change_pct: net_change_pct.map(|v| v.to_string()),
+    }
+}
+
+const SWAP_OPERATION: &str = "swap";
+const SOL_ASSET_ID: &str = "sol-native";
+const WRAPPED_SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+/// Jupiter reports native SOL legs as the wrapped SOL mint; both are held as the native
+/// SOL balance so the user's SOL never fragments into separate SOL and wSOL rows.
+fn native_asset_id_for_mint(mint: &str) -> Option<&'static str> {
+    (mint == WRAPPED_SOL_MINT).then_some(SOL_ASSET_ID)
+}
+
+async fn find_swap_asset(store: &Store, mint: &str) -> Result<Option<store::asset::Asset>, store::error::UserError> {
+    let native_asset = match native_asset_id_for_mint(mint) {
+        Some(asset_id) => store.get_asset_by_id(asset_id).await?,
+        None => None,
+    };
+
+    match native_asset {
+        Some(asset) => Ok(Some(asset)),
+        None => store.get_asset_by_mint(mint).await,
+    }
+}
+
+/// Why a swap mint with no asset row could not be registered.
+#[derive(Debug, PartialEq)]
+enum UnknownSwapAsset {
+    /// `AUTO_CREATE_SWAP_ASSETS` is off.
+    NotRegistered,
+    /// The mint account doesn't exist or isn't an SPL mint, so it has no decimals.
+    NotAMint(String),
+    /// Auto-creation is on but the mint's decimals could not be read.
+    MetadataUnavailable(String),
+}
+
+impl From<MintMetadataError> for UnknownSwapAsset {
+    fn from(e: MintMetadataError) -> Self {
+        match e {
+            MintMetadataError::NotAMint(reason) => UnknownSwapAsset::NotAMint(reason),
+            MintMetadataError::Rpc(reason) => UnknownSwapAsset::MetadataUnavailable(reason),
+        }
+    }
+}
+
+impl UnknownSwapAsset {
+    fn response(&self, mint: &str) -> HttpResponse {
+        let (mut builder, error) = match self {
+            UnknownSwapAsset::NotRegistered => (
+                HttpResponse::UnprocessableEntity(),
+                format!("Asset not registered for mint {}. Register it before swapping.", mint),
+            ),
+            UnknownSwapAsset::NotAMint(reason) => (
+                HttpResponse::BadRequest(),
+                format!("Cannot determine decimals for mint {}: {}", mint, reason),
+            ),
+            UnknownSwapAsset::MetadataUnavailable(reason) => (
+                HttpResponse::BadGateway(),
+                format!("Could not fetch metadata for mint {}: {}", mint, reason),
+            ),
+        };
+        builder.json(SwapResponse {
+            success: false,
+            transaction_signature: None,
+            error: Some(error),
+            swap_details: None,
+            balance_updates: None,
+        })
+    }
+}
+
+/// The asset row to create for an unregistered swap mint. Decimals, name and symbol come from
+/// the chain and the fetch is only made when auto-creation is on; a failed fetch fails the swap
+/// rather than guessing. Only a mint without on-chain metadata gets a placeholder name.
+async fn new_swap_asset_request<F, Fut>(
+    mint: &str,
+    mint_key: SolanaPubkey,
+    auto_create: bool,
+    fetch_metadata: F,
+) -> Result<store::asset::CreateAssetRequest, UnknownSwapAsset>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<MintMetadata, MintMetadataError>>,
+{
+    if !auto_create {
+        return Err(UnknownSwapAsset::NotRegistered);
+    }
+
+    let metadata = fetch_metadata().await?;
+    Ok(store::asset::CreateAssetRequest {
+        mint_address: mint_key,
+        decimals: metadata.decimals as i32,
+        name: metadata.name.unwrap_or_else(|| format!("Token {}", &mint[..8])),
+        symbol: metadata.symbol.unwrap_or_else(|| format!("TK{}", &mint[..4])),
+        logo_url: None,
+    })
+}
+
+/// Swaps whose input amount (in whole input tokens) exceeds `SWAP_CONFIRMATION_THRESHOLD`
+/// must be confirmed by re-submitting the returned challenge. Unset means no confirmation.
+pub fn swap_confirmation_threshold() -> Option<Decimal> {
+    std::env::var("SWAP_CONFIRMATION_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+}
+
+fn requires_confirmation(amount: Decimal, threshold: Option<Decimal>) -> bool {
+    threshold.is_some_and(|t| amount > t)
+}
+
+fn swap_build_request(user_public_key: &str, quote_response: &serde_json::Value, priority_fee: &PriorityFeeConfig) -> serde_json::Value {
+    serde_json::json!({
+        "userPublicKey": user_public_key,
+        "quoteResponse": quote_response,
+        "prioritizationFeeLamports": priority_fee.to_jupiter_json(),
+        "dynamicComputeUnitLimit": true,
+        "wrapAndUnwrapSol": true
+    })
+}
+
+const DEFAULT_REQUOTE_TOLERANCE_BPS: u64 = 100;
+
+/// How far (in basis points) a fresh quote's output may fall below the original before an
+/// automatic retry is abandoned. `SWAP_REQUOTE_TOLERANCE_BPS`, default 100 (1%).
+pub fn requote_tolerance_bps() -> u64 {
+    std::env::var("SWAP_REQUOTE_TOLERANCE_BPS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_REQUOTE_TOLERANCE_BPS)
+}
+
+/// Failures a fresh quote can fix: the price moved past the slippage limit (Jupiter's
+/// `SlippageToleranceExceeded`, custom error 0x1771) or the quote or its blockhash went stale.
+fn is_requotable_failure(error: &str) -> bool {
+    let error = error.to_ascii_lowercase();
+    ["slippage", "0x1771", "blockhash not found", "block height exceeded", "expired"]
+        .iter()
+        .any(|needle| error.contains(needle))
+}
+
+fn quote_out_amount(quote: &serde_json::Value) -> u64 {
+    quote.get("outAmount")
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Whether a fresh quote pays out at least the original less the tolerance. A better price
+/// always passes.
+fn within_requote_tolerance(original_out: u64, fresh_out: u64, tolerance_bps: u64) -> bool {
+    let floor = original_out as u128 * 10_000u128.saturating_sub(tolerance_bps as u128) / 10_000;
+    fresh_out as u128 >= floor
+}
+
+fn jupiter_quote_url(input_mint: &str, output_mint: &str, amount: impl std::fmt::Display, slippage_bps: impl std::fmt::Display) -> String {
+    format!(
+        "https://lite-api.jup.ag/swap/v1/quote?inputMint={}&outputMint={}&amount={}&slippageBps={}&restrictIntermediateTokens=true",
+        input_mint,
+        output_mint,
+        amount,
+        slippage_bps
+    )
+}
+
+/// A new quote for the same pair, input amount and slippage as `quote`.
+async fn fetch_fresh_quote(quote: &serde_json::Value) -> std::result::Result<serde_json::Value, String> {
+    let field = |name: &str| quote.get(name).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let slippage_bps = quote.get("slippageBps").and_then(|v| v.as_u64()).unwrap_or(50);
+    let url = jupiter_quote_url(&field("inputMint"), &field("outputMint"), field("inAmount"), slippage_bps);
+
+    if !JUPITER_QUOTE_BREAKER.allow_request() {
+        return Err("Jupiter quote API is temporarily unavailable".to_string());
+    }
+
+    let client = &*JUPITER_CLIENT;
+    let response = match send_with_retry(|| client.get(&url).header("Accept", "application/json")).await {
+        Ok(response) => response,
+        Err(e) => {
+            JUPITER_QUOTE_BREAKER.record_failure();
+            return Err(format!("Failed to call Jupiter quote API: {}", e));
+        }
+    };
+    if response.status().is_server_error() {
+        JUPITER_QUOTE_BREAKER.record_failure();
+    } else {
+        JUPITER_QUOTE_BREAKER.record_success();
+    }
+    if !response.status().is_success() {
+        return Err(format!("Jupiter quote API returned {}", response.status()));
+    }
+
+    response.json().await.map_err(|e| format!("Failed to parse Jupiter quote: {}", e))
+}
+
+struct SwapAttempt {
+    quote: serde_json::Value,
+    success: bool,
+    transaction_signature: Option<String>,
+    error: Option<String>,
+    fee_lamports: Option<u64>,
+    /// Set when the attempt failed before reaching the chain; the status to report it with.
+    failure_status: Option<StatusCode>,
+    /// Set when the MPC service may have sent the transaction but gave no usable answer.
+    outcome_unknown: bool,
+    /// Set when an open circuit breaker stopped the attempt before anything was built or sent.
+    refused: bool,
+}
+
+impl SwapAttempt {
+    fn not_sent(quote: &serde_json::Value, status: StatusCode, error: impl Into<String>) -> Self {
+        Self {
+            quote: quote.clone(),
+            success: false,
+            transaction_signature: None,
+            error: Some(error.into()),
+            fee_lamports: None,
+            failure_status: Some(status),
+            outcome_unknown: false,
+            refused: false,
+        }
+    }
+
+    fn refused(quote: &serde_json::Value, error: impl Into<String>) -> Self {
+        Self {
+            refused: true,
+            ..Self::not_sent(quote, StatusCode::SERVICE_UNAVAILABLE, error)
+        }
+    }
+
+    fn unknown(quote: &serde_json::Value, error: impl Into<String>) -> Self {
+        Self {
+            quote: quote.clone(),
+            success: false,
+            transaction_signature: None,
+            error: Some(error.into()),
+            fee_lamports: None,
+            failure_status: None,
+            outcome_unknown: true,
+            refused: false,
+        }
+    }
+
+    fn report(&self) -> SwapAttemptReport {
+        SwapAttemptReport {
+            out_amount: quote_out_amount(&self.quote).to_string(),
+            success: self.success,
+            transaction_signature: self.transaction_signature.clone(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+struct RequoteOutcome {
+    first: SwapAttempt,
+    retry: Option<SwapAttempt>,
+    requote_error: Option<String>,
+}
+
+impl RequoteOutcome {
+    fn final_attempt(&self) -> &SwapAttempt {
+        self.retry.as_ref().unwrap_or(&self.first)
+    }
+
+    fn attempts(&self) -> impl Iterator<Item = &SwapAttempt> {
+        std::iter::once(&self.first).chain(self.retry.as_ref())
+    }
+
+    /// Whether the only attempt was refused by a circuit breaker, leaving nothing to replay.
+    fn refused(&self) -> bool {
+        self.first.refused && self.retry.is_none()
+    }
+}
+
+/// Run `attempt` on `quote` and, when `auto_requote` is set and it failed in a way a fresh
+/// quote can fix, once more on a quote from `requote`, provided its price is within
+/// `tolerance_bps` of the original.
+async fn swap_with_requote(
+    quote: serde_json::Value,
+    auto_requote: bool,
+    tolerance_bps: u64,
+    mut attempt: impl AsyncFnMut(&serde_json::Value) -> SwapAttempt,
+    mut requote: impl AsyncFnMut(&serde_json::Value) -> std::result::Result<serde_json::Value, String>,
+) -> RequoteOutcome {
+    let first = attempt(&quote).await;
+    let mut outcome = RequoteOutcome { first, retry: None, requote_error: None };
+
+    let requotable = !outcome.first.success
+        && !outcome.first.outcome_unknown
+        && outcome.first.error.as_deref().is_some_and(is_requotable_failure);
+    if !auto_requote || !requotable {
+        return outcome;
+    }
+
+    match requote(&quote).await {
+        Ok(fresh) => {
+            let (original_out, fresh_out) = (quote_out_amount(&quote), quote_out_amount(&fresh));
+            if within_requote_tolerance(original_out, fresh_out, tolerance_bps) {
+                println!("Retrying swap on a fresh quote: out amount {} -> {}", original_out, fresh_out);
+                outcome.retry = Some(attempt(&fresh).await);
+            } else {
+                outcome.requote_error = Some(format!(
+                    "Fresh quote pays {} where the original paid {}, beyond the {} bps requote tolerance",
+                    fresh_out, original_out, tolerance_bps
+                ));
+            }
+        }
+        Err(e) => outcome.requote_error = Some(format!("Failed to get a fresh quote: {}", e)),
+    }
+
+    outcome
+}
+
+/// The swap response, with the attempts made when the caller asked for `auto_requote`.
+fn swap_reply(mut builder: HttpResponseBuilder, response: SwapResponse, outcome: &RequoteOutcome, auto_requote: bool) -> HttpResponse {
+    if !auto_requote {
+        return builder.json(response);
+    }
+    builder.json(RequotedSwapResponse {
+        response,
+        attempts: outcome.attempts().map(SwapAttempt::report).collect(),
+        requote_error: outcome.requote_error.clone(),
+    })
+}
+
+struct SwapExecution<'a> {
+    user_id: &'a str,
+    user_public_key: &'a str,
+    priority_fee: &'a PriorityFeeConfig,
+    protection: &'a QuoteProtection,
+    request_id: Option<&'a str>,
+}
+
+impl SwapExecution<'_> {
+    /// Build the swap transaction for `quote` with Jupiter, then have the MPC service sign
+    /// and send it.
+    async fn attempt(&self, quote: &serde_json::Value) -> SwapAttempt {
+        // A fresh quote from auto_requote is held to the same limits as the saved one
+        if let Err(reason) = self.protection.check(quote) {
+            return SwapAttempt::not_sent(quote, StatusCode::BAD_REQUEST, reason);
+        }
+
+        let client = &*JUPITER_CLIENT;
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("Content-Type", reqwest::header::HeaderValue::from_static("application/json"));
+        headers.insert("Accept", reqwest::header::HeaderValue::from_static("application/json"));
+
+        let swap_build_request = swap_build_request(self.user_public_key, quote, self.priority_fee);
+
+        println!("Building swap transaction with Jupiter API...");
+
+        if !MPC_BREAKER.allow_request() {
+            return SwapAttempt::refused(quote, "MPC service is temporarily unavailable, please retry shortly");
+        }
+
+        if !JUPITER_SWAP_BREAKER.allow_request() {
+            return SwapAttempt::refused(quote, "Jupiter swap API is temporarily unavailable, please retry shortly");
+        }
+
+        let jupiter_response = match send_with_retry(|| {
+            client
+                .post("https://lite-api.jup.ag/swap/v1/swap")
+                .headers(headers.clone())
+                .json(&swap_build_request)
+        })
+        .await
+        {
+            Ok(response) => {
+                if response.status().is_server_error() {
+                    JUPITER_SWAP_BREAKER.record_failure();
+                } else {
+                    JUPITER_SWAP_BREAKER.record_success();
+                }
+                response
+            }
+            Err(e) => {
+                println!("Failed to call Jupiter swap API: {}", e);
+                JUPITER_SWAP_BREAKER.record_failure();
+                return SwapAttempt::not_sent(quote, StatusCode::INTERNAL_SERVER_ERROR, "Failed to build swap transaction");
+            }
+        };
+
+        if !jupiter_response.status().is_success() {
+            let error_text = jupiter_response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            println!("Jupiter API returned error: {}", error_text);
+            return SwapAttempt::not_sent(quote, StatusCode::BAD_REQUEST, format!("Jupiter API error: {}", error_text));
+        }
+
+        let jupiter_swap_response: serde_json::Value = match jupiter_response.json().await {
+            Ok(response) => {
+                println!("Successfully built swap transaction");
+                response
+            }
+            Err(e) => {
+                println!("Failed to parse Jupiter response: {}", e);
+                return SwapAttempt::not_sent(quote, StatusCode::INTERNAL_SERVER_ERROR, "Failed to parse Jupiter response");
+            }
+        };
+
+        println!("Forwarding transaction to MPC service for signing...");
+
+        let mpc_request = serde_json::json!({
+            "user_id": self.user_id,
+            "user_public_key": self.user_public_key,
+            "swap_transaction": jupiter_swap_response.get("swapTransaction"),
+            "operation": "jupiter_swap"
+        });
+
+        let mpc_client = mpc_client();
+        let mpc_url = format!("{}/api/jupiter-swap", mpc_service_url());
+        let mpc_send = send_with_connect_retry(|| {
+            with_request_id(mpc_client.post(&mpc_url), self.request_id).json(&mpc_request)
+        })
+        .await;
+        record_mpc_outcome(&mpc_send);
+
+        // Only a connection that never opened or the service's own refusal means nothing was sent
+        let mpc_result = match read_mpc_reply(mpc_send).await {
+            MpcReply::Answered(result) => result,
+            MpcReply::Rejected(_, error) => {
+                println!("MPC service refused the swap: {}", error);
+                serde_json::json!({ "success": false, "error": error })
+            }
+            MpcReply::NotSent(e) => {
+                println!("Failed to connect to MPC service: {}", e);
+                return SwapAttempt::not_sent(quote, StatusCode::SERVICE_UNAVAILABLE, "Failed to connect to MPC service");
+            }
+            MpcReply::Unknown(e) => {
+                println!("No usable answer from MPC service for the swap: {}", e);
+                return SwapAttempt::unknown(quote, "No usable answer from the MPC service");
+            }
+        };
+
+        SwapAttempt {
+            quote: quote.clone(),
+            success: mpc_result.get("success").and_then(|v| v.as_bool()).unwrap_or(false),
+            transaction_signature: mpc_result.get("transaction_signature")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            error: mpc_result.get("error")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            fee_lamports: mpc_result.get("fee_lamports").and_then(|v| v.as_u64()),
+            failure_status: None,
+            outcome_unknown: false,
+            refused: false,
+        }
+    }
 }
 
 #[actix_web::post("/quote")]
-pub async fn quote(req: web::Json<QuoteRequest>, store: web::Data<Arc<Mutex<Store>>>) -> Result<HttpResponse> {
+pub async fn quote(req: web::Json<QuoteRequest>, store: web::Data<Store>) -> Result<HttpResponse> {
     // let response = QuoteResponse {};
     
     // let quote = reqwest::Client::new();
@@ -89,24 +656,43 @@ pub async fn quote(req: web::Json<QuoteRequest>, store: web::Data<Arc<Mutex<Stor
     //         actix_web::error::ErrorInternalServerError("Failed to call Jup API")
     //     })?;
 
-    let client = reqwest::Client::builder().build()
-        .map_err(|_e| actix_web::error::ErrorInternalServerError("Failed to build HTTP client"))?;
+    if let Err(reason) = MintPolicy::from_env().check_pair(&req.input_mint, &req.output_mint) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": reason
+        })));
+    }
+    if let Err(reason) = SwapMinimums::from_env().check(&req.input_mint, req.amount) {
+        return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": reason
+        })));
+    }
+
+    let client = &*JUPITER_CLIENT;
 
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert("Accept", "application/json".parse()?);
 
-    let url = format!(
-        "https://lite-api.jup.ag/swap/v1/quote?inputMint={}&outputMint={}&amount={}&slippageBps={}&restrictIntermediateTokens=true",
-        req.input_mint, 
-        req.output_mint, 
-        req.amount, 
-        req.slippage_bps
-    );
+    let url = jupiter_quote_url(&req.input_mint, &req.output_mint, req.amount, req.slippage_bps);
 
-    let request = client.request(reqwest::Method::GET, url)
-        .headers(headers);
+    if !JUPITER_QUOTE_BREAKER.allow_request() {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Jupiter quote API is temporarily unavailable, please retry shortly"
+        })));
+    }
 
-    let response = request.send().await.map_err(|_e| actix_web::error::ErrorInternalServerError("Failed to call Jup API"))?;
+    let response = match send_with_retry(|| client.request(reqwest::Method::GET, &url).headers(headers.clone())).await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("Failed to call Jupiter quote API: {}", e);
+            JUPITER_QUOTE_BREAKER.record_failure();
+            return Err(actix_web::error::ErrorInternalServerError("Failed to call Jup API"));
+        }
+    };
+    if response.status().is_server_error() {
+        JUPITER_QUOTE_BREAKER.record_failure();
+    } else {
+        JUPITER_QUOTE_BREAKER.record_success();
+    }
     let body = response.text().await.map_err(|_e| actix_web::error::ErrorInternalServerError("Failed to read response body"))?;
 
     println!("Jupiter Quote Response: {}", body);
@@ -121,8 +707,7 @@ pub async fn quote(req: web::Json<QuoteRequest>, store: web::Data<Arc<Mutex<Stor
         quote_response: quote_response.clone(),
     };
 
-    let store_guard = store.lock().await;
-    match store_guard.save_quote(save_request).await {
+    match store.save_quote(save_request).await {
         Ok(saved_quote) => {
             println!("Quote saved successfully for user: {}", saved_quote.user_id);
         }
@@ -131,7 +716,6 @@ pub async fn quote(req: web::Json<QuoteRequest>, store: web::Data<Arc<Mutex<Stor
             // Continue anyway - don't fail the request if quote saving fails
         }
     }
-    drop(store_guard);
 
     // Extract necessary information for user response
     let user_quote_response = QuoteResponse {
@@ -182,23 +766,51 @@ pub async fn quote(req: web::Json<QuoteRequest>, store: web::Data<Arc<Mutex<Stor
     Ok(HttpResponse::Ok().json(user_quote_response))
 }
 
+/// A repeated `Idempotency-Key` replays the first response instead of swapping again.
 #[actix_web::post("/swap")]
-pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>>>) -> Result<HttpResponse> {
+pub async fn swap(http_req: HttpRequest, req: web::Json<SwapRequest>, store: web::Data<Store>) -> Result<HttpResponse> {
+    let user_id = req.user_id.clone();
+    idempotent(&http_req, &store, &user_id, "swap", || execute_swap(http_req.clone(), req, store.clone())).await
+}
+
+async fn execute_swap(http_req: HttpRequest, req: web::Json<SwapRequest>, store: web::Data<Store>) -> Result<HttpResponse> {
+    if let Some(response) = operation_disabled(Operation::Swap) {
+        return Ok(response);
+    }
+
+    let request_id = request_id(&http_req);
     println!("Processing swap request for user: {}", req.user_id);
 
+    let priority_fee = match PriorityFeeConfig::from_env()
+        .with_overrides(req.priority_level.as_deref(), req.max_priority_fee_lamports)
+    {
+        Ok(priority_fee) => priority_fee,
+        Err(reason) => {
+            return Ok(HttpResponse::BadRequest().json(SwapResponse {
+                success: false,
+                transaction_signature: None,
+                error: Some(reason),
+                swap_details: None,
+                balance_updates: None,
+            }));
+        }
+    };
+
     // Step 1: Get the saved quote from database
-    let store_guard = store.lock().await;
-    let quote_response = match store_guard.get_active_quote(&req.user_id).await {
-        Ok(Some(quote_data)) => {
+    let quote_response = match store.get_active_quote(&req.user_id).await {
+        Ok(quote_data) => {
             println!("Retrieved active quote for user: {}", req.user_id);
             quote_data
         }
-        Ok(None) => {
-            println!("No active quote found for user: {}", req.user_id);
+        Err(store::error::UserError::QuoteNotFound) => {
+            println!("No fresh quote found for user: {}", req.user_id);
             return Ok(HttpResponse::BadRequest().json(SwapResponse {
                 success: false,
                 transaction_signature: None,
-                error: Some("No active quote found for user. Please get a quote first.".to_string()),
+                error: Some(format!(
+                    "Quote expired or not found, request a new one. Quotes are valid for {} seconds.",
+                    store::quote::max_quote_age_secs()
+                )),
                 swap_details: None,
                 balance_updates: None,
             }));
@@ -214,7 +826,6 @@ pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>
             }));
         }
     };
-    drop(store_guard);
 
     // Extract swap information from quote
     let input_mint = quote_response.get("inputMint")
@@ -229,32 +840,72 @@ pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>
         .and_then(|v| v.as_str())
         .unwrap_or("0")
         .to_string();
-    let output_amount_str = quote_response.get("outAmount")
-        .and_then(|v| v.as_str())
-        .unwrap_or("0")
-        .to_string();
 
-    // Parse amounts for balance calculations
+    // Parse amounts for balance calculations; the output is read from whichever quote is executed
     let input_amount: u64 = input_amount_str.parse().unwrap_or(0);
-    let output_amount: u64 = output_amount_str.parse().unwrap_or(0);
 
-    // Step 2: Ensure assets exist in our database
-    let store_guard = store.lock().await;
+    // Re-checked here since the policy may have changed since the quote was taken
+    if let Err(reason) = MintPolicy::from_env().check_pair(&input_mint, &output_mint) {
+        return Ok(HttpResponse::Forbidden().json(SwapResponse {
+            success: false,
+            transaction_signature: None,
+            error: Some(reason),
+            swap_details: None,
+            balance_updates: None,
+        }));
+    }
+    if let Err(reason) = SwapMinimums::from_env().check(&input_mint, input_amount) {
+        return Ok(HttpResponse::UnprocessableEntity().json(SwapResponse {
+            success: false,
+            transaction_signature: None,
+            error: Some(reason),
+            swap_details: None,
+            balance_updates: None,
+        }));
+    }
+    let protection = QuoteProtection::from_env().with_overrides(req.max_price_impact_pct, req.min_out_amount);
+    if let Err(reason) = protection.check(&quote_response) {
+        println!("Refusing swap for user {}: {}", req.user_id, reason);
+        return Ok(HttpResponse::BadRequest().json(SwapResponse {
+            success: false,
+            transaction_signature: None,
+            error: Some(reason),
+            swap_details: None,
+            balance_updates: None,
+        }));
+    }
+
+    let (input_mint_key, output_mint_key) = match (input_mint.parse::<SolanaPubkey>(), output_mint.parse::<SolanaPubkey>()) {
+        (Ok(input_mint_key), Ok(output_mint_key)) => (input_mint_key, output_mint_key),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("Saved quote for user {} has an invalid mint: {}", req.user_id, e);
+            return Ok(HttpResponse::BadRequest().json(SwapResponse {
+                success: false,
+                transaction_signature: None,
+                error: Some("Saved quote has an invalid mint. Please get a new quote.".to_string()),
+                swap_details: None,
+                balance_updates: None,
+            }));
+        }
+    };
+
+    // Step 2: Ensure assets exist in our database, registering unknown mints only if allowed
     
     // Check/create input asset
-    let input_asset = match store_guard.get_asset_by_mint(&input_mint).await {
+    let input_asset = match find_swap_asset(&store, &input_mint).await {
         Ok(Some(asset)) => asset,
         Ok(None) => {
-            // Try to create asset with default values (you might want to fetch from token registry)
-            let create_request = store::asset::CreateAssetRequest {
-                mint_address: input_mint.clone(),
-                decimals: 9, // Default, should be fetched from chain/registry
-                name: format!("Token {}", &input_mint[..8]),
-                symbol: format!("TK{}", &input_mint[..4]),
-                logo_url: None,
+            let create_request = match new_swap_asset_request(&input_mint, input_mint_key.clone(), auto_create_swap_assets(), || async {
+                fetch_mint_metadata(&rpc_client(), &input_mint_key).await
+            }).await {
+                Ok(create_request) => create_request,
+                Err(reason) => {
+                    println!("Not registering input asset {}: {:?}", input_mint, reason);
+                    return Ok(reason.response(&input_mint));
+                }
             };
-            
-            match store_guard.create_asset(create_request).await {
+
+            match store.create_asset(create_request).await {
                 Ok(asset) => {
                     println!("Created input asset: {}", asset.symbol);
                     asset
@@ -284,18 +935,20 @@ pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>
     };
 
     // Check/create output asset
-    let output_asset = match store_guard.get_asset_by_mint(&output_mint).await {
+    let output_asset = match find_swap_asset(&store, &output_mint).await {
         Ok(Some(asset)) => asset,
         Ok(None) => {
-            let create_request = store::asset::CreateAssetRequest {
-                mint_address: output_mint.clone(),
-                decimals: 9, // Default, should be fetched from chain/registry
-                name: format!("Token {}", &output_mint[..8]),
-                symbol: format!("TK{}", &output_mint[..4]),
-                logo_url: None,
+            let create_request = match new_swap_asset_request(&output_mint, output_mint_key.clone(), auto_create_swap_assets(), || async {
+                fetch_mint_metadata(&rpc_client(), &output_mint_key).await
+            }).await {
+                Ok(create_request) => create_request,
+                Err(reason) => {
+                    println!("Not registering output asset {}: {:?}", output_mint, reason);
+                    return Ok(reason.response(&output_mint));
+                }
             };
-            
-            match store_guard.create_asset(create_request).await {
+
+            match store.create_asset(create_request).await {
                 Ok(asset) => {
                     println!("Created output asset: {}", asset.symbol);
                     asset
@@ -325,7 +978,7 @@ pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>
     };
 
     // Step 3: Check user has sufficient input balance
-    let input_balance = match store_guard.get_balance(&req.user_id, &input_asset.id).await {
+    let input_balance = match store.get_balance(&req.user_id, &input_asset.id).await {
         Ok(Some(balance)) => balance,
         Ok(None) => {
             return Ok(HttpResponse::BadRequest().json(SwapResponse {
@@ -365,181 +1018,299 @@ pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>
         }));
     }
 
-    drop(store_guard);
-
-    // Step 4: Build swap transaction using Jupiter API
-    let client = reqwest::Client::new();
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse()
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create header"))?);
-    headers.insert("Accept", "application/json".parse()
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to create header"))?);
-
-    let swap_build_request = serde_json::json!({
-        "userPublicKey": req.user_public_key,
-        "quoteResponse": quote_response,
-        "prioritizationFeeLamports": {
-            "priorityLevelWithMaxLamports": {
-                "maxLamports": 10000000,
-                "priorityLevel": "veryHigh"
-            }
-        },
-        "dynamicComputeUnitLimit": true
-    });
-
-    println!("Building swap transaction with Jupiter API...");
-
-    let jupiter_response = match client
-        .post("https://lite-api.jup.ag/swap/v1/swap")
-        .headers(headers)
-        .json(&swap_build_request)
-        .send()
-        .await
-    {
-        Ok(response) => response,
-        Err(e) => {
-            println!("Failed to call Jupiter swap API: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(SwapResponse {
+    // Check the balance cap before executing, since the output is credited only after the swap lands
+    match store.check_balance_limit(&req.user_id, &output_asset.id).await {
+        Ok(()) => {}
+        Err(store::error::UserError::TooManyBalances(max)) => {
+            return Ok(HttpResponse::BadRequest().json(SwapResponse {
                 success: false,
                 transaction_signature: None,
-                error: Some("Failed to build swap transaction".to_string()),
+                error: Some(format!("Balance limit reached: at most {} nonzero balances per user", max)),
                 swap_details: None,
                 balance_updates: None,
             }));
         }
-    };
-
-    if !jupiter_response.status().is_success() {
-        let error_text = jupiter_response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        println!("Jupiter API returned error: {}", error_text);
-        return Ok(HttpResponse::BadRequest().json(SwapResponse {
-            success: false,
-            transaction_signature: None,
-            error: Some(format!("Jupiter API error: {}", error_text)),
-            swap_details: None,
-            balance_updates: None,
-        }));
-    }
-
-    let jupiter_swap_response: serde_json::Value = match jupiter_response.json().await {
-        Ok(response) => {
-            println!("Successfully built swap transaction");
-            response
-        }
         Err(e) => {
-            println!("Failed to parse Jupiter response: {}", e);
+            println!("Failed to check balance limit: {:?}", e);
             return Ok(HttpResponse::InternalServerError().json(SwapResponse {
                 success: false,
                 transaction_signature: None,
-                error: Some("Failed to parse Jupiter response".to_string()),
+                error: Some("Failed to check balance limit".to_string()),
                 swap_details: None,
                 balance_updates: None,
             }));
         }
-    };
+    }
 
-    // Step 5: Forward to MPC service for secure signing and broadcasting
-    let mpc_service_url = std::env::var("MPC_SIMPLE_URL")
-        .unwrap_or_else(|_| "http://127.0.0.1:8081".to_string());
+    // Large swaps need a second request carrying the confirmation id
+    let threshold = match store.get_settings(&req.user_id).await {
+        Ok(settings) => settings.require_confirmation_over.or_else(swap_confirmation_threshold),
+        Err(e) => {
+            println!("Failed to load settings for user {}, using default threshold: {}", req.user_id, e);
+            swap_confirmation_threshold()
+        }
+    };
+    let mut confirmation_payload = None;
+    if requires_confirmation(input_amount_decimal, threshold) {
+        let expected_payload = serde_json::json!({
+            "input_mint": input_mint,
+            "output_mint": output_mint,
+            "in_amount": input_amount_str,
+        });
 
-    println!("Forwarding transaction to MPC service for signing...");
+        match &req.confirmation_id {
+            None => {
+                let create_request = store::pending_operation::CreatePendingOperationRequest {
+                    user_id: req.user_id.clone(),
+                    operation_type: SWAP_OPERATION.to_string(),
+                    payload: expected_payload,
+                };
 
-    let mpc_request = serde_json::json!({
-        "user_id": req.user_id,
-        "user_public_key": req.user_public_key,
-        "swap_transaction": jupiter_swap_response.get("swapTransaction"),
-        "operation": "jupiter_swap"
-    });
+                return match store.create_pending_operation(create_request).await {
+                    Ok(pending) => {
+                        println!("Swap for user {} requires confirmation: {}", req.user_id, pending.id);
+                        Ok(HttpResponse::Accepted().json(SwapConfirmationResponse {
+                            success: false,
+                            confirmation_required: true,
+                            confirmation_id: pending.id,
+                            expires_at: pending.expires_at,
+                            input_amount: format_amount(input_amount_decimal, input_asset.decimals),
+                            threshold: threshold.unwrap_or_default().to_string(),
+                        }))
+                    }
+                    Err(e) => {
+                        println!("Failed to create swap confirmation: {:?}", e);
+                        Ok(HttpResponse::InternalServerError().json(SwapResponse {
+                            success: false,
+                            transaction_signature: None,
+                            error: Some("Failed to create swap confirmation".to_string()),
+                            swap_details: None,
+                            balance_updates: None,
+                        }))
+                    }
+                };
+            }
+            // Checked and used up together with the debit below
+            Some(_) => confirmation_payload = Some(expected_payload),
+        }
+    }
 
-    let mpc_response = match client
-        .post(format!("{}/api/jupiter-swap", mpc_service_url))
-        .json(&mpc_request)
-        .send()
-        .await
-    {
-        Ok(response) => response,
-        Err(e) => {
-            println!("Failed to connect to MPC service: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(SwapResponse {
+    // Reserve the input before building so a concurrent swap or send can't spend it too;
+    // refunded below if the swap doesn't go through. A confirmation is consumed in the same
+    // transaction, so a debit that fails leaves it usable for a retry.
+    let debit = match (&req.confirmation_id, &confirmation_payload) {
+        (Some(confirmation_id), Some(payload)) => {
+            // The challenge is bound to the quote it was issued for; a different one doesn't use it up
+            let claim = ConfirmationClaim { operation_id: confirmation_id, operation_type: SWAP_OPERATION, payload };
+            match store.try_debit_balance_confirmed(claim, &req.user_id, &input_asset.id, input_amount_decimal, LedgerReason::Swap).await {
+                Ok(Some(debited)) => {
+                    println!("Swap confirmation {} accepted for user: {}", confirmation_id, req.user_id);
+                    Ok(debited)
+                }
+                Ok(None) => {
+                    return Ok(HttpResponse::BadRequest().json(SwapResponse {
+                        success: false,
+                        transaction_signature: None,
+                        error: Some("Invalid or expired swap confirmation".to_string()),
+                        swap_details: None,
+                        balance_updates: None,
+                    }));
+                }
+                Err(e) => Err(e),
+            }
+        }
+        _ => store.try_debit_balance(&req.user_id, &input_asset.id, input_amount_decimal, LedgerReason::Swap, None).await,
+    };
+    let (debited_input, debit_entry) = match debit {
+        Ok(debited) => debited,
+        Err(store::error::UserError::InsufficientBalance) => {
+            return Ok(HttpResponse::BadRequest().json(SwapResponse {
                 success: false,
                 transaction_signature: None,
-                error: Some("Failed to connect to MPC service".to_string()),
+                error: Some(format!("Insufficient {} balance. Required: {}", input_asset.symbol, input_amount_decimal)),
                 swap_details: None,
                 balance_updates: None,
             }));
         }
-    };
-
-    let mpc_result: serde_json::Value = match mpc_response.json().await {
-        Ok(result) => result,
         Err(e) => {
-            println!("Failed to parse MPC service response: {}", e);
+            println!("Failed to debit input balance: {:?}", e);
             return Ok(HttpResponse::InternalServerError().json(SwapResponse {
                 success: false,
                 transaction_signature: None,
-                error: Some("Failed to parse MPC service response".to_string()),
+                error: Some("Failed to update input balance".to_string()),
                 swap_details: None,
                 balance_updates: None,
             }));
         }
     };
 
-    let swap_success = mpc_result.get("success").and_then(|v| v.as_bool()).unwrap_or(false);
-    
-    // Step 6: Update balances if swap was successful
-    let balance_updates = if swap_success {
-        println!("Swap successful, updating user balances...");
-        
-        let store_guard = store.lock().await;
-        
-        // Decrease input token balance
-        let new_input_balance = input_balance.amount - input_amount_decimal;
-        let input_update_request = store::balance::UpdateBalanceRequest {
-            user_id: req.user_id.clone(),
-            asset_id: input_asset.id.clone(),
-            amount: new_input_balance,
-        };
-        
-        match store_guard.update_balance(input_update_request).await {
-            Ok(_) => {
-                println!("Updated {} balance: -{}", input_asset.symbol, input_amount_decimal);
-            }
-            Err(e) => {
-                println!("Failed to update input balance: {:?}", e);
-                // Continue - don't fail the whole operation if balance update fails
+    // Steps 4 and 5: build the transaction with Jupiter, then sign and send it through the
+    // MPC service. With auto_requote a stale-quote failure is retried once on a fresh quote.
+    let execution = SwapExecution {
+        user_id: &req.user_id,
+        user_public_key: &req.user_public_key,
+        priority_fee: &priority_fee,
+        protection: &protection,
+        request_id: request_id.as_deref(),
+    };
+    let outcome = swap_with_requote(
+        quote_response,
+        req.auto_requote,
+        requote_tolerance_bps(),
+        async |quote: &serde_json::Value| execution.attempt(quote).await,
+        async |quote: &serde_json::Value| {
+            let fresh = fetch_fresh_quote(quote).await?;
+            let save_request = store::quote::SaveQuoteRequest {
+                user_id: req.user_id.clone(),
+                quote_response: fresh.clone(),
+            };
+            if let Err(e) = store.save_quote(save_request).await {
+                println!("Failed to save fresh quote for user {}: {:?}", req.user_id, e);
             }
+            Ok(fresh)
+        },
+    ).await;
+
+    let executed = outcome.final_attempt();
+    let swap_success = executed.success;
+
+    let executed_signature = executed.transaction_signature.as_deref();
+
+    // The input was debited before signing; now tie it to the transaction
+    if let Some(signature) = executed_signature {
+        if let Err(e) = store.set_ledger_reference(&debit_entry.id, signature).await {
+            println!("Failed to record signature {} on ledger entry {}: {:?}", signature, debit_entry.id, e);
         }
-        
-        // Increase output token balance
-        let output_amount_decimal = rust_decimal::Decimal::from(output_amount) / 
+    }
+
+    // Failed attempts that landed still paid their fee, even though the swap didn't happen
+    let failed_attempt_fees: Vec<(u64, Option<&str>)> = outcome.attempts()
+        .filter(|attempt| !attempt.success)
+        .filter_map(|attempt| attempt.fee_lamports.map(|fee| (fee, attempt.transaction_signature.as_deref())))
+        .collect();
+    if !swap_success || !failed_attempt_fees.is_empty() {
+        if !swap_success && !executed.outcome_unknown {
+            let reason = executed.error.as_deref().unwrap_or("swap failure");
+            super::solana::refund_debit(&store, &req.user_id, &input_asset.id, input_amount_decimal, LedgerReason::Swap, executed_signature, reason).await;
+        }
+        for (fee_lamports, signature) in failed_attempt_fees {
+            super::solana::charge_network_fee(&store, &req.user_id, fee_lamports, signature).await;
+        }
+    }
+
+    // The swap may have landed, so the input stays debited until its signature settles it
+    if executed.outcome_unknown {
+        let output_amount = rust_decimal::Decimal::from(quote_out_amount(&executed.quote)) /
             rust_decimal::Decimal::from(10u64.pow(output_asset.decimals as u32));
-        
-        let output_balance_request = store::balance::CreateBalanceRequest {
-            user_id: req.user_id.clone(),
-            asset_id: output_asset.id.clone(),
-            amount: output_amount_decimal,
+        let payload = UnresolvedPayload {
+            reason: LedgerReason::Swap,
+            debit_entry_id: Some(debit_entry.id.clone()),
+            refund: Some(AssetAmount { asset_id: input_asset.id.clone(), amount: input_amount_decimal }),
+            owed: Some(AssetAmount { asset_id: output_asset.id.clone(), amount: output_amount }),
+            signature: None,
+            request_id: request_id.clone(),
+            error: executed.error.clone().unwrap_or_default(),
         };
-        
-        let final_output_balance = match store_guard.create_or_update_balance(output_balance_request).await {
-            Ok(balance) => {
+        let operation_id = record_unresolved(&store, &req.user_id, SWAP_OPERATION, &payload).await;
+        return Ok(unresolved_response(operation_id.as_deref(), "No usable answer from the MPC service"));
+    }
+
+    if let Some(status) = executed.failure_status {
+        let mut builder = HttpResponse::build(status);
+        if outcome.refused() {
+            builder.insert_header((NOT_ATTEMPTED_HEADER, "true"));
+        }
+        return Ok(swap_reply(builder, SwapResponse {
+            success: false,
+            transaction_signature: None,
+            error: executed.error.clone(),
+            swap_details: None,
+            balance_updates: None,
+        }, &outcome, req.auto_requote));
+    }
+
+    let transaction_signature = executed.transaction_signature.clone();
+    let fee_lamports = executed.fee_lamports.filter(|_| swap_success);
+    let output_amount_str = executed.quote.get("outAmount")
+        .and_then(|v| v.as_str())
+        .unwrap_or("0")
+        .to_string();
+    let output_amount: u64 = output_amount_str.parse().unwrap_or(0);
+
+    // Step 6: Update balances if swap was successful
+    let output_amount_decimal = rust_decimal::Decimal::from(output_amount) / 
+        rust_decimal::Decimal::from(10u64.pow(output_asset.decimals as u32));
+
+    let applied_balances = if swap_success {
+        println!("Swap successful, updating user balances...");
+
+        // The input was debited before signing; only the output remains to credit
+        match store.credit_balance(&req.user_id, &output_asset.id, output_amount_decimal, LedgerReason::Swap, executed_signature).await {
+            Ok(output_after) => {
+                println!("Updated {} balance: -{}", input_asset.symbol, input_amount_decimal);
                 println!("Updated {} balance: +{}", output_asset.symbol, output_amount_decimal);
-                balance.amount
+                Some((debited_input.amount, output_after.amount))
             }
             Err(e) => {
-                println!("Failed to update output balance: {:?}", e);
-                output_amount_decimal // Fallback
+                // The swap landed, so the output is owed; record it for settlement by signature
+                println!("Failed to credit swap output for user {}: {:?}", req.user_id, e);
+                let payload = UnresolvedPayload {
+                    reason: LedgerReason::Swap,
+                    debit_entry_id: None,
+                    refund: None,
+                    owed: Some(AssetAmount { asset_id: output_asset.id.clone(), amount: output_amount_decimal }),
+                    signature: transaction_signature.clone(),
+                    request_id: request_id.clone(),
+                    error: format!("Failed to credit swap output: {}", e),
+                };
+                record_unresolved(&store, &req.user_id, SWAP_OPERATION, &payload).await;
+                None
             }
-        };
+        }
+    } else {
+        None
+    };
+
+    let balance_updates = if let Some((new_input_balance, final_output_balance)) = applied_balances {
+
+        // The fee comes out of SOL, which may be one side of the swap
+        let mut new_input_balance = new_input_balance;
+        let mut final_output_balance = final_output_balance;
+        if let Some(fee_lamports) = fee_lamports {
+            let sol_after_fee = super::solana::charge_network_fee(&store, &req.user_id, fee_lamports, executed_signature).await;
+            if let Some(sol_balance) = sol_after_fee {
+                if input_asset.id == SOL_ASSET_ID {
+                    new_input_balance = sol_balance;
+                }
+                if output_asset.id == SOL_ASSET_ID {
+                    final_output_balance = sol_balance;
+                }
+            }
+        }
         
-        drop(store_guard);
+
+        let prices = super::price_oracle::get_usd_prices(&[&input_mint, &output_mint]).await;
+        let usd = swap_usd_summary(
+            input_amount_decimal,
+            prices.get(&input_mint).copied(),
+            output_amount_decimal,
+            prices.get(&output_mint).copied(),
+        );
         
         Some(BalanceUpdates {
-            input_token_balance: new_input_balance.to_string(),
-            output_token_balance: final_output_balance.to_string(),
+            input_token_balance: format_amount(new_input_balance, input_asset.decimals),
+            output_token_balance: format_amount(final_output_balance, output_asset.decimals),
+            input_token_balance_base_units: to_base_units(new_input_balance, input_asset.decimals),
+            output_token_balance_base_units: to_base_units(final_output_balance, output_asset.decimals),
             input_token_symbol: input_asset.symbol.clone(),
             output_token_symbol: output_asset.symbol.clone(),
+            usd,
         })
     } else {
+        // A swap that landed but whose output couldn't be credited still paid its fee
+        if let Some(fee_lamports) = fee_lamports {
+            super::solana::charge_network_fee(&store, &req.user_id, fee_lamports, executed_signature).await;
+        }
         None
     };
 
@@ -548,7 +1319,9 @@ pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>
         output_mint,
         input_amount: input_amount_str,
         output_amount: output_amount_str,
-        price_impact_pct: quote_response.get("priceImpactPct")
+        input: AmountView::from_base_units(input_amount, input_asset.decimals),
+        output: AmountView::from_base_units(output_amount, output_asset.decimals),
+        price_impact_pct: executed.quote.get("priceImpactPct")
             .and_then(|v| v.as_str())
             .unwrap_or("0")
             .to_string(),
@@ -556,12 +1329,8 @@ pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>
 
     let final_response = SwapResponse {
         success: swap_success,
-        transaction_signature: mpc_result.get("transaction_signature")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string()),
-        error: mpc_result.get("error")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string()),
+        transaction_signature,
+        error: executed.error.clone(),
         swap_details: Some(swap_details),
         balance_updates,
     };
@@ -578,5 +1347,232 @@ pub async fn swap(req: web::Json<SwapRequest>, store: web::Data<Arc<Mutex<Store>
         }
     }
 
-    Ok(HttpResponse::Ok().json(final_response))
-}
\ No newline at end of file
+    Ok(swap_reply(HttpResponse::Ok(), final_response, &outcome, req.auto_requote))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_build_request_uses_configured_priority_fee() {
+        let priority_fee = PriorityFeeConfig::new("medium", 250_000, 1_000_000).unwrap();
+        let quote = serde_json::json!({ "inAmount": "1000" });
+
+        let request = swap_build_request("user-pubkey", &quote, &priority_fee);
+        let fee = &request["prioritizationFeeLamports"]["priorityLevelWithMaxLamports"];
+        assert_eq!(fee["priorityLevel"], "medium");
+        assert_eq!(fee["maxLamports"], 250_000);
+        assert_eq!(request["quoteResponse"], quote);
+    }
+
+    #[test]
+    fn test_swap_below_threshold_executes_directly() {
+        let threshold = Some(Decimal::new(100, 0));
+        assert!(!requires_confirmation(Decimal::new(50, 0), threshold));
+        assert!(!requires_confirmation(Decimal::new(100, 0), threshold));
+        assert!(!requires_confirmation(Decimal::new(1_000_000, 0), None));
+    }
+
+    #[test]
+    fn test_swap_above_threshold_requires_confirmation() {
+        let threshold = Some(Decimal::new(100, 0));
+        assert!(requires_confirmation(Decimal::new(10001, 2), threshold));
+        assert!(requires_confirmation(Decimal::new(5000, 0), threshold));
+    }
+
+    #[test]
+    fn test_wrapped_sol_output_maps_to_native_sol_balance() {
+        // A USDC -> SOL swap reports its output as the wrapped SOL mint
+        assert_eq!(native_asset_id_for_mint(WRAPPED_SOL_MINT), Some(SOL_ASSET_ID));
+        assert_eq!(native_asset_id_for_mint("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"), None);
+    }
+
+    #[test]
+    fn test_swap_usd_summary() {
+        // 2 SOL at $150 swapped for 297 USDC at $1
+        let summary = swap_usd_summary(Decimal::from(2), Some(Decimal::from(150)), Decimal::from(297), Some(Decimal::ONE));
+
+        assert_eq!(summary.input_usd_value.as_deref(), Some("300"));
+        assert_eq!(summary.output_usd_value.as_deref(), Some("297"));
+        assert_eq!(summary.net_usd_change.as_deref(), Some("-3"));
+        assert_eq!(summary.net_change_pct.unwrap().parse::<Decimal>().unwrap(), Decimal::from(-1));
+    }
+
+    #[test]
+    fn test_swap_usd_summary_omits_missing_prices() {
+        let summary = swap_usd_summary(Decimal::from(2), None, Decimal::from(297), Some(Decimal::ONE));
+
+        assert_eq!(summary.input_usd_value, None);
+        assert_eq!(summary.output_usd_value.as_deref(), Some("297"));
+        assert_eq!(summary.net_usd_change, None);
+        assert_eq!(summary.net_change_pct, None);
+    }
+
+    const BONK_MINT: &str = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263";
+
+    fn bonk_metadata(name: Option<&str>, symbol: Option<&str>) -> MintMetadata {
+        MintMetadata { decimals: 5, name: name.map(str::to_string), symbol: symbol.map(str::to_string) }
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_swap_asset_rejected_when_auto_create_off() {
+        let mint_key: SolanaPubkey = BONK_MINT.parse().unwrap();
+
+        let fetched = std::cell::Cell::new(false);
+
+        let result = new_swap_asset_request(BONK_MINT, mint_key, false, || async {
+            fetched.set(true);
+            Ok(bonk_metadata(None, None))
+        }).await;
+
+        assert_eq!(result.unwrap_err(), UnknownSwapAsset::NotRegistered);
+        assert!(!fetched.get(), "metadata must not be fetched when auto-creation is off");
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_swap_asset_uses_fetched_metadata_when_auto_create_on() {
+        let request = new_swap_asset_request(BONK_MINT, BONK_MINT.parse().unwrap(), true, || async {
+            Ok(bonk_metadata(Some("Bonk"), Some("Bonk")))
+        }).await.unwrap();
+        assert_eq!(request.decimals, 5);
+        assert_eq!(request.symbol, "Bonk");
+        assert_eq!(request.name, "Bonk");
+
+        // Without on-chain name/symbol the real decimals are still used
+        let unnamed = new_swap_asset_request(BONK_MINT, BONK_MINT.parse().unwrap(), true, || async {
+            Ok(bonk_metadata(None, None))
+        }).await.unwrap();
+        assert_eq!(unnamed.decimals, 5);
+        assert_eq!(unnamed.symbol, "TKDezX");
+
+        let failed = new_swap_asset_request(BONK_MINT, BONK_MINT.parse().unwrap(), true, || async {
+            Err(MintMetadataError::Rpc("RPC request failed".to_string()))
+        }).await;
+        assert!(matches!(failed.unwrap_err(), UnknownSwapAsset::MetadataUnavailable(_)));
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_swap_asset_that_is_not_a_mint_is_a_bad_request() {
+        let result = new_swap_asset_request(BONK_MINT, BONK_MINT.parse().unwrap(), true, || async {
+            Err(MintMetadataError::NotAMint("account is not an SPL token mint".to_string()))
+        }).await;
+
+        let reason = result.unwrap_err();
+        assert!(matches!(reason, UnknownSwapAsset::NotAMint(_)));
+        assert_eq!(reason.response(BONK_MINT).status(), StatusCode::BAD_REQUEST);
+    }
+
+    fn quote_paying(out_amount: &str) -> serde_json::Value {
+        serde_json::json!({ "inAmount": "1000000000", "outAmount": out_amount, "slippageBps": 50 })
+    }
+
+    fn landed(quote: &serde_json::Value, success: bool, error: Option<&str>) -> SwapAttempt {
+        SwapAttempt {
+            quote: quote.clone(),
+            success,
+            transaction_signature: Some("sig".to_string()),
+            error: error.map(str::to_string),
+            fee_lamports: Some(5_000),
+            failure_status: None,
+            outcome_unknown: false,
+            refused: false,
+        }
+    }
+
+    #[test]
+    fn test_within_requote_tolerance() {
+        assert!(within_requote_tolerance(10_000, 9_900, 100));
+        assert!(!within_requote_tolerance(10_000, 9_899, 100));
+        assert!(within_requote_tolerance(10_000, 12_000, 0));
+    }
+
+    #[test]
+    fn test_is_requotable_failure() {
+        assert!(is_requotable_failure("Transaction failed: custom program error: 0x1771"));
+        assert!(is_requotable_failure("Slippage tolerance exceeded"));
+        assert!(is_requotable_failure("Blockhash not found"));
+        assert!(!is_requotable_failure("insufficient funds for rent"));
+    }
+
+    #[actix_web::test]
+    async fn test_stale_quote_failure_retries_on_fresh_quote() {
+        let calls = std::cell::Cell::new(0);
+
+        let outcome = swap_with_requote(
+            quote_paying("150000000"),
+            true,
+            100,
+            async |quote: &serde_json::Value| {
+                calls.set(calls.get() + 1);
+                if calls.get() == 1 {
+                    landed(quote, false, Some("Slippage tolerance exceeded (0x1771)"))
+                } else {
+                    landed(quote, true, None)
+                }
+            },
+            async |_quote: &serde_json::Value| Ok(quote_paying("149500000")),
+        ).await;
+
+        assert_eq!(calls.get(), 2);
+        assert!(outcome.final_attempt().success);
+        assert_eq!(quote_out_amount(&outcome.final_attempt().quote), 149_500_000);
+        assert_eq!(outcome.attempts().count(), 2);
+        assert_eq!(outcome.requote_error, None);
+    }
+
+    #[actix_web::test]
+    async fn test_requote_beyond_tolerance_is_not_retried() {
+        let calls = std::cell::Cell::new(0);
+
+        let outcome = swap_with_requote(
+            quote_paying("150000000"),
+            true,
+            100,
+            async |quote: &serde_json::Value| {
+                calls.set(calls.get() + 1);
+                landed(quote, false, Some("Slippage tolerance exceeded (0x1771)"))
+            },
+            async |_quote: &serde_json::Value| Ok(quote_paying("140000000")),
+        ).await;
+
+        assert_eq!(calls.get(), 1);
+        assert!(!outcome.final_attempt().success);
+        assert!(outcome.requote_error.unwrap().contains("tolerance"));
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_outcome_is_not_retried() {
+        let calls = std::cell::Cell::new(0);
+
+        // A timed-out attempt may have landed, so a second swap must not be sent
+        let outcome = swap_with_requote(
+            quote_paying("150000000"),
+            true,
+            100,
+            async |quote: &serde_json::Value| {
+                calls.set(calls.get() + 1);
+                SwapAttempt::unknown(quote, "Blockhash expired while waiting")
+            },
+            async |_quote: &serde_json::Value| Ok(quote_paying("150000000")),
+        ).await;
+
+        assert_eq!(calls.get(), 1);
+        assert!(outcome.final_attempt().outcome_unknown);
+    }
+
+    #[actix_web::test]
+    async fn test_failure_without_auto_requote_is_not_retried() {
+        let outcome = swap_with_requote(
+            quote_paying("150000000"),
+            false,
+            100,
+            async |quote: &serde_json::Value| landed(quote, false, Some("Slippage tolerance exceeded (0x1771)")),
+            async |_quote: &serde_json::Value| -> std::result::Result<serde_json::Value, String> {
+                panic!("no fresh quote should be requested")
+            },
+        ).await;
+
+        assert_eq!(outcome.attempts().count(), 1);
+    }
+}
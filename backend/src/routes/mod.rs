@@ -3,9 +3,41 @@ pub mod solana;
 pub mod jupiter;
 pub mod asset;
 pub mod balance;
+pub mod pagination;
+pub mod admin;
+pub mod auth;
+pub mod rpc;
+pub mod price_oracle;
+pub mod circuit_breaker;
+pub mod mpc_client;
+pub mod swap_policy;
+pub mod priority_fee;
+pub mod transaction_status;
+pub mod request_id;
+pub mod reconcile;
+pub mod settings;
+pub mod capabilities;
+pub mod indexer_client;
+pub mod amount;
+pub mod feature_flags;
+pub mod effective_config;
+pub mod extractor_errors;
+pub mod idempotency;
+pub mod rate_limit;
+pub mod retry;
+pub mod token;
+pub mod unresolved;
 
 pub use user::*;
 pub use solana::*;
 pub use jupiter::*;
 pub use asset::*;
 pub use balance::*;
+pub use reconcile::*;
+pub use settings::*;
+pub use transaction_status::*;
+pub use capabilities::*;
+pub use admin::*;
+pub use effective_config::*;
+pub use token::*;
+pub use unresolved::*;
@@ -0,0 +1,159 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use super::circuit_breaker::CircuitBreaker;
+
+/// mpc-simple's confirmation polling defaults (`MPC_CONFIRM_MAX_POLLS` and
+/// `MPC_CONFIRM_POLL_INTERVAL_MS`); read from the same variables when set here too.
+const MPC_CONFIRM_MAX_POLLS: u64 = 60;
+const MPC_CONFIRM_POLL_INTERVAL_MS: u64 = 500;
+
+/// Headroom beyond the confirmation window for decrypting shares, signing, broadcasting,
+/// the RPC round trips of each poll and the fee lookup.
+const MPC_SIGNING_ALLOWANCE_SECS: u64 = 30;
+
+/// Outlasts the MPC service's own confirmation window, so the backend doesn't give up on a
+/// send the service is still confirming.
+const DEFAULT_MPC_TIMEOUT_SECS: u64 =
+    MPC_CONFIRM_MAX_POLLS * MPC_CONFIRM_POLL_INTERVAL_MS / 1000 + MPC_SIGNING_ALLOWANCE_SECS;
+
+/// Opened by consecutive MPC failures so value-moving requests are rejected before any
+/// balance is debited, instead of debiting and rolling back on every attempt.
+pub static MPC_BREAKER: LazyLock<CircuitBreaker> =
+    LazyLock::new(|| CircuitBreaker::from_env("mpc_service", "MPC"));
+
+pub fn mpc_service_url() -> String {
+    std::env::var("MPC_SIMPLE_URL")
+        .unwrap_or_else(|_| "http://127.0.0.1:8081".to_string())
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+/// How long the MPC service may spend polling for confirmation of one transaction.
+fn confirmation_window(max_polls: u64, poll_interval_ms: u64) -> Duration {
+    Duration::from_millis(max_polls.saturating_mul(poll_interval_ms))
+}
+
+/// `MPC_REQUEST_TIMEOUT_SECS`, raised past the confirmation window if set shorter: a timeout
+/// that fires while the service is still confirming leaves the send's outcome unknown.
+fn mpc_timeout(configured_secs: Option<u64>, window: Duration) -> Duration {
+    let floor = window + Duration::from_secs(MPC_SIGNING_ALLOWANCE_SECS);
+    match configured_secs.map(Duration::from_secs) {
+        Some(timeout) if timeout < floor => {
+            println!("MPC_REQUEST_TIMEOUT_SECS is shorter than the MPC confirmation window, using {}s", floor.as_secs());
+            floor
+        }
+        Some(timeout) => timeout,
+        None => floor.max(Duration::from_secs(DEFAULT_MPC_TIMEOUT_SECS)),
+    }
+}
+
+/// HTTP client for the MPC service with an explicit timeout (`MPC_REQUEST_TIMEOUT_SECS`).
+pub fn mpc_client() -> reqwest::Client {
+    let window = confirmation_window(
+        env_u64("MPC_CONFIRM_MAX_POLLS").unwrap_or(MPC_CONFIRM_MAX_POLLS),
+        env_u64("MPC_CONFIRM_POLL_INTERVAL_MS").unwrap_or(MPC_CONFIRM_POLL_INTERVAL_MS),
+    );
+
+    reqwest::Client::builder()
+        .timeout(mpc_timeout(env_u64("MPC_REQUEST_TIMEOUT_SECS"), window))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+/// Feed the outcome of an MPC call into the breaker. Only transport errors and 5xx
+/// responses count; a 4xx is the service correctly rejecting a request.
+pub fn record_mpc_outcome(result: &Result<reqwest::Response, reqwest::Error>) {
+    match result {
+        Ok(response) if !response.status().is_server_error() => MPC_BREAKER.record_success(),
+        _ => MPC_BREAKER.record_failure(),
+    }
+}
+
+/// What a sign-and-send call to the MPC service tells the backend about the transaction.
+#[derive(Debug)]
+pub enum MpcReply {
+    /// A 2xx JSON report of what happened on chain.
+    Answered(serde_json::Value),
+    /// The service refused with its own non-2xx JSON error, which it only does before
+    /// broadcasting.
+    Rejected(StatusCode, String),
+    /// The connection was never made, so nothing was signed or sent.
+    NotSent(String),
+    /// The request may have reached the service but no usable answer came back (a timeout,
+    /// an unreadable 2xx body, or an error page from something in between). The transaction
+    /// may have landed, so the debit must stand until its signature is checked.
+    Unknown(String),
+}
+
+/// Classify the result of a sign-and-send call. Only `NotSent` and `Rejected` are safe to
+/// refund.
+pub async fn read_mpc_reply(result: Result<reqwest::Response, reqwest::Error>) -> MpcReply {
+    let response = match result {
+        Ok(response) => response,
+        Err(e) if e.is_connect() => return MpcReply::NotSent(e.to_string()),
+        Err(e) => return MpcReply::Unknown(e.to_string()),
+    };
+
+    let status = response.status();
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(e) => return MpcReply::Unknown(format!("Failed to read MPC response: {}", e)),
+    };
+    classify_mpc_body(status, &body)
+}
+
+fn classify_mpc_body(status: StatusCode, body: &str) -> MpcReply {
+    let json: Option<serde_json::Value> = serde_json::from_str(body).ok();
+
+    if status.is_success() {
+        return match json {
+            Some(json) => MpcReply::Answered(json),
+            None => MpcReply::Unknown(format!("Unreadable {} response from MPC service", status)),
+        };
+    }
+
+    // The service's own refusals are JSON with `success: false`
+    match json.filter(|json| json.get("success").and_then(|v| v.as_bool()) == Some(false)) {
+        Some(json) => {
+            let error = json.get("error").and_then(|v| v.as_str()).unwrap_or(body).to_string();
+            MpcReply::Rejected(status, error)
+        }
+        None => MpcReply::Unknown(format!("MPC service returned {} without its own error body", status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_timeout_outlasts_confirmation_window() {
+        let window = confirmation_window(MPC_CONFIRM_MAX_POLLS, MPC_CONFIRM_POLL_INTERVAL_MS);
+        assert!(Duration::from_secs(DEFAULT_MPC_TIMEOUT_SECS) > window);
+        assert!(mpc_timeout(None, window) > window);
+
+        // A longer polling window, or a configured timeout inside it, is raised past it
+        let long_window = confirmation_window(200, 500);
+        assert!(mpc_timeout(None, long_window) > long_window);
+        assert!(mpc_timeout(Some(10), window) > window);
+        assert_eq!(mpc_timeout(Some(600), window), Duration::from_secs(600));
+    }
+
+    #[test]
+    fn test_classify_mpc_body() {
+        let answered = classify_mpc_body(StatusCode::OK, r#"{"success":true,"transaction_signature":"sig"}"#);
+        assert!(matches!(answered, MpcReply::Answered(json) if json["transaction_signature"] == "sig"));
+
+        let rejected = classify_mpc_body(StatusCode::INTERNAL_SERVER_ERROR, r#"{"success":false,"error":"No key shares"}"#);
+        assert!(matches!(rejected, MpcReply::Rejected(_, error) if error == "No key shares"));
+
+        // Neither says what happened to the transaction
+        assert!(matches!(classify_mpc_body(StatusCode::OK, "<html>"), MpcReply::Unknown(_)));
+        assert!(matches!(classify_mpc_body(StatusCode::GATEWAY_TIMEOUT, "upstream timed out"), MpcReply::Unknown(_)));
+    }
+}
@@ -0,0 +1,64 @@
+use serde::Serialize;
+
+pub const DEFAULT_PER_PAGE: i64 = 20;
+pub const MAX_PER_PAGE: i64 = 100;
+
+/// Page number and page size with defaults applied and out-of-range values clamped.
+pub fn resolve_page(page: Option<i64>, per_page: Option<i64>) -> (i64, i64) {
+    let page = page.unwrap_or(1).max(1);
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+    (page, per_page)
+}
+
+/// Rows to skip to reach `page`, or `None` when a huge page number would overflow.
+pub fn page_offset(page: i64, per_page: i64) -> Option<i64> {
+    (page - 1).checked_mul(per_page)
+}
+
+/// Envelope for list endpoints that return one page at a time.
+#[derive(Serialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub per_page: i64,
+    /// Rows skipped before `items`. Exact for `limit`/`offset` requests, where `page` is
+    /// the page holding the first item.
+    pub offset: i64,
+    pub total: i64,
+    pub total_pages: i64,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(items: Vec<T>, page: i64, per_page: i64, total: i64) -> Self {
+        let offset = (page - 1).saturating_mul(per_page);
+        Self::from_window(items, per_page, offset, total)
+    }
+
+    /// The envelope for a `limit`/`offset` window, with `per_page` set to `limit`.
+    pub fn from_window(items: Vec<T>, limit: i64, offset: i64, total: i64) -> Self {
+        let total_pages = (total + limit - 1) / limit;
+        Self { items, page: offset / limit + 1, per_page: limit, offset, total, total_pages }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_offset() {
+        assert_eq!(page_offset(1, 20), Some(0));
+        assert_eq!(page_offset(3, 20), Some(40));
+        assert_eq!(page_offset(i64::MAX, MAX_PER_PAGE), None);
+    }
+
+    #[test]
+    fn test_window_envelope_reports_the_offset() {
+        let paged = Paginated::new(vec![1, 2], 3, 20, 45);
+        assert_eq!((paged.page, paged.per_page, paged.offset, paged.total_pages), (3, 20, 40, 3));
+
+        // An offset between page boundaries is kept as asked rather than rounded to a page
+        let windowed = Paginated::from_window(vec![1, 2], 10, 25, 45);
+        assert_eq!((windowed.page, windowed.per_page, windowed.offset, windowed.total_pages), (3, 10, 25, 5));
+    }
+}
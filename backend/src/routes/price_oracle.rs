@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use rust_decimal::Decimal;
+
+const JUPITER_PRICE_URL: &str = "https://lite-api.jup.ag/price/v2";
+const PRICE_CACHE_TTL: Duration = Duration::from_secs(30);
+/// Prices are optional, so a slow price API shouldn't hold up the balance views waiting on it.
+const PRICE_TIMEOUT_SECS: u64 = 5;
+
+static PRICE_CACHE: LazyLock<PriceCache> = LazyLock::new(PriceCache::default);
+
+static PRICE_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(PRICE_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+});
+
+/// Prices fetched in the last `PRICE_CACHE_TTL`, keyed by mint. A mint Jupiter had no price
+/// for is cached as `None` so it isn't asked for again on every call.
+#[derive(Default)]
+struct PriceCache {
+    entries: Mutex<HashMap<String, (Instant, Option<Decimal>)>>,
+}
+
+impl PriceCache {
+    /// Cached prices for `mints`, and the mints that have to be fetched.
+    fn lookup(&self, mints: &[&str], now: Instant) -> (HashMap<String, Decimal>, Vec<String>) {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let mut prices = HashMap::new();
+        let mut missing = Vec::new();
+
+        for mint in mints {
+            match entries.get(*mint) {
+                Some((fetched_at, price)) if now.duration_since(*fetched_at) < PRICE_CACHE_TTL => {
+                    if let Some(price) = price {
+                        prices.insert(mint.to_string(), *price);
+                    }
+                }
+                _ => missing.push(mint.to_string()),
+            }
+        }
+        (prices, missing)
+    }
+
+    fn store(&self, mints: &[String], prices: &HashMap<String, Decimal>, now: Instant) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (fetched_at, _)| now.duration_since(*fetched_at) < PRICE_CACHE_TTL);
+        for mint in mints {
+            entries.insert(mint.clone(), (now, prices.get(mint).copied()));
+        }
+    }
+}
+
+/// USD prices for `mints` from the Jupiter price API, cached for 30s. Mints without a price
+/// (or a failed request) are simply absent from the result, so callers should treat prices
+/// as optional.
+pub async fn get_usd_prices(mints: &[&str]) -> HashMap<String, Decimal> {
+    let (mut prices, missing) = PRICE_CACHE.lookup(mints, Instant::now());
+    if missing.is_empty() {
+        return prices;
+    }
+
+    // A failed request isn't cached, so the next call retries it
+    if let Some(fetched) = fetch_usd_prices(&missing).await {
+        PRICE_CACHE.store(&missing, &fetched, Instant::now());
+        prices.extend(fetched);
+    }
+    prices
+}
+
+async fn fetch_usd_prices(mints: &[String]) -> Option<HashMap<String, Decimal>> {
+    let url = format!("{}?ids={}", JUPITER_PRICE_URL, mints.join(","));
+    let response = match PRICE_CLIENT.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("Failed to call Jupiter price API: {}", e);
+            return None;
+        }
+    };
+    if !response.status().is_success() {
+        println!("Jupiter price API returned {}", response.status());
+        return None;
+    }
+
+    let body: serde_json::Value = match response.json().await {
+        Ok(body) => body,
+        Err(e) => {
+            println!("Failed to parse Jupiter price response: {}", e);
+            return None;
+        }
+    };
+
+    let prices = parse_prices(&body, mints);
+    if prices.is_none() {
+        println!("Jupiter price response has no data: {}", body);
+    }
+    prices
+}
+
+/// Prices for the `mints` listed in the response's `data`. A body without `data` isn't a
+/// price answer, so it yields `None` rather than every mint unpriced.
+fn parse_prices(body: &serde_json::Value, mints: &[String]) -> Option<HashMap<String, Decimal>> {
+    let data = body.get("data").filter(|data| data.is_object())?;
+
+    let mut prices = HashMap::new();
+    for mint in mints {
+        let price = data.get(mint.as_str())
+            .and_then(|entry| entry.get("price"))
+            .and_then(|price| price.as_str())
+            .and_then(|price| price.parse::<Decimal>().ok());
+
+        if let Some(price) = price {
+            prices.insert(mint.clone(), price);
+        }
+    }
+
+    Some(prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const UNPRICED: &str = "11111111111111111111111111111111";
+
+    #[test]
+    fn test_parse_prices_skips_unpriced_mints() {
+        let body = serde_json::json!({
+            "data": {
+                SOL: { "id": SOL, "type": "derivedPrice", "price": "151.25" },
+                UNPRICED: null
+            },
+            "timeTaken": 0.002
+        });
+
+        let prices = parse_prices(&body, &[SOL.to_string(), UNPRICED.to_string()]).unwrap();
+        assert_eq!(prices.get(SOL), Some(&Decimal::new(15125, 2)));
+        assert!(!prices.contains_key(UNPRICED));
+    }
+
+    #[test]
+    fn test_error_body_is_not_a_price_answer() {
+        let body = serde_json::json!({ "error": "Too many requests" });
+        assert_eq!(parse_prices(&body, &[SOL.to_string()]), None);
+    }
+
+    #[test]
+    fn test_price_cache_expires_after_ttl() {
+        let cache = PriceCache::default();
+        let now = Instant::now();
+        let fetched = HashMap::from([(SOL.to_string(), Decimal::ONE_HUNDRED)]);
+        cache.store(&[SOL.to_string(), UNPRICED.to_string()], &fetched, now);
+
+        let (prices, missing) = cache.lookup(&[SOL, UNPRICED], now + Duration::from_secs(10));
+        assert_eq!(prices.get(SOL), Some(&Decimal::ONE_HUNDRED));
+        assert!(missing.is_empty());
+
+        let (prices, missing) = cache.lookup(&[SOL, UNPRICED], now + PRICE_CACHE_TTL);
+        assert!(prices.is_empty());
+        assert_eq!(missing.len(), 2);
+    }
+}
@@ -0,0 +1,107 @@
+/// Priority levels Jupiter accepts. Each one maps to a percentile of recently paid
+/// priority fees, so the fee tracks congestion instead of being a fixed amount.
+pub const PRIORITY_LEVELS: [&str; 3] = ["medium", "high", "veryHigh"];
+
+const DEFAULT_PRIORITY_LEVEL: &str = "veryHigh";
+const DEFAULT_MAX_LAMPORTS: u64 = 10_000_000;
+
+/// Priority fee strategy for Jupiter swap builds, from `SWAP_PRIORITY_LEVEL`,
+/// `SWAP_PRIORITY_MAX_LAMPORTS` and `SWAP_PRIORITY_FEE_CAP_LAMPORTS`. Callers may ask for a
+/// different level or ceiling per request, but never above the operator cap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriorityFeeConfig {
+    priority_level: String,
+    max_lamports: u64,
+    cap_lamports: u64,
+}
+
+fn parse_priority_level(level: &str) -> Result<String, String> {
+    PRIORITY_LEVELS.iter()
+        .find(|known| known.eq_ignore_ascii_case(level.trim()))
+        .map(|known| known.to_string())
+        .ok_or_else(|| format!("priority_level must be one of: {}", PRIORITY_LEVELS.join(", ")))
+}
+
+impl PriorityFeeConfig {
+    pub fn new(priority_level: &str, max_lamports: u64, cap_lamports: u64) -> Result<Self, String> {
+        Ok(Self {
+            priority_level: parse_priority_level(priority_level)?,
+            max_lamports: max_lamports.min(cap_lamports),
+            cap_lamports,
+        })
+    }
+
+    pub fn from_env() -> Self {
+        let cap_lamports = std::env::var("SWAP_PRIORITY_FEE_CAP_LAMPORTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_LAMPORTS);
+        let max_lamports = std::env::var("SWAP_PRIORITY_MAX_LAMPORTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_LAMPORTS);
+        let priority_level = std::env::var("SWAP_PRIORITY_LEVEL")
+            .unwrap_or_else(|_| DEFAULT_PRIORITY_LEVEL.to_string());
+
+        Self::new(&priority_level, max_lamports, cap_lamports).unwrap_or_else(|e| {
+            println!("Ignoring SWAP_PRIORITY_LEVEL: {}", e);
+            Self {
+                priority_level: DEFAULT_PRIORITY_LEVEL.to_string(),
+                max_lamports: max_lamports.min(cap_lamports),
+                cap_lamports,
+            }
+        })
+    }
+
+    /// Apply a caller's per-request choices; the requested ceiling is clamped to the cap.
+    pub fn with_overrides(mut self, priority_level: Option<&str>, max_lamports: Option<u64>) -> Result<Self, String> {
+        if let Some(level) = priority_level {
+            self.priority_level = parse_priority_level(level)?;
+        }
+        if let Some(max_lamports) = max_lamports {
+            self.max_lamports = max_lamports.min(self.cap_lamports);
+        }
+        Ok(self)
+    }
+
+    pub fn priority_level(&self) -> &str {
+        &self.priority_level
+    }
+
+    pub fn max_lamports(&self) -> u64 {
+        self.max_lamports
+    }
+
+    /// The `prioritizationFeeLamports` value of a Jupiter swap-build request.
+    pub fn to_jupiter_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "priorityLevelWithMaxLamports": {
+                "maxLamports": self.max_lamports,
+                "priorityLevel": self.priority_level
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_are_clamped_to_cap() {
+        let config = PriorityFeeConfig::new("high", 500_000, 1_000_000).unwrap();
+
+        let raised = config.clone().with_overrides(Some("medium"), Some(50_000_000)).unwrap();
+        assert_eq!(raised.priority_level(), "medium");
+        assert_eq!(raised.max_lamports(), 1_000_000);
+
+        assert!(config.with_overrides(Some("ludicrous"), None).is_err());
+    }
+
+    #[test]
+    fn test_priority_level_is_case_insensitive() {
+        let config = PriorityFeeConfig::new("VERYHIGH", 20_000_000, 5_000_000).unwrap();
+        assert_eq!(config.priority_level(), "veryHigh");
+        assert_eq!(config.max_lamports(), 5_000_000);
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::{HttpRequest, HttpResponse};
+
+const DEFAULT_AUTH_RATE_LIMIT_PER_MIN: u32 = 10;
+// Past this many tracked addresses, buckets that have refilled are dropped
+const MAX_TRACKED_ADDRESSES: usize = 10_000;
+
+/// Shared by signup and signin, so a client can't dodge the limit by alternating them.
+pub static AUTH_RATE_LIMITER: LazyLock<RateLimiter> = LazyLock::new(|| {
+    let per_minute = std::env::var("AUTH_RATE_LIMIT_PER_MIN")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_AUTH_RATE_LIMIT_PER_MIN);
+    RateLimiter::per_minute("auth", per_minute)
+});
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Per-IP token bucket: each address may burst `capacity` requests, then earns one more
+/// every `60 / capacity` seconds. A capacity of 0 turns the limiter off.
+#[derive(Debug)]
+pub struct RateLimiter {
+    name: &'static str,
+    capacity: u32,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(name: &'static str, capacity: u32) -> Self {
+        Self {
+            name,
+            capacity,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.capacity as f64 / 60.0
+    }
+
+    /// Take a token for `ip`, or return how long until one is available.
+    fn acquire(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+
+        let capacity = self.capacity as f64;
+        let rate = self.refill_per_sec();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_TRACKED_ADDRESSES {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.saturating_duration_since(bucket.refilled_at).as_secs_f64() * rate < capacity
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket { tokens: capacity, refilled_at: now });
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+
+    /// The 429 to return when the client has used up its requests, or `None` if it may proceed.
+    pub fn check(&self, req: &HttpRequest, trust_proxy: bool) -> Option<HttpResponse> {
+        let ip = client_ip(req, trust_proxy);
+        let retry_after = self.acquire(ip, Instant::now()).err()?;
+        let retry_after_secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+
+        println!("Rate limited {} request from {} (retry after {}s)", self.name, ip, retry_after_secs);
+        Some(
+            HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_secs.to_string()))
+                .json(serde_json::json!({
+                    "error": "Too many requests, try again later",
+                    "retry_after_secs": retry_after_secs
+                })),
+        )
+    }
+}
+
+/// `TRUST_PROXY=true` means the backend sits behind a proxy that sets `X-Forwarded-For`.
+pub fn trust_proxy() -> bool {
+    std::env::var("TRUST_PROXY")
+        .map(|v| v.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// The client address: the last `X-Forwarded-For` entry when the proxy is trusted, otherwise
+/// the peer address. The proxy appends the address it saw, so earlier entries come from the
+/// client and can be forged. Without either, every such request shares one bucket.
+pub fn client_ip(req: &HttpRequest, trust_proxy: bool) -> IpAddr {
+    let forwarded = trust_proxy
+        .then(|| req.headers().get("X-Forwarded-For"))
+        .flatten()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .and_then(|last| last.trim().parse().ok());
+
+    forwarded
+        .or_else(|| req.peer_addr().map(|addr| addr.ip()))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// The 429 for signup/signin when the caller's address is over `AUTH_RATE_LIMIT_PER_MIN`.
+pub fn auth_rate_limited(req: &HttpRequest) -> Option<HttpResponse> {
+    AUTH_RATE_LIMITER.check(req, trust_proxy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_from(peer: &str, forwarded_for: Option<&str>) -> HttpRequest {
+        let mut req = actix_web::test::TestRequest::default().peer_addr(peer.parse().unwrap());
+        if let Some(forwarded_for) = forwarded_for {
+            req = req.insert_header(("X-Forwarded-For", forwarded_for));
+        }
+        req.to_http_request()
+    }
+
+    #[test]
+    fn test_request_past_the_limit_gets_429() {
+        let limiter = RateLimiter::per_minute("auth", 3);
+        let req = request_from("10.0.0.1:5000", None);
+
+        for _ in 0..3 {
+            assert!(limiter.check(&req, false).is_none());
+        }
+        let limited = limiter.check(&req, false).expect("fourth request should be limited");
+        assert_eq!(limited.status(), 429);
+        assert_eq!(limited.headers().get("Retry-After").unwrap(), "20");
+
+        // Another address has its own bucket
+        assert!(limiter.check(&request_from("10.0.0.2:5000", None), false).is_none());
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let limiter = RateLimiter::per_minute("auth", 2);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.acquire(ip, start).is_ok());
+        assert!(limiter.acquire(ip, start).is_ok());
+        assert!(limiter.acquire(ip, start).is_err());
+        assert!(limiter.acquire(ip, start + Duration::from_secs(30)).is_ok());
+    }
+
+    #[test]
+    fn test_forwarded_for_is_only_used_behind_a_trusted_proxy() {
+        let req = request_from("10.0.0.1:5000", Some("203.0.113.7"));
+        assert_eq!(client_ip(&req, true), "203.0.113.7".parse::<IpAddr>().unwrap());
+        assert_eq!(client_ip(&req, false), "10.0.0.1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_spoofed_forwarded_for_entries_are_ignored() {
+        // The client sent its own header; the proxy appended the address it actually saw
+        let req = request_from("10.0.0.1:5000", Some("198.51.100.1, 192.0.2.9, 203.0.113.7"));
+        assert_eq!(client_ip(&req, true), "203.0.113.7".parse::<IpAddr>().unwrap());
+
+        // Rotating the forged entries doesn't escape the client's bucket
+        let limiter = RateLimiter::per_minute("auth", 1);
+        assert!(limiter.check(&req, true).is_none());
+        let rotated = request_from("10.0.0.1:5000", Some("198.51.100.2, 203.0.113.7"));
+        assert!(limiter.check(&rotated, true).is_some());
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use store::{balance::BalanceWithDetails, Store};
+use rust_decimal::Decimal;
+
+use super::{admin::is_admin, rpc};
+
+const SOL_ASSET_ID: &str = "sol-native";
+const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+#[derive(Deserialize)]
+pub struct ReconcileQuery {
+    #[serde(default)]
+    pub fix: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BalanceDiscrepancy {
+    /// `None` for tokens held on chain that have no asset in the store.
+    pub asset_id: Option<String>,
+    pub mint_address: String,
+    pub symbol: Option<String>,
+    pub store_amount: Decimal,
+    pub chain_amount: Decimal,
+    pub difference: Decimal,
+}
+
+#[derive(Serialize)]
+pub struct ReconcileResponse {
+    pub user_id: String,
+    pub public_key: String,
+    pub in_sync: bool,
+    pub fixed: bool,
+    pub discrepancies: Vec<BalanceDiscrepancy>,
+}
+
+/// Compare stored balances with on-chain ones (keyed by mint). Stored assets missing on chain
+/// count as zero on chain; chain tokens missing from the store count as zero in the store.
+fn diff_balances(
+    stored: &[BalanceWithDetails],
+    chain: &HashMap<String, Decimal>,
+) -> Vec<BalanceDiscrepancy> {
+    let mut discrepancies = Vec::new();
+
+    for balance in stored {
+        let chain_amount = chain.get(&balance.asset_mint_address).copied().unwrap_or(Decimal::ZERO);
+        if chain_amount != balance.amount {
+            discrepancies.push(BalanceDiscrepancy {
+                asset_id: Some(balance.asset_id.clone()),
+                mint_address: balance.asset_mint_address.clone(),
+                symbol: Some(balance.asset_symbol.clone()),
+                store_amount: balance.amount,
+                chain_amount,
+                difference: chain_amount - balance.amount,
+            });
+        }
+    }
+
+    let mut untracked: Vec<_> = chain.iter()
+        .filter(|(mint, amount)| {
+            !amount.is_zero() && !stored.iter().any(|b| &&b.asset_mint_address == mint)
+        })
+        .collect();
+    untracked.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (mint, amount) in untracked {
+        discrepancies.push(BalanceDiscrepancy {
+            asset_id: None,
+            mint_address: mint.clone(),
+            symbol: None,
+            store_amount: Decimal::ZERO,
+            chain_amount: *amount,
+            difference: *amount,
+        });
+    }
+
+    discrepancies
+}
+
+#[actix_web::post("/users/{user_id}/reconcile")]
+pub async fn reconcile_user(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ReconcileQuery>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let user_id = path.into_inner();
+
+    if query.fix && !is_admin(&http_req) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "fix=true requires an admin key"
+        })));
+    }
+
+    let user = match store.get_user_by_id(&user_id).await {
+        Ok(user) => user,
+        Err(store::error::UserError::UserNotFound) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "User not found"
+            })));
+        }
+        Err(e) => {
+            println!("Failed to get user {}: {:?}", user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to retrieve user"
+            })));
+        }
+    };
+
+    let public_key = match user.public_key {
+        Some(public_key) => public_key.into_inner(),
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "User has no wallet public key"
+            })));
+        }
+    };
+
+    let client = rpc::rpc_client();
+    let lamports = match rpc::get_sol_balance(&client, &public_key).await {
+        Ok(lamports) => lamports,
+        Err(e) => {
+            println!("Failed to fetch SOL balance for {}: {}", public_key, e);
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Failed to fetch on-chain SOL balance"
+            })));
+        }
+    };
+    let mut chain = match rpc::get_token_balances(&client, &public_key).await {
+        Ok(balances) => balances,
+        Err(e) => {
+            println!("Failed to fetch token balances for {}: {}", public_key, e);
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Failed to fetch on-chain token balances"
+            })));
+        }
+    };
+    // Native SOL is tracked under the wrapped SOL mint
+    *chain.entry(SOL_MINT.to_string()).or_insert(Decimal::ZERO) +=
+        Decimal::from(lamports) / Decimal::from(1_000_000_000u64);
+
+    let mut stored = match store.get_user_balances(&user_id).await {
+        Ok(balances) => balances,
+        Err(e) => {
+            println!("Failed to get balances for user {}: {:?}", user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to retrieve balances"
+            })));
+        }
+    };
+    // Always compare SOL, even if the user has no stored SOL row yet
+    let missing_sol = if stored.iter().any(|b| b.asset_id == SOL_ASSET_ID) {
+        None
+    } else {
+        store.get_asset_by_id(SOL_ASSET_ID).await.ok().flatten()
+    };
+    if let Some(sol) = missing_sol {
+        stored.push(BalanceWithDetails {
+            id: String::new(),
+            amount: Decimal::ZERO,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            user_id: user_id.clone(),
+            asset_id: sol.id,
+            asset_mint_address: sol.mint_address,
+            asset_name: sol.name,
+            asset_symbol: sol.symbol,
+            asset_decimals: sol.decimals,
+            asset_logo_url: sol.logo_url,
+        });
+    }
+
+    let discrepancies = diff_balances(&stored, &chain);
+
+    if query.fix {
+        for discrepancy in &discrepancies {
+            // Tokens without an asset row are reported only; register the asset first to fix them
+            let asset_id = match &discrepancy.asset_id {
+                Some(asset_id) => asset_id.clone(),
+                None => continue,
+            };
+
+            let update_request = store::balance::UpdateBalanceRequest {
+                user_id: user_id.clone(),
+                asset_id: asset_id.clone(),
+                amount: discrepancy.chain_amount,
+            };
+            if let Err(e) = store.reconcile_balance(update_request).await {
+                println!("Failed to correct {} balance for user {}: {:?}", asset_id, user_id, e);
+                return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to correct balance",
+                    "asset_id": asset_id
+                })));
+            }
+
+            println!("Reconciled {} balance for user {}: {} -> {}",
+                     asset_id, user_id, discrepancy.store_amount, discrepancy.chain_amount);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ReconcileResponse {
+        user_id,
+        public_key,
+        in_sync: discrepancies.is_empty(),
+        fixed: query.fix,
+        discrepancies,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(asset_id: &str, mint: &str, amount: i64) -> BalanceWithDetails {
+        BalanceWithDetails {
+            id: format!("balance-{}", asset_id),
+            amount: Decimal::from(amount),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            user_id: "user-1".to_string(),
+            asset_id: asset_id.to_string(),
+            asset_mint_address: mint.to_string(),
+            asset_name: asset_id.to_string(),
+            asset_symbol: asset_id.to_uppercase(),
+            asset_decimals: 9,
+            asset_logo_url: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_balances_reports_mismatches_and_untracked_tokens() {
+        let stored = vec![
+            stored("sol-native", SOL_MINT, 2),
+            stored("usdc-spl", "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", 10),
+        ];
+        let chain = HashMap::from([
+            (SOL_MINT.to_string(), Decimal::from(2)),
+            ("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(), Decimal::from(7)),
+            ("UntrackedMint1111111111111111111111111111111".to_string(), Decimal::from(5)),
+        ]);
+
+        let diff = diff_balances(&stored, &chain);
+
+        assert_eq!(diff.len(), 2);
+        assert_eq!(diff[0].asset_id.as_deref(), Some("usdc-spl"));
+        assert_eq!(diff[0].difference, Decimal::from(-3));
+        assert_eq!(diff[1].asset_id, None);
+        assert_eq!(diff[1].store_amount, Decimal::ZERO);
+        assert_eq!(diff[1].chain_amount, Decimal::from(5));
+    }
+
+    #[test]
+    fn test_diff_balances_in_sync() {
+        let stored = vec![stored("sol-native", SOL_MINT, 1)];
+        let chain = HashMap::from([(SOL_MINT.to_string(), Decimal::from(1))]);
+
+        assert!(diff_balances(&stored, &chain).is_empty());
+    }
+}
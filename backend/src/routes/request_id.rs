@@ -0,0 +1,72 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    Error, HttpMessage, HttpRequest,
+};
+
+/// Correlation id carried from the backend to the MPC service so one swap or send can be
+/// followed through every service's logs.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Keep a caller-supplied id if it is reasonable, otherwise mint a new one.
+fn accept_or_generate(provided: Option<&str>) -> String {
+    match provided {
+        Some(id) if !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LEN
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') => id.to_string(),
+        _ => uuid::Uuid::new_v4().to_string(),
+    }
+}
+
+/// Middleware: attach a request id to every request (stored in extensions) and echo it back.
+pub async fn propagate_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let provided = req.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok());
+    let id = accept_or_generate(provided);
+
+    println!("[{}] {} {}", id, req.method(), req.path());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut res = next.call(req).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+    }
+    Ok(res)
+}
+
+/// The id assigned by `propagate_request_id`, if the middleware ran.
+pub fn request_id(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<RequestId>().map(|id| id.0.clone())
+}
+
+/// Forward the request id on an outgoing call to another service.
+pub fn with_request_id(builder: reqwest::RequestBuilder, id: Option<&str>) -> reqwest::RequestBuilder {
+    match id {
+        Some(id) => builder.header(REQUEST_ID_HEADER, id),
+        None => builder,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_or_generate() {
+        assert_eq!(accept_or_generate(Some("swap-123_abc")), "swap-123_abc");
+
+        let generated = accept_or_generate(None);
+        assert!(uuid::Uuid::parse_str(&generated).is_ok());
+        assert_ne!(accept_or_generate(Some("bad id\nInjected: 1")), "bad id\nInjected: 1");
+        assert_ne!(accept_or_generate(Some(&"a".repeat(MAX_REQUEST_ID_LEN + 1))).len(), MAX_REQUEST_ID_LEN + 1);
+    }
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_millis(200);
+// Neither the backoff nor a server's Retry-After may hold a request longer than this
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Total attempts per outbound Jupiter call or MPC forward (`JUPITER_MAX_RETRIES`, at least 1).
+pub fn max_attempts_from_env() -> u32 {
+    std::env::var("JUPITER_MAX_RETRIES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS)
+        .max(1)
+}
+
+/// 429 and 5xx are worth another try; any other status is the server's real answer.
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// `Retry-After` in seconds; the HTTP-date form is ignored in favour of the backoff.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Delay before retry number `retry` (0-based): the server's `Retry-After` if it sent one,
+/// otherwise exponential backoff, both capped at `MAX_BACKOFF`.
+fn backoff_delay(retry: u32, retry_after: Option<Duration>) -> Duration {
+    retry_after
+        .unwrap_or_else(|| BASE_BACKOFF.saturating_mul(2u32.saturating_pow(retry)))
+        .min(MAX_BACKOFF)
+}
+
+/// Send the request built by `build`, retrying 429/5xx responses and connection errors up to
+/// `JUPITER_MAX_RETRIES` attempts. Returns the last response or error, so callers record a
+/// single outcome in their circuit breaker.
+pub async fn send_with_retry<F>(build: F) -> Result<reqwest::Response, reqwest::Error>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    send_with_attempts(max_attempts_from_env(), true, build).await
+}
+
+/// `send_with_retry` with a fixed number of attempts, for services other than Jupiter.
+pub async fn send_with_retries<F>(max_attempts: u32, build: F) -> Result<reqwest::Response, reqwest::Error>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    send_with_attempts(max_attempts, true, build).await
+}
+
+/// Like `send_with_retry`, but only a connection that never opened is retried. For requests
+/// that must not run twice, such as forwarding a transaction to the MPC service: a 5xx or 429
+/// may come after the transaction was signed and sent.
+pub async fn send_with_connect_retry<F>(build: F) -> Result<reqwest::Response, reqwest::Error>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    send_with_attempts(max_attempts_from_env(), false, build).await
+}
+
+async fn send_with_attempts<F>(max_attempts: u32, retry_statuses: bool, build: F) -> Result<reqwest::Response, reqwest::Error>
+where
+    F: Fn() -> reqwest::RequestBuilder,
+{
+    let mut retry = 0;
+    loop {
+        let result = build().send().await;
+        if retry + 1 >= max_attempts {
+            return result;
+        }
+
+        let delay = match &result {
+            Ok(response) if retry_statuses && is_retryable_status(response.status()) => {
+                println!("Upstream returned {}, retrying ({}/{})", response.status(), retry + 2, max_attempts);
+                backoff_delay(retry, retry_after(response))
+            }
+            Err(e) if e.is_connect() => {
+                println!("Upstream connection failed: {}, retrying ({}/{})", e, retry + 2, max_attempts);
+                backoff_delay(retry, None)
+            }
+            _ => return result,
+        };
+
+        actix_web::rt::time::sleep(delay).await;
+        retry += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    #[test]
+    fn test_backoff_doubles_and_honours_retry_after() {
+        assert_eq!(backoff_delay(0, None), Duration::from_millis(200));
+        assert_eq!(backoff_delay(1, None), Duration::from_millis(400));
+        assert_eq!(backoff_delay(10, None), MAX_BACKOFF);
+        assert_eq!(backoff_delay(0, Some(Duration::from_secs(2))), Duration::from_secs(2));
+        assert_eq!(backoff_delay(0, Some(Duration::from_secs(120))), MAX_BACKOFF);
+
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+    }
+
+    /// Mock upstream that answers 503 to the first `failures` requests, then 200.
+    async fn flaky_server(failures: usize) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let server = HttpServer::new(move || {
+            let counter = counter.clone();
+            App::new().route(
+                "/",
+                web::get().to(move || {
+                    let counter = counter.clone();
+                    async move {
+                        if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                            HttpResponse::ServiceUnavailable().insert_header(("Retry-After", "0")).finish()
+                        } else {
+                            HttpResponse::Ok().body("ok")
+                        }
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}/", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        (url, hits)
+    }
+
+    #[actix_web::test]
+    async fn test_retries_503_until_success() {
+        let (url, hits) = flaky_server(2).await;
+        let client = reqwest::Client::new();
+
+        let response = send_with_attempts(3, true, || client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_gives_up_after_max_attempts() {
+        let (url, hits) = flaky_server(5).await;
+        let client = reqwest::Client::new();
+
+        let response = send_with_attempts(2, true, || client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_connect_retry_returns_first_503() {
+        let (url, hits) = flaky_server(1).await;
+        let client = reqwest::Client::new();
+
+        // The upstream may have acted before answering 503, so it isn't asked again
+        let response = send_with_attempts(3, false, || client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rust_decimal::Decimal;
+
+use store::pubkey::{SolanaPubkey, SPL_TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID};
+
+pub fn rpc_url() -> String {
+    std::env::var("SOLANA_RPC_URL")
+        .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string())
+}
+
+/// Timeout for a single Solana JSON-RPC request.
+const RPC_TIMEOUT_SECS: u64 = 15;
+
+/// HTTP client for Solana JSON-RPC calls against `rpc_url()`.
+pub fn rpc_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(RPC_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+/// The full JSON-RPC response, including any `error` object.
+async fn rpc_request(
+    client: &reqwest::Client,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+
+    client
+        .post(rpc_url())
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("RPC request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Invalid RPC response: {}", e))
+}
+
+async fn rpc_call(
+    client: &reqwest::Client,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    let response = rpc_request(client, method, params).await?;
+
+    if let Some(error) = response.get("error") {
+        return Err(format!("RPC error: {}", error));
+    }
+
+    response.get("result").cloned().ok_or_else(|| "RPC response has no result".to_string())
+}
+
+/// Native SOL balance of `pubkey` in lamports.
+pub async fn get_sol_balance(client: &reqwest::Client, pubkey: &str) -> Result<u64, String> {
+    let result = rpc_call(client, "getBalance", serde_json::json!([pubkey])).await?;
+
+    result.get("value")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| "getBalance returned no value".to_string())
+}
+
+/// Token balances owned by `owner` under both SPL Token and Token-2022, summed per mint, in
+/// whole tokens.
+pub async fn get_token_balances(client: &reqwest::Client, owner: &str) -> Result<HashMap<String, Decimal>, String> {
+    let mut balances = HashMap::new();
+
+    for program_id in [SPL_TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID] {
+        let result = rpc_call(
+            client,
+            "getTokenAccountsByOwner",
+            serde_json::json!([
+                owner,
+                { "programId": program_id },
+                { "encoding": "jsonParsed" }
+            ]),
+        ).await?;
+        add_token_accounts(&mut balances, &result)?;
+    }
+
+    Ok(balances)
+}
+
+/// Add the accounts in a `getTokenAccountsByOwner` result to `balances`, per mint. An account
+/// the RPC couldn't parse is an error rather than a zero balance: callers use these balances to
+/// decide a wallet is empty, and an unknown account may hold funds.
+pub(super) fn add_token_accounts(balances: &mut HashMap<String, Decimal>, result: &serde_json::Value) -> Result<(), String> {
+    let accounts = result.get("value").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+
+    for account in accounts {
+        let info = &account["account"]["data"]["parsed"]["info"];
+        let unreadable = || format!("Unreadable token account {}", account["pubkey"].as_str().unwrap_or("(unknown)"));
+        let mint = info.get("mint").and_then(|v| v.as_str()).ok_or_else(unreadable)?.to_string();
+        let amount = info["tokenAmount"].get("amount").and_then(|v| v.as_str()).ok_or_else(unreadable)?;
+        let decimals = info["tokenAmount"].get("decimals").and_then(|v| v.as_u64()).ok_or_else(unreadable)?;
+
+        let raw: Decimal = amount.parse().map_err(|_| format!("Invalid token amount for mint {}", mint))?;
+        let mut ui_amount = raw;
+        ui_amount.set_scale(decimals as u32).map_err(|_| format!("Invalid decimals for mint {}", mint))?;
+
+        *balances.entry(mint).or_insert(Decimal::ZERO) += ui_amount;
+    }
+
+    Ok(())
+}
+
+/// JSON-RPC "invalid params" code, returned e.g. when a token account does not exist.
+const RPC_INVALID_PARAMS: i64 = -32602;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenAccountBalance {
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+/// Balance of an SPL token account, or `None` if the account does not exist.
+pub async fn get_token_account_balance(client: &reqwest::Client, token_account: &str) -> Result<Option<TokenAccountBalance>, String> {
+    let response = rpc_request(client, "getTokenAccountBalance", serde_json::json!([token_account])).await?;
+
+    if let Some(error) = response.get("error") {
+        if error.get("code").and_then(|c| c.as_i64()) == Some(RPC_INVALID_PARAMS) {
+            return Ok(None);
+        }
+        return Err(format!("RPC error: {}", error));
+    }
+
+    let value = &response["result"]["value"];
+    let amount = value.get("amount")
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| "getTokenAccountBalance returned no amount".to_string())?;
+    let decimals = value.get("decimals")
+        .and_then(|v| v.as_u64())
+        .and_then(|v| u8::try_from(v).ok())
+        .ok_or_else(|| "getTokenAccountBalance returned no decimals".to_string())?;
+
+    Ok(Some(TokenAccountBalance { amount, decimals }))
+}
+
+/// What the chain says about a mint: its decimals, and the name and symbol when the mint
+/// carries Token-2022 metadata or has a Metaplex metadata account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MintMetadata {
+    pub decimals: u8,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MintMetadataError {
+    /// The account is missing or is not an SPL token mint.
+    NotAMint(String),
+    /// The RPC could not be reached or returned something unusable.
+    Rpc(String),
+}
+
+/// Read the mint account for its decimals, then look up the name and symbol. A missing
+/// Metaplex account is not an error; the name and symbol are just left unset.
+pub async fn fetch_mint_metadata(client: &reqwest::Client, mint: &SolanaPubkey) -> Result<MintMetadata, MintMetadataError> {
+    let result = rpc_call(
+        client,
+        "getAccountInfo",
+        serde_json::json!([mint.as_str(), { "encoding": "jsonParsed" }]),
+    ).await.map_err(MintMetadataError::Rpc)?;
+
+    let mut metadata = parse_mint_account(&result["value"])?;
+    if metadata.name.is_none() && metadata.symbol.is_none() {
+        match fetch_metaplex_name_symbol(client, mint).await {
+            Ok(Some((name, symbol))) => {
+                metadata.name = Some(name);
+                metadata.symbol = Some(symbol);
+            }
+            Ok(None) => {}
+            Err(e) => println!("Failed to read Metaplex metadata for mint {}: {}", mint, e),
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Decimals (and Token-2022 name/symbol, if any) from a `jsonParsed` account value.
+fn parse_mint_account(value: &serde_json::Value) -> Result<MintMetadata, MintMetadataError> {
+    if value.is_null() {
+        return Err(MintMetadataError::NotAMint("account does not exist".to_string()));
+    }
+
+    let parsed = &value["data"]["parsed"];
+    if parsed.get("type").and_then(|v| v.as_str()) != Some("mint") {
+        return Err(MintMetadataError::NotAMint("account is not an SPL token mint".to_string()));
+    }
+
+    let info = &parsed["info"];
+    let decimals = info.get("decimals")
+        .and_then(|v| v.as_u64())
+        .and_then(|v| u8::try_from(v).ok())
+        .ok_or_else(|| MintMetadataError::NotAMint("mint has no decimals".to_string()))?;
+
+    let token_metadata = info.get("extensions")
+        .and_then(|v| v.as_array())
+        .and_then(|extensions| extensions.iter().find(|e| e["extension"] == "tokenMetadata"))
+        .map(|extension| &extension["state"]);
+    let field = |name: &str| token_metadata
+        .and_then(|state| state.get(name))
+        .and_then(|v| v.as_str())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    Ok(MintMetadata { decimals, name: field("name"), symbol: field("symbol") })
+}
+
+/// The token program that owns `mint`, under which its holders' associated token accounts are
+/// derived. Only SPL Token and Token-2022 mints are accepted.
+pub async fn fetch_mint_token_program(client: &reqwest::Client, mint: &SolanaPubkey) -> Result<SolanaPubkey, MintMetadataError> {
+    let result = rpc_call(
+        client,
+        "getAccountInfo",
+        serde_json::json!([mint.as_str(), { "encoding": "jsonParsed" }]),
+    ).await.map_err(MintMetadataError::Rpc)?;
+
+    parse_mint_token_program(&result["value"])
+}
+
+fn parse_mint_token_program(value: &serde_json::Value) -> Result<SolanaPubkey, MintMetadataError> {
+    parse_mint_account(value)?;
+
+    match value["data"].get("program").and_then(|v| v.as_str()) {
+        Some("spl-token") | Some("spl-token-2022") => {}
+        _ => return Err(MintMetadataError::NotAMint("mint is not owned by SPL Token or Token-2022".to_string())),
+    }
+    value.get("owner")
+        .and_then(|v| v.as_str())
+        .and_then(|owner| owner.parse().ok())
+        .ok_or_else(|| MintMetadataError::NotAMint("mint account has no owner".to_string()))
+}
+
+async fn fetch_metaplex_name_symbol(client: &reqwest::Client, mint: &SolanaPubkey) -> Result<Option<(String, String)>, String> {
+    let result = rpc_call(
+        client,
+        "getAccountInfo",
+        serde_json::json!([mint.metadata_address().as_str(), { "encoding": "base64" }]),
+    ).await?;
+
+    let encoded = match result["value"]["data"].get(0).and_then(|v| v.as_str()) {
+        Some(encoded) => encoded,
+        None => return Ok(None),
+    };
+    let data = STANDARD.decode(encoded).map_err(|e| format!("Invalid account data: {}", e))?;
+    Ok(parse_metaplex_name_symbol(&data))
+}
+
+/// Name and symbol from a Metaplex metadata account: a key byte, the update authority and
+/// mint, then Borsh strings that the program pads with NULs.
+fn parse_metaplex_name_symbol(data: &[u8]) -> Option<(String, String)> {
+    fn borsh_string(data: &[u8], offset: &mut usize) -> Option<String> {
+        let len_bytes = data.get(*offset..*offset + 4)?;
+        let len = u32::from_le_bytes(len_bytes.try_into().ok()?) as usize;
+        let bytes = data.get(*offset + 4..*offset + 4 + len)?;
+        *offset += 4 + len;
+        Some(String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string())
+    }
+
+    let mut offset = 1 + 32 + 32;
+    let name = borsh_string(data, &mut offset)?;
+    let symbol = borsh_string(data, &mut offset)?;
+    (!symbol.is_empty()).then_some((name, symbol))
+}
+
+/// Raw `getSignatureStatuses` entries, one per signature and `null` for unknown ones.
+/// The RPC accepts at most 256 signatures per call.
+pub async fn get_signature_statuses(client: &reqwest::Client, signatures: &[String]) -> Result<Vec<serde_json::Value>, String> {
+    let result = rpc_call(
+        client,
+        "getSignatureStatuses",
+        serde_json::json!([signatures, { "searchTransactionHistory": true }]),
+    ).await?;
+
+    result.get("value")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .ok_or_else(|| "getSignatureStatuses returned no value".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn borsh_string(value: &str, padded_len: usize) -> Vec<u8> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize(padded_len, 0);
+        let mut encoded = (padded_len as u32).to_le_bytes().to_vec();
+        encoded.extend(bytes);
+        encoded
+    }
+
+    #[test]
+    fn test_parse_metaplex_name_symbol_strips_padding() {
+        let mut data = vec![4u8];
+        data.extend([0u8; 64]);
+        data.extend(borsh_string("Bonk", 32));
+        data.extend(borsh_string("BONK", 10));
+        data.extend(borsh_string("https://example.com", 200));
+
+        assert_eq!(parse_metaplex_name_symbol(&data), Some(("Bonk".to_string(), "BONK".to_string())));
+        assert_eq!(parse_metaplex_name_symbol(&data[..70]), None);
+    }
+
+    fn token_accounts(accounts: &[(&str, &str, &str, u64)]) -> serde_json::Value {
+        let value: Vec<serde_json::Value> = accounts.iter().map(|(program, mint, amount, decimals)| serde_json::json!({
+            "account": { "data": { "program": program, "parsed": { "type": "account", "info": {
+                "mint": mint,
+                "tokenAmount": { "amount": amount, "decimals": decimals }
+            } } } }
+        })).collect();
+        serde_json::json!({ "value": value })
+    }
+
+    #[test]
+    fn test_token_accounts_of_both_programs_merge_per_mint() {
+        const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        const PYUSD: &str = "2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo";
+
+        let mut balances = HashMap::new();
+        add_token_accounts(&mut balances, &token_accounts(&[("spl-token", USDC, "1500000", 6)])).unwrap();
+        add_token_accounts(&mut balances, &token_accounts(&[
+            ("spl-token-2022", PYUSD, "2500000", 6),
+            ("spl-token-2022", USDC, "500000", 6),
+        ])).unwrap();
+
+        assert_eq!(balances.get(USDC), Some(&Decimal::new(2, 0)));
+        assert_eq!(balances.get(PYUSD), Some(&Decimal::new(25, 1)));
+
+        // An account the RPC returned unparsed may hold anything
+        let unparsed = serde_json::json!({ "value": [{
+            "pubkey": "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin",
+            "account": { "data": ["AAAA", "base64"] }
+        }] });
+        assert!(add_token_accounts(&mut balances, &unparsed).is_err());
+    }
+
+    #[test]
+    fn test_parse_mint_account() {
+        let mint = serde_json::json!({
+            "data": { "parsed": { "type": "mint", "info": { "decimals": 5, "supply": "100" } } }
+        });
+        assert_eq!(parse_mint_account(&mint), Ok(MintMetadata { decimals: 5, name: None, symbol: None }));
+
+        let token_2022 = serde_json::json!({
+            "data": { "parsed": { "type": "mint", "info": {
+                "decimals": 6,
+                "extensions": [{ "extension": "tokenMetadata", "state": { "name": "Paypal USD", "symbol": "PYUSD" } }]
+            } } }
+        });
+        let metadata = parse_mint_account(&token_2022).unwrap();
+        assert_eq!(metadata.symbol.as_deref(), Some("PYUSD"));
+        assert_eq!(metadata.name.as_deref(), Some("Paypal USD"));
+
+        let token_account = serde_json::json!({ "data": { "parsed": { "type": "account", "info": {} } } });
+        assert!(matches!(parse_mint_account(&token_account), Err(MintMetadataError::NotAMint(_))));
+        assert!(matches!(parse_mint_account(&serde_json::Value::Null), Err(MintMetadataError::NotAMint(_))));
+    }
+
+    #[test]
+    fn test_parse_mint_token_program() {
+        let mint = |program: &str, owner: &str| serde_json::json!({
+            "owner": owner,
+            "data": { "program": program, "parsed": { "type": "mint", "info": { "decimals": 6 } } }
+        });
+
+        let spl = parse_mint_token_program(&mint("spl-token", SPL_TOKEN_PROGRAM_ID)).unwrap();
+        assert_eq!(spl.as_str(), SPL_TOKEN_PROGRAM_ID);
+
+        // A Token-2022 mint's accounts are derived under its own program
+        let token_2022_program = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin";
+        let token_2022 = parse_mint_token_program(&mint("spl-token-2022", token_2022_program)).unwrap();
+        assert_eq!(token_2022.as_str(), token_2022_program);
+
+        assert!(matches!(
+            parse_mint_token_program(&mint("some-other-token", SPL_TOKEN_PROGRAM_ID)),
+            Err(MintMetadataError::NotAMint(_))
+        ));
+        assert!(matches!(parse_mint_token_program(&serde_json::Value::Null), Err(MintMetadataError::NotAMint(_))));
+    }
+
+    /// Devnet USDC, a 6-decimal mint.
+    #[cfg(feature = "network-tests")]
+    #[actix_web::test]
+    async fn test_fetch_mint_metadata_from_devnet() {
+        let mint: SolanaPubkey = "4zMMC9srt5Ri5X14GAgXhaHii3GnPAEERYPJgZJDncDU".parse().unwrap();
+        let metadata = fetch_mint_metadata(&rpc_client(), &mint).await.unwrap();
+        assert_eq!(metadata.decimals, 6);
+    }
+}
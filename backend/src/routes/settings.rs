@@ -0,0 +1,61 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use store::{error::UserError, settings::UpdateSettingsRequest, Store};
+
+use super::auth::authenticated_user;
+
+#[actix_web::get("/user/settings")]
+pub async fn get_user_settings(
+    http_req: HttpRequest,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let user_id = match authenticated_user(&http_req) {
+        Some(user_id) => user_id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Missing or invalid bearer token"
+            })));
+        }
+    };
+
+    match store.get_settings(&user_id).await {
+        Ok(settings) => Ok(HttpResponse::Ok().json(settings)),
+        Err(e) => {
+            println!("Failed to load settings for user {}: {}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load settings"
+            })))
+        }
+    }
+}
+
+#[actix_web::put("/user/settings")]
+pub async fn update_user_settings(
+    http_req: HttpRequest,
+    req: web::Json<UpdateSettingsRequest>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let user_id = match authenticated_user(&http_req) {
+        Some(user_id) => user_id,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Missing or invalid bearer token"
+            })));
+        }
+    };
+
+    match store.update_settings(&user_id, req.into_inner()).await {
+        Ok(settings) => {
+            println!("Updated settings for user {}", user_id);
+            Ok(HttpResponse::Ok().json(settings))
+        }
+        Err(UserError::InvalidInput(msg)) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": msg
+        }))),
+        Err(e) => {
+            println!("Failed to update settings for user {}: {}", user_id, e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update settings"
+            })))
+        }
+    }
+}
@@ -1,23 +1,44 @@
-use std::sync::Arc;
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
-use store::Store;
-use tokio::sync::Mutex;
+use store::{ledger::LedgerReason, pubkey::SolanaPubkey, Store};
 use rust_decimal::Decimal;
 
+use super::amount::{AmountView, SOL_DECIMALS};
+use super::feature_flags::{operation_disabled, Operation};
+use super::request_id::{request_id, with_request_id};
+use super::idempotency::{idempotent, NOT_ATTEMPTED_HEADER};
+use super::mpc_client::{mpc_client, mpc_service_url, read_mpc_reply, record_mpc_outcome, MpcReply, MPC_BREAKER};
+use super::unresolved::{record_unresolved, unresolved_response, AssetAmount, UnresolvedPayload};
+use super::rpc::{fetch_mint_token_program, get_sol_balance, get_token_account_balance, rpc_client, MintMetadataError};
+
 #[derive(Serialize)]
 pub struct BalanceResponse {
+    pub pubkey: String,
+    pub lamports: u64,
+    pub sol: Decimal,
 }
 
 #[derive(Serialize)]
 pub struct TokenBalanceResponse {
+    pub owner: String,
+    pub mint: String,
+    pub token_account: String,
+    /// False when the associated token account has not been created; the balance is zero.
+    pub account_exists: bool,
+    /// Unknown when the account does not exist.
+    pub decimals: Option<u8>,
+    #[serde(flatten)]
+    pub amount: AmountView,
 }
 
 #[derive(Deserialize)]
 pub struct SendSolRequest {
     pub user_id: String,
-    pub to: String,
+    pub to: SolanaPubkey,
     pub lamports: u64,
+    /// Spend into the minimum SOL reserve. Off by default so fees stay payable.
+    #[serde(default)]
+    pub allow_below_reserve: bool,
 }
 
 #[derive(Deserialize)]
@@ -33,30 +54,171 @@ pub struct SendSolResponse {
     pub error: Option<String>,
 }
 
+/// Default SOL kept back from transfers so the user can still pay fees afterwards.
+const DEFAULT_MIN_SOL_RESERVE: &str = "0.01";
+
+/// Global minimum SOL reserve, read from `MIN_SOL_RESERVE` (in SOL).
+pub fn min_sol_reserve() -> Decimal {
+    std::env::var("MIN_SOL_RESERVE")
+        .ok()
+        .and_then(|v| v.parse::<Decimal>().ok())
+        .unwrap_or_else(|| DEFAULT_MIN_SOL_RESERVE.parse().unwrap_or_default())
+}
+
+/// Whether spending `amount` out of `balance` would leave less than `reserve` behind.
+pub fn breaches_reserve(balance: Decimal, amount: Decimal, reserve: Decimal) -> bool {
+    balance - amount < reserve
+}
+
+/// Debit a confirmed transaction's network fee from the user's SOL balance and record it
+/// as its own ledger entry, referencing the transaction, so the ledger reconciles with the
+/// on-chain balance. Returns the SOL balance after the fee, if it was charged.
+pub async fn charge_network_fee(store: &Store, user_id: &str, fee_lamports: u64, signature: Option<&str>) -> Option<Decimal> {
+    const SOL_ASSET_ID: &str = "sol-native";
+
+    if fee_lamports == 0 {
+        return None;
+    }
+    let fee = Decimal::from(fee_lamports) / Decimal::from(1_000_000_000u64);
+
+    // A conditional debit, so a concurrent send or refund isn't overwritten by a stale read
+    let updated = match store.try_debit_balance(user_id, SOL_ASSET_ID, fee, LedgerReason::Fee, signature).await {
+        Ok((updated, _)) => updated,
+        Err(store::error::UserError::InsufficientBalance) => {
+            println!("User {} has too little SOL to charge a {} SOL fee against", user_id, fee);
+            return None;
+        }
+        Err(e) => {
+            println!("Failed to charge network fee for user {}: {}", user_id, e);
+            return None;
+        }
+    };
+
+    Some(updated.amount)
+}
+
+/// Give back a debit taken before an operation that then failed. Credits the amount rather
+/// than restoring the earlier balance, which would undo any change made in between.
+pub async fn refund_debit(
+    store: &Store,
+    user_id: &str,
+    asset_id: &str,
+    amount: Decimal,
+    ledger_reason: LedgerReason,
+    signature: Option<&str>,
+    reason: &str,
+) {
+    match store.credit_balance(user_id, asset_id, amount, ledger_reason, signature).await {
+        Ok(_) => println!("Refunded {} of {} to user {} due to {}", amount, asset_id, user_id, reason),
+        Err(e) => println!("CRITICAL: Failed to refund {} of {} to user {}: {}", amount, asset_id, user_id, e),
+    }
+}
+
 #[actix_web::get("/sol-balance/{pubkey}")]
-pub async fn sol_balance() -> Result<HttpResponse> {
-    
+pub async fn sol_balance(path: web::Path<String>) -> Result<HttpResponse> {
+    let pubkey: SolanaPubkey = match path.into_inner().parse() {
+        Ok(pubkey) => pubkey,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+
+    let lamports = match get_sol_balance(&rpc_client(), pubkey.as_str()).await {
+        Ok(lamports) => lamports,
+        Err(e) => {
+            println!("Failed to fetch SOL balance for {}: {}", pubkey, e);
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Failed to fetch balance from Solana RPC"
+            })));
+        }
+    };
+
     let response = BalanceResponse {
+        pubkey: pubkey.into_inner(),
+        lamports,
+        sol: Decimal::from(lamports) / Decimal::from(1_000_000_000u64),
     };
     
     Ok(HttpResponse::Ok().json(response))
 }
 
 #[actix_web::get("/token-balance/{pubkey}/{mint}")]
-pub async fn token_balance() -> Result<HttpResponse> {    
-    
+pub async fn token_balance(path: web::Path<(String, String)>) -> Result<HttpResponse> {
+    let (owner, mint) = path.into_inner();
+    let (owner, mint) = match (owner.parse::<SolanaPubkey>(), mint.parse::<SolanaPubkey>()) {
+        (Ok(owner), Ok(mint)) => (owner, mint),
+        (Err(e), _) | (_, Err(e)) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": e.to_string()
+            })));
+        }
+    };
+
+    let client = rpc_client();
+    let token_program = match fetch_mint_token_program(&client, &mint).await {
+        Ok(token_program) => token_program,
+        Err(MintMetadataError::NotAMint(reason)) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("{} is not a token mint: {}", mint, reason)
+            })));
+        }
+        Err(MintMetadataError::Rpc(e)) => {
+            println!("Failed to fetch mint {}: {}", mint, e);
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Failed to fetch mint from Solana RPC"
+            })));
+        }
+    };
+
+    let token_account = owner.associated_token_address(&mint, &token_program);
+    let balance = match get_token_account_balance(&client, token_account.as_str()).await {
+        Ok(balance) => balance,
+        Err(e) => {
+            println!("Failed to fetch {} balance for {}: {}", mint, owner, e);
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Failed to fetch token balance from Solana RPC"
+            })));
+        }
+    };
+
     let response = TokenBalanceResponse {
-        
+        owner: owner.into_inner(),
+        mint: mint.into_inner(),
+        token_account: token_account.into_inner(),
+        account_exists: balance.is_some(),
+        decimals: balance.as_ref().map(|b| b.decimals),
+        amount: match &balance {
+            Some(b) => AmountView::from_base_units(b.amount, b.decimals as i32),
+            None => AmountView::from_base_units(0, 0),
+        },
     };
     
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// A repeated `Idempotency-Key` replays the first response instead of sending again.
 #[actix_web::post("/send-sol")]
 pub async fn send_sol(
+    http_req: HttpRequest,
     req: web::Json<SendSolRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
+    let user_id = req.user_id.clone();
+    idempotent(&http_req, &store, &user_id, "send_sol", || execute_send_sol(http_req.clone(), req, store.clone())).await
+}
+
+async fn execute_send_sol(
+    http_req: HttpRequest,
+    req: web::Json<SendSolRequest>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if let Some(response) = operation_disabled(Operation::SendSol) {
+        return Ok(response);
+    }
+
+    let request_id = request_id(&http_req);
     println!("Processing SOL transfer request for user: {}", req.user_id);
     
     // SOL asset ID 
@@ -65,11 +227,22 @@ pub async fn send_sol(
     // Convert lamports to SOL (1 SOL = 1_000_000_000 lamports)
     let sol_amount = Decimal::from(req.lamports) / Decimal::from(1_000_000_000u64);
     
+    // Fail fast during an MPC outage rather than debiting and rolling back
+    if !MPC_BREAKER.allow_request() {
+        return Ok(HttpResponse::ServiceUnavailable().insert_header((NOT_ATTEMPTED_HEADER, "true")).json(serde_json::json!({
+            "success": false,
+            "error": "MPC service is temporarily unavailable, please retry shortly",
+            "transaction_signature": null,
+            "from_address": "unknown",
+            "to_address": req.to,
+            "amount_lamports": req.lamports
+        })));
+    }
+
     // Check user's SOL balance and decrease it
-    let store_guard = store.lock().await;
     
     // Get current balance
-    let current_balance = match store_guard.get_balance(&req.user_id, SOL_ASSET_ID).await {
+    let current_balance = match store.get_balance(&req.user_id, SOL_ASSET_ID).await {
         Ok(Some(balance)) => balance,
         Ok(None) => {
             return Ok(HttpResponse::BadRequest().json(serde_json::json!({
@@ -107,16 +280,41 @@ pub async fn send_sol(
         })));
     }
     
-    // decrease the balance first 
-    let new_balance = current_balance.amount - sol_amount;
-    let update_request = store::balance::UpdateBalanceRequest {
-        user_id: req.user_id.clone(),
-        asset_id: SOL_ASSET_ID.to_string(),
-        amount: new_balance,
+    // A per-user reserve from settings takes precedence over the global default
+    let reserve = match store.get_settings(&req.user_id).await {
+        Ok(settings) => settings.min_sol_reserve.unwrap_or_else(min_sol_reserve),
+        Err(e) => {
+            println!("Failed to load settings for user {}, using default reserve: {}", req.user_id, e);
+            min_sol_reserve()
+        }
     };
-    
-    let updated_balance = match store_guard.update_balance(update_request).await {
-        Ok(balance) => balance,
+    if !req.allow_below_reserve && breaches_reserve(current_balance.amount, sol_amount, reserve) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "error": format!("Transfer would leave {} SOL, below the minimum reserve of {} SOL. Set allow_below_reserve to override.",
+                           current_balance.amount - sol_amount, reserve),
+            "transaction_signature": null,
+            "from_address": "unknown",
+            "to_address": req.to,
+            "amount_lamports": req.lamports
+        })));
+    }
+
+    // Debit in one conditional update so a concurrent send can't spend the same funds or
+    // take the balance below the reserve the check above allowed for
+    let kept_reserve = if req.allow_below_reserve { Decimal::ZERO } else { reserve };
+    let (updated_balance, debit_entry) = match store.try_debit_balance_keeping(&req.user_id, SOL_ASSET_ID, sol_amount, kept_reserve, LedgerReason::Withdrawal, None).await {
+        Ok(debited) => debited,
+        Err(store::error::UserError::InsufficientBalance) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false,
+                "error": format!("Insufficient balance. Required: {} SOL plus a {} SOL reserve", sol_amount, kept_reserve),
+                "transaction_signature": null,
+                "from_address": "unknown",
+                "to_address": req.to,
+                "amount_lamports": req.lamports
+            })));
+        }
         Err(e) => {
             println!("Failed to update balance: {}", e);
             return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
@@ -129,18 +327,14 @@ pub async fn send_sol(
             })));
         }
     };
+    let new_balance = updated_balance.amount;
     
-    println!("Updated user {} balance from {} to {} SOL", 
-             req.user_id, current_balance.amount, updated_balance.amount);
-    
-    // release the store lock before making external call
-    drop(store_guard);
+    println!("Debited {} SOL from user {}, {} SOL remaining", 
+             sol_amount, req.user_id, new_balance);
     
     // forward the request to MPC service for secure key aggregation and transaction signing
-    let mpc_service_url = std::env::var("MPC_SIMPLE_URL")
-        .unwrap_or_else(|_| "http://127.0.0.1:8081".to_string());
-    
-    let client = reqwest::Client::new();
+    let mpc_service_url = mpc_service_url();
+    let client = mpc_client();
     
     // Prepare the request for MPC service
     let mpc_request = serde_json::json!({
@@ -150,31 +344,22 @@ pub async fn send_sol(
     });
     
     // Send request to MPC service
-    let mpc_response = match client
-        .post(format!("{}/api/send-sol", mpc_service_url))
+    let mpc_send = with_request_id(client.post(format!("{}/api/send-sol", mpc_service_url)), request_id.as_deref())
         .json(&mpc_request)
         .send()
-        .await
-    {
-        Ok(response) => response,
-        Err(e) => {
+        .await;
+    record_mpc_outcome(&mpc_send);
+
+    // Refund only when the transaction certainly wasn't sent; an unknown outcome keeps the
+    // debit until the signature's status settles it
+    let mpc_result = match read_mpc_reply(mpc_send).await {
+        MpcReply::Answered(result) => result,
+        MpcReply::NotSent(e) => {
             println!("Failed to connect to MPC service: {}", e);
             
-            // Rollback balance change
-            let store_guard = store.lock().await;
-            let rollback_request = store::balance::UpdateBalanceRequest {
-                user_id: req.user_id.clone(),
-                asset_id: SOL_ASSET_ID.to_string(),
-                amount: current_balance.amount, // Restore original balance
-            };
-            
-            if let Err(rollback_err) = store_guard.update_balance(rollback_request).await {
-                println!("CRITICAL: Failed to rollback balance for user {}: {}", req.user_id, rollback_err);
-            } else {
-                println!("Rolled back balance for user {} due to MPC service failure", req.user_id);
-            }
+            refund_debit(&store, &req.user_id, SOL_ASSET_ID, sol_amount, LedgerReason::Withdrawal, None, "MPC service failure").await;
             
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
                 "success": false,
                 "error": "Failed to connect to MPC service",
                 "transaction_signature": null,
@@ -183,66 +368,35 @@ pub async fn send_sol(
                 "amount_lamports": req.lamports
             })));
         }
-    };
-    
-    // check if MPC service request was successful
-    if !mpc_response.status().is_success() {
-        let error_text = mpc_response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        println!("MPC service returned error: {}", error_text);
-        
-        // Rollback balance change
-        let store_guard = store.lock().await;
-        let rollback_request = store::balance::UpdateBalanceRequest {
-            user_id: req.user_id.clone(),
-            asset_id: SOL_ASSET_ID.to_string(),
-            amount: current_balance.amount, // Restore original balance
-        };
-        
-        if let Err(rollback_err) = store_guard.update_balance(rollback_request).await {
-            println!("CRITICAL: Failed to rollback balance for user {}: {}", req.user_id, rollback_err);
-        } else {
-            println!("Rolled back balance for user {} due to MPC service error", req.user_id);
-        }
-        
-        return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-            "success": false,
-            "error": format!("MPC service error: {}", error_text),
-            "transaction_signature": null,
-            "from_address": "unknown", 
-            "to_address": req.to,
-            "amount_lamports": req.lamports
-        })));
-    }
-    
-    // Parse and forward the MPC service response
-    let mpc_result: serde_json::Value = match mpc_response.json().await {
-        Ok(result) => result,
-        Err(e) => {
-            println!("Failed to parse MPC service response: {}", e);
+        MpcReply::Rejected(_, error_text) => {
+            println!("MPC service returned error: {}", error_text);
             
-            // Rollback balance change
-            let store_guard = store.lock().await;
-            let rollback_request = store::balance::UpdateBalanceRequest {
-                user_id: req.user_id.clone(),
-                asset_id: SOL_ASSET_ID.to_string(),
-                amount: current_balance.amount, // Restore original balance
-            };
-            
-            if let Err(rollback_err) = store_guard.update_balance(rollback_request).await {
-                println!("CRITICAL: Failed to rollback balance for user {}: {}", req.user_id, rollback_err);
-            } else {
-                println!("Rolled back balance for user {} due to response parsing failure", req.user_id);
-            }
+            refund_debit(&store, &req.user_id, SOL_ASSET_ID, sol_amount, LedgerReason::Withdrawal, None, "MPC service error").await;
             
             return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
                 "success": false,
-                "error": "Failed to parse MPC service response",
+                "error": format!("MPC service error: {}", error_text),
                 "transaction_signature": null,
-                "from_address": "unknown",
+                "from_address": "unknown", 
                 "to_address": req.to,
                 "amount_lamports": req.lamports
             })));
         }
+        MpcReply::Unknown(e) => {
+            println!("No usable answer from MPC service for user {}: {}", req.user_id, e);
+
+            let payload = UnresolvedPayload {
+                reason: LedgerReason::Withdrawal,
+                debit_entry_id: Some(debit_entry.id.clone()),
+                refund: Some(AssetAmount { asset_id: SOL_ASSET_ID.to_string(), amount: sol_amount }),
+                owed: None,
+                signature: None,
+                request_id: request_id.clone(),
+                error: e,
+            };
+            let operation_id = record_unresolved(&store, &req.user_id, "send_sol", &payload).await;
+            return Ok(unresolved_response(operation_id.as_deref(), "No usable answer from the MPC service"));
+        }
     };
     
     // Check if the actual transaction was successful
@@ -251,34 +405,50 @@ pub async fn send_sol(
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
     
-    if !transaction_success {
-        // Transaction failed, rollback the balance
-        let store_guard = store.lock().await;
-        let rollback_request = store::balance::UpdateBalanceRequest {
-            user_id: req.user_id.clone(),
-            asset_id: SOL_ASSET_ID.to_string(),
-            amount: current_balance.amount, // Restore original balance
-        };
-        
-        if let Err(rollback_err) = store_guard.update_balance(rollback_request).await {
-            println!("CRITICAL: Failed to rollback balance for user {}: {}", req.user_id, rollback_err);
-        } else {
-            println!("Rolled back balance for user {} due to transaction failure", req.user_id);
+    let fee_lamports = mpc_result.get("fee_lamports").and_then(|v| v.as_u64());
+    let signature = mpc_result.get("transaction_signature").and_then(|v| v.as_str());
+
+    // The debit was taken before signing; now tie it to the transaction
+    if let Some(signature) = signature {
+        if let Err(e) = store.set_ledger_reference(&debit_entry.id, signature).await {
+            println!("Failed to record signature {} on ledger entry {}: {}", signature, debit_entry.id, e);
         }
+    }
+    
+    if !transaction_success {
+        // Transaction failed, refund the debit
+        refund_debit(&store, &req.user_id, SOL_ASSET_ID, sol_amount, LedgerReason::Withdrawal, signature, "transaction failure").await;
     } else {
         println!("SOL transfer completed successfully for user {}: {} lamports sent", 
                  req.user_id, req.lamports);
         println!("User {} balance updated: {} SOL remaining", req.user_id, new_balance);
     }
+
+    // A transaction that landed pays its fee even if it failed on-chain
+    if let Some(fee_lamports) = fee_lamports {
+        charge_network_fee(&store, &req.user_id, fee_lamports, signature).await;
+    }
+
+    let mut response = mpc_result;
+    if let Some(fields) = response.as_object_mut() {
+        fields.insert("amount".to_string(), serde_json::json!(AmountView::from_base_units(req.lamports, SOL_DECIMALS)));
+        if transaction_success {
+            fields.insert("balance_after".to_string(), serde_json::json!(AmountView::new(new_balance, SOL_DECIMALS)));
+        }
+    }
     
-    Ok(HttpResponse::Ok().json(mpc_result))
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[actix_web::post("/add-sol-balance")]
 pub async fn add_sol_balance(
     req: web::Json<AddBalanceRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
+    if let Some(response) = operation_disabled(Operation::AddSolBalance) {
+        return Ok(response);
+    }
+
     println!("Adding SOL balance for user: {}", req.user_id);
     
     // SOL asset ID (native Solana)
@@ -287,7 +457,6 @@ pub async fn add_sol_balance(
     // Convert lamports to SOL (1 SOL = 1_000_000_000 lamports)
     let sol_amount = Decimal::from(req.lamports) / Decimal::from(1_000_000_000u64);
     
-    let store_guard = store.lock().await;
     
     // Create or update balance
     let create_request = store::balance::CreateBalanceRequest {
@@ -296,7 +465,7 @@ pub async fn add_sol_balance(
         amount: sol_amount,
     };
     
-    match store_guard.create_or_update_balance(create_request).await {
+    match store.create_or_update_balance(create_request).await {
         Ok(balance) => {
             println!("Successfully added {} lamports ({} SOL) to user {}", 
                      req.lamports, sol_amount, req.user_id);
@@ -308,6 +477,8 @@ pub async fn add_sol_balance(
                 "added_lamports": req.lamports,
                 "added_sol": sol_amount,
                 "new_balance_sol": balance.amount,
+                "added": AmountView::from_base_units(req.lamports, SOL_DECIMALS),
+                "new_balance": AmountView::new(balance.amount, SOL_DECIMALS),
                 "message": format!("Added {} SOL to user balance", sol_amount)
             })))
         }
@@ -321,4 +492,41 @@ pub async fn add_sol_balance(
             })))
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaches_reserve() {
+        let reserve = Decimal::new(1, 2); // 0.01 SOL
+
+        assert!(!breaches_reserve(Decimal::ONE, Decimal::new(5, 1), reserve));
+        assert!(!breaches_reserve(Decimal::ONE, Decimal::new(99, 2), reserve));
+        assert!(breaches_reserve(Decimal::ONE, Decimal::new(995, 3), reserve));
+        assert!(breaches_reserve(Decimal::ONE, Decimal::ONE, reserve));
+    }
+
+    #[actix_web::test]
+    async fn test_sol_balance_rejects_invalid_pubkey() {
+        let app = actix_web::test::init_service(actix_web::App::new().service(sol_balance)).await;
+
+        let request = actix_web::test::TestRequest::get().uri("/sol-balance/not-a-pubkey").to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_token_balance_rejects_invalid_mint() {
+        let app = actix_web::test::init_service(actix_web::App::new().service(token_balance)).await;
+
+        let request = actix_web::test::TestRequest::get()
+            .uri("/token-balance/9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin/not-a-mint")
+            .to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{HashMap, HashSet};
+
+use rust_decimal::Decimal;
+
+/// Operator restrictions on which mints can be swapped, from `SWAP_MINT_ALLOWLIST` and
+/// `SWAP_MINT_DENYLIST` (comma separated). An empty allowlist allows every mint; the
+/// denylist always applies.
+#[derive(Debug, Default)]
+pub struct MintPolicy {
+    allowlist: HashSet<String>,
+    denylist: HashSet<String>,
+}
+
+fn parse_mint_list(value: &str) -> HashSet<String> {
+    value.split(',')
+        .map(|mint| mint.trim())
+        .filter(|mint| !mint.is_empty())
+        .map(|mint| mint.to_string())
+        .collect()
+}
+
+impl MintPolicy {
+    pub fn new(allowlist: &str, denylist: &str) -> Self {
+        Self {
+            allowlist: parse_mint_list(allowlist),
+            denylist: parse_mint_list(denylist),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            &std::env::var("SWAP_MINT_ALLOWLIST").unwrap_or_default(),
+            &std::env::var("SWAP_MINT_DENYLIST").unwrap_or_default(),
+        )
+    }
+
+    /// `Err` carries the reason the mint was rejected.
+    pub fn check(&self, mint: &str) -> Result<(), String> {
+        if self.denylist.contains(mint) {
+            return Err(format!("Mint {} is blocked for swaps", mint));
+        }
+        if !self.allowlist.is_empty() && !self.allowlist.contains(mint) {
+            return Err(format!("Mint {} is not in the list of supported swap mints", mint));
+        }
+        Ok(())
+    }
+
+    pub fn check_pair(&self, input_mint: &str, output_mint: &str) -> Result<(), String> {
+        self.check(input_mint)?;
+        self.check(output_mint)
+    }
+}
+
+/// Smallest input, in base units, accepted per mint, from `SWAP_MIN_INPUT_AMOUNTS`
+/// (comma separated `mint:amount` pairs). Jupiter can't route dust amounts and fails with
+/// an unhelpful error, so these are rejected before calling it. Unlisted mints have no minimum.
+#[derive(Debug, Default)]
+pub struct SwapMinimums {
+    minimums: HashMap<String, u64>,
+}
+
+impl SwapMinimums {
+    /// Malformed entries are skipped with a warning rather than failing every swap.
+    pub fn new(value: &str) -> Self {
+        let mut minimums = HashMap::new();
+        for entry in value.split(',').map(|entry| entry.trim()).filter(|entry| !entry.is_empty()) {
+            match entry.split_once(':').map(|(mint, amount)| (mint.trim(), amount.trim().parse::<u64>())) {
+                Some((mint, Ok(amount))) if !mint.is_empty() => {
+                    minimums.insert(mint.to_string(), amount);
+                }
+                _ => println!("Ignoring invalid SWAP_MIN_INPUT_AMOUNTS entry: {}", entry),
+            }
+        }
+        Self { minimums }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(&std::env::var("SWAP_MIN_INPUT_AMOUNTS").unwrap_or_default())
+    }
+
+    /// `Err` carries the reason the amount was rejected.
+    pub fn check(&self, input_mint: &str, amount: u64) -> Result<(), String> {
+        match self.minimums.get(input_mint) {
+            Some(&minimum) if amount < minimum => Err(format!(
+                "Swap amount {} is below the minimum of {} base units for mint {}",
+                amount, minimum, input_mint
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Limits a quote must meet to be executed, so a route moved by a sandwich or thin liquidity
+/// isn't signed. `max_price_impact_pct` is in percent and defaults to `SWAP_MAX_PRICE_IMPACT_PCT`
+/// (5, and 0 turns it off); `min_out_amount` is in output base units and only set per request.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QuoteProtection {
+    pub max_price_impact_pct: Option<Decimal>,
+    pub min_out_amount: Option<u64>,
+}
+
+const DEFAULT_MAX_PRICE_IMPACT_PCT: Decimal = Decimal::from_parts(5, 0, 0, false, 0);
+
+impl QuoteProtection {
+    pub fn from_env() -> Self {
+        let max_price_impact_pct = std::env::var("SWAP_MAX_PRICE_IMPACT_PCT")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_MAX_PRICE_IMPACT_PCT);
+        Self {
+            max_price_impact_pct: (max_price_impact_pct > Decimal::ZERO).then_some(max_price_impact_pct),
+            min_out_amount: None,
+        }
+    }
+
+    /// The request's own limits take precedence over the configured defaults.
+    pub fn with_overrides(self, max_price_impact_pct: Option<Decimal>, min_out_amount: Option<u64>) -> Self {
+        Self {
+            max_price_impact_pct: max_price_impact_pct.or(self.max_price_impact_pct),
+            min_out_amount: min_out_amount.or(self.min_out_amount),
+        }
+    }
+
+    /// `Err` carries the reason the quote was rejected. A quote missing a field that has a
+    /// limit is rejected, since it can't be shown to be within it.
+    pub fn check(&self, quote: &serde_json::Value) -> Result<(), String> {
+        let field = |name: &str| quote.get(name).and_then(|v| v.as_str()).map(str::trim);
+
+        if let Some(max) = self.max_price_impact_pct {
+            // Despite its name, Jupiter's priceImpactPct is a fraction: 0.01 is 1%
+            let impact = field("priceImpactPct")
+                .and_then(|v| v.parse::<Decimal>().ok())
+                .and_then(|fraction| fraction.checked_mul(Decimal::ONE_HUNDRED))
+                .ok_or("Quote has no price impact; request a new quote")?;
+            if impact.abs() > max {
+                return Err(format!("Price impact of {}% exceeds the maximum of {}%", impact.abs().normalize(), max.normalize()));
+            }
+        }
+
+        if let Some(min_out) = self.min_out_amount {
+            let out_amount = field("outAmount")
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or("Quote has no output amount; request a new quote")?;
+            if out_amount < min_out {
+                return Err(format!("Quoted output {} is below the minimum of {} base units", out_amount, min_out));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a swap involving a mint with no asset row registers it on the fly. Off by default,
+/// so the catalog only grows through explicit registration with the mint's real decimals.
+pub fn auto_create_swap_assets() -> bool {
+    std::env::var("AUTO_CREATE_SWAP_ASSETS")
+        .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const SCAM: &str = "ScamMint1111111111111111111111111111111111";
+
+    #[test]
+    fn test_allowed_when_no_lists_configured() {
+        let policy = MintPolicy::new("", "");
+        assert!(policy.check_pair(SOL, SCAM).is_ok());
+    }
+
+    #[test]
+    fn test_denylist_always_applies() {
+        let policy = MintPolicy::new(&format!("{},{}", SOL, SCAM), SCAM);
+
+        assert!(policy.check(SOL).is_ok());
+        let err = policy.check_pair(SOL, SCAM).unwrap_err();
+        assert!(err.contains("blocked"));
+    }
+
+    #[test]
+    fn test_rejects_mint_missing_from_allowlist() {
+        let policy = MintPolicy::new(&format!(" {} , {} ", SOL, USDC), "");
+
+        assert!(policy.check_pair(SOL, USDC).is_ok());
+        let err = policy.check_pair(USDC, SCAM).unwrap_err();
+        assert!(err.contains("not in the list"));
+    }
+
+    #[test]
+    fn test_minimum_boundary() {
+        let minimums = SwapMinimums::new(&format!("{}:10000, {}:500,bad-entry,{}:x", SOL, USDC, SCAM));
+
+        assert!(minimums.check(SOL, 9_999).is_err());
+        assert!(minimums.check(SOL, 10_000).is_ok());
+        assert!(minimums.check(USDC, 499).unwrap_err().contains("below the minimum"));
+        assert!(minimums.check(SCAM, 1).is_ok());
+    }
+    #[test]
+    fn test_quote_with_high_price_impact_is_rejected() {
+        let protection = QuoteProtection { max_price_impact_pct: Some(Decimal::ONE), min_out_amount: None };
+        let quote = serde_json::json!({ "priceImpactPct": "0.025", "outAmount": "1000" });
+
+        let err = protection.check(&quote).unwrap_err();
+        assert!(err.contains("Price impact of 2.5% exceeds the maximum of 1%"), "{}", err);
+
+        let short = protection.with_overrides(Some(Decimal::from(5)), Some(2000));
+        assert!(short.check(&quote).unwrap_err().contains("below the minimum of 2000"));
+        assert!(protection.check(&serde_json::json!({ "outAmount": "1000" })).is_err());
+    }
+
+    #[test]
+    fn test_quote_within_limits_passes() {
+        let protection = QuoteProtection::default().with_overrides(Some(Decimal::ONE), Some(1000));
+        let quote = serde_json::json!({ "priceImpactPct": "0.00123", "outAmount": "1000" });
+
+        assert!(protection.check(&quote).is_ok());
+        assert!(QuoteProtection::default().check(&serde_json::json!({})).is_ok());
+    }
+
+    /// A SOL -> USDC quote as returned by Jupiter's `/swap/v1/quote`.
+    fn jupiter_quote(price_impact_pct: &str) -> serde_json::Value {
+        serde_json::json!({
+            "inputMint": SOL,
+            "inAmount": "1000000000",
+            "outputMint": USDC,
+            "outAmount": "150123456",
+            "otherAmountThreshold": "149372839",
+            "swapMode": "ExactIn",
+            "slippageBps": 50,
+            "platformFee": null,
+            "priceImpactPct": price_impact_pct,
+            "routePlan": [{
+                "swapInfo": {
+                    "ammKey": "Czfq3xZZDmsdGdUyrNLtRhGc47cXcZtLG4crryfu44zE",
+                    "label": "Whirlpool",
+                    "inputMint": SOL,
+                    "outputMint": USDC,
+                    "inAmount": "1000000000",
+                    "outAmount": "150123456",
+                    "feeAmount": "400000",
+                    "feeMint": SOL
+                },
+                "percent": 100
+            }],
+            "contextSlot": 371234567,
+            "timeTaken": 0.002
+        })
+    }
+
+    #[test]
+    fn test_default_limit_reads_price_impact_as_a_fraction() {
+        let protection = QuoteProtection { max_price_impact_pct: Some(DEFAULT_MAX_PRICE_IMPACT_PCT), min_out_amount: None };
+
+        // 0.02% and 4.9% impact pass the 5% default, 6.2% doesn't
+        assert!(protection.check(&jupiter_quote("0.0002379")).is_ok());
+        assert!(protection.check(&jupiter_quote("0.049")).is_ok());
+        let err = protection.check(&jupiter_quote("0.062")).unwrap_err();
+        assert!(err.contains("Price impact of 6.2% exceeds the maximum of 5%"), "{}", err);
+    }
+}
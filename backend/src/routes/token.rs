@@ -0,0 +1,49 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use store::{error::UserError, refresh_token::RefreshOutcome, Store};
+
+use super::rate_limit::auth_rate_limited;
+
+#[derive(Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+/// Trade a refresh token for a new access/refresh pair. The old refresh token stops working;
+/// presenting it again revokes every token descended from the same sign-in.
+#[actix_web::post("/token/refresh")]
+pub async fn refresh_token(
+    http_req: HttpRequest,
+    req: web::Json<RefreshTokenRequest>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if let Some(response) = auth_rate_limited(&http_req) {
+        return Ok(response);
+    }
+
+    match store.rotate_refresh_token(req.refresh_token.trim()).await? {
+        RefreshOutcome::Rotated(tokens) => Ok(HttpResponse::Ok().json(tokens)),
+        RefreshOutcome::Reused(reuse) => {
+            eprintln!(
+                "Refresh token reuse for user {}, revoked {} token(s) in family {}",
+                reuse.user_id, reuse.revoked, reuse.family_id
+            );
+            Err(UserError::InvalidToken.into())
+        }
+    }
+}
+
+/// Sign out: revoke the refresh token and the rest of its family.
+#[actix_web::post("/token/revoke")]
+pub async fn revoke_token(
+    req: web::Json<RefreshTokenRequest>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if !store.revoke_refresh_token(req.refresh_token.trim()).await? {
+        return Err(UserError::InvalidToken.into());
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "revoked": true
+    })))
+}
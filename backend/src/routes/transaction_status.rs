@@ -0,0 +1,120 @@
+use actix_web::{web, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use store::helper::is_valid_signature;
+
+use super::rpc::{get_signature_statuses, rpc_client};
+
+/// `getSignatureStatuses` accepts at most this many signatures per call.
+pub const MAX_BATCH_SIGNATURES: usize = 256;
+
+#[derive(Deserialize)]
+pub struct BatchStatusRequest {
+    pub signatures: Vec<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SignatureStatusEntry {
+    pub signature: String,
+    /// processed, confirmed, finalized, failed or unknown
+    pub status: String,
+    pub slot: Option<u64>,
+    pub confirmations: Option<u64>,
+    pub error: Option<String>,
+}
+
+pub(super) fn status_entry(signature: &str, status: &serde_json::Value) -> SignatureStatusEntry {
+    if status.is_null() {
+        return SignatureStatusEntry {
+            signature: signature.to_string(),
+            status: "unknown".to_string(),
+            slot: None,
+            confirmations: None,
+            error: None,
+        };
+    }
+
+    let error = status.get("err").filter(|err| !err.is_null()).map(|err| err.to_string());
+    let state = if error.is_some() {
+        "failed".to_string()
+    } else {
+        status.get("confirmationStatus")
+            .and_then(|v| v.as_str())
+            .unwrap_or("processed")
+            .to_string()
+    };
+
+    SignatureStatusEntry {
+        signature: signature.to_string(),
+        status: state,
+        slot: status.get("slot").and_then(|v| v.as_u64()),
+        confirmations: status.get("confirmations").and_then(|v| v.as_u64()),
+        error,
+    }
+}
+
+/// Pair each requested signature with its RPC status entry (missing entries are unknown).
+fn status_entries(signatures: &[String], statuses: &[serde_json::Value]) -> Vec<SignatureStatusEntry> {
+    signatures.iter().enumerate()
+        .map(|(i, signature)| status_entry(signature, statuses.get(i).unwrap_or(&serde_json::Value::Null)))
+        .collect()
+}
+
+async fn lookup_statuses(signatures: &[String]) -> HttpResponse {
+    if let Some(invalid) = signatures.iter().find(|s| !is_valid_signature(s)) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Invalid transaction signature: {}", invalid)
+        }));
+    }
+
+    match get_signature_statuses(&rpc_client(), signatures).await {
+        Ok(statuses) => HttpResponse::Ok().json(serde_json::json!({
+            "statuses": status_entries(signatures, &statuses)
+        })),
+        Err(e) => {
+            println!("Failed to fetch signature statuses: {}", e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Failed to fetch signature statuses"
+            }))
+        }
+    }
+}
+
+#[actix_web::get("/transaction-status/{signature}")]
+pub async fn transaction_status(path: web::Path<String>) -> Result<HttpResponse> {
+    Ok(lookup_statuses(&[path.into_inner()]).await)
+}
+
+#[actix_web::post("/transaction-status/batch")]
+pub async fn transaction_status_batch(req: web::Json<BatchStatusRequest>) -> Result<HttpResponse> {
+    if req.signatures.is_empty() || req.signatures.len() > MAX_BATCH_SIGNATURES {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Provide between 1 and {} signatures", MAX_BATCH_SIGNATURES)
+        })));
+    }
+
+    Ok(lookup_statuses(&req.signatures).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_entries_mix_of_known_and_unknown() {
+        let signatures = vec!["sig-confirmed".to_string(), "sig-unknown".to_string(), "sig-failed".to_string()];
+        let statuses = vec![
+            serde_json::json!({"slot": 100, "confirmations": 10, "err": null, "confirmationStatus": "confirmed"}),
+            serde_json::Value::Null,
+            serde_json::json!({"slot": 101, "confirmations": null, "err": {"InstructionError": [0, "Custom"]}, "confirmationStatus": "finalized"}),
+        ];
+
+        let entries = status_entries(&signatures, &statuses);
+
+        assert_eq!(entries[0].status, "confirmed");
+        assert_eq!(entries[0].slot, Some(100));
+        assert_eq!(entries[1].status, "unknown");
+        assert_eq!(entries[1].slot, None);
+        assert_eq!(entries[2].status, "failed");
+        assert!(entries[2].error.is_some());
+    }
+}
@@ -0,0 +1,268 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use store::{
+    helper::is_valid_signature,
+    ledger::LedgerReason,
+    pending_operation::{CreatePendingOperationRequest, SettlementCredit, UNRESOLVED_STATUS},
+    Store,
+};
+
+use super::admin::is_admin;
+use super::rpc::{get_signature_statuses, rpc_client};
+use super::transaction_status::status_entry;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AssetAmount {
+    pub asset_id: String,
+    pub amount: Decimal,
+}
+
+/// What a send or swap left open when its on-chain outcome couldn't be determined. The debit
+/// stands until the transaction's signature status settles it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UnresolvedPayload {
+    pub reason: LedgerReason,
+    /// The debit taken before signing; given the signature once the transaction lands.
+    pub debit_entry_id: Option<String>,
+    /// Credited back if the transaction failed or never landed.
+    pub refund: Option<AssetAmount>,
+    /// Credited if the transaction landed, e.g. a swap's output.
+    pub owed: Option<AssetAmount>,
+    pub signature: Option<String>,
+    /// The id the MPC service logged the call under, for finding a signature it didn't return.
+    pub request_id: Option<String>,
+    pub error: String,
+}
+
+/// Record `payload` for later settlement and return the operation id. A failure is logged as
+/// critical, since the debit then has nothing tracking it but the ledger.
+pub async fn record_unresolved(store: &Store, user_id: &str, operation_type: &str, payload: &UnresolvedPayload) -> Option<String> {
+    let request = CreatePendingOperationRequest {
+        user_id: user_id.to_string(),
+        operation_type: operation_type.to_string(),
+        payload: serde_json::json!(payload),
+    };
+
+    match store.record_unresolved_operation(request).await {
+        Ok(operation) => {
+            eprintln!("Recorded unresolved {} {} for user {}: {}", operation_type, operation.id, user_id, payload.error);
+            Some(operation.id)
+        }
+        Err(e) => {
+            eprintln!("CRITICAL: Failed to record unresolved {} for user {} ({:?}): {:?}", operation_type, user_id, payload, e);
+            None
+        }
+    }
+}
+
+/// The 202 returned when a send or swap was recorded as unresolved instead of refunded.
+pub fn unresolved_response(operation_id: Option<&str>, error: &str) -> HttpResponse {
+    HttpResponse::Accepted().json(serde_json::json!({
+        "success": false,
+        "pending": true,
+        "unresolved_operation_id": operation_id,
+        "transaction_signature": null,
+        "error": format!("{}. The transaction may have been sent; the debit is held until its status is known.", error)
+    }))
+}
+
+#[derive(Debug, PartialEq)]
+enum Settlement {
+    Landed,
+    Failed,
+    NeverLanded,
+    /// Still processing, or unknown while it could yet land.
+    Undetermined,
+}
+
+impl Settlement {
+    fn outcome(&self) -> &'static str {
+        match self {
+            Settlement::Landed => "landed",
+            Settlement::Failed => "failed",
+            Settlement::NeverLanded => "not_landed",
+            Settlement::Undetermined => UNRESOLVED_STATUS,
+        }
+    }
+}
+
+/// Settle on a `transaction_status` state. An unknown signature only counts as never landed
+/// once its blockhash must have expired.
+fn settlement(status: &str, landing_window_passed: bool) -> Settlement {
+    match status {
+        "confirmed" | "finalized" => Settlement::Landed,
+        "failed" => Settlement::Failed,
+        "unknown" if landing_window_passed => Settlement::NeverLanded,
+        _ => Settlement::Undetermined,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ResolveRequest {
+    /// Needed when the operation didn't record one: the signature the MPC service logged
+    /// under the operation's `request_id`.
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+/// Sends and swaps whose outcome is unknown, oldest first.
+#[actix_web::get("/admin/unresolved-operations")]
+pub async fn list_unresolved_operations(
+    http_req: HttpRequest,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    match store.list_unresolved_operations().await {
+        Ok(operations) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "count": operations.len(),
+            "operations": operations
+        }))),
+        Err(e) => {
+            eprintln!("Failed to list unresolved operations: {}", e);
+            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to list unresolved operations"
+            })))
+        }
+    }
+}
+
+/// Settle an unresolved operation by its transaction's signature status: a landed transaction
+/// keeps the debit and credits what it owes, a failed or never-landed one refunds the debit.
+/// Network fees are left for reconciliation.
+#[actix_web::post("/admin/unresolved-operations/{operation_id}/resolve")]
+pub async fn resolve_unresolved_operation(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<ResolveRequest>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    let operation_id = path.into_inner();
+    let operation = match store.get_pending_operation(&operation_id).await {
+        Ok(Some(operation)) if operation.status == UNRESOLVED_STATUS => operation,
+        Ok(_) => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "No unresolved operation with this id"
+            })));
+        }
+        Err(e) => {
+            eprintln!("Failed to load unresolved operation {}: {}", operation_id, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to load operation"
+            })));
+        }
+    };
+
+    let payload: UnresolvedPayload = match serde_json::from_value(operation.payload.clone()) {
+        Ok(payload) => payload,
+        Err(e) => {
+            eprintln!("Unresolved operation {} has an unreadable payload: {}", operation_id, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Operation payload is unreadable"
+            })));
+        }
+    };
+
+    let signature = match req.signature.clone().or_else(|| payload.signature.clone()) {
+        Some(signature) if is_valid_signature(&signature) => signature,
+        Some(signature) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid transaction signature: {}", signature)
+            })));
+        }
+        None => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "No signature recorded; pass the one the MPC service logged for this request id",
+                "request_id": payload.request_id
+            })));
+        }
+    };
+
+    let statuses = match get_signature_statuses(&rpc_client(), std::slice::from_ref(&signature)).await {
+        Ok(statuses) => statuses,
+        Err(e) => {
+            eprintln!("Failed to fetch status of {}: {}", signature, e);
+            return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Failed to fetch signature status"
+            })));
+        }
+    };
+    let status = status_entry(&signature, statuses.first().unwrap_or(&serde_json::Value::Null));
+
+    let settlement = settlement(&status.status, chrono::Utc::now() > operation.expires_at);
+    let credit = match settlement {
+        Settlement::Landed => payload.owed.as_ref(),
+        Settlement::Failed | Settlement::NeverLanded => payload.refund.as_ref(),
+        Settlement::Undetermined => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Transaction is not final yet, retry later",
+                "status": status
+            })));
+        }
+    };
+    let credit = credit.map(|credit| SettlementCredit {
+        asset_id: &credit.asset_id,
+        amount: credit.amount,
+        reason: payload.reason,
+        reference: Some(&signature),
+    });
+
+    match store.settle_unresolved_operation(&operation_id, settlement.outcome(), credit).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Operation was already settled"
+            })));
+        }
+        Err(e) => {
+            eprintln!("Failed to settle unresolved operation {}: {}", operation_id, e);
+            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to settle operation"
+            })));
+        }
+    }
+
+    if settlement == Settlement::Landed {
+        if let Some(entry_id) = &payload.debit_entry_id {
+            if let Err(e) = store.set_ledger_reference(entry_id, &signature).await {
+                eprintln!("Failed to record signature {} on ledger entry {}: {:?}", signature, entry_id, e);
+            }
+        }
+    }
+
+    eprintln!("Settled unresolved operation {} as {} ({})", operation_id, settlement.outcome(), signature);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "operation_id": operation_id,
+        "outcome": settlement.outcome(),
+        "signature": signature,
+        "status": status
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settlement_waits_out_the_landing_window() {
+        assert_eq!(settlement("finalized", false), Settlement::Landed);
+        assert_eq!(settlement("confirmed", true), Settlement::Landed);
+        assert_eq!(settlement("failed", false), Settlement::Failed);
+        assert_eq!(settlement("processed", true), Settlement::Undetermined);
+
+        // An unknown signature may still land until its blockhash expires
+        assert_eq!(settlement("unknown", false), Settlement::Undetermined);
+        assert_eq!(settlement("unknown", true), Settlement::NeverLanded);
+    }
+}
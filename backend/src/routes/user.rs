@@ -1,8 +1,15 @@
-use std::sync::Arc;
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use rust_decimal::Decimal;
 use store::Store;
-use tokio::sync::Mutex;
+
+use super::admin::is_admin;
+use super::feature_flags::{operation_disabled, Operation};
+use super::auth::authenticated_user;
+use super::indexer_client::{add_user_key, remove_user_keys};
+use super::rate_limit::auth_rate_limited;
+use super::rpc::{get_sol_balance, get_token_balances, rpc_client};
 
 #[derive(Deserialize)]
 pub struct SignUpRequest {
@@ -19,6 +26,13 @@ pub struct SignInRequest {
 #[derive(Serialize)]
 pub struct AuthResponse {
     pub token: String,
+    pub refresh_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Deserialize)]
+pub struct RotateKeysRequest {
+    pub user_id: String,
 }
 
 #[derive(Serialize)]
@@ -32,71 +46,79 @@ pub struct TokenValidationResponse {
     user_id: Option<String>,
 }
 
-// #[actix_web::post("/validate-token")]
-// pub async fn validate_token(
-//     req: web::Json<serde_json::Value>,
-//     store: web::Data<Store>,
-// ) -> Result<HttpResponse> {
-//     if let Some(token) = req.get("token").and_then(|t| t.as_str()) {
-//         match store.validate_token(token) {
-//             Ok(user_id) => {
-//                 let response = TokenValidationResponse {
-//                     valid: true,
-//                     user_id: Some(user_id),
-//                 };
-//                 Ok(HttpResponse::Ok().json(response))
-//             }
-//             Err(_) => {
-//                 let response = TokenValidationResponse {
-//                     valid: false,
-//                     user_id: None,
-//                 };
-//                 Ok(HttpResponse::Ok().json(response))
-//             }
-//         }
-//     } else {
-//         Ok(HttpResponse::BadRequest().json(serde_json::json!({
-//             "error": "Token is required"
-//         })))
-//     }
-// }
+#[actix_web::post("/validate-token")]
+pub async fn validate_token(
+    req: web::Json<serde_json::Value>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    if let Some(token) = req.get("token").and_then(|t| t.as_str()) {
+        match store.validate_token(token) {
+            Ok(user_id) => {
+                let response = TokenValidationResponse {
+                    valid: true,
+                    user_id: Some(user_id),
+                };
+                Ok(HttpResponse::Ok().json(response))
+            }
+            Err(_) => {
+                let response = TokenValidationResponse {
+                    valid: false,
+                    user_id: None,
+                };
+                Ok(HttpResponse::Ok().json(response))
+            }
+        }
+    } else {
+        Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Token is required"
+        })))
+    }
+}
 
 #[actix_web::post("/signup")]
 pub async fn sign_up(
+    http_req: HttpRequest,
     req: web::Json<SignUpRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
+    if let Some(response) = operation_disabled(Operation::Signup) {
+        return Ok(response);
+    }
+
+    if let Some(response) = auth_rate_limited(&http_req) {
+        return Ok(response);
+    }
+
     let user_request = store::user::CreateUserRequest {
         email: req.email.clone(),
         password: req.password.clone(),
     };
 
-    let store_guard = store.lock().await;
-    match store_guard.create_user(user_request).await {
-        Ok(_user) => {
-            let response = SignupResponse {
-                message: "User created successfully".to_string(),
-            };
-            Ok(HttpResponse::Created().json(response))
-        }
-        Err(e) => {
-            eprintln!("Error creating user: {}", e);
-            Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                "error": e.to_string()
-            })))
-        }
-    }
+    store.create_user(user_request).await?;
+    Ok(HttpResponse::Created().json(SignupResponse {
+        message: "User created successfully".to_string(),
+    }))
 }
 
 #[actix_web::post("/signin")]
 pub async fn sign_in(
+    http_req: HttpRequest,
     req: web::Json<SignInRequest>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
-    let store_guard = store.lock().await;
-    match store_guard.authenticate_user(&req.email, &req.password).await {
-        Ok(token) => {
-            let response = AuthResponse { token };
+    if let Some(response) = auth_rate_limited(&http_req) {
+        return Ok(response);
+    }
+
+    let ip_address = http_req.connection_info().realip_remote_addr().map(str::to_string);
+
+    match store.authenticate_user(&req.email, &req.password, ip_address.as_deref()).await {
+        Ok(tokens) => {
+            let response = AuthResponse {
+                token: tokens.access_token,
+                refresh_token: tokens.refresh_token,
+                expires_in: tokens.expires_in,
+            };
             Ok(HttpResponse::Ok().json(response))
         }
         Err(e) => {
@@ -111,20 +133,234 @@ pub async fn sign_in(
 #[actix_web::get("/user/{id}")]
 pub async fn get_user(
     path: web::Path<String>,
-    store: web::Data<Arc<Mutex<Store>>>,
+    store: web::Data<Store>,
 ) -> Result<HttpResponse> {
     let user_id = path.into_inner();
     
-    let store_guard = store.lock().await;
-    match store_guard.get_user_by_id(&user_id).await {
-        Ok(user) => {
-            Ok(HttpResponse::Ok().json(user))
+    let user = store.get_user_by_id(&user_id).await?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+/// Deactivate a user (admin, or the user themselves) and stop the indexer monitoring their
+/// keys. Removal is best-effort: if the indexer is unreachable the user stays flagged and
+/// the background reconcile job finishes the removal later.
+#[actix_web::post("/user/{id}/deactivate")]
+pub async fn deactivate_user(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let user_id = path.into_inner();
+    let is_self = authenticated_user(&http_req).is_some_and(|id| id == user_id);
+    if !is_self && !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Only the user or an admin can deactivate this account"
+        })));
+    }
+
+    let public_keys = store.deactivate_user(&user_id).await?;
+
+    let indexer_keys_removed = match remove_user_keys(&user_id, &public_keys).await {
+        Ok(()) => match store.mark_keys_removed(&user_id).await {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Failed to mark keys removed for user {}: {}", user_id, e);
+                false
+            }
+        },
+        Err(e) => {
+            println!("Indexer key removal for user {} deferred: {}", user_id, e);
+            false
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "deactivated": true,
+        "indexer_keys_removed": indexer_keys_removed
+    })))
+}
+
+/// Permanently delete the account: the user row with their balances and quotes, then their
+/// key shares in the MPC service and the indexer's subscription to their wallet. Shares and
+/// subscriptions that can't be removed are reported, not retried, since the user is gone.
+/// Destroying the shares loses the wallet, so it and the stored balances must be empty first.
+#[actix_web::delete("/user/{id}")]
+pub async fn delete_user(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let user_id = path.into_inner();
+    let is_self = authenticated_user(&http_req).is_some_and(|id| id == user_id);
+    if !is_self && !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Only the user or an admin can delete this account"
+        })));
+    }
+
+    let public_key = store.get_user_by_id(&user_id).await?.public_key;
+    if let Some(public_key) = &public_key {
+        match wallet_has_funds(public_key.as_str()).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                    "error": "Move all SOL and tokens out of the wallet before deleting the account",
+                    "public_key": public_key
+                })));
+            }
+            Err(e) => {
+                eprintln!("Failed to check wallet {} before deleting user {}: {}", public_key, user_id, e);
+                return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": "Could not check the wallet's balance"
+                })));
+            }
         }
+    }
+
+    let held: Vec<String> = store.get_user_balances(&user_id).await?
+        .into_iter()
+        .filter(|balance| !balance.amount.is_zero())
+        .map(|balance| balance.asset_symbol)
+        .collect();
+    if !held.is_empty() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Withdraw all balances before deleting the account",
+            "assets": held
+        })));
+    }
+
+    let deleted = store.delete_user(&user_id).await?;
+
+    let shares_deleted = match store.delete_shares_via_mpc(&user_id).await {
+        Ok(count) => Some(count),
         Err(e) => {
-            eprintln!("Error fetching user: {}", e);
-            Ok(HttpResponse::NotFound().json(serde_json::json!({
-                "error": "User not found"
-            })))
+            eprintln!("Failed to delete key shares for deleted user {}: {}", user_id, e);
+            None
+        }
+    };
+
+    let public_keys: Vec<String> = deleted.public_key.iter().cloned().collect();
+    let indexer_keys_removed = match remove_user_keys(&user_id, &public_keys).await {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Failed to remove indexer keys for deleted user {}: {}", user_id, e);
+            false
+        }
+    };
+
+    println!(
+        "Deleted user {}: {} balances, {} quotes, {:?} key shares",
+        user_id, deleted.balances_deleted, deleted.quotes_deleted, shares_deleted
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "deleted": true,
+        "balances_deleted": deleted.balances_deleted,
+        "quotes_deleted": deleted.quotes_deleted,
+        "shares_deleted": shares_deleted,
+        "indexer_keys_removed": indexer_keys_removed
+    })))
+}
+
+/// Whether anything is left at `public_key` on chain: lamports or a non-zero balance in an
+/// SPL Token or Token-2022 account. A token account that can't be read is an error, so
+/// callers refuse to destroy the key rather than take it for empty.
+async fn wallet_has_funds(public_key: &str) -> std::result::Result<bool, String> {
+    let client = rpc_client();
+    let lamports = get_sol_balance(&client, public_key).await?;
+    if lamports > 0 {
+        return Ok(true);
+    }
+    let tokens = get_token_balances(&client, public_key).await?;
+    Ok(holds_funds(lamports, &tokens))
+}
+
+fn holds_funds(lamports: u64, tokens: &HashMap<String, Decimal>) -> bool {
+    lamports > 0 || tokens.values().any(|amount| !amount.is_zero())
+}
+
+/// Replace the user's wallet key (self or admin), e.g. when their shares may be compromised.
+/// The old key is destroyed, so the old address must be empty first. The MPC service swaps
+/// the shares all-or-nothing; only then is the user's public key updated and the indexer
+/// moved over to the new address (best-effort, reported as `indexer_updated`).
+#[actix_web::post("/rotate-keys")]
+pub async fn rotate_keys(
+    http_req: HttpRequest,
+    req: web::Json<RotateKeysRequest>,
+    store: web::Data<Store>,
+) -> Result<HttpResponse> {
+    let user_id = req.user_id.clone();
+    let is_self = authenticated_user(&http_req).is_some_and(|id| id == user_id);
+    if !is_self && !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Only the user or an admin can rotate this account's keys"
+        })));
+    }
+
+    let previous_public_key = store.get_user_by_id(&user_id).await?.public_key;
+    if let Some(previous) = &previous_public_key {
+        match wallet_has_funds(previous.as_str()).await {
+            Ok(false) => {}
+            Ok(true) => {
+                return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                    "error": "Move all SOL and tokens out of the wallet before rotating its key",
+                    "public_key": previous
+                })));
+            }
+            Err(e) => {
+                eprintln!("Failed to check wallet {} before key rotation: {}", previous, e);
+                return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": "Could not check the wallet's balance"
+                })));
+            }
         }
     }
+
+    let public_key = store.rotate_keypair_via_mpc(&user_id).await?;
+    if let Err(e) = store.set_public_key(&user_id, &public_key).await {
+        // The shares already belong to the new key; the recorded key must follow
+        eprintln!("Rotated key for user {} to {} but failed to record it: {}", user_id, public_key, e);
+        return Err(e.into());
+    }
+
+    let old_keys: Vec<String> = previous_public_key.iter().map(|key| key.to_string()).collect();
+    let indexer_updated = match remove_user_keys(&user_id, &old_keys).await {
+        Ok(()) => add_user_key(&user_id, public_key.as_str()).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = &indexer_updated {
+        println!("Indexer update after key rotation for user {} failed: {}", user_id, e);
+    }
+
+    println!("Rotated key for user {}: {:?} -> {}", user_id, previous_public_key, public_key);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "public_key": public_key,
+        "previous_public_key": previous_public_key,
+        "indexer_updated": indexer_updated.is_ok()
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::rpc::add_token_accounts;
+
+    #[test]
+    fn test_token_2022_balance_counts_as_funds() {
+        let token_2022_accounts = serde_json::json!({ "value": [{
+            "account": { "data": { "program": "spl-token-2022", "parsed": { "type": "account", "info": {
+                "mint": "2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo",
+                "tokenAmount": { "amount": "1", "decimals": 6 }
+            } } } }
+        }] });
+        let mut tokens = HashMap::new();
+        add_token_accounts(&mut tokens, &token_2022_accounts).unwrap();
+
+        assert!(holds_funds(0, &tokens));
+        assert!(!holds_funds(0, &HashMap::new()));
+        assert!(holds_funds(1, &HashMap::new()));
+    }
 }
@@ -0,0 +1,74 @@
+//! Arithmetic in GF(2^8) with the AES reduction polynomial x^8 + x^4 + x^3 + x + 1, shared by
+//! both MPC services' Shamir splits. Addition and subtraction are both XOR.
+
+pub fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse via a^254 (a^255 = 1 for every nonzero a).
+pub fn inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = mul(result, base);
+        }
+        base = mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Evaluate the polynomial with the given coefficients (constant term first) at `x`.
+pub fn eval_polynomial(coefficients: &[u8], x: u8) -> u8 {
+    coefficients.iter().rev().fold(0u8, |acc, &c| mul(acc, x) ^ c)
+}
+
+/// Lagrange basis values at x = 0 for distinct, nonzero points `xs`.
+pub fn lagrange_weights_at_zero(xs: &[u8]) -> Vec<u8> {
+    xs.iter()
+        .enumerate()
+        .map(|(i, &xi)| {
+            xs.iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .fold(1u8, |acc, (_, &xj)| mul(acc, mul(xj, inv(xj ^ xi))))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_interpolation_recovers_constant_term() {
+        // f(x) = 0x2a + 0x13x + 0x07x^2, sampled at three points
+        let coefficients = [0x2a, 0x13, 0x07];
+        let xs = [1u8, 4, 9];
+        let weights = lagrange_weights_at_zero(&xs);
+        let secret = xs.iter().zip(&weights)
+            .fold(0u8, |acc, (&x, &w)| acc ^ mul(eval_polynomial(&coefficients, x), w));
+        assert_eq!(secret, 0x2a);
+    }
+}
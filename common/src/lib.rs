@@ -0,0 +1,5 @@
+pub mod gf256;
+pub mod redact;
+pub mod serde_error;
+pub mod server;
+pub mod shard;
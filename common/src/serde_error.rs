@@ -0,0 +1,21 @@
+/// The field a serde error names, e.g. `user_id` from "missing field `user_id` at line 1 column 2".
+pub fn field_from_message(message: &str) -> Option<String> {
+    ["missing field `", "unknown field `", "duplicate field `"].iter().find_map(|prefix| {
+        let rest = &message[message.find(prefix)? + prefix.len()..];
+        rest.find('`').map(|end| rest[..end].to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_from_message() {
+        assert_eq!(field_from_message("missing field `user_id` at line 1 column 2").as_deref(), Some("user_id"));
+        assert_eq!(field_from_message("unknown field `amout`, expected `amount`").as_deref(), Some("amout"));
+        assert_eq!(field_from_message("duplicate field `email` at line 1 column 30").as_deref(), Some("email"));
+        assert_eq!(field_from_message("invalid type: integer `1`, expected a string"), None);
+        assert_eq!(field_from_message("expected value at line 1 column 1"), None);
+    }
+}
@@ -0,0 +1,85 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+/// HTTP server tuning every service reads the same way: `SERVER_WORKERS` (defaults to the CPU
+/// count), `SERVER_KEEP_ALIVE_SECS` (default 5) and `SERVER_CLIENT_TIMEOUT_MS` (default 5000).
+/// A value that is set but invalid is an error rather than silently replaced by the default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerTuning {
+    pub workers: usize,
+    pub keep_alive_secs: u64,
+    pub client_timeout_ms: u64,
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.trim().parse().map_err(|e| format!("Invalid {}: {}", name, e))
+}
+
+impl ServerTuning {
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let tuning = Self {
+            workers: match lookup("SERVER_WORKERS") {
+                Some(workers) => parse("SERVER_WORKERS", &workers)?,
+                None => std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            },
+            keep_alive_secs: lookup("SERVER_KEEP_ALIVE_SECS")
+                .map(|secs| parse("SERVER_KEEP_ALIVE_SECS", &secs))
+                .transpose()?
+                .unwrap_or(5),
+            client_timeout_ms: lookup("SERVER_CLIENT_TIMEOUT_MS")
+                .map(|ms| parse("SERVER_CLIENT_TIMEOUT_MS", &ms))
+                .transpose()?
+                .unwrap_or(5000),
+        };
+
+        if tuning.workers == 0 {
+            return Err("SERVER_WORKERS must be at least 1".to_string());
+        }
+
+        Ok(tuning)
+    }
+
+    pub fn keep_alive(&self) -> Duration {
+        Duration::from_secs(self.keep_alive_secs)
+    }
+
+    pub fn client_timeout(&self) -> Duration {
+        Duration::from_millis(self.client_timeout_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn tuning(vars: &[(&str, &str)]) -> Result<ServerTuning, String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        ServerTuning::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults_and_overrides() {
+        let defaults = tuning(&[]).unwrap();
+        assert!(defaults.workers >= 1);
+        assert_eq!((defaults.keep_alive_secs, defaults.client_timeout_ms), (5, 5000));
+
+        let tuned = tuning(&[("SERVER_WORKERS", "4"), ("SERVER_KEEP_ALIVE_SECS", "30"), ("SERVER_CLIENT_TIMEOUT_MS", " 250 ")]).unwrap();
+        assert_eq!(tuned, ServerTuning { workers: 4, keep_alive_secs: 30, client_timeout_ms: 250 });
+    }
+
+    #[test]
+    fn test_invalid_values_are_rejected() {
+        assert!(tuning(&[("SERVER_WORKERS", "0")]).unwrap_err().contains("at least 1"));
+        assert!(tuning(&[("SERVER_WORKERS", "many")]).unwrap_err().contains("Invalid SERVER_WORKERS"));
+        assert!(tuning(&[("SERVER_KEEP_ALIVE_SECS", "-1")]).is_err());
+        assert!(tuning(&[("SERVER_CLIENT_TIMEOUT_MS", "5s")]).is_err());
+    }
+}
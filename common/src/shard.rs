@@ -0,0 +1,87 @@
+use serde::Serialize;
+use std::env;
+
+/// Number of shard databases the key shares are spread over.
+pub const SHARD_COUNT: usize = 3;
+
+/// Where share `share_index` (1-based) is stored as `(shard, slot)`. Shares go round-robin
+/// over the shards, so with five shares shard 0 holds shares 1 and 4 (slots 0 and 1).
+pub fn share_location(share_index: i32) -> (usize, usize) {
+    let offset = (share_index - 1).max(0) as usize;
+    (offset % SHARD_COUNT, offset / SHARD_COUNT)
+}
+
+/// Pool tuning for one shard database. Each value comes from `MPC{n}_DB_<SETTING>` when set,
+/// otherwise from the shared `MPC_DB_<SETTING>`, otherwise the default below.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShardPoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+    pub max_lifetime_secs: u64,
+}
+
+impl Default for ShardPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 1,
+            acquire_timeout_secs: 30,
+            idle_timeout_secs: 600,
+            max_lifetime_secs: 1800,
+        }
+    }
+}
+
+impl ShardPoolConfig {
+    pub fn from_env(shard: usize) -> Self {
+        Self::from_lookup(shard, |key| env::var(key).ok())
+    }
+
+    fn from_lookup(shard: usize, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let setting = |name: &str| {
+            lookup(&format!("MPC{}_DB_{}", shard, name))
+                .or_else(|| lookup(&format!("MPC_DB_{}", name)))
+        };
+        let defaults = Self::default();
+
+        Self {
+            max_connections: setting("MAX_CONNECTIONS").and_then(|v| v.parse().ok()).unwrap_or(defaults.max_connections),
+            min_connections: setting("MIN_CONNECTIONS").and_then(|v| v.parse().ok()).unwrap_or(defaults.min_connections),
+            acquire_timeout_secs: setting("ACQUIRE_TIMEOUT_SECS").and_then(|v| v.parse().ok()).unwrap_or(defaults.acquire_timeout_secs),
+            idle_timeout_secs: setting("IDLE_TIMEOUT_SECS").and_then(|v| v.parse().ok()).unwrap_or(defaults.idle_timeout_secs),
+            max_lifetime_secs: setting("MAX_LIFETIME_SECS").and_then(|v| v.parse().ok()).unwrap_or(defaults.max_lifetime_secs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_share_location_round_robins_over_the_shards() {
+        let locations: Vec<_> = (1..=5).map(share_location).collect();
+        assert_eq!(locations, vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_shard_pool_config_prefers_shard_override() {
+        let vars: HashMap<&str, &str> = HashMap::from([
+            ("MPC_DB_MAX_CONNECTIONS", "25"),
+            ("MPC2_DB_MAX_CONNECTIONS", "40"),
+            ("MPC_DB_ACQUIRE_TIMEOUT_SECS", "5"),
+        ]);
+        let lookup = |key: &str| vars.get(key).map(|v| v.to_string());
+
+        let shard1 = ShardPoolConfig::from_lookup(1, lookup);
+        let shard2 = ShardPoolConfig::from_lookup(2, lookup);
+
+        assert_eq!(shard1.max_connections, 25);
+        assert_eq!(shard2.max_connections, 40);
+        assert_eq!(shard2.acquire_timeout_secs, 5);
+        assert_eq!(shard2.min_connections, ShardPoolConfig::default().min_connections);
+    }
+}
@@ -0,0 +1,18 @@
+-- Dead-letter queue for events the backend could not accept.
+-- Deliveries are at-least-once: the retry worker may re-send an event the backend
+-- already processed, and retried events can arrive out of order relative to live ones.
+CREATE TABLE failed_deliveries (
+    id VARCHAR PRIMARY KEY,
+    delivery_type VARCHAR NOT NULL, -- 'balance_update' | 'transaction_event'
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_error TEXT,
+    status VARCHAR NOT NULL DEFAULT 'pending', -- 'pending' | 'dead'
+    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_failed_deliveries_updated_at BEFORE UPDATE ON failed_deliveries FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE INDEX idx_failed_deliveries_due ON failed_deliveries (status, next_attempt_at);
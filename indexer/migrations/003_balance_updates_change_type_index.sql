@@ -0,0 +1,2 @@
+-- Supports listing a user's balance updates filtered by change type, newest first
+CREATE INDEX idx_balance_updates_user_change_type ON balance_updates (user_id, change_type, processed_at DESC);
@@ -0,0 +1,16 @@
+-- One-off lifecycle events per wallet, e.g. the first deposit into a monitored key.
+-- The unique constraint is what stops an event from firing twice.
+CREATE TABLE wallet_events (
+    id VARCHAR PRIMARY KEY,
+    user_id VARCHAR NOT NULL,
+    public_key VARCHAR(44) NOT NULL,
+    event_type VARCHAR NOT NULL, -- 'wallet_funded'
+    balance_update_id VARCHAR NOT NULL,
+    amount DECIMAL(20,9) NOT NULL,
+    slot BIGINT NOT NULL,
+    notified_at TIMESTAMP WITH TIME ZONE, -- set once the callback accepted the event
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (public_key, event_type)
+);
+
+CREATE INDEX idx_wallet_events_user_id ON wallet_events (user_id);
@@ -0,0 +1,135 @@
+use crate::config::Config;
+use crate::routes::ErrorResponse;
+use actix_cors::Cors;
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use subtle::ConstantTimeEq;
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Access level a route needs. Admin keys satisfy read routes as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Public,
+    Read,
+    Admin,
+}
+
+/// Health stays open for probes; anything that changes which keys are monitored, and the
+/// admin endpoints, are admin-only.
+pub fn required_access(method: &Method, path: &str) -> Access {
+    if *method == Method::OPTIONS || path.ends_with("/health") {
+        return Access::Public;
+    }
+
+    let path = path.strip_prefix("/api/v1").unwrap_or(path);
+    let manages_keys = path.starts_with("/keys") && *method != Method::GET;
+    if manages_keys || path.starts_with("/cache") || path.starts_with("/admin") {
+        return Access::Admin;
+    }
+
+    Access::Read
+}
+
+fn is_authorized(access: Access, provided: Option<&str>, read_key: Option<&str>, admin_key: Option<&str>) -> bool {
+    let accepted: Vec<&str> = match access {
+        Access::Public => return true,
+        Access::Read => match read_key {
+            // No read key configured keeps read routes open
+            None => return true,
+            Some(read) => std::iter::once(read).chain(admin_key).collect(),
+        },
+        // Admin routes change what is monitored, so they stay closed without an admin key
+        Access::Admin => match admin_key {
+            None => return false,
+            Some(admin) => vec![admin],
+        },
+    };
+
+    provided.is_some_and(|key| accepted.iter().any(|k| keys_match(key.as_bytes(), k.as_bytes())))
+}
+
+/// Compared in constant time, so response timing doesn't reveal how much of a guess was right.
+fn keys_match(provided: &[u8], expected: &[u8]) -> bool {
+    provided.ct_eq(expected).into()
+}
+
+/// Middleware checking `X-API-Key` against the read or admin key for the requested route.
+pub async fn require_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let access = required_access(req.method(), req.path());
+    let authorized = match req.app_data::<web::Data<Config>>() {
+        Some(config) => {
+            let provided = req.headers().get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+            is_authorized(access, provided, config.api_key.as_deref(), config.admin_api_key.as_deref())
+        }
+        None => access == Access::Public,
+    };
+
+    if !authorized {
+        let response = HttpResponse::Unauthorized().json(ErrorResponse::new(
+            "Unauthorized",
+            "Missing or invalid API key",
+        ));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// CORS policy from `CORS_ALLOWED_ORIGINS`: `*` allows any origin, otherwise a comma-separated
+/// list. With nothing configured, cross-origin browser requests are refused.
+pub fn cors(config: &Config) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST", "DELETE"])
+        .allowed_headers(vec!["Content-Type", "Authorization", API_KEY_HEADER])
+        .max_age(3600);
+
+    for origin in &config.cors_allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+
+    cors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_access() {
+        assert_eq!(required_access(&Method::GET, "/api/v1/health"), Access::Public);
+        assert_eq!(required_access(&Method::GET, "/api/v1/stats"), Access::Read);
+        assert_eq!(required_access(&Method::GET, "/api/v1/keys/abc"), Access::Read);
+        assert_eq!(required_access(&Method::GET, "/api/v1/users/u1/keys"), Access::Read);
+        assert_eq!(required_access(&Method::POST, "/api/v1/keys"), Access::Admin);
+        assert_eq!(required_access(&Method::DELETE, "/api/v1/keys"), Access::Admin);
+        assert_eq!(required_access(&Method::POST, "/api/v1/keys/bulk"), Access::Admin);
+        assert_eq!(required_access(&Method::DELETE, "/api/v1/keys/bulk"), Access::Admin);
+        assert_eq!(required_access(&Method::POST, "/api/v1/cache/refresh"), Access::Admin);
+        assert_eq!(required_access(&Method::GET, "/api/v1/admin/config"), Access::Admin);
+    }
+
+    #[test]
+    fn test_admin_routes_fail_closed_without_admin_key() {
+        assert!(!is_authorized(Access::Admin, None, Some("read-key"), None));
+        assert!(!is_authorized(Access::Admin, Some("read-key"), Some("read-key"), None));
+        assert!(is_authorized(Access::Read, Some("read-key"), Some("read-key"), None));
+        assert!(is_authorized(Access::Read, None, None, None));
+
+        assert!(is_authorized(Access::Admin, Some("admin-key"), Some("read-key"), Some("admin-key")));
+        assert!(is_authorized(Access::Read, Some("admin-key"), Some("read-key"), Some("admin-key")));
+        assert!(!is_authorized(Access::Admin, Some("admin-ke"), Some("read-key"), Some("admin-key")));
+    }
+}
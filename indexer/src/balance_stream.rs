@@ -0,0 +1,161 @@
+use crate::models::BalanceUpdate;
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use actix_ws::{CloseCode, CloseReason, Message};
+use futures::StreamExt;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+/// Updates buffered per subscriber; one that falls further behind is disconnected.
+const BALANCE_STREAM_CAPACITY: usize = 1024;
+
+/// Fan-out of processed balance updates to WebSocket subscribers.
+#[derive(Clone)]
+pub struct BalanceBroadcaster {
+    tx: broadcast::Sender<BalanceUpdate>,
+}
+
+impl BalanceBroadcaster {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(BALANCE_STREAM_CAPACITY);
+        Self { tx }
+    }
+
+    /// Hand an update to every connected subscriber. Having none is not an error.
+    pub fn publish(&self, update: &BalanceUpdate) {
+        let _ = self.tx.send(update.clone());
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BalanceUpdate> {
+        self.tx.subscribe()
+    }
+}
+
+// Stream a user's balance updates over a WebSocket as JSON text frames
+pub async fn stream_balances(
+    req: HttpRequest,
+    body: web::Payload,
+    path: web::Path<String>,
+    broadcaster: web::Data<BalanceBroadcaster>,
+) -> ActixResult<HttpResponse> {
+    let user_id = path.into_inner();
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    let updates = broadcaster.subscribe();
+
+    info!("Balance stream opened for user {}", user_id);
+    actix_web::rt::spawn(forward_updates(user_id, updates, session, messages));
+
+    Ok(response)
+}
+
+async fn forward_updates(
+    user_id: String,
+    mut updates: broadcast::Receiver<BalanceUpdate>,
+    mut session: actix_ws::Session,
+    mut messages: actix_ws::MessageStream,
+) {
+    let close_reason = loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) if update.user_id == user_id => {
+                    let payload = match serde_json::to_string(&update) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            warn!("Failed to serialize balance update {}: {}", update.id, e);
+                            continue;
+                        }
+                    };
+                    if session.text(payload).await.is_err() {
+                        // The client has gone away
+                        break None;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Balance stream for user {} lagged by {} updates, disconnecting", user_id, skipped);
+                    break Some(CloseReason {
+                        code: CloseCode::Again,
+                        description: Some("Too far behind; reconnect and reload balances".to_string()),
+                    });
+                }
+                Err(RecvError::Closed) => break Some(CloseCode::Away.into()),
+            },
+            message = messages.next() => match message {
+                Some(Ok(Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break None;
+                    }
+                }
+                Some(Ok(Message::Close(reason))) => break reason,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    debug!("Balance stream for user {} failed: {}", user_id, e);
+                    break None;
+                }
+                None => break None,
+            },
+        }
+    };
+
+    let _ = session.close(close_reason).await;
+    info!("Balance stream closed for user {}", user_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::BalanceChangeType;
+    use actix_web::{App, HttpServer};
+    use rust_decimal::Decimal;
+
+    fn update_for(user_id: &str) -> BalanceUpdate {
+        BalanceUpdate::new(
+            user_id.to_string(),
+            "11111111111111111111111111111111".to_string(),
+            "11111111111111111111111111111112".to_string(),
+            Decimal::from(1),
+            Decimal::from(3),
+            BalanceChangeType::Increase,
+            None,
+            42,
+        )
+    }
+
+    #[actix_web::test]
+    async fn test_subscriber_receives_only_its_users_updates() {
+        let broadcaster = BalanceBroadcaster::new();
+        let app_broadcaster = broadcaster.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(app_broadcaster.clone()))
+                .route("/api/v1/ws/balances/{user_id}", web::get().to(stream_balances))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let (_, mut socket) = awc::Client::new()
+            .ws(format!("ws://{}/api/v1/ws/balances/user-1", addr))
+            .connect()
+            .await
+            .unwrap();
+
+        // The handler subscribes before upgrading, so the receiver is live once connected
+        broadcaster.publish(&update_for("user-2"));
+        let expected = update_for("user-1");
+        broadcaster.publish(&expected);
+
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+            .await
+            .expect("no balance update received")
+            .unwrap()
+            .unwrap();
+        let awc::ws::Frame::Text(text) = frame else {
+            panic!("expected a text frame, got {:?}", frame);
+        };
+        let received: BalanceUpdate = serde_json::from_slice(&text).unwrap();
+        assert_eq!(received.id, expected.id);
+        assert_eq!(received.user_id, "user-1");
+    }
+}
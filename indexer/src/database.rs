@@ -1,6 +1,9 @@
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use sqlx::{postgres::PgPoolOptions, PgPool, Postgres, QueryBuilder};
 use std::time::Duration;
 use anyhow::Result;
+use rust_decimal::Decimal;
+
+use crate::models::{BalanceChangeType, BalanceUpdate};
 
 #[derive(Clone)]
 pub struct Database {
@@ -43,4 +46,189 @@ impl Database {
     pub async fn get_pool(&self) -> &PgPool {
         &self.pool
     }
-}
\ No newline at end of file
+
+    /// A page of the user's balance updates, newest first, optionally limited to one
+    /// change type. Also returns the total number of matching rows.
+    pub async fn get_balance_updates_by_type(
+        &self,
+        user_id: &str,
+        change_type: Option<BalanceChangeType>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<BalanceUpdate>, i64)> {
+        let updates = sqlx::query_as::<_, BalanceUpdate>(
+            r#"
+            SELECT id, user_id, public_key, mint_address, old_balance, new_balance, change_amount,
+                   change_type, transaction_signature, slot, block_time, processed_at
+            FROM balance_updates
+            WHERE user_id = $1 AND ($2::balance_change_type IS NULL OR change_type = $2)
+            ORDER BY processed_at DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(user_id)
+        .bind(change_type)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM balance_updates WHERE user_id = $1 AND ($2::balance_change_type IS NULL OR change_type = $2)"
+        )
+        .bind(user_id)
+        .bind(change_type)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((updates, total))
+    }
+
+    /// One page (1-based) of balance updates across all users, newest first, optionally
+    /// limited to one user and/or public key. Also returns the total number of matching rows.
+    pub async fn get_balance_updates(
+        &self,
+        user_id: Option<&str>,
+        public_key: Option<&str>,
+        page: i32,
+        per_page: i32,
+    ) -> Result<(Vec<BalanceUpdate>, i64)> {
+        let offset = (page.max(1) as i64 - 1) * per_page as i64;
+        let updates = sqlx::query_as::<_, BalanceUpdate>(
+            r#"
+            SELECT id, user_id, public_key, mint_address, old_balance, new_balance, change_amount,
+                   change_type, transaction_signature, slot, block_time, processed_at
+            FROM balance_updates
+            WHERE ($1::text IS NULL OR user_id = $1) AND ($2::text IS NULL OR public_key = $2)
+            ORDER BY processed_at DESC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(user_id)
+        .bind(public_key)
+        .bind(per_page as i64)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM balance_updates WHERE ($1::text IS NULL OR user_id = $1) AND ($2::text IS NULL OR public_key = $2)"
+        )
+        .bind(user_id)
+        .bind(public_key)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((updates, total))
+    }
+
+    /// The most recently recorded balance of a key for a mint, or `None` if the indexer has
+    /// never stored one.
+    pub async fn get_last_balance(&self, public_key: &str, mint_address: &str) -> Result<Option<Decimal>> {
+        let balance = sqlx::query_scalar(
+            "
+            SELECT new_balance FROM balance_updates
+            WHERE public_key = $1 AND mint_address = $2
+            ORDER BY processed_at DESC
+            LIMIT 1
+            "
+        )
+        .bind(public_key)
+        .bind(mint_address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(balance)
+    }
+
+    /// The latest recorded balance of every key for a mint, used to seed the subscriber's
+    /// last-known-balance cache on startup.
+    pub async fn get_latest_balances(&self, mint_address: &str) -> Result<Vec<(String, Decimal)>> {
+        let balances = sqlx::query_as(
+            "
+            SELECT DISTINCT ON (public_key) public_key, new_balance FROM balance_updates
+            WHERE mint_address = $1
+            ORDER BY public_key, processed_at DESC
+            "
+        )
+        .bind(mint_address)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(balances)
+    }
+
+    /// Write a batch of balance updates in a single multi-row INSERT. Returns the rows written.
+    pub async fn insert_balance_updates(&self, updates: &[BalanceUpdate]) -> Result<u64> {
+        if updates.is_empty() {
+            return Ok(0);
+        }
+
+        let mut query = QueryBuilder::<Postgres>::new(
+            "INSERT INTO balance_updates (id, user_id, public_key, mint_address, old_balance, new_balance, change_amount, change_type, transaction_signature, slot, block_time, processed_at) "
+        );
+        query.push_values(updates, |mut row, update| {
+            row.push_bind(&update.id)
+                .push_bind(&update.user_id)
+                .push_bind(&update.public_key)
+                .push_bind(&update.mint_address)
+                .push_bind(update.old_balance)
+                .push_bind(update.new_balance)
+                .push_bind(update.change_amount)
+                .push_bind(update.change_type)
+                .push_bind(&update.transaction_signature)
+                .push_bind(update.slot)
+                .push_bind(update.block_time)
+                .push_bind(update.processed_at);
+        });
+
+        let result = query.build().execute(&self.pool).await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_get_balance_updates_pages_newest_first() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL");
+        let database = Database::new(&url).await.unwrap();
+        let user_id = format!("page-test-{}", uuid::Uuid::new_v4());
+        let public_key = format!("page-key-{}", uuid::Uuid::new_v4());
+        let start = chrono::Utc::now();
+
+        let seeded: Vec<BalanceUpdate> = (0..5).map(|i| {
+            let mut update = BalanceUpdate::new(
+                user_id.clone(),
+                if i < 3 { public_key.clone() } else { "other-key".to_string() },
+                "11111111111111111111111111111112".to_string(),
+                Decimal::from(i),
+                Decimal::from(i + 1),
+                BalanceChangeType::Increase,
+                None,
+                i,
+            );
+            update.processed_at = start + chrono::Duration::seconds(i);
+            update
+        }).collect();
+        database.insert_balance_updates(&seeded).await.unwrap();
+
+        let (page, total) = database.get_balance_updates(Some(&user_id), None, 2, 2).await.unwrap();
+        assert_eq!(total, 5);
+        let slots: Vec<i64> = page.iter().map(|update| update.slot).collect();
+        assert_eq!(slots, vec![2, 1]);
+
+        let (page, total) = database.get_balance_updates(Some(&user_id), Some(&public_key), 1, 10).await.unwrap();
+        assert_eq!(total, 3);
+        assert!(page.iter().all(|update| update.public_key == public_key));
+
+        sqlx::query("DELETE FROM balance_updates WHERE user_id = $1")
+            .bind(&user_id)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::wallet_events;
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::Row;
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn, error};
+use uuid::Uuid;
+
+/// Kind of event the indexer delivers. Each kind has its own destination (a backend endpoint,
+/// or the wallet funded callback), and the tag is stored with failed deliveries so the shared
+/// retry worker knows where to re-send.
+///
+/// Delivery is at-least-once: a retried event may already have been applied by the receiver,
+/// and retries are not ordered relative to live events, so receivers must treat events
+/// idempotently (each carries a unique `id`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryKind {
+    BalanceUpdate,
+    TransactionEvent,
+    WalletFunded,
+}
+
+impl DeliveryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryKind::BalanceUpdate => "balance_update",
+            DeliveryKind::TransactionEvent => "transaction_event",
+            DeliveryKind::WalletFunded => "wallet_funded",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "balance_update" => Some(DeliveryKind::BalanceUpdate),
+            "transaction_event" => Some(DeliveryKind::TransactionEvent),
+            "wallet_funded" => Some(DeliveryKind::WalletFunded),
+            _ => None,
+        }
+    }
+
+    fn url(&self, config: &Config) -> Result<String> {
+        match self {
+            DeliveryKind::BalanceUpdate => Ok(format!("{}/api/balance/update", config.backend_url)),
+            DeliveryKind::TransactionEvent => Ok(format!("{}/api/transactions/event", config.backend_url)),
+            DeliveryKind::WalletFunded => config.wallet_funded_callback_url.clone()
+                .ok_or_else(|| anyhow::anyhow!("WALLET_FUNDED_CALLBACK_URL is not set")),
+        }
+    }
+}
+
+/// Send one event to its kind's destination. Any transport error or non-2xx status is an error.
+pub async fn deliver<T: Serialize + ?Sized>(
+    client: &reqwest::Client,
+    config: &Config,
+    kind: DeliveryKind,
+    payload: &T,
+) -> Result<()> {
+    let response = client
+        .post(kind.url(config)?)
+        .json(payload)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("{} returned status {}", kind.as_str(), response.status()));
+    }
+
+    Ok(())
+}
+
+/// Park a delivery that failed so the retry worker picks it up later.
+pub async fn record_failed_delivery<T: Serialize>(
+    database: &Database,
+    config: &Config,
+    kind: DeliveryKind,
+    payload: &T,
+    error: &str,
+) -> Result<()> {
+    sqlx::query(
+        "
+        INSERT INTO failed_deliveries (id, delivery_type, payload, attempts, last_error, status, next_attempt_at)
+        VALUES ($1, $2, $3, 1, $4, 'pending', NOW() + make_interval(secs => $5))
+        "
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(kind.as_str())
+    .bind(serde_json::to_value(payload)?)
+    .bind(error)
+    .bind(retry_delay_secs(config.delivery_retry_interval_secs, 1) as f64)
+    .execute(database.get_pool().await)
+    .await?;
+
+    warn!("Queued failed {} delivery for retry: {}", kind.as_str(), error);
+    Ok(())
+}
+
+/// Exponential backoff for the given attempt count, capped at 64x the base interval.
+fn retry_delay_secs(base_secs: u64, attempts: i32) -> u64 {
+    base_secs.saturating_mul(1u64 << (attempts - 1).clamp(0, 6))
+}
+
+/// Periodically re-send due failed deliveries of every kind. Rows that still fail after
+/// `delivery_max_attempts` are marked `dead` and left for manual inspection.
+pub async fn start_retry_worker(database: Database, config: Config) -> Result<()> {
+    info!("Starting failed delivery retry worker");
+
+    let client = reqwest::Client::new();
+
+    loop {
+        sleep(Duration::from_secs(config.delivery_retry_interval_secs)).await;
+
+        if let Err(e) = retry_due_deliveries(&database, &config, &client).await {
+            error!("Failed delivery retry pass failed: {}", e);
+        }
+    }
+}
+
+async fn retry_due_deliveries(database: &Database, config: &Config, client: &reqwest::Client) -> Result<()> {
+    let rows = sqlx::query(
+        "
+        SELECT id, delivery_type, payload, attempts
+        FROM failed_deliveries
+        WHERE status = 'pending' AND next_attempt_at <= NOW()
+        ORDER BY created_at
+        LIMIT 100
+        "
+    )
+    .fetch_all(database.get_pool().await)
+    .await?;
+
+    for row in rows {
+        let id: String = row.get("id");
+        let delivery_type: String = row.get("delivery_type");
+        let payload: serde_json::Value = row.get("payload");
+        let attempts: i32 = row.get("attempts");
+
+        let kind = match DeliveryKind::parse(&delivery_type) {
+            Some(kind) => kind,
+            None => {
+                warn!("Skipping failed delivery {} with unknown type {}", id, delivery_type);
+                continue;
+            }
+        };
+
+        match deliver(client, config, kind, &payload).await {
+            Ok(()) => {
+                if kind == DeliveryKind::WalletFunded {
+                    if let Some(event_id) = payload.get("id").and_then(|v| v.as_str()) {
+                        wallet_events::mark_notified(database, event_id).await?;
+                    }
+                }
+                sqlx::query("DELETE FROM failed_deliveries WHERE id = $1")
+                    .bind(&id)
+                    .execute(database.get_pool().await)
+                    .await?;
+                info!("Redelivered {} {} after {} attempts", kind.as_str(), id, attempts + 1);
+            }
+            Err(e) => {
+                let attempts = attempts + 1;
+                let status = if attempts as u32 >= config.delivery_max_attempts { "dead" } else { "pending" };
+
+                sqlx::query(
+                    "
+                    UPDATE failed_deliveries
+                    SET attempts = $2, last_error = $3, status = $4,
+                        next_attempt_at = NOW() + make_interval(secs => $5)
+                    WHERE id = $1
+                    "
+                )
+                .bind(&id)
+                .bind(attempts)
+                .bind(e.to_string())
+                .bind(status)
+                .bind(retry_delay_secs(config.delivery_retry_interval_secs, attempts) as f64)
+                .execute(database.get_pool().await)
+                .await?;
+
+                if status == "dead" {
+                    error!("Giving up on {} {} after {} attempts: {}", kind.as_str(), id, attempts, e);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delivery_kind_tags_round_trip() {
+        for kind in [DeliveryKind::BalanceUpdate, DeliveryKind::TransactionEvent, DeliveryKind::WalletFunded] {
+            assert_eq!(DeliveryKind::parse(kind.as_str()), Some(kind));
+        }
+        assert_eq!(DeliveryKind::parse("unknown"), None);
+    }
+}
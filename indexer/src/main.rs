@@ -1,20 +1,26 @@
+mod auth;
+mod balance_stream;
 mod config;
 mod database;
+mod delivery;
 mod models;
 mod registry;
 mod subscriber;
+mod wallet_events;
 mod yellowstone;
 mod routes;
 
-use actix_web::{web, App, HttpServer, middleware::Logger};
+use actix_web::{web, App, HttpServer, middleware::{from_fn, Logger}};
 use anyhow::Result;
 use std::sync::Arc;
 use tokio::signal;
-use tracing::{info, error};
+use tracing::{info, warn, error};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use balance_stream::BalanceBroadcaster;
 use config::Config;
 use database::Database;
+use delivery::DeliveryKind;
 use registry::PublicKeyRegistry;
 use subscriber::YellowstoneSubscriber;
 
@@ -44,9 +50,16 @@ async fn main() -> Result<()> {
     info!("Database migrations completed");
 
     // Initialize public key registry
-    let registry = Arc::new(PublicKeyRegistry::new(database.clone()).await?);
+    let registry = Arc::new(PublicKeyRegistry::new(database.clone(), config.registry_cache_capacity).await?);
     info!("Public key registry initialized");
 
+    // Pick up keys changed outside this process on a fixed schedule
+    let refresh_registry = registry.clone();
+    let refresh_interval = std::time::Duration::from_secs(config.registry_refresh_secs);
+    tokio::spawn(async move {
+        refresh_registry.run_periodic_refresh(refresh_interval).await;
+    });
+
     // Initialize Yellowstone subscriber
     let (subscriber, balance_rx, transaction_rx) = YellowstoneSubscriber::new(
         registry.clone(),
@@ -57,23 +70,46 @@ async fn main() -> Result<()> {
     
     info!("Yellowstone subscriber initialized");
 
+    // Balance updates are also streamed to WebSocket subscribers
+    let balance_broadcaster = BalanceBroadcaster::new();
+
     // Start balance processor
+    let balance_processor_broadcaster = balance_broadcaster.clone();
     let balance_processor_registry = registry.clone();
+    let balance_processor_database = database.clone();
     let balance_processor_config = config.clone();
     tokio::spawn(async move {
-        if let Err(e) = start_balance_processor(balance_rx, balance_processor_registry, balance_processor_config).await {
+        if let Err(e) = start_balance_processor(balance_rx, balance_processor_registry, balance_processor_database, balance_processor_config, balance_processor_broadcaster).await {
             error!("Balance processor error: {}", e);
         }
     });
 
     // Start transaction processor
+    let transaction_processor_database = database.clone();
     let transaction_processor_config = config.clone();
     tokio::spawn(async move {
-        if let Err(e) = start_transaction_processor(transaction_rx, transaction_processor_config).await {
+        if let Err(e) = start_transaction_processor(transaction_rx, transaction_processor_database, transaction_processor_config).await {
             error!("Transaction processor error: {}", e);
         }
     });
 
+    // Start retry worker for deliveries the backend rejected or never received
+    let retry_worker_database = database.clone();
+    let retry_worker_config = config.clone();
+    tokio::spawn(async move {
+        if let Err(e) = delivery::start_retry_worker(retry_worker_database, retry_worker_config).await {
+            error!("Delivery retry worker error: {}", e);
+        }
+    });
+
+    // Periodically write balance updates buffered for batched inserts
+    if config.balance_batch_size > 1 {
+        let flusher_subscriber = subscriber.clone();
+        tokio::spawn(async move {
+            flusher_subscriber.run_balance_flusher().await;
+        });
+    }
+
     // Start Yellowstone subscriber in background
     let yellowstone_subscriber = subscriber.clone();
     tokio::spawn(async move {
@@ -82,7 +118,16 @@ async fn main() -> Result<()> {
         }
     });
 
+    if config.api_key.is_none() {
+        warn!("INDEXER_API_KEY not set; read routes are unauthenticated");
+    }
+    if config.admin_api_key.is_none() {
+        warn!("INDEXER_ADMIN_KEY not set; key management and admin routes are disabled");
+    }
+
     // Start HTTP server
+    let shutdown_subscriber = subscriber.clone();
+    let server_config = config.clone();
     info!("Starting HTTP server on {}:{}", config.server_host, config.server_port);
     
     let server = HttpServer::new(move || {
@@ -90,9 +135,16 @@ async fn main() -> Result<()> {
             .app_data(web::Data::new(database.clone()))
             .app_data(web::Data::new(registry.clone()))
             .app_data(web::Data::new(subscriber.clone()))
+            .app_data(web::Data::new(server_config.clone()))
+            .app_data(web::Data::new(balance_broadcaster.clone()))
+            .wrap(from_fn(auth::require_api_key))
+            .wrap(auth::cors(&server_config))
             .wrap(Logger::default())
             .configure(routes::configure_routes)
     })
+    .workers(config.server_workers)
+    .keep_alive(std::time::Duration::from_secs(config.server_keep_alive_secs))
+    .client_request_timeout(std::time::Duration::from_millis(config.server_client_timeout_ms))
     .bind((config.server_host.clone(), config.server_port))?
     .run();
 
@@ -109,18 +161,29 @@ async fn main() -> Result<()> {
     }
 
     info!("Shutting down indexer service...");
+
+    match shutdown_subscriber.flush_balance_updates().await {
+        Ok(count) => info!("Flushed {} buffered balance updates", count),
+        Err(e) => error!("Failed to flush buffered balance updates: {}", e),
+    }
+
     Ok(())
 }
 
 async fn start_balance_processor(
     mut balance_rx: tokio::sync::mpsc::UnboundedReceiver<models::BalanceUpdate>,
     _registry: Arc<PublicKeyRegistry>,
+    database: Database,
     config: Config,
+    broadcaster: BalanceBroadcaster,
 ) -> Result<()> {
     info!("Starting balance processor");
 
+    let client = reqwest::Client::new();
+
     while let Some(balance_update) = balance_rx.recv().await {
-        if let Err(e) = process_balance_update(&balance_update, &config).await {
+        broadcaster.publish(&balance_update);
+        if let Err(e) = process_balance_update(&balance_update, &client, &database, &config).await {
             error!("Failed to process balance update: {}", e);
         }
     }
@@ -130,12 +193,15 @@ async fn start_balance_processor(
 
 async fn start_transaction_processor(
     mut transaction_rx: tokio::sync::mpsc::UnboundedReceiver<models::TransactionEvent>,
+    database: Database,
     config: Config,
 ) -> Result<()> {
     info!("Starting transaction processor");
 
+    let client = reqwest::Client::new();
+
     while let Some(transaction_event) = transaction_rx.recv().await {
-        if let Err(e) = process_transaction_event(&transaction_event, &config).await {
+        if let Err(e) = process_transaction_event(&transaction_event, &client, &database, &config).await {
             error!("Failed to process transaction event: {}", e);
         }
     }
@@ -145,41 +211,41 @@ async fn start_transaction_processor(
 
 async fn process_balance_update(
     balance_update: &models::BalanceUpdate,
+    client: &reqwest::Client,
+    database: &Database,
     config: &Config,
 ) -> Result<()> {
-    // Send balance update to main backend service
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&format!("{}/api/balance/update", config.backend_url))
-        .json(balance_update)
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        info!("Successfully sent balance update for user {} to backend", balance_update.user_id);
-    } else {
-        error!("Failed to send balance update to backend: status {}", response.status());
+    // Send balance update to main backend service, parking it for retry on failure
+    match delivery::deliver(client, config, DeliveryKind::BalanceUpdate, balance_update).await {
+        Ok(()) => {
+            info!("Successfully sent balance update for user {} to backend", balance_update.user_id);
+        }
+        Err(e) => {
+            error!("Failed to send balance update to backend: {}", e);
+            delivery::record_failed_delivery(database, config, DeliveryKind::BalanceUpdate, balance_update, &e.to_string()).await?;
+        }
     }
 
+    wallet_events::handle_balance_update(client, database, config, balance_update).await?;
+
     Ok(())
 }
 
 async fn process_transaction_event(
     transaction_event: &models::TransactionEvent,
+    client: &reqwest::Client,
+    database: &Database,
     config: &Config,
 ) -> Result<()> {
-    // Send transaction event to main backend service
-    let client = reqwest::Client::new();
-    let response = client
-        .post(&format!("{}/api/transactions/event", config.backend_url))
-        .json(transaction_event)
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        info!("Successfully sent transaction event {} to backend", transaction_event.signature);
-    } else {
-        error!("Failed to send transaction event to backend: status {}", response.status());
+    // Send transaction event to main backend service, parking it for retry on failure
+    match delivery::deliver(client, config, DeliveryKind::TransactionEvent, transaction_event).await {
+        Ok(()) => {
+            info!("Successfully sent transaction event {} to backend", transaction_event.signature);
+        }
+        Err(e) => {
+            error!("Failed to send transaction event to backend: {}", e);
+            delivery::record_failed_delivery(database, config, DeliveryKind::TransactionEvent, transaction_event, &e.to_string()).await?;
+        }
     }
 
     Ok(())
@@ -37,7 +37,7 @@ pub struct SubscribedKey {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "subscription_type", rename_all = "lowercase")]
 pub enum SubscriptionType {
     Account,      // Monitor account balance changes
@@ -45,6 +45,16 @@ pub enum SubscriptionType {
     Both,         // Monitor both account and transactions
 }
 
+impl SubscriptionType {
+    pub fn includes_accounts(&self) -> bool {
+        matches!(self, SubscriptionType::Account | SubscriptionType::Both)
+    }
+
+    pub fn includes_transactions(&self) -> bool {
+        matches!(self, SubscriptionType::Transaction | SubscriptionType::Both)
+    }
+}
+
 /// Records balance update events from the blockchain
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct BalanceUpdate {
@@ -62,8 +72,9 @@ pub struct BalanceUpdate {
     pub processed_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
-#[sqlx(type_name = "balance_change_type", rename_all = "lowercase")]
+// The Postgres enum spells the swap variants 'swapIn' / 'swapOut'
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "balance_change_type", rename_all = "camelCase")]
 pub enum BalanceChangeType {
     Increase,
     Decrease,
@@ -73,7 +84,27 @@ pub enum BalanceChangeType {
     Unknown,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
+impl BalanceChangeType {
+    pub const ALL: [BalanceChangeType; 6] = [
+        BalanceChangeType::Increase,
+        BalanceChangeType::Decrease,
+        BalanceChangeType::SwapIn,
+        BalanceChangeType::SwapOut,
+        BalanceChangeType::Transfer,
+        BalanceChangeType::Unknown,
+    ];
+
+    /// Parse a query value; case, `_` and `-` are ignored so `SwapIn`, `swapIn` and `swap_in` all match.
+    pub fn parse(value: &str) -> Option<Self> {
+        let normalized: String = value.chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        Self::ALL.into_iter().find(|change_type| format!("{:?}", change_type).to_ascii_lowercase() == normalized)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "transaction_event_type", rename_all = "lowercase")]
 pub enum TransactionEventType {
     Send,
@@ -82,7 +113,7 @@ pub enum TransactionEventType {
     Unknown,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "transaction_status", rename_all = "lowercase")]
 pub enum TransactionStatus {
     Success,
@@ -91,7 +122,7 @@ pub enum TransactionStatus {
 }
 
 /// Tracks transaction events for user accounts
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TransactionEvent {
     pub id: String,
     pub public_key: String,
@@ -116,7 +147,7 @@ pub struct AddPublicKeyRequest {
     pub subscription_type: SubscriptionType,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, Clone)]
 pub struct RemovePublicKeyRequest {
     pub user_id: String,
     pub public_key: String,
@@ -246,4 +277,16 @@ impl BalanceUpdate {
             processed_at: Utc::now(),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balance_change_type_parse() {
+        assert_eq!(BalanceChangeType::parse("SwapIn"), Some(BalanceChangeType::SwapIn));
+        assert_eq!(BalanceChangeType::parse("swap_out"), Some(BalanceChangeType::SwapOut));
+        assert_eq!(BalanceChangeType::parse("increase"), Some(BalanceChangeType::Increase));
+        assert_eq!(BalanceChangeType::parse("refund"), None);
+    }
+}
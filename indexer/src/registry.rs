@@ -1,23 +1,33 @@
-use crate::models::{SubscribedKey, AddPublicKeyRequest, RemovePublicKeyRequest};
+use crate::models::{SubscribedKey, SubscriptionType, AddPublicKeyRequest, RemovePublicKeyRequest};
 use crate::database::Database;
 use anyhow::Result;
+use lru::LruCache;
 use sqlx::Row;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::num::NonZeroUsize;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex, RwLock};
 use tracing::{info, warn, error};
 
 pub struct PublicKeyRegistry {
     db: Database,
-    // In-memory cache of active public keys for fast lookup
-    active_keys: Arc<RwLock<HashSet<String>>>,
+    // In-memory cache of active public keys and what to monitor them for
+    active_keys: Arc<RwLock<HashMap<String, SubscriptionType>>>,
+    // Bounded cache of subscription details, consulted on every account update
+    subscriptions: SubscriptionCache,
+    // Bumped whenever the active key set changes, so the subscriber can re-subscribe
+    key_changes: watch::Sender<u64>,
 }
 
 impl PublicKeyRegistry {
-    pub async fn new(db: Database) -> Result<Self> {
+    pub async fn new(db: Database, cache_capacity: usize) -> Result<Self> {
         let registry = Self {
             db,
-            active_keys: Arc::new(RwLock::new(HashSet::new())),
+            active_keys: Arc::new(RwLock::new(HashMap::new())),
+            subscriptions: SubscriptionCache::new(cache_capacity),
+            key_changes: watch::Sender::new(0),
         };
 
         // Load existing keys from database
@@ -32,7 +42,7 @@ impl PublicKeyRegistry {
         info!("Adding public key {} for user {}", request.public_key, request.user_id);
 
         // Validate public key format
-        self.validate_public_key(&request.public_key)?;
+        validate_public_key(&request.public_key)?;
 
         let subscribed_key = SubscribedKey::new(
             request.user_id.clone(),
@@ -41,17 +51,7 @@ impl PublicKeyRegistry {
         );
 
         // Insert into database
-        let query = "
-            INSERT INTO subscribed_keys (id, user_id, public_key, is_active, subscription_type, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            ON CONFLICT (user_id, public_key) 
-            DO UPDATE SET 
-                is_active = $4,
-                subscription_type = $5,
-                updated_at = $7
-        ";
-        
-        sqlx::query(query)
+        sqlx::query(UPSERT_SUBSCRIBED_KEY)
             .bind(&subscribed_key.id)
             .bind(&subscribed_key.user_id)
             .bind(&subscribed_key.public_key)
@@ -65,8 +65,11 @@ impl PublicKeyRegistry {
         // Add to in-memory cache
         {
             let mut keys = self.active_keys.write().await;
-            keys.insert(request.public_key.clone());
+            insert_key_type(&mut keys, request.public_key.clone(), subscribed_key.subscription_type.clone());
         }
+        // A re-added key may have been reactivated with a different subscription type
+        self.subscriptions.invalidate(&request.public_key).await;
+        self.notify_key_change();
 
         info!("Successfully added public key {} for user {}", request.public_key, request.user_id);
         Ok(subscribed_key)
@@ -87,9 +90,18 @@ impl PublicKeyRegistry {
         let removed = result.rows_affected() > 0;
 
         if removed {
-            // Remove from in-memory cache
+            // Other users may still watch this key, so its entry is rebuilt from their rows
+            let remaining: Vec<SubscriptionType> = sqlx::query_scalar(
+                "SELECT subscription_type FROM subscribed_keys WHERE public_key = $1 AND is_active = true"
+            )
+            .bind(&request.public_key)
+            .fetch_all(self.db.get_pool().await)
+            .await?;
+
             let mut keys = self.active_keys.write().await;
-            keys.remove(&request.public_key);
+            rebuild_key_entry(&mut keys, &request.public_key, remaining);
+            self.subscriptions.invalidate(&request.public_key).await;
+            self.notify_key_change();
             info!("Successfully removed public key {} for user {}", request.public_key, request.user_id);
         } else {
             warn!("Public key {} not found for user {}", request.public_key, request.user_id);
@@ -98,24 +110,76 @@ impl PublicKeyRegistry {
         Ok(removed)
     }
 
+    /// A receiver that is marked changed whenever a key is added or removed. Changes made
+    /// before this call are already seen.
+    pub fn watch_key_changes(&self) -> watch::Receiver<u64> {
+        self.key_changes.subscribe()
+    }
+
+    fn notify_key_change(&self) {
+        self.key_changes.send_modify(|generation| *generation += 1);
+    }
+
     /// Get all active public keys
     pub async fn get_active_public_keys(&self) -> Vec<String> {
         let keys = self.active_keys.read().await;
-        keys.iter().cloned().collect()
+        keys.keys().cloned().collect()
+    }
+
+    /// Get all active public keys with the kind of updates they are subscribed to
+    pub async fn get_active_keys_with_type(&self) -> Vec<(String, SubscriptionType)> {
+        let keys = self.active_keys.read().await;
+        keys.iter().map(|(key, subscription_type)| (key.clone(), subscription_type.clone())).collect()
+    }
+
+    /// The keys among `public_keys` that are subscribed to transaction updates.
+    pub async fn transaction_keys(&self, public_keys: &[String]) -> HashSet<String> {
+        let keys = self.active_keys.read().await;
+        public_keys.iter()
+            .filter(|key| keys.get(*key).is_some_and(|t| t.includes_transactions()))
+            .cloned()
+            .collect()
     }
 
     /// Get all subscribed keys for a user
-    pub async fn get_user_keys(&self, _user_id: &str) -> Result<Vec<SubscribedKey>> {
-        // For now, return empty vector to avoid sqlx offline issues
-        // In production, implement proper query handling
-        Ok(vec![])
+    pub async fn get_user_keys(&self, user_id: &str) -> Result<Vec<SubscribedKey>> {
+        let keys = sqlx::query_as::<_, SubscribedKey>(
+            "
+            SELECT id, user_id, public_key, is_active, subscription_type, created_at, updated_at
+            FROM subscribed_keys
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "
+        )
+        .bind(user_id)
+        .fetch_all(self.db.get_pool().await)
+        .await?;
+
+        Ok(keys)
     }
 
     /// Get subscription details for a specific public key
-    pub async fn get_key_subscription(&self, _public_key: &str) -> Result<Option<SubscribedKey>> {
-        // For now, return None to avoid sqlx offline issues
-        // In production, implement proper query handling
-        Ok(None)
+    pub async fn get_key_subscription(&self, public_key: &str) -> Result<Option<SubscribedKey>> {
+        self.subscriptions
+            .get_or_load(public_key, || self.load_key_subscription(public_key))
+            .await
+    }
+
+    async fn load_key_subscription(&self, public_key: &str) -> Result<Option<SubscribedKey>> {
+        let subscription = sqlx::query_as::<_, SubscribedKey>(
+            "
+            SELECT id, user_id, public_key, is_active, subscription_type, created_at, updated_at
+            FROM subscribed_keys
+            WHERE public_key = $1 AND is_active = true
+            ORDER BY updated_at DESC
+            LIMIT 1
+            "
+        )
+        .bind(public_key)
+        .fetch_optional(self.db.get_pool().await)
+        .await?;
+
+        Ok(subscription)
     }
 
     /// Refresh the in-memory cache from database
@@ -123,76 +187,123 @@ impl PublicKeyRegistry {
         info!("Refreshing public key cache from database");
 
         let rows = sqlx::query(
-            "SELECT public_key FROM subscribed_keys WHERE is_active = true"
+            "SELECT public_key, subscription_type FROM subscribed_keys WHERE is_active = true"
         )
         .fetch_all(self.db.get_pool().await)
         .await?;
 
-        let mut keys = self.active_keys.write().await;
-        keys.clear();
+        let mut refreshed = HashMap::new();
         for row in rows {
             let public_key: String = row.get("public_key");
-            keys.insert(public_key);
+            let subscription_type: SubscriptionType = row.get("subscription_type");
+            insert_key_type(&mut refreshed, public_key, subscription_type);
         }
 
+        let mut keys = self.active_keys.write().await;
+        let changed = *keys != refreshed;
+        *keys = refreshed;
+        self.subscriptions.clear().await;
         info!("Refreshed cache with {} active public keys", keys.len());
+        drop(keys);
+
+        // Keys added or removed by another instance, or directly in the database
+        if changed {
+            self.notify_key_change();
+        }
         Ok(())
     }
 
+    /// Reload the cache from the database every `interval`, independent of stream traffic.
+    pub async fn run_periodic_refresh(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately, and `new` has just loaded the cache
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.refresh_cache().await {
+                warn!("Failed to refresh registry cache: {}", e);
+            }
+        }
+    }
+
     /// Get statistics about subscribed keys
     pub async fn get_stats(&self) -> Result<PublicKeyRegistryStats> {
-        // Return default stats to avoid sqlx offline issues
-        // In production, implement proper query handling
-        Ok(PublicKeyRegistryStats {
-            total_keys: 0,
-            active_keys: 0,
-            inactive_keys: 0,
-            unique_users: 0,
-        })
+        query_stats(self.db.get_pool().await).await
     }
 
     /// Check if a public key is being monitored
     pub async fn is_key_monitored(&self, public_key: &str) -> bool {
         let keys = self.active_keys.read().await;
-        keys.contains(public_key)
+        keys.contains_key(public_key)
     }
 
-    /// Validate public key format
-    fn validate_public_key(&self, public_key: &str) -> Result<()> {
-        // Solana public keys are base58 encoded and should be 44 characters
-        if public_key.len() != 44 {
-            return Err(anyhow::anyhow!("Invalid public key length: expected 44 characters, got {}", public_key.len()));
+    /// Bulk add public keys (useful for migration or batch operations). The batch is
+    /// validated up front and inserted in a single transaction, so it is applied entirely
+    /// or not at all.
+    pub async fn bulk_add_keys(&self, keys: Vec<AddPublicKeyRequest>) -> Result<BulkAddResult> {
+        let validation = validate_bulk_add(&keys);
+        if validation.iter().any(|result| result.status == BulkKeyStatus::Invalid) {
+            return Err(anyhow::anyhow!("Bulk add batch contains invalid keys"));
         }
 
-        // Try to decode as base58
-        match bs58::decode(public_key).into_vec() {
-            Ok(bytes) => {
-                if bytes.len() != 32 {
-                    return Err(anyhow::anyhow!("Invalid public key: decoded length should be 32 bytes, got {}", bytes.len()));
-                }
-            }
-            Err(_) => {
-                return Err(anyhow::anyhow!("Invalid public key: not valid base58"));
+        let subscribed_keys: Vec<SubscribedKey> = keys.into_iter()
+            .map(|request| SubscribedKey::new(request.user_id, request.public_key, request.subscription_type))
+            .collect();
+
+        let mut tx = self.db.get_pool().await.begin().await?;
+        for subscribed_key in &subscribed_keys {
+            sqlx::query(UPSERT_SUBSCRIBED_KEY)
+                .bind(&subscribed_key.id)
+                .bind(&subscribed_key.user_id)
+                .bind(&subscribed_key.public_key)
+                .bind(subscribed_key.is_active)
+                .bind(&subscribed_key.subscription_type)
+                .bind(subscribed_key.created_at)
+                .bind(subscribed_key.updated_at)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        // Caches are only touched once the whole batch is committed
+        {
+            let mut active_keys = self.active_keys.write().await;
+            for subscribed_key in &subscribed_keys {
+                insert_key_type(&mut active_keys, subscribed_key.public_key.clone(), subscribed_key.subscription_type.clone());
             }
         }
+        for subscribed_key in &subscribed_keys {
+            self.subscriptions.invalidate(&subscribed_key.public_key).await;
+        }
+        self.notify_key_change();
 
-        Ok(())
+        info!("Bulk added {} public keys", subscribed_keys.len());
+
+        let results = validation.into_iter()
+            .map(|result| BulkKeyResult { status: BulkKeyStatus::Added, ..result })
+            .collect();
+
+        Ok(BulkAddResult {
+            added: subscribed_keys.len() as u32,
+            results,
+        })
     }
 
-    /// Bulk add public keys (useful for migration or batch operations)
-    pub async fn bulk_add_keys(&self, keys: Vec<AddPublicKeyRequest>) -> Result<BulkOperationResult> {
+    /// Bulk remove public keys, e.g. every key of a deactivated user. Keys that are already
+    /// inactive count as successful, so callers can safely retry a partial removal.
+    pub async fn bulk_remove_keys(&self, keys: Vec<RemovePublicKeyRequest>) -> Result<BulkOperationResult> {
         let mut successful = 0;
         let mut failed = 0;
         let mut errors = Vec::new();
 
         for key_request in keys {
-            match self.add_public_key(key_request.clone()).await {
+            match self.remove_public_key(key_request.clone()).await {
                 Ok(_) => successful += 1,
                 Err(e) => {
                     failed += 1;
-                    errors.push(format!("Failed to add key {} for user {}: {}", 
+                    errors.push(format!("Failed to remove key {} for user {}: {}",
                         key_request.public_key, key_request.user_id, e));
-                    error!("Failed to add key {}: {}", key_request.public_key, e);
+                    error!("Failed to remove key {}: {}", key_request.public_key, e);
                 }
             }
         }
@@ -205,6 +316,154 @@ impl PublicKeyRegistry {
     }
 }
 
+const UPSERT_SUBSCRIBED_KEY: &str = "
+    INSERT INTO subscribed_keys (id, user_id, public_key, is_active, subscription_type, created_at, updated_at)
+    VALUES ($1, $2, $3, $4, $5, $6, $7)
+    ON CONFLICT (user_id, public_key) 
+    DO UPDATE SET 
+        is_active = $4,
+        subscription_type = $5,
+        updated_at = $7
+";
+
+/// Key and user counts over `subscribed_keys`, in one pass.
+async fn query_stats<'e, E: sqlx::PgExecutor<'e>>(executor: E) -> Result<PublicKeyRegistryStats> {
+    let row = sqlx::query(
+        "
+        SELECT COUNT(*) AS total_keys,
+               COUNT(*) FILTER (WHERE is_active) AS active_keys,
+               COUNT(*) FILTER (WHERE NOT is_active) AS inactive_keys,
+               COUNT(DISTINCT user_id) AS unique_users
+        FROM subscribed_keys
+        "
+    )
+    .fetch_one(executor)
+    .await?;
+
+    let count = |column: &str| -> Result<u32> {
+        let value: i64 = row.try_get(column)?;
+        Ok(u32::try_from(value).unwrap_or(u32::MAX))
+    };
+
+    Ok(PublicKeyRegistryStats {
+        total_keys: count("total_keys")?,
+        active_keys: count("active_keys")?,
+        inactive_keys: count("inactive_keys")?,
+        unique_users: count("unique_users")?,
+    })
+}
+
+/// Largest batch `bulk_add_keys` accepts in one request.
+pub const MAX_BULK_ADD_KEYS: usize = 1000;
+
+/// Validate public key format
+fn validate_public_key(public_key: &str) -> Result<()> {
+    // Solana public keys are base58 encoded and should be 44 characters
+    if public_key.len() != 44 {
+        return Err(anyhow::anyhow!("Invalid public key length: expected 44 characters, got {}", public_key.len()));
+    }
+
+    // Try to decode as base58
+    match bs58::decode(public_key).into_vec() {
+        Ok(bytes) => {
+            if bytes.len() != 32 {
+                return Err(anyhow::anyhow!("Invalid public key: decoded length should be 32 bytes, got {}", bytes.len()));
+            }
+        }
+        Err(_) => {
+            return Err(anyhow::anyhow!("Invalid public key: not valid base58"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check every key of a bulk add before anything is written. Each entry gets a result at its
+/// index: `Invalid` with the reason, or `Skipped` if it is fine but the batch is rejected.
+pub fn validate_bulk_add(keys: &[AddPublicKeyRequest]) -> Vec<BulkKeyResult> {
+    let mut seen: HashMap<(&str, &str), usize> = HashMap::new();
+
+    keys.iter().enumerate().map(|(index, request)| {
+        let error = if request.user_id.trim().is_empty() {
+            Some("user_id cannot be empty".to_string())
+        } else if let Err(e) = validate_public_key(&request.public_key) {
+            Some(e.to_string())
+        } else if let Some(first) = seen.get(&(request.user_id.as_str(), request.public_key.as_str())) {
+            Some(format!("Duplicate of the key at index {}", first))
+        } else {
+            seen.insert((request.user_id.as_str(), request.public_key.as_str()), index);
+            None
+        };
+
+        BulkKeyResult {
+            index,
+            user_id: request.user_id.clone(),
+            public_key: request.public_key.clone(),
+            status: if error.is_some() { BulkKeyStatus::Invalid } else { BulkKeyStatus::Skipped },
+            error,
+        }
+    }).collect()
+}
+
+/// A key watched by several users is monitored for everything any of them asked for.
+fn insert_key_type(keys: &mut HashMap<String, SubscriptionType>, public_key: String, subscription_type: SubscriptionType) {
+    let merged = match keys.get(&public_key) {
+        Some(existing) if *existing != subscription_type => SubscriptionType::Both,
+        _ => subscription_type,
+    };
+    keys.insert(public_key, merged);
+}
+
+/// Replace `public_key`'s entry with the merge of the subscriptions still active for it,
+/// dropping it when none are left.
+fn rebuild_key_entry(keys: &mut HashMap<String, SubscriptionType>, public_key: &str, remaining: Vec<SubscriptionType>) {
+    keys.remove(public_key);
+    for subscription_type in remaining {
+        insert_key_type(keys, public_key.to_string(), subscription_type);
+    }
+}
+
+/// LRU of `public_key -> SubscribedKey`. Only found subscriptions are cached; misses go to
+/// the loader every time so newly added keys are picked up immediately.
+struct SubscriptionCache {
+    entries: Mutex<LruCache<String, SubscribedKey>>,
+}
+
+impl SubscriptionCache {
+    fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    async fn get_or_load<F, Fut>(&self, public_key: &str, load: F) -> Result<Option<SubscribedKey>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<SubscribedKey>>>,
+    {
+        if let Some(subscription) = self.entries.lock().await.get(public_key) {
+            return Ok(Some(subscription.clone()));
+        }
+
+        // Lock is not held across the load so other keys are not blocked on the DB
+        let subscription = load().await?;
+        if let Some(subscription) = &subscription {
+            self.entries.lock().await.put(public_key.to_string(), subscription.clone());
+        }
+
+        Ok(subscription)
+    }
+
+    async fn invalidate(&self, public_key: &str) {
+        self.entries.lock().await.pop(public_key);
+    }
+
+    async fn clear(&self) {
+        self.entries.lock().await.clear();
+    }
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct PublicKeyRegistryStats {
     pub total_keys: u32,
@@ -213,9 +472,234 @@ pub struct PublicKeyRegistryStats {
     pub unique_users: u32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkKeyStatus {
+    Added,
+    Invalid,
+    Skipped,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct BulkKeyResult {
+    pub index: usize,
+    pub user_id: String,
+    pub public_key: String,
+    pub status: BulkKeyStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct BulkAddResult {
+    pub added: u32,
+    pub results: Vec<BulkKeyResult>,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct BulkOperationResult {
     pub successful: u32,
     pub failed: u32,
     pub errors: Vec<String>,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SubscriptionType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const KEY: &str = "So11111111111111111111111111111111111111112";
+
+    fn subscription() -> SubscribedKey {
+        SubscribedKey::new("user-1".to_string(), KEY.to_string(), SubscriptionType::Both)
+    }
+
+    fn add(user_id: &str, public_key: &str) -> AddPublicKeyRequest {
+        AddPublicKeyRequest {
+            user_id: user_id.to_string(),
+            public_key: public_key.to_string(),
+            subscription_type: SubscriptionType::Both,
+        }
+    }
+
+    #[test]
+    fn test_validate_bulk_add_reports_each_bad_index() {
+        let valid = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        let keys = vec![
+            add("user-1", valid),
+            add("user-1", "not-a-key"),
+            add("", valid),
+            add("user-1", valid),
+        ];
+
+        let results = validate_bulk_add(&keys);
+
+        let statuses: Vec<BulkKeyStatus> = results.iter().map(|r| r.status).collect();
+        assert_eq!(statuses, vec![
+            BulkKeyStatus::Skipped,
+            BulkKeyStatus::Invalid,
+            BulkKeyStatus::Invalid,
+            BulkKeyStatus::Invalid,
+        ]);
+        assert_eq!(results[3].error.as_deref(), Some("Duplicate of the key at index 0"));
+        assert!(results[0].error.is_none());
+    }
+
+    #[test]
+    fn test_rebuilt_entry_keeps_other_users_subscriptions() {
+        let mut keys = HashMap::new();
+        insert_key_type(&mut keys, KEY.to_string(), SubscriptionType::Account);
+        insert_key_type(&mut keys, KEY.to_string(), SubscriptionType::Transaction);
+        assert_eq!(keys[KEY], SubscriptionType::Both);
+
+        // The transaction subscriber left; the account subscriber still needs balance updates
+        rebuild_key_entry(&mut keys, KEY, vec![SubscriptionType::Account]);
+        assert_eq!(keys[KEY], SubscriptionType::Account);
+
+        rebuild_key_entry(&mut keys, KEY, Vec::new());
+        assert!(!keys.contains_key(KEY));
+    }
+
+    #[tokio::test]
+    async fn test_second_lookup_is_served_from_cache() {
+        let cache = SubscriptionCache::new(16);
+        let counter = AtomicUsize::new(0);
+        let loads = &counter;
+        let load = move || async move {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(subscription()))
+        };
+
+        let first = cache.get_or_load(KEY, load).await.unwrap();
+        let second = cache.get_or_load(KEY, load).await.unwrap();
+
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(first.unwrap().id, second.unwrap().id);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_forces_reload() {
+        let cache = SubscriptionCache::new(16);
+        let counter = AtomicUsize::new(0);
+        let loads = &counter;
+        let load = move || async move {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(subscription()))
+        };
+
+        cache.get_or_load(KEY, load).await.unwrap();
+        cache.invalidate(KEY).await;
+        cache.get_or_load(KEY, load).await.unwrap();
+
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_added_key_is_read_back() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL");
+        let registry = PublicKeyRegistry::new(Database::new(&url).await.unwrap(), 16).await.unwrap();
+        let user_id = format!("registry-test-{}", uuid::Uuid::new_v4());
+        // A high leading byte keeps the base58 form at the full 44 characters
+        let mut key_bytes = rand::random::<[u8; 32]>();
+        key_bytes[0] |= 0x80;
+        let public_key = bs58::encode(key_bytes).into_string();
+        let key_changes = registry.watch_key_changes();
+
+        registry.add_public_key(AddPublicKeyRequest {
+            user_id: user_id.clone(),
+            public_key: public_key.clone(),
+            subscription_type: SubscriptionType::Both,
+        }).await.unwrap();
+        // The subscriber re-subscribes on this
+        assert!(key_changes.has_changed().unwrap());
+
+        let keys = registry.get_user_keys(&user_id).await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].public_key, public_key);
+        assert_eq!(keys[0].subscription_type, SubscriptionType::Both);
+
+        let subscription = registry.get_key_subscription(&public_key).await.unwrap().unwrap();
+        assert_eq!(subscription.user_id, user_id);
+
+        // Deactivated keys are still listed for the user but no longer resolve a subscription
+        registry.remove_public_key(RemovePublicKeyRequest {
+            user_id: user_id.clone(),
+            public_key: public_key.clone(),
+        }).await.unwrap();
+        assert!(registry.get_key_subscription(&public_key).await.unwrap().is_none());
+        assert!(!registry.get_user_keys(&user_id).await.unwrap()[0].is_active);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_periodic_refresh_picks_up_database_changes() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL");
+        let db = Database::new(&url).await.unwrap();
+        let registry = Arc::new(PublicKeyRegistry::new(db.clone(), 16).await.unwrap());
+        let refresh = tokio::spawn(registry.clone().run_periodic_refresh(Duration::from_millis(200)));
+
+        // Written behind the registry's back, as another instance would, with no stream traffic
+        let key = SubscribedKey::new(format!("refresh-test-{}", uuid::Uuid::new_v4()), uuid::Uuid::new_v4().to_string(), SubscriptionType::Account);
+        sqlx::query(UPSERT_SUBSCRIBED_KEY)
+            .bind(&key.id)
+            .bind(&key.user_id)
+            .bind(&key.public_key)
+            .bind(key.is_active)
+            .bind(&key.subscription_type)
+            .bind(key.created_at)
+            .bind(key.updated_at)
+            .execute(db.get_pool().await)
+            .await
+            .unwrap();
+        assert!(!registry.is_key_monitored(&key.public_key).await);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(registry.is_key_monitored(&key.public_key).await);
+
+        refresh.abort();
+        sqlx::query("DELETE FROM subscribed_keys WHERE user_id = $1")
+            .bind(&key.user_id)
+            .execute(db.get_pool().await)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_stats_count_active_inactive_and_users() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL");
+        let db = Database::new(&url).await.unwrap();
+
+        // Measure the seeded rows as a delta inside one snapshot, then roll them back
+        let mut tx = db.get_pool().await.begin().await.unwrap();
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ").execute(&mut *tx).await.unwrap();
+        let before = query_stats(&mut *tx).await.unwrap();
+
+        let users = [format!("stats-test-{}", uuid::Uuid::new_v4()), format!("stats-test-{}", uuid::Uuid::new_v4())];
+        for (user_id, is_active) in [(&users[0], true), (&users[0], false), (&users[1], true)] {
+            let mut key = SubscribedKey::new(user_id.clone(), uuid::Uuid::new_v4().to_string(), SubscriptionType::Account);
+            key.is_active = is_active;
+            sqlx::query(UPSERT_SUBSCRIBED_KEY)
+                .bind(&key.id)
+                .bind(&key.user_id)
+                .bind(&key.public_key)
+                .bind(key.is_active)
+                .bind(&key.subscription_type)
+                .bind(key.created_at)
+                .bind(key.updated_at)
+                .execute(&mut *tx)
+                .await
+                .unwrap();
+        }
+
+        let after = query_stats(&mut *tx).await.unwrap();
+        tx.rollback().await.unwrap();
+
+        assert_eq!(after.total_keys - before.total_keys, 3);
+        assert_eq!(after.active_keys - before.active_keys, 2);
+        assert_eq!(after.inactive_keys - before.inactive_keys, 1);
+        assert_eq!(after.unique_users - before.unique_users, 2);
+    }
+}
@@ -1,8 +1,11 @@
-use crate::models::{AddPublicKeyRequest, RemovePublicKeyRequest, PublicKeyResponse};
-use crate::registry::{PublicKeyRegistry, PublicKeyRegistryStats};
+use crate::models::{AddPublicKeyRequest, RemovePublicKeyRequest, PublicKeyResponse, BalanceChangeType, BalanceUpdateResponse};
+use crate::registry::{validate_bulk_add, BulkKeyStatus, PublicKeyRegistry, PublicKeyRegistryStats, MAX_BULK_ADD_KEYS};
 use crate::subscriber::{YellowstoneSubscriber, YellowstoneStats};
+use crate::balance_stream::stream_balances;
 use crate::database::Database;
-use actix_web::{web, HttpResponse, Result as ActixResult};
+use crate::config::Config;
+use actix_web::{error::{InternalError, JsonPayloadError}, web, HttpRequest, HttpResponse, Result as ActixResult};
+use common::serde_error::field_from_message;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::{info, error};
@@ -29,6 +32,8 @@ pub struct DatabaseHealth {
 pub struct ErrorResponse {
     pub error: String,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
@@ -37,9 +42,16 @@ impl ErrorResponse {
         Self {
             error: error.to_string(),
             message: message.to_string(),
+            details: None,
             timestamp: chrono::Utc::now(),
         }
     }
+
+    /// Attach structured context, e.g. per-item validation errors.
+    pub fn with_details<T: Serialize>(mut self, details: &T) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
 }
 
 // Success response
@@ -190,6 +202,97 @@ pub async fn get_user_keys(
     }
 }
 
+const DEFAULT_BALANCE_UPDATES_PER_PAGE: i32 = 50;
+const MAX_BALANCE_UPDATES_PER_PAGE: i32 = 200;
+
+#[derive(Deserialize)]
+pub struct BalanceUpdatesQuery {
+    pub change_type: Option<String>,
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+}
+
+// Get user's balance updates endpoint, optionally filtered by change type
+pub async fn get_balance_updates(
+    db: web::Data<Database>,
+    path: web::Path<String>,
+    query: web::Query<BalanceUpdatesQuery>,
+) -> ActixResult<HttpResponse> {
+    let user_id = path.into_inner();
+
+    let change_type = match query.change_type.as_deref() {
+        None => None,
+        Some(value) => match BalanceChangeType::parse(value) {
+            Some(change_type) => Some(change_type),
+            None => {
+                let valid: Vec<String> = BalanceChangeType::ALL.iter().map(|t| format!("{:?}", t)).collect();
+                return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+                    "InvalidChangeType",
+                    &format!("change_type must be one of: {}", valid.join(", ")),
+                )));
+            }
+        },
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_BALANCE_UPDATES_PER_PAGE).clamp(1, MAX_BALANCE_UPDATES_PER_PAGE);
+    info!("Getting balance updates for user {} (change_type: {:?}, page {})", user_id, change_type, page);
+
+    let offset = (page as i64 - 1) * per_page as i64;
+    match db.get_balance_updates_by_type(&user_id, change_type, per_page as i64, offset).await {
+        Ok((updates, total_count)) => {
+            Ok(HttpResponse::Ok().json(SuccessResponse::new(BalanceUpdateResponse {
+                updates,
+                total_count,
+                page,
+                per_page,
+            })))
+        }
+        Err(e) => {
+            error!("Failed to get balance updates: {}", e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse::new(
+                "GetBalanceUpdatesError",
+                &format!("Failed to get balance updates: {}", e),
+            )))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AllBalanceUpdatesQuery {
+    pub user_id: Option<String>,
+    pub public_key: Option<String>,
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+}
+
+// List balance updates across users, optionally filtered by user and/or public key
+pub async fn list_balance_updates(
+    db: web::Data<Database>,
+    query: web::Query<AllBalanceUpdatesQuery>,
+) -> ActixResult<HttpResponse> {
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_BALANCE_UPDATES_PER_PAGE).clamp(1, MAX_BALANCE_UPDATES_PER_PAGE);
+    info!("Listing balance updates (user_id: {:?}, public_key: {:?}, page {})", query.user_id, query.public_key, page);
+
+    match db.get_balance_updates(query.user_id.as_deref(), query.public_key.as_deref(), page, per_page).await {
+        Ok((updates, total_count)) => {
+            Ok(HttpResponse::Ok().json(SuccessResponse::new(BalanceUpdateResponse {
+                updates,
+                total_count,
+                page,
+                per_page,
+            })))
+        }
+        Err(e) => {
+            error!("Failed to list balance updates: {}", e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse::new(
+                "GetBalanceUpdatesError",
+                &format!("Failed to get balance updates: {}", e),
+            )))
+        }
+    }
+}
+
 // Get public key details endpoint
 pub async fn get_public_key_details(
     registry: web::Data<Arc<PublicKeyRegistry>>,
@@ -274,7 +377,25 @@ pub async fn bulk_add_keys(
 ) -> ActixResult<HttpResponse> {
     info!("Bulk adding {} public keys", request.keys.len());
 
-    match registry.bulk_add_keys(request.keys.clone()).await {
+    let keys = request.into_inner().keys;
+    if keys.is_empty() || keys.len() > MAX_BULK_ADD_KEYS {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "InvalidBatchSize",
+            &format!("A bulk add must contain between 1 and {} keys, got {}", MAX_BULK_ADD_KEYS, keys.len()),
+        )));
+    }
+
+    // Reject the whole batch before any insert if a single key is bad
+    let validation = validate_bulk_add(&keys);
+    let invalid = validation.iter().filter(|result| result.status == BulkKeyStatus::Invalid).count();
+    if invalid > 0 {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "BulkValidationError",
+            &format!("{} of {} keys are invalid; nothing was added", invalid, keys.len()),
+        ).with_details(&validation)));
+    }
+
+    match registry.bulk_add_keys(keys).await {
         Ok(result) => {
             Ok(HttpResponse::Ok().json(SuccessResponse::new(result)))
         }
@@ -288,17 +409,90 @@ pub async fn bulk_add_keys(
     }
 }
 
+#[derive(Deserialize)]
+pub struct BulkRemoveKeysRequest {
+    pub keys: Vec<RemovePublicKeyRequest>,
+}
+
+// Bulk remove public keys endpoint
+pub async fn bulk_remove_keys(
+    registry: web::Data<Arc<PublicKeyRegistry>>,
+    request: web::Json<BulkRemoveKeysRequest>,
+) -> ActixResult<HttpResponse> {
+    info!("Bulk removing {} public keys", request.keys.len());
+
+    match registry.bulk_remove_keys(request.into_inner().keys).await {
+        Ok(result) => {
+            Ok(HttpResponse::Ok().json(SuccessResponse::new(result)))
+        }
+        Err(e) => {
+            error!("Failed to bulk remove keys: {}", e);
+            Ok(HttpResponse::InternalServerError().json(ErrorResponse::new(
+                "BulkRemoveKeysError",
+                &format!("Failed to bulk remove keys: {}", e),
+            )))
+        }
+    }
+}
+
+// Effective configuration with credentials masked
+pub async fn get_config(config: web::Data<Config>) -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(SuccessResponse::new(config.redacted())))
+}
+
+fn extractor_error(error: &str, message: String) -> ErrorResponse {
+    let field = field_from_message(&message);
+    let response = ErrorResponse::new(error, &message);
+    match field {
+        Some(field) => response.with_details(&serde_json::json!({ "field": field })),
+        None => response,
+    }
+}
+
+/// Malformed bodies get the `ErrorResponse` envelope instead of actix's plain-text 400.
+fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _req: &HttpRequest| {
+        let response = match &err {
+            JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+                HttpResponse::PayloadTooLarge().json(extractor_error("PayloadTooLarge", err.to_string()))
+            }
+            JsonPayloadError::ContentType => {
+                HttpResponse::UnsupportedMediaType().json(extractor_error("UnsupportedMediaType", err.to_string()))
+            }
+            JsonPayloadError::Deserialize(e) => {
+                HttpResponse::BadRequest().json(extractor_error("InvalidRequestBody", e.to_string()))
+            }
+            _ => HttpResponse::BadRequest().json(extractor_error("InvalidRequestBody", err.to_string())),
+        };
+        InternalError::from_response(err, response).into()
+    })
+}
+
+fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|err, _req: &HttpRequest| {
+        let response = HttpResponse::BadRequest().json(extractor_error("InvalidQuery", err.to_string()));
+        InternalError::from_response(err, response).into()
+    })
+}
+
 // Configure routes
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(json_config());
+    cfg.app_data(query_config());
     cfg.service(
         web::scope("/api/v1")
             .route("/health", web::get().to(health_check))
             .route("/keys", web::post().to(add_public_key))
             .route("/keys", web::delete().to(remove_public_key))
             .route("/keys/bulk", web::post().to(bulk_add_keys))
+            .route("/keys/bulk", web::delete().to(bulk_remove_keys))
             .route("/users/{user_id}/keys", web::get().to(get_user_keys))
+            .route("/balance-updates", web::get().to(list_balance_updates))
+            .route("/users/{user_id}/balance-updates", web::get().to(get_balance_updates))
+            .route("/ws/balances/{user_id}", web::get().to(stream_balances))
             .route("/keys/{public_key}", web::get().to(get_public_key_details))
             .route("/stats", web::get().to(get_registry_stats))
             .route("/cache/refresh", web::post().to(refresh_cache))
+            .route("/admin/config", web::get().to(get_config))
     );
 }
\ No newline at end of file
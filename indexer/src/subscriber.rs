@@ -1,18 +1,141 @@
-use crate::models::{BalanceUpdate, TransactionEvent, BalanceChangeType};
+use crate::models::{BalanceUpdate, TransactionEvent, TransactionEventType, TransactionStatus, BalanceChangeType, SubscriptionType};
 use crate::registry::PublicKeyRegistry;
 use crate::database::Database;
-use crate::config::Config;
+use crate::config::{yellowstone_uses_tls, Config};
 use crate::yellowstone::GeyserGrpcClient;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use futures::StreamExt;
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use rust_decimal::prelude::ToPrimitive;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
 use tokio::time::{sleep, Duration};
+use tonic::transport::{Certificate, ClientTlsConfig};
 use tracing::{info, warn, error, debug};
 use yellowstone_grpc_proto::prelude::*;
 
+/// Native SOL mint, which account updates are recorded against.
+const NATIVE_SOL_MINT: &str = "11111111111111111111111111111112";
+
+/// How long the key set must stay unchanged before the subscription is rebuilt, so a burst
+/// of key additions costs one re-subscription.
+const KEY_CHANGE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Last lamport balance seen for each key, so an account update can be compared with the
+/// previous one without a database round trip.
+#[derive(Clone, Default)]
+pub struct LastBalanceCache {
+    balances: Arc<RwLock<HashMap<String, u64>>>,
+}
+
+impl LastBalanceCache {
+    /// Fill the cache from the latest stored balance of each key.
+    pub async fn seed(&self, database: &Database) -> Result<usize> {
+        let latest = database.get_latest_balances(NATIVE_SOL_MINT).await?;
+        let mut balances = self.balances.write().await;
+        for (public_key, balance) in latest {
+            if let Some(lamports) = balance.to_u64() {
+                balances.insert(public_key, lamports);
+            }
+        }
+        Ok(balances.len())
+    }
+
+    pub async fn get(&self, public_key: &str) -> Option<u64> {
+        self.balances.read().await.get(public_key).copied()
+    }
+
+    pub async fn set(&self, public_key: &str, lamports: u64) {
+        self.balances.write().await.insert(public_key.to_string(), lamports);
+    }
+}
+
+/// Balance updates waiting to be written together in one multi-row INSERT.
+#[derive(Clone)]
+pub struct BalanceUpdateBatcher {
+    database: Database,
+    pending: Arc<Mutex<Vec<BalanceUpdate>>>,
+    batch_size: usize,
+}
+
+/// Batches' worth of failed updates kept for the next write while the database is down;
+/// beyond this the oldest are dropped.
+const MAX_REQUEUED_BATCHES: usize = 10;
+
+impl BalanceUpdateBatcher {
+    pub fn new(database: Database, batch_size: usize) -> Self {
+        Self {
+            database,
+            pending: Arc::new(Mutex::new(Vec::with_capacity(batch_size))),
+            batch_size,
+        }
+    }
+
+    /// Queue an update, writing the whole batch once it holds `batch_size` updates. With a
+    /// batch size of 1 every update is written straight away.
+    pub async fn push(&self, update: BalanceUpdate) -> Result<()> {
+        let batch = {
+            let mut pending = self.pending.lock().await;
+            pending.push(update);
+            if pending.len() < self.batch_size {
+                return Ok(());
+            }
+            std::mem::take(&mut *pending)
+        };
+
+        self.write(batch).await?;
+        Ok(())
+    }
+
+    /// Write whatever is queued, returning how many updates were written.
+    pub async fn flush(&self) -> Result<usize> {
+        let batch = std::mem::take(&mut *self.pending.lock().await);
+        self.write(batch).await
+    }
+
+    /// Insert `batch`, falling back to one row at a time if the multi-row insert fails so one
+    /// bad row doesn't lose the rest. Rows that still fail go back in the queue for the next
+    /// write. Returns how many were written.
+    async fn write(&self, batch: Vec<BalanceUpdate>) -> Result<usize> {
+        let batch_error = match self.database.insert_balance_updates(&batch).await {
+            Ok(_) => return Ok(batch.len()),
+            Err(e) => e,
+        };
+        warn!("Batch insert of {} balance updates failed, writing them one by one: {}", batch.len(), batch_error);
+
+        let total = batch.len();
+        let mut failed = Vec::new();
+        for update in batch {
+            if let Err(e) = self.database.insert_balance_updates(std::slice::from_ref(&update)).await {
+                debug!("Failed to insert balance update {}: {}", update.id, e);
+                failed.push(update);
+            }
+        }
+        if failed.is_empty() {
+            return Ok(total);
+        }
+
+        let failed_count = failed.len();
+        let mut pending = self.pending.lock().await;
+        // Failed updates are older than anything queued since, so they go first
+        failed.append(&mut pending);
+        let limit = self.batch_size * MAX_REQUEUED_BATCHES;
+        if failed.len() > limit {
+            let dropped = failed.len() - limit;
+            failed.drain(..dropped);
+            error!("Dropped {} balance updates that could not be written", dropped);
+        }
+        *pending = failed;
+
+        Err(anyhow::anyhow!(
+            "{} of {} balance updates could not be written and were requeued: {}",
+            failed_count, total, batch_error
+        ))
+    }
+}
+
 #[derive(Clone)]
 pub struct YellowstoneSubscriber {
     registry: Arc<PublicKeyRegistry>,
@@ -22,6 +145,16 @@ pub struct YellowstoneSubscriber {
     balance_tx: mpsc::UnboundedSender<BalanceUpdate>,
     // Channel for transaction events
     transaction_tx: mpsc::UnboundedSender<TransactionEvent>,
+    // Balance updates held back while their debounce window is open, keyed by public key
+    pending_balances: Arc<Mutex<HashMap<String, BalanceUpdate>>>,
+    // Highest slot seen on the stream; a reconnect resumes from here
+    last_slot: Arc<AtomicU64>,
+    // Keys included in the most recent subscription, to report churn on reconnect
+    subscribed_keys: Arc<Mutex<HashSet<String>>>,
+    // Previous balance of each key, to compute the change an account update represents
+    last_balances: LastBalanceCache,
+    // Balance updates buffered for the next multi-row insert
+    balance_batcher: BalanceUpdateBatcher,
 }
 
 impl YellowstoneSubscriber {
@@ -35,10 +168,15 @@ impl YellowstoneSubscriber {
 
         let subscriber = Self {
             registry,
+            balance_batcher: BalanceUpdateBatcher::new(database.clone(), config.balance_batch_size),
             database,
             config,
             balance_tx,
             transaction_tx,
+            pending_balances: Arc::new(Mutex::new(HashMap::new())),
+            last_slot: Arc::new(AtomicU64::new(0)),
+            subscribed_keys: Arc::new(Mutex::new(HashSet::new())),
+            last_balances: LastBalanceCache::default(),
         };
 
         (subscriber, balance_rx, transaction_rx)
@@ -48,6 +186,12 @@ impl YellowstoneSubscriber {
     pub async fn start(&self) -> Result<()> {
         info!("Starting Yellowstone subscriber for endpoint: {}", self.config.yellowstone_endpoint);
 
+        // Without a seed, keys fall back to the database on their first update
+        match self.last_balances.seed(&self.database).await {
+            Ok(count) => info!("Seeded last-known balances for {} keys", count),
+            Err(e) => warn!("Failed to seed last-known balances: {}", e),
+        }
+
         let mut reconnect_attempts = 0;
         let max_reconnect_attempts = 10;
 
@@ -76,74 +220,63 @@ impl YellowstoneSubscriber {
 
     async fn connect_and_subscribe(&self) -> Result<()> {
         // Create gRPC client using the existing yellowstone client
-        let mut client = GeyserGrpcClient::build_from_shared(self.config.yellowstone_endpoint.clone())?
-            .x_token(Some(self.config.yellowstone_x_token.clone()))?
-            .connect()
-            .await?;
+        let mut builder = GeyserGrpcClient::build_from_shared(self.config.yellowstone_endpoint.clone())?
+            .x_token(Some(self.config.yellowstone_x_token.clone()))?;
+        if let Some(tls_config) = yellowstone_tls_config(&self.config)? {
+            builder = builder.tls_config(tls_config)?;
+        }
+        let mut client = builder.connect().await?;
 
         info!("Connected to Yellowstone Geyser");
 
+        // Watch from before the key list is read, so no change slips in between
+        let mut key_changes = self.registry.watch_key_changes();
+
         // Get current active public keys
-        let public_keys = self.registry.get_active_public_keys().await;
-        if public_keys.is_empty() {
+        let keys = self.registry.get_active_keys_with_type().await;
+        if keys.is_empty() {
             warn!("No public keys to monitor, waiting for subscriptions...");
-            sleep(Duration::from_secs(30)).await;
+            tokio::select! {
+                _ = sleep(Duration::from_secs(30)) => {}
+                _ = wait_for_key_change(&mut key_changes, KEY_CHANGE_DEBOUNCE) => {}
+            }
             return Ok(());
         }
 
-        info!("Monitoring {} public keys", public_keys.len());
-
-        // Create subscription request
-        let mut accounts = HashMap::new();
-        let mut transactions = HashMap::new();
-
-        // Subscribe to account updates for balance monitoring
-        for (i, public_key) in public_keys.iter().enumerate() {
-            accounts.insert(
-                format!("account_{}", i),
-                SubscribeRequestFilterAccounts {
-                    account: vec![public_key.clone()],
-                    owner: vec![],
-                    filters: vec![],
-                    nonempty_txn_signature: None,
-                },
-            );
+        info!("Monitoring {} public keys", keys.len());
+
+        let current: HashSet<String> = keys.iter().map(|(key, _)| key.clone()).collect();
+        let previous = std::mem::replace(&mut *self.subscribed_keys.lock().await, current.clone());
+        if !previous.is_empty() {
+            let (added, removed) = key_set_delta(&previous, &current);
+            info!("Re-subscribing with {} keys: {} added, {} removed since last connection", current.len(), added, removed);
         }
 
-        // Subscribe to transactions involving our monitored accounts
-        transactions.insert(
-            "transactions".to_string(),
-            SubscribeRequestFilterTransactions {
-                vote: Some(false),
-                failed: Some(false),
-                signature: None,
-                account_include: public_keys.clone(),
-                account_exclude: vec![],
-                account_required: vec![],
-            },
-        );
+        let from_slot = resume_slot(self.last_slot.load(Ordering::Relaxed));
+        if let Some(slot) = from_slot {
+            info!("Resuming Yellowstone subscription from slot {}", slot);
+        }
 
-        let subscribe_request = SubscribeRequest {
-            accounts,
-            slots: HashMap::new(),
-            transactions,
-            blocks: HashMap::new(),
-            blocks_meta: HashMap::new(),
-            entry: HashMap::new(),
-            commitment: Some(CommitmentLevel::Confirmed as i32),
-            accounts_data_slice: vec![],
-            from_slot: None,
-            ping: None,
-            transactions_status: HashMap::new(),
-        };
+        let subscribe_request = build_subscribe_request(&keys, from_slot);
 
         // Start subscription
         let mut stream = client.subscribe_once(subscribe_request).await?;
 
         info!("Yellowstone subscription active");
 
-        // Process stream messages
-        while let Some(message) = stream.next().await {
+        // Process stream messages until the stream ends or the key set changes
+        loop {
+            let message = tokio::select! {
+                message = stream.next() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = wait_for_key_change(&mut key_changes, KEY_CHANGE_DEBOUNCE) => {
+                    info!("Monitored key set changed, rebuilding Yellowstone subscription");
+                    return Ok(());
+                }
+            };
+
             match message {
                 Ok(msg) => {
                     if let Err(e) = self.process_message(msg).await {
@@ -155,13 +288,6 @@ impl YellowstoneSubscriber {
                     return Err(e.into());
                 }
             }
-
-            // Periodically refresh public keys
-            if rand::random::<f64>() < 0.001 { // ~0.1% chance per message
-                if let Err(e) = self.registry.refresh_cache().await {
-                    warn!("Failed to refresh registry cache: {}", e);
-                }
-            }
         }
 
         Ok(())
@@ -201,6 +327,7 @@ impl YellowstoneSubscriber {
         let pubkey = bs58::encode(&account.pubkey).into_string();
         let lamports = account.lamports;
         let slot = update.slot;
+        self.last_slot.fetch_max(slot, Ordering::Relaxed);
 
         debug!("Account update: {} lamports: {} slot: {}", pubkey, lamports, slot);
 
@@ -215,31 +342,90 @@ impl YellowstoneSubscriber {
             None => return Ok(()),
         };
 
-        // Create balance update with proper parameters
-        let balance_update = BalanceUpdate::new(
-            subscription.user_id,
-            pubkey.clone(),
-            "11111111111111111111111111111112".to_string(), // Native SOL mint
-            Decimal::from(0), // We don't have old balance here, would need to track it
-            Decimal::from(lamports),
-            BalanceChangeType::Transfer, // Use existing enum value
-            None, // No transaction signature for account updates
-            slot as i64,
-        );
+        // The previous balance is the last one we saw; a key we've never seen starts at zero
+        let old_balance = match self.last_balances.get(&pubkey).await {
+            Some(previous) => Decimal::from(previous),
+            None => self.database
+                .get_last_balance(&pubkey, NATIVE_SOL_MINT)
+                .await?
+                .unwrap_or(Decimal::ZERO),
+        };
 
-        // Send to balance processor
-        if let Err(e) = self.balance_tx.send(balance_update.clone()) {
-            error!("Failed to send balance update: {}", e);
+        let balance_update = account_balance_update(subscription.user_id, pubkey.clone(), old_balance, lamports, slot);
+
+        if self.config.balance_debounce_ms == 0 {
+            // Send to balance processor
+            if let Err(e) = self.balance_tx.send(balance_update.clone()) {
+                error!("Failed to send balance update: {}", e);
+            }
+
+            // Store in database
+            self.store_balance_update(&balance_update).await?;
+        } else {
+            if self.config.balance_persist_all {
+                self.store_balance_update(&balance_update).await?;
+            }
+            self.debounce_balance_update(balance_update).await;
         }
 
-        // Store in database
-        self.store_balance_update(&balance_update).await?;
+        self.last_balances.set(&pubkey, lamports).await;
 
         info!("Processed balance update for {}: {} lamports", pubkey, lamports);
 
         Ok(())
     }
 
+    /// Coalesce updates for the same key that arrive within `balance_debounce_ms` of the first
+    /// one. When the window closes, a single update carrying the latest balance is forwarded.
+    async fn debounce_balance_update(&self, update: BalanceUpdate) {
+        let public_key = update.public_key.clone();
+        let opens_window = {
+            let mut pending = self.pending_balances.lock().await;
+            match pending.get_mut(&public_key) {
+                Some(existing) => {
+                    *existing = coalesce_balance_updates(existing, update);
+                    false
+                }
+                None => {
+                    pending.insert(public_key.clone(), update);
+                    true
+                }
+            }
+        };
+
+        if !opens_window {
+            return;
+        }
+
+        let subscriber = self.clone();
+        let window = Duration::from_millis(self.config.balance_debounce_ms);
+        tokio::spawn(async move {
+            sleep(window).await;
+
+            // Already released if a shutdown flush drained it first
+            let update = match subscriber.pending_balances.lock().await.remove(&public_key) {
+                Some(update) => update,
+                None => return,
+            };
+            subscriber.release_debounced_update(update).await;
+        });
+    }
+
+    /// Forward a coalesced update whose window closed, and buffer it for persistence.
+    async fn release_debounced_update(&self, update: BalanceUpdate) {
+        if let Err(e) = self.balance_tx.send(update.clone()) {
+            error!("Failed to send balance update: {}", e);
+        }
+
+        // With persist_all every intermediate value was already stored
+        if self.config.balance_persist_all {
+            return;
+        }
+        if let Err(e) = self.store_balance_update(&update).await {
+            error!("Failed to store debounced balance update for {}: {}", update.public_key, e);
+        }
+    }
+
     async fn process_transaction_update(&self, update: SubscribeUpdateTransaction) -> Result<()> {
         let transaction = match update.transaction {
             Some(tx) => tx,
@@ -249,42 +435,53 @@ impl YellowstoneSubscriber {
         // Convert signature bytes to base58 string
         let signature = bs58::encode(&transaction.signature).into_string();
         let slot = update.slot;
+        self.last_slot.fetch_max(slot, Ordering::Relaxed);
 
         debug!("Transaction update: {} slot: {}", signature, slot);
 
-        // Parse transaction and extract relevant information
-        if let Some(_meta) = transaction.meta {
-            // For now, just log transaction info since transaction parsing is complex
-            debug!("Processing transaction meta for {}", signature);
+        let monitored = self.registry.transaction_keys(&transaction_account_keys(&transaction)).await;
+        if monitored.is_empty() {
+            return Ok(());
+        }
+
+        for event in parse_transaction_events(&transaction, slot, &monitored) {
+            if let Err(e) = self.transaction_tx.send(event.clone()) {
+                error!("Failed to send transaction event: {}", e);
+            }
+
+            self.store_transaction_event(&event).await?;
+            info!("Processed {:?} transaction {} for {}", event.event_type, event.signature, event.public_key);
         }
 
         Ok(())
     }
 
     async fn store_balance_update(&self, update: &BalanceUpdate) -> Result<()> {
-        // Use simple execute instead of macro to avoid sqlx offline issues
-        let query = "
-            INSERT INTO balance_updates (id, user_id, public_key, mint_address, old_balance, new_balance, change_amount, change_type, transaction_signature, slot, block_time, processed_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
-        ";
-        
-        sqlx::query(query)
-            .bind(&update.id)
-            .bind(&update.user_id)
-            .bind(&update.public_key)
-            .bind(&update.mint_address)
-            .bind(update.old_balance)
-            .bind(update.new_balance)
-            .bind(update.change_amount)
-            .bind(&update.change_type)
-            .bind(&update.transaction_signature)
-            .bind(update.slot)
-            .bind(update.block_time)
-            .bind(update.processed_at)
-            .execute(self.database.get_pool().await)
-            .await?;
+        self.balance_batcher.push(update.clone()).await
+    }
 
-        Ok(())
+    /// Write buffered balance updates every `balance_batch_window_ms`, so a quiet period
+    /// doesn't leave a partial batch unpersisted.
+    pub async fn run_balance_flusher(&self) {
+        let window = Duration::from_millis(self.config.balance_batch_window_ms);
+        loop {
+            sleep(window).await;
+            match self.balance_batcher.flush().await {
+                Ok(0) => {}
+                Ok(count) => debug!("Flushed {} buffered balance updates", count),
+                Err(e) => error!("Failed to flush buffered balance updates: {}", e),
+            }
+        }
+    }
+
+    /// Write any balance updates still buffered, e.g. on shutdown. Updates still inside their
+    /// debounce window are released first, so their latest balance isn't lost.
+    pub async fn flush_balance_updates(&self) -> Result<usize> {
+        let pending: Vec<BalanceUpdate> = self.pending_balances.lock().await.drain().map(|(_, update)| update).collect();
+        for update in pending {
+            self.release_debounced_update(update).await;
+        }
+        self.balance_batcher.flush().await
     }
 
     async fn store_transaction_event(&self, event: &TransactionEvent) -> Result<()> {
@@ -326,8 +523,481 @@ impl YellowstoneSubscriber {
     }
 }
 
+/// TLS settings for an https Yellowstone endpoint: system roots by default, plus the CA in
+/// `YELLOWSTONE_TLS_CA` and the server name override in `YELLOWSTONE_TLS_DOMAIN` when set.
+fn yellowstone_tls_config(config: &Config) -> Result<Option<ClientTlsConfig>> {
+    if !yellowstone_uses_tls(&config.yellowstone_endpoint)? {
+        return Ok(None);
+    }
+
+    let mut tls_config = ClientTlsConfig::new().with_native_roots();
+    if let Some(ca_path) = &config.yellowstone_tls_ca {
+        let pem = std::fs::read(ca_path)
+            .with_context(|| format!("Failed to read YELLOWSTONE_TLS_CA {}", ca_path))?;
+        tls_config = tls_config.ca_certificate(Certificate::from_pem(pem));
+    }
+    if let Some(domain) = &config.yellowstone_tls_domain {
+        tls_config = tls_config.domain_name(domain.clone());
+    }
+
+    Ok(Some(tls_config))
+}
+
+/// Build the Geyser subscription: account filters for keys watching balances and a single
+/// transaction filter for keys watching transactions, so each key only produces the updates
+/// its subscription type asks for.
+fn build_subscribe_request(keys: &[(String, SubscriptionType)], from_slot: Option<u64>) -> SubscribeRequest {
+    let mut accounts = HashMap::new();
+    let mut transactions = HashMap::new();
+
+    // Subscribe to account updates for balance monitoring
+    for (i, (public_key, _)) in keys.iter().filter(|(_, t)| t.includes_accounts()).enumerate() {
+        accounts.insert(
+            format!("account_{}", i),
+            SubscribeRequestFilterAccounts {
+                account: vec![public_key.clone()],
+                owner: vec![],
+                filters: vec![],
+                nonempty_txn_signature: None,
+            },
+        );
+    }
+
+    // Subscribe to transactions involving our monitored accounts
+    let transaction_keys: Vec<String> = keys.iter()
+        .filter(|(_, t)| t.includes_transactions())
+        .map(|(key, _)| key.clone())
+        .collect();
+    if !transaction_keys.is_empty() {
+        transactions.insert(
+            "transactions".to_string(),
+            SubscribeRequestFilterTransactions {
+                vote: Some(false),
+                failed: Some(false),
+                signature: None,
+                account_include: transaction_keys,
+                account_exclude: vec![],
+                account_required: vec![],
+            },
+        );
+    }
+
+    SubscribeRequest {
+        accounts,
+        slots: HashMap::new(),
+        transactions,
+        blocks: HashMap::new(),
+        blocks_meta: HashMap::new(),
+        entry: HashMap::new(),
+        commitment: Some(CommitmentLevel::Confirmed as i32),
+        accounts_data_slice: vec![],
+        from_slot,
+        ping: None,
+        transactions_status: HashMap::new(),
+    }
+}
+
+/// Every account a transaction loads, in the order its balances are reported: the static keys
+/// followed by the writable and readonly addresses loaded from lookup tables.
+fn transaction_account_keys(transaction: &SubscribeUpdateTransactionInfo) -> Vec<String> {
+    let static_keys = transaction.transaction.as_ref()
+        .and_then(|tx| tx.message.as_ref())
+        .map(|message| message.account_keys.as_slice())
+        .unwrap_or_default();
+    let loaded = transaction.meta.as_ref()
+        .map(|meta| (meta.loaded_writable_addresses.as_slice(), meta.loaded_readonly_addresses.as_slice()))
+        .unwrap_or_default();
+
+    static_keys.iter()
+        .chain(loaded.0)
+        .chain(loaded.1)
+        .map(|key| bs58::encode(key).into_string())
+        .collect()
+}
+
+/// Net change in each SPL token mint held by `owner`, in base units.
+fn token_deltas(meta: &TransactionStatusMeta, owner: &str) -> HashMap<String, i128> {
+    let mut deltas = HashMap::new();
+    for (balances, sign) in [(&meta.pre_token_balances, -1), (&meta.post_token_balances, 1)] {
+        for balance in balances.iter().filter(|balance| balance.owner == owner) {
+            let amount: i128 = balance.ui_token_amount.as_ref()
+                .and_then(|a| a.amount.parse().ok())
+                .unwrap_or(0);
+            *deltas.entry(balance.mint.clone()).or_insert(0) += sign * amount;
+        }
+    }
+    deltas
+}
+
+/// One event per monitored account the transaction touched. SOL movements are classified as
+/// send or receive from the account's lamport change, with the fee added back for the payer.
+/// Balances of the same owner moving in opposite directions (SOL out and a token in, or one
+/// token for another) make it a swap.
+fn parse_transaction_events(
+    transaction: &SubscribeUpdateTransactionInfo,
+    slot: u64,
+    monitored: &HashSet<String>,
+) -> Vec<TransactionEvent> {
+    let meta = match &transaction.meta {
+        Some(meta) => meta,
+        None => return vec![],
+    };
+
+    let signature = bs58::encode(&transaction.signature).into_string();
+    let keys = transaction_account_keys(transaction);
+    let status = if meta.err.is_some() { TransactionStatus::Failed } else { TransactionStatus::Success };
+
+    // Lamport change per account, excluding the fee the payer (always index 0) was charged
+    let deltas: Vec<i64> = keys.iter().enumerate().map(|(index, _)| {
+        let pre = meta.pre_balances.get(index).copied().unwrap_or(0) as i64;
+        let post = meta.post_balances.get(index).copied().unwrap_or(0) as i64;
+        let fee = if index == 0 { meta.fee as i64 } else { 0 };
+        post - pre + fee
+    }).collect();
+
+    keys.iter().enumerate()
+        .filter(|(_, key)| monitored.contains(*key))
+        .map(|(index, key)| {
+            let delta = deltas[index];
+            let directions: HashSet<i128> = token_deltas(meta, key).into_values()
+                .chain([delta as i128])
+                .filter(|d| *d != 0)
+                .map(i128::signum)
+                .collect();
+            let event_type = match delta {
+                _ if directions.len() > 1 => TransactionEventType::Swap,
+                0 => TransactionEventType::Unknown,
+                d if d < 0 => TransactionEventType::Send,
+                _ => TransactionEventType::Receive,
+            };
+
+            // The counterparty is the account whose change mirrors this one the most
+            let counterparty = |receiving: bool| {
+                deltas.iter().enumerate()
+                    .filter(|(other, d)| *other != index && if receiving { **d > 0 } else { **d < 0 })
+                    .max_by_key(|(_, d)| d.abs())
+                    .map(|(other, _)| keys[other].clone())
+            };
+            let (from_address, to_address) = match event_type {
+                TransactionEventType::Send => (Some(key.clone()), counterparty(true)),
+                TransactionEventType::Receive => (counterparty(false), Some(key.clone())),
+                _ => (None, None),
+            };
+
+            TransactionEvent {
+                id: uuid::Uuid::new_v4().to_string(),
+                public_key: key.clone(),
+                signature: signature.clone(),
+                slot,
+                block_time: None,
+                event_type,
+                amount: (delta != 0).then_some(delta.abs()),
+                mint: (delta != 0).then(|| NATIVE_SOL_MINT.to_string()),
+                from_address,
+                to_address,
+                fee: Some(meta.fee),
+                status: status.clone(),
+                created_at: chrono::Utc::now(),
+            }
+        })
+        .collect()
+}
+
+/// Increase or decrease by the direction of the change, `Unknown` when the balance didn't move.
+fn change_type_between(old_balance: Decimal, new_balance: Decimal) -> BalanceChangeType {
+    match new_balance.cmp(&old_balance) {
+        std::cmp::Ordering::Greater => BalanceChangeType::Increase,
+        std::cmp::Ordering::Less => BalanceChangeType::Decrease,
+        std::cmp::Ordering::Equal => BalanceChangeType::Unknown,
+    }
+}
+
+/// The update for an account now holding `lamports`, classified against the previous balance.
+fn account_balance_update(user_id: String, public_key: String, old_balance: Decimal, lamports: u64, slot: u64) -> BalanceUpdate {
+    let new_balance = Decimal::from(lamports);
+    let change_type = change_type_between(old_balance, new_balance);
+
+    BalanceUpdate::new(
+        user_id,
+        public_key,
+        NATIVE_SOL_MINT.to_string(),
+        old_balance,
+        new_balance,
+        change_type,
+        None, // No transaction signature for account updates
+        slot as i64,
+    )
+}
+
+/// Resolve once the key set has changed and then gone `debounce` without changing again.
+/// Never resolves if the registry has been dropped.
+async fn wait_for_key_change(key_changes: &mut watch::Receiver<u64>, debounce: Duration) {
+    if key_changes.changed().await.is_err() {
+        return std::future::pending().await;
+    }
+    while let Ok(Ok(())) = tokio::time::timeout(debounce, key_changes.changed()).await {}
+}
+
+/// Slot to resume a subscription from; `None` before anything has been processed.
+fn resume_slot(last_slot: u64) -> Option<u64> {
+    (last_slot > 0).then_some(last_slot)
+}
+
+/// How many keys were added and removed between two subscriptions.
+fn key_set_delta(previous: &HashSet<String>, current: &HashSet<String>) -> (usize, usize) {
+    (current.difference(previous).count(), previous.difference(current).count())
+}
+
+/// Merge a newer update into the one already pending for the same key: the starting balance
+/// is kept so the forwarded change covers the whole window, and the change type follows the
+/// net direction over it. Everything else is taken from `latest`.
+fn coalesce_balance_updates(pending: &BalanceUpdate, latest: BalanceUpdate) -> BalanceUpdate {
+    BalanceUpdate {
+        old_balance: pending.old_balance,
+        change_amount: latest.new_balance - pending.old_balance,
+        change_type: change_type_between(pending.old_balance, latest.new_balance),
+        ..latest
+    }
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct YellowstoneStats {
     pub monitored_keys: u32,
     pub connection_status: String,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(old_balance: i64, new_balance: i64, slot: i64) -> BalanceUpdate {
+        BalanceUpdate::new(
+            "user-1".to_string(),
+            "So11111111111111111111111111111111111111112".to_string(),
+            NATIVE_SOL_MINT.to_string(),
+            Decimal::from(old_balance),
+            Decimal::from(new_balance),
+            BalanceChangeType::Transfer,
+            None,
+            slot,
+        )
+    }
+
+    #[test]
+    fn test_account_only_keys_are_not_in_transaction_filter() {
+        let keys = vec![
+            ("account-key".to_string(), SubscriptionType::Account),
+            ("tx-key".to_string(), SubscriptionType::Transaction),
+            ("both-key".to_string(), SubscriptionType::Both),
+        ];
+
+        let request = build_subscribe_request(&keys, None);
+
+        let tx_filter = &request.transactions["transactions"];
+        assert_eq!(tx_filter.account_include, vec!["tx-key".to_string(), "both-key".to_string()]);
+
+        let mut account_keys: Vec<&String> = request.accounts.values().flat_map(|f| &f.account).collect();
+        account_keys.sort();
+        assert_eq!(account_keys, vec!["account-key", "both-key"]);
+    }
+
+    #[test]
+    fn test_account_only_keys_skip_transaction_subscription() {
+        let keys = vec![("account-key".to_string(), SubscriptionType::Account)];
+
+        let request = build_subscribe_request(&keys, None);
+
+        assert!(request.transactions.is_empty());
+        assert_eq!(request.accounts.len(), 1);
+    }
+
+    #[test]
+    fn test_resubscribe_request_resumes_from_saved_slot() {
+        let keys = vec![("account-key".to_string(), SubscriptionType::Account)];
+
+        let request = build_subscribe_request(&keys, resume_slot(1234));
+        assert_eq!(request.from_slot, Some(1234));
+
+        let first = build_subscribe_request(&keys, resume_slot(0));
+        assert_eq!(first.from_slot, None);
+    }
+
+    #[test]
+    fn test_key_set_delta() {
+        let previous: HashSet<String> = ["a", "b", "c"].iter().map(|k| k.to_string()).collect();
+        let current: HashSet<String> = ["b", "c", "d", "e"].iter().map(|k| k.to_string()).collect();
+
+        assert_eq!(key_set_delta(&previous, &current), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_key_change_triggers_resubscription_after_debounce() {
+        let (key_changes_tx, mut key_changes) = watch::channel(0u64);
+        let debounce = Duration::from_millis(50);
+
+        // Nothing changed, so no re-subscription
+        assert!(tokio::time::timeout(Duration::from_millis(100), wait_for_key_change(&mut key_changes, debounce)).await.is_err());
+
+        // A burst of additions is coalesced into one re-subscription once it settles
+        let sender = tokio::spawn(async move {
+            for _ in 0..3 {
+                key_changes_tx.send_modify(|generation| *generation += 1);
+                sleep(Duration::from_millis(20)).await;
+            }
+            key_changes_tx
+        });
+        let started = tokio::time::Instant::now();
+        tokio::time::timeout(Duration::from_secs(1), wait_for_key_change(&mut key_changes, debounce)).await
+            .expect("key change should trigger a re-subscription");
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!(*key_changes.borrow(), 3);
+        drop(sender.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_sequential_account_updates_report_delta() {
+        let cache = LastBalanceCache::default();
+        let key = "So11111111111111111111111111111111111111112";
+
+        // Same steps as process_account_update: read the previous balance, build, then record
+        let mut updates = Vec::new();
+        for (lamports, slot) in [(1_000u64, 10u64), (400, 11)] {
+            let old_balance = cache.get(key).await.map(Decimal::from).unwrap_or(Decimal::ZERO);
+            updates.push(account_balance_update("user-1".to_string(), key.to_string(), old_balance, lamports, slot));
+            cache.set(key, lamports).await;
+        }
+
+        assert_eq!(updates[0].change_type, BalanceChangeType::Increase);
+        assert_eq!(updates[0].change_amount, Decimal::from(1_000));
+
+        assert_eq!(updates[1].old_balance, Decimal::from(1_000));
+        assert_eq!(updates[1].new_balance, Decimal::from(400));
+        assert_eq!(updates[1].change_amount, Decimal::from(-600));
+        assert_eq!(updates[1].change_type, BalanceChangeType::Decrease);
+        assert_eq!(cache.get(key).await, Some(400));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_buffered_balance_updates_are_persisted_on_flush() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL");
+        let database = Database::new(&url).await.unwrap();
+        let batcher = BalanceUpdateBatcher::new(database.clone(), 10);
+        let user_id = format!("batch-test-{}", uuid::Uuid::new_v4());
+
+        let count_rows = async || -> i64 {
+            sqlx::query_scalar("SELECT COUNT(*) FROM balance_updates WHERE user_id = $1")
+                .bind(&user_id)
+                .fetch_one(&database.pool)
+                .await
+                .unwrap()
+        };
+
+        for slot in 0..3 {
+            let mut buffered = update(slot * 10, (slot + 1) * 10, slot);
+            buffered.user_id = user_id.clone();
+            batcher.push(buffered).await.unwrap();
+        }
+        assert_eq!(count_rows().await, 0);
+
+        assert_eq!(batcher.flush().await.unwrap(), 3);
+        assert_eq!(count_rows().await, 3);
+        assert_eq!(batcher.flush().await.unwrap(), 0);
+
+        sqlx::query("DELETE FROM balance_updates WHERE user_id = $1")
+            .bind(&user_id)
+            .execute(&database.pool)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_coalesce_keeps_first_old_balance_and_latest_value() {
+        let first = update(100, 150, 10);
+        let latest = update(150, 120, 12);
+        let latest_id = latest.id.clone();
+
+        let merged = coalesce_balance_updates(&first, latest);
+
+        assert_eq!(merged.id, latest_id);
+        assert_eq!(merged.old_balance, Decimal::from(100));
+        assert_eq!(merged.new_balance, Decimal::from(120));
+        assert_eq!(merged.change_amount, Decimal::from(20));
+        assert_eq!(merged.change_type, BalanceChangeType::Increase);
+        assert_eq!(merged.slot, 12);
+    }
+
+    #[test]
+    fn test_coalesce_classifies_the_net_change() {
+        // The latest update went up, but over the whole window the balance fell
+        let merged = coalesce_balance_updates(&update(100, 50, 10), update(50, 80, 12));
+        assert_eq!(merged.change_amount, Decimal::from(-20));
+        assert_eq!(merged.change_type, BalanceChangeType::Decrease);
+
+        let merged = coalesce_balance_updates(&update(100, 50, 10), update(50, 100, 12));
+        assert_eq!(merged.change_type, BalanceChangeType::Unknown);
+    }
+
+    fn sol_transfer(from: [u8; 32], to: [u8; 32], lamports: u64, fee: u64) -> SubscribeUpdateTransaction {
+        SubscribeUpdateTransaction {
+            transaction: Some(SubscribeUpdateTransactionInfo {
+                signature: vec![7; 64],
+                transaction: Some(Transaction {
+                    signatures: vec![vec![7; 64]],
+                    message: Some(Message {
+                        account_keys: vec![from.to_vec(), to.to_vec(), vec![0; 32]],
+                        ..Default::default()
+                    }),
+                }),
+                meta: Some(TransactionStatusMeta {
+                    fee,
+                    pre_balances: vec![5_000_000, 1_000_000, 1],
+                    post_balances: vec![5_000_000 - lamports - fee, 1_000_000 + lamports, 1],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            slot: 42,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sol_transfer_emits_send_and_receive_events() {
+        let (from, to) = ([1u8; 32], [2u8; 32]);
+        let from_key = bs58::encode(from).into_string();
+        let to_key = bs58::encode(to).into_string();
+        let update = sol_transfer(from, to, 250_000, 5_000);
+        let monitored: HashSet<String> = [from_key.clone(), to_key.clone()].into_iter().collect();
+
+        let events = parse_transaction_events(update.transaction.as_ref().unwrap(), update.slot, &monitored);
+
+        assert_eq!(events.len(), 2);
+        let send = &events[0];
+        assert_eq!(send.public_key, from_key);
+        assert_eq!(send.signature, bs58::encode(vec![7u8; 64]).into_string());
+        assert_eq!(send.slot, 42);
+        assert_eq!(send.event_type, TransactionEventType::Send);
+        assert_eq!(send.status, TransactionStatus::Success);
+        assert_eq!(send.amount, Some(250_000));
+        assert_eq!(send.fee, Some(5_000));
+        assert_eq!(send.mint.as_deref(), Some(NATIVE_SOL_MINT));
+        assert_eq!(send.from_address.as_deref(), Some(from_key.as_str()));
+        assert_eq!(send.to_address.as_deref(), Some(to_key.as_str()));
+
+        let receive = &events[1];
+        assert_eq!(receive.public_key, to_key);
+        assert_eq!(receive.event_type, TransactionEventType::Receive);
+        assert_eq!(receive.amount, Some(250_000));
+        assert_eq!(receive.from_address.as_deref(), Some(from_key.as_str()));
+    }
+
+    #[test]
+    fn test_unmonitored_accounts_produce_no_events() {
+        let update = sol_transfer([1; 32], [2; 32], 250_000, 5_000);
+
+        let events = parse_transaction_events(update.transaction.as_ref().unwrap(), update.slot, &HashSet::new());
+
+        assert!(events.is_empty());
+    }
+}
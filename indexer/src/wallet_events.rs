@@ -0,0 +1,123 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::delivery::{self, DeliveryKind};
+use crate::models::BalanceUpdate;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const WALLET_FUNDED: &str = "wallet_funded";
+
+/// Sent to `WALLET_FUNDED_CALLBACK_URL` the first time a monitored key receives funds.
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletFundedEvent {
+    pub id: String,
+    pub event_type: &'static str,
+    pub user_id: String,
+    pub public_key: String,
+    pub mint_address: String,
+    pub amount: Decimal,
+    pub slot: i64,
+    pub balance_update_id: String,
+    pub funded_at: DateTime<Utc>,
+}
+
+impl WalletFundedEvent {
+    fn from_update(update: &BalanceUpdate) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            event_type: WALLET_FUNDED,
+            user_id: update.user_id.clone(),
+            public_key: update.public_key.clone(),
+            mint_address: update.mint_address.clone(),
+            amount: update.new_balance,
+            slot: update.slot,
+            balance_update_id: update.id.clone(),
+            funded_at: update.processed_at,
+        }
+    }
+}
+
+/// A key counts as funded when it goes from an empty (or never seen) balance to a positive one.
+pub fn is_first_funding(old_balance: Decimal, new_balance: Decimal) -> bool {
+    old_balance <= Decimal::ZERO && new_balance > Decimal::ZERO
+}
+
+/// Record a `wallet_funded` event if this update is the key's first deposit and notify the
+/// configured callback. The `(public_key, event_type)` unique constraint makes this fire at
+/// most once per key, even if the key is later drained and funded again. A failed callback is
+/// parked for the delivery retry worker, which sets `notified_at` once it gets through.
+pub async fn handle_balance_update(
+    client: &reqwest::Client,
+    database: &Database,
+    config: &Config,
+    update: &BalanceUpdate,
+) -> Result<()> {
+    if !is_first_funding(update.old_balance, update.new_balance) {
+        return Ok(());
+    }
+
+    let event = WalletFundedEvent::from_update(update);
+    let inserted = sqlx::query(
+        "
+        INSERT INTO wallet_events (id, user_id, public_key, event_type, balance_update_id, amount, slot)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (public_key, event_type) DO NOTHING
+        "
+    )
+    .bind(&event.id)
+    .bind(&event.user_id)
+    .bind(&event.public_key)
+    .bind(event.event_type)
+    .bind(&event.balance_update_id)
+    .bind(event.amount)
+    .bind(event.slot)
+    .execute(database.get_pool().await)
+    .await?
+    .rows_affected();
+
+    if inserted == 0 {
+        return Ok(());
+    }
+
+    info!("Wallet {} of user {} funded with {}", event.public_key, event.user_id, event.amount);
+
+    if config.wallet_funded_callback_url.is_none() {
+        return Ok(());
+    }
+
+    match delivery::deliver(client, config, DeliveryKind::WalletFunded, &event).await {
+        Ok(()) => mark_notified(database, &event.id).await?,
+        Err(e) => {
+            warn!("Failed to notify wallet funded callback for {}: {}", event.public_key, e);
+            delivery::record_failed_delivery(database, config, DeliveryKind::WalletFunded, &event, &e.to_string()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Record that the callback for wallet event `event_id` got through.
+pub async fn mark_notified(database: &Database, event_id: &str) -> Result<()> {
+    sqlx::query("UPDATE wallet_events SET notified_at = NOW() WHERE id = $1")
+        .bind(event_id)
+        .execute(database.get_pool().await)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_first_funding() {
+        assert!(is_first_funding(Decimal::ZERO, Decimal::from(1_000)));
+        assert!(!is_first_funding(Decimal::from(500), Decimal::from(1_000)));
+        assert!(!is_first_funding(Decimal::ZERO, Decimal::ZERO));
+        assert!(!is_first_funding(Decimal::from(1_000), Decimal::ZERO));
+    }
+}
@@ -11,7 +11,10 @@ use {
         codec::{CompressionEncoding, Streaming},
         metadata::{errors::InvalidMetadataValue, AsciiMetadataValue, MetadataValue},
         service::interceptor::InterceptedService,
-        transport::channel::{Channel, Endpoint},
+        transport::{
+            channel::{Channel, Endpoint},
+            ClientTlsConfig,
+        },
         Request, Response, Status,
     },
     tonic_health::pb::{health_client::HealthClient, HealthCheckRequest, HealthCheckResponse},
@@ -386,14 +389,12 @@ impl GeyserGrpcBuilder {
         }
     }
 
-    /*
     pub fn tls_config(self, tls_config: ClientTlsConfig) -> GeyserGrpcBuilderResult<Self> {
         Ok(Self {
             endpoint: self.endpoint.tls_config(tls_config)?,
             ..self
         })
     }
-    */
 
     // Geyser options
     pub fn send_compressed(self, encoding: CompressionEncoding) -> Self {
@@ -1,8 +1,76 @@
-use sqlx::{PgPool, Row};
+use sqlx::{postgres::PgPoolOptions, PgPool, Row};
 use anyhow::Result;
 use std::env;
+use std::time::Duration;
+use common::shard::{share_location, ShardPoolConfig, SHARD_COUNT};
 use crate::models::{KeyShare, MPCSession};
 
+/// Open the pool for one shard database with its `ShardPoolConfig` tuning.
+async fn connect_shard(config: &ShardPoolConfig, database_url: &str) -> Result<PgPool> {
+    let pool = PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections.min(config.max_connections))
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+        .idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+        .max_lifetime(Duration::from_secs(config.max_lifetime_secs))
+        .connect(database_url)
+        .await?;
+    Ok(pool)
+}
+
+/// Whether `shares` is the full set its own `total_shares` describes.
+pub fn is_complete(shares: &[KeyShare]) -> bool {
+    shares.first().is_some_and(|first| shares.len() == first.total_shares as usize)
+}
+
+/// Insert a share, overwriting the user's existing share with the same index.
+async fn upsert_key_share<'e, E: sqlx::PgExecutor<'e>>(executor: E, share: &KeyShare) -> Result<()> {
+    let query = r#"
+        INSERT INTO key_shares (id, user_id, public_key, encrypted_share, share_index, threshold, total_shares, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (user_id, share_index) 
+        DO UPDATE SET 
+            public_key = EXCLUDED.public_key,
+            encrypted_share = EXCLUDED.encrypted_share,
+            threshold = EXCLUDED.threshold,
+            total_shares = EXCLUDED.total_shares,
+            created_at = EXCLUDED.created_at
+    "#;
+
+    sqlx::query(query)
+        .bind(share.id)
+        .bind(&share.user_id)
+        .bind(&share.public_key)
+        .bind(&share.encrypted_share)
+        .bind(share.share_index)
+        .bind(share.threshold)
+        .bind(share.total_shares)
+        .bind(share.created_at)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+/// Why `replace_user_shares` failed, and how far it got.
+#[derive(Debug)]
+pub struct ShareReplaceError {
+    /// Shards that committed the new shares before the failure. Empty means every shard
+    /// still holds the previous shares.
+    pub committed_shards: Vec<usize>,
+    pub error: anyhow::Error,
+}
+
+impl std::fmt::Display for ShareReplaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.committed_shards.is_empty() {
+            write!(f, "{}", self.error)
+        } else {
+            write!(f, "{} after shards {:?} committed", self.error, self.committed_shards)
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct DatabaseManager {
     pub mpc1_pool: PgPool,
@@ -19,9 +87,9 @@ impl DatabaseManager {
         let mpc3_url = env::var("MPC3_DATABASE_URL")
             .expect("MPC3_DATABASE_URL must be set");
 
-        let mpc1_pool = PgPool::connect(&mpc1_url).await?;
-        let mpc2_pool = PgPool::connect(&mpc2_url).await?;
-        let mpc3_pool = PgPool::connect(&mpc3_url).await?;
+        let mpc1_pool = connect_shard(&ShardPoolConfig::from_env(1), &mpc1_url).await?;
+        let mpc2_pool = connect_shard(&ShardPoolConfig::from_env(2), &mpc2_url).await?;
+        let mpc3_pool = connect_shard(&ShardPoolConfig::from_env(3), &mpc3_url).await?;
 
         // Initialize tables for all databases
         Self::initialize_tables(&mpc1_pool).await?;
@@ -102,34 +170,45 @@ impl DatabaseManager {
         database_index: usize,
     ) -> Result<()> {
         let pool = self.get_pool_by_index(database_index);
-        
-        let query = r#"
-            INSERT INTO key_shares (id, user_id, public_key, encrypted_share, share_index, threshold, total_shares, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-            ON CONFLICT (user_id, share_index) 
-            DO UPDATE SET 
-                public_key = EXCLUDED.public_key,
-                encrypted_share = EXCLUDED.encrypted_share,
-                threshold = EXCLUDED.threshold,
-                total_shares = EXCLUDED.total_shares,
-                created_at = EXCLUDED.created_at
-        "#;
+        upsert_key_share(pool, share).await
+    }
 
-        sqlx::query(query)
-            .bind(share.id)
-            .bind(&share.user_id)
-            .bind(&share.public_key)
-            .bind(&share.encrypted_share)
-            .bind(share.share_index)
-            .bind(share.threshold)
-            .bind(share.total_shares)
-            .bind(share.created_at)
-            .execute(pool)
-            .await?;
+    /// Swap the user's shares for `shares` (all for one new key) on every shard. Each shard
+    /// is written in its own transaction and the transactions are committed only once every
+    /// shard has been written, so a failure before the commits leaves the old shares intact.
+    /// The commits themselves can't be made atomic across shards: if one fails after others
+    /// succeeded, the error lists the shards that now hold the new key.
+    pub async fn replace_user_shares(&self, user_id: &str, shares: &[KeyShare]) -> std::result::Result<(), ShareReplaceError> {
+        let public_key = shares.first().map(|s| s.public_key.as_str()).unwrap_or_default();
+        let not_applied = |error: anyhow::Error| ShareReplaceError { committed_shards: Vec::new(), error };
+
+        let mut transactions = Vec::with_capacity(SHARD_COUNT);
+        for i in 0..SHARD_COUNT {
+            let mut tx = self.get_pool_by_index(i).begin().await.map_err(|e| not_applied(e.into()))?;
+            for share in shares.iter().filter(|s| share_location(s.share_index).0 == i) {
+                upsert_key_share(&mut *tx, share).await.map_err(not_applied)?;
+            }
+            // Old shares the new set doesn't overwrite, e.g. a higher share index
+            sqlx::query("DELETE FROM key_shares WHERE user_id = $1 AND public_key <> $2")
+                .bind(user_id)
+                .bind(public_key)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| not_applied(e.into()))?;
+            transactions.push(tx);
+        }
 
+        let mut committed_shards = Vec::with_capacity(SHARD_COUNT);
+        for (i, tx) in transactions.into_iter().enumerate() {
+            if let Err(e) = tx.commit().await {
+                return Err(ShareReplaceError { committed_shards, error: e.into() });
+            }
+            committed_shards.push(i);
+        }
         Ok(())
     }
 
+    /// The share in slot 0 of shard `database_index`, i.e. share `database_index + 1`.
     pub async fn get_key_share(
         &self,
         user_id: &str,
@@ -152,13 +231,20 @@ impl DatabaseManager {
         Ok(result)
     }
 
+    /// Every share the user has on every shard, in share index order per shard.
     pub async fn get_all_user_shares(&self, user_id: &str) -> Result<Vec<KeyShare>> {
         let mut all_shares = Vec::new();
 
-        for i in 0..3 {
-            if let Some(share) = self.get_key_share(user_id, i).await? {
-                all_shares.push(share);
-            }
+        for i in 0..SHARD_COUNT {
+            let pool = self.get_pool_by_index(i);
+            let query = r#"
+                SELECT id, user_id, public_key, encrypted_share, share_index, threshold, total_shares, created_at
+                FROM key_shares
+                WHERE user_id = $1
+                ORDER BY share_index
+            "#;
+            let shares = sqlx::query_as::<_, KeyShare>(query).bind(user_id).fetch_all(pool).await?;
+            all_shares.extend(shares);
         }
 
         Ok(all_shares)
@@ -246,17 +332,31 @@ impl DatabaseManager {
         Ok(())
     }
 
-    pub async fn delete_user_shares(&self, user_id: &str) -> Result<()> {
-        for i in 0..3 {
+    /// Delete the user's share from every shard. Returns how many shares were removed.
+    pub async fn delete_user_shares(&self, user_id: &str) -> Result<u64> {
+        let mut deleted = 0;
+        for i in 0..SHARD_COUNT {
             let pool = self.get_pool_by_index(i);
             let query = "DELETE FROM key_shares WHERE user_id = $1";
-            sqlx::query(query).bind(user_id).execute(pool).await?;
+            deleted += sqlx::query(query).bind(user_id).execute(pool).await?.rows_affected();
         }
-        Ok(())
+        Ok(deleted)
+    }
+
+    /// Up to `limit` distinct users holding a share on any shard, in user id order.
+    pub async fn sample_user_ids(&self, limit: i64) -> Result<Vec<String>> {
+        let mut user_ids = std::collections::BTreeSet::new();
+        for i in 0..SHARD_COUNT {
+            let pool = self.get_pool_by_index(i);
+            let query = "SELECT DISTINCT user_id FROM key_shares ORDER BY user_id LIMIT $1";
+            let ids: Vec<String> = sqlx::query_scalar(query).bind(limit).fetch_all(pool).await?;
+            user_ids.extend(ids);
+        }
+        Ok(user_ids.into_iter().take(limit as usize).collect())
     }
 
     pub async fn user_has_shares(&self, user_id: &str) -> Result<bool> {
         let shares = self.get_all_user_shares(user_id).await?;
-        Ok(shares.len() == 3) // Should have shares in all 3 databases
+        Ok(is_complete(&shares))
     }
 }
@@ -1,13 +1,16 @@
-use actix_web::{web, App, HttpResponse, HttpServer, middleware::Logger};
+use actix_web::{web, App, HttpResponse, HttpServer, middleware::{from_fn, Logger}};
 
 // mod error;
 
 mod models;
 mod database;
+mod share_crypto;
+mod shamir;
 
 mod routes;
 use routes::*;
 
+use common::server::ServerTuning;
 use database::DatabaseManager;
 
 #[actix_web::main]
@@ -17,6 +20,15 @@ async fn main() -> Result<(), std::io::Error> {
     
     println!("🚀 MPC Server starting on http://127.0.0.1:8081");
     
+    // Shares can't be generated or used without the key, so refuse to start without it
+    if let Err(e) = share_crypto::ShareKey::from_env() {
+        println!("❌ {}", e);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("Share encryption key unavailable: {}", e),
+        ));
+    }
+
     // Initialize database connections
     let db_manager = match DatabaseManager::new().await {
         Ok(db) => {
@@ -32,17 +44,39 @@ async fn main() -> Result<(), std::io::Error> {
         }
     };
     
-    HttpServer::new(move || {
+    let server_tuning = match ServerTuning::from_env() {
+        Ok(tuning) => tuning,
+        Err(e) => {
+            println!("❌ Invalid configuration: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        }
+    };
+    let drain_timeout = routes::shutdown::drain_timeout_from_env();
+    let shutdown_state = web::Data::new(routes::shutdown::ShutdownState::default());
+    let app_shutdown_state = shutdown_state.clone();
+
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(db_manager.clone()))
+            .app_data(app_shutdown_state.clone())
+            .app_data(routes::extractor_errors::json_config())
+            .wrap(from_fn(routes::shutdown::reject_while_draining))
+            .wrap(from_fn(routes::request_id::log_request_id))
             .wrap(Logger::default())
             .service(
                 web::scope("/api")
                     .route("/generate", web::post().to(generate))
+                    .route("/rotate-keys", web::post().to(rotate_keys))
             //         .route("/send-single", web::post().to(send_single))
                     .route("/aggregate", web::post().to(aggregate_keys))
                     .route("/send-sol", web::post().to(send_sol))
                     .route("/jupiter-swap", web::post().to(jupiter_swap))
+                    .route("/sign-batch", web::post().to(sign_batch))
+                    .route("/derive-pubkey/{user_id}", web::post().to(derive_pubkey))
+                    .route("/public-key/{user_id}", web::get().to(get_public_key))
+                    .route("/delete-shares", web::post().to(delete_shares))
+                    .route("/admin/config", web::get().to(admin_config))
+                    .route("/admin/shard-consistency", web::get().to(shard_consistency))
             //         .route("/agg-send-step1", web::post().to(routes::agg_send_step1))
             //         .route("/agg-send-step2", web::post().to(routes::agg_send_step2))
             //         .route("/aggregate-signatures-broadcast", web::post().to(routes::aggregate_signatures_broadcast))
@@ -50,9 +84,18 @@ async fn main() -> Result<(), std::io::Error> {
             )
             .route("/", web::get().to(index))
     })
+    .workers(server_tuning.workers)
+    .keep_alive(server_tuning.keep_alive())
+    .client_request_timeout(server_tuning.client_timeout())
+    // Ctrl-C is handled below so signing requests can drain before the workers stop
+    .disable_signals()
+    .shutdown_timeout(drain_timeout.as_secs())
     .bind("127.0.0.1:8081")?
-    .run()
-    .await
+    .run();
+
+    actix_web::rt::spawn(routes::shutdown::shutdown_on_ctrl_c(server.handle(), shutdown_state, drain_timeout));
+
+    server.await
 }
 
 async fn index() -> HttpResponse {
@@ -62,10 +105,17 @@ async fn index() -> HttpResponse {
         "status": "running",
         "endpoints": [
             "POST /api/generate - Generate threshold keypair",
+            "POST /api/rotate-keys - Replace a user's threshold keypair with a fresh one",
             "POST /api/send-single - Check single key share",
-            "POST /api/aggregate - Aggregate keys for user", 
+            "POST /api/aggregate - Sign a message with the user's aggregated key", 
             "POST /api/send-sol - Send SOL transaction using aggregated keys",
             "POST /api/jupiter-swap - Execute Jupiter swap with MPC signing",
+            "POST /api/sign-batch - Sign several transactions with one key reconstruction",
+            "POST /api/derive-pubkey/{user_id} - Preview the public key the user's shares reconstruct to",
+            "GET /api/public-key/{user_id} - Public key recorded with the user's shares, with cross-shard consistency",
+            "POST /api/delete-shares - Purge a user's key shares from all databases",
+            "GET /api/admin/config - Effective configuration with database passwords masked (admin)",
+            "GET /api/admin/shard-consistency - Check shards agree on each user's share metadata, ?user_id= for one user (admin)",
             "POST /api/agg-send-step1 - MPC Step 1",
             "POST /api/agg-send-step2 - MPC Step 2", 
             "POST /api/aggregate-signatures-broadcast - Aggregate signatures",
@@ -7,7 +7,7 @@ pub struct KeyShare {
     pub user_id: String,
     pub public_key: String,
     pub encrypted_share: String, // encrypted private key share
-    pub share_index: i32, // which share this is (1, 2, or 3)
+    pub share_index: i32, // which share this is (1-based, up to total_shares)
     pub threshold: i32, // threshold for reconstruction
     pub total_shares: i32, // total number of shares
     pub created_at: chrono::DateTime<chrono::Utc>,
@@ -32,6 +32,12 @@ pub struct MPCSession {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GenerateRequest {
     pub user_id: String,
+    /// Shares needed to rebuild the key (default 2)
+    #[serde(default)]
+    pub threshold: Option<u8>,
+    /// Shares created (default 3, at most 5)
+    #[serde(default)]
+    pub total_shares: Option<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,18 +45,56 @@ pub struct GenerateResponse {
     pub user_id: String,
     pub public_key: String,
     pub shares_created: bool,
+    pub threshold: i32,
+    pub total_shares: i32,
+    pub shares: Vec<ShareStorage>,
+}
+
+/// Where one generated share goes and whether it was written there.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareStorage {
+    pub share_index: i32,
+    pub database_index: usize,
+    pub stored: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateKeysRequest {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateKeysResponse {
+    pub user_id: String,
+    pub public_key: String,
+    pub previous_public_key: String,
+    pub threshold: i32,
+    pub total_shares: i32,
+    pub shares: Vec<ShareStorage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteSharesRequest {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteSharesResponse {
+    pub user_id: String,
+    pub shares_deleted: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AggregateRequest {
     pub user_id: String,
+    pub message: String, // message to sign
 }
 
+/// Shared by `mpc` and `mpc-simple`. The reconstructed key never leaves the service;
+/// callers get the signature and the public key to verify it against.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AggregateResponse {
-    pub user_id: String,
+    pub signature: String,
     pub public_key: String,
-    pub private_key: String, // Reconstructed private key
-    pub shares_used: Vec<i32>, // Which share indices were used
     pub success: bool,
 }
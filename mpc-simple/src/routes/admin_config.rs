@@ -0,0 +1,80 @@
+use actix_web::{HttpRequest, HttpResponse, Result};
+use common::redact::redact_url;
+use common::shard::ShardPoolConfig;
+use serde::Serialize;
+use subtle::ConstantTimeEq;
+
+use crate::share_crypto::ShareKey;
+use super::confirmation::ConfirmConfig;
+
+pub const ADMIN_KEY_HEADER: &str = "X-Admin-Key";
+
+/// Same contract as the backend: `ADMIN_API_KEY` must be set and sent in `X-Admin-Key`.
+pub(crate) fn is_admin(req: &HttpRequest) -> bool {
+    let expected = match std::env::var("ADMIN_API_KEY") {
+        Ok(key) if !key.is_empty() => key,
+        _ => return false,
+    };
+
+    // Compared in constant time, so response timing doesn't reveal how much of a guess was right
+    req.headers()
+        .get(ADMIN_KEY_HEADER)
+        .is_some_and(|provided| bool::from(provided.as_bytes().ct_eq(expected.as_bytes())))
+}
+
+#[derive(Serialize)]
+pub struct ShardConfig {
+    pub shard: usize,
+    pub database_url: Option<String>,
+    pub pool: ShardPoolConfig,
+}
+
+#[derive(Serialize)]
+pub struct EffectiveConfig {
+    pub shards: Vec<ShardConfig>,
+    pub solana_rpc_url: String,
+    pub confirm_poll_interval_ms: u128,
+    pub confirm_max_polls: u32,
+    pub server_workers: Option<String>,
+    pub server_keep_alive_secs: Option<String>,
+    pub server_client_timeout_ms: Option<String>,
+    pub shutdown_drain_timeout_secs: u64,
+    /// Whether `SHARE_ENCRYPTION_KEY` holds a usable key; the key itself is never shown.
+    pub share_encryption_key_valid: bool,
+}
+
+impl EffectiveConfig {
+    fn from_env() -> Self {
+        let shards = (1..=3).map(|shard| ShardConfig {
+            shard,
+            database_url: std::env::var(format!("MPC{}_DATABASE_URL", shard)).ok().map(|url| redact_url(&url)),
+            pool: ShardPoolConfig::from_env(shard),
+        }).collect();
+
+        let confirm = ConfirmConfig::from_env();
+        Self {
+            shards,
+            solana_rpc_url: redact_url(
+                &std::env::var("SOLANA_RPC_URL").unwrap_or_else(|_| "https://api.devnet.solana.com".to_string()),
+            ),
+            confirm_poll_interval_ms: confirm.poll_interval.as_millis(),
+            confirm_max_polls: confirm.max_polls,
+            server_workers: std::env::var("SERVER_WORKERS").ok(),
+            server_keep_alive_secs: std::env::var("SERVER_KEEP_ALIVE_SECS").ok(),
+            server_client_timeout_ms: std::env::var("SERVER_CLIENT_TIMEOUT_MS").ok(),
+            shutdown_drain_timeout_secs: super::shutdown::drain_timeout_from_env().as_secs(),
+            share_encryption_key_valid: ShareKey::from_env().is_ok(),
+        }
+    }
+}
+
+/// The configuration this instance is running with, database passwords masked.
+pub async fn admin_config(http_req: HttpRequest) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(EffectiveConfig::from_env()))
+}
@@ -1,9 +1,11 @@
 use actix_web::{web, HttpResponse, Result};
 use serde_json::json;
+use solana_sdk::signer::Signer;
 
 use crate::{
     database::DatabaseManager,
     models::{AggregateRequest, AggregateResponse},
+    routes::{reconstruct_keypair, KeyReconstructionError},
 };
 
 pub async fn aggregate_keys(
@@ -11,77 +13,38 @@ pub async fn aggregate_keys(
     req: web::Json<AggregateRequest>,
 ) -> Result<HttpResponse> {
     println!("Aggregating key shares for user: {}", req.user_id);
-    
-    // Fetch all key shares for the user from all databases
-    let shares = match db.get_all_user_shares(&req.user_id).await {
-        Ok(shares) => shares,
-        Err(e) => {
-            println!("Failed to fetch key shares for user {}: {}", req.user_id, e);
-            return Ok(HttpResponse::InternalServerError().json(json!({
-                "error": "Failed to fetch key shares from databases"
-            })));
-        }
-    };
-
-    // Check if we have enough shares (need at least threshold)
-    if shares.is_empty() {
-        println!("No key shares found for user: {}", req.user_id);
-        return Ok(HttpResponse::NotFound().json(json!({
-            "error": "No key shares found for user"
-        })));
-    }
-
-    // Verify all shares have the same public key and threshold
-    let first_share = &shares[0];
-    let expected_public_key = first_share.public_key.clone();
-    let threshold = first_share.threshold;
-    
-    for share in &shares {
-        if share.public_key != expected_public_key {
-            println!("Mismatched public keys in shares for user: {}", req.user_id);
-            return Ok(HttpResponse::BadRequest().json(json!({
-                "error": "Inconsistent public keys across shares"
-            })));
-        }
-    }
 
-    if shares.len() < threshold as usize {
-        println!("Insufficient shares for user {}: found {}, need {}", 
-                 req.user_id, shares.len(), threshold);
+    if req.message.is_empty() {
         return Ok(HttpResponse::BadRequest().json(json!({
-            "error": format!("Insufficient shares: found {}, need {}", shares.len(), threshold)
+            "error": "Message to sign must not be empty"
         })));
     }
 
-    // Sort shares by index to ensure correct reconstruction order
-    let mut sorted_shares = shares;
-    sorted_shares.sort_by_key(|s| s.share_index);
-
-    // This is a simplified reconstruction - in a real implementation, 
-    // you would use proper secret sharing algorithms like Shamir's Secret Sharing
-    let mut reconstructed_private_key = String::new();
-    let mut share_indices_used = Vec::new();
+    let keypair = match reconstruct_keypair(&db, &req.user_id).await {
+        Ok(keypair) => keypair,
+        Err(e) => {
+            println!("Failed to reconstruct key for user {}: {}", req.user_id, e);
+            let response = json!({ "error": e.to_string() });
+            return Ok(match e {
+                KeyReconstructionError::NoShares => HttpResponse::NotFound().json(response),
+                KeyReconstructionError::InsufficientShares { .. } | KeyReconstructionError::InconsistentShares => {
+                    HttpResponse::BadRequest().json(response)
+                }
+                _ => HttpResponse::InternalServerError().json(response),
+            });
+        }
+    };
 
-    // Take the required number of shares (threshold)
-    for share in sorted_shares.iter().take(threshold as usize) {
-        reconstructed_private_key.push_str(&share.encrypted_share);
-        share_indices_used.push(share.share_index);
-        
-        println!("Using share {} for user {}: {}", 
-                 share.share_index, req.user_id, share.encrypted_share);
-    }
+    let signature = keypair.sign_message(req.message.as_bytes());
+    let public_key = keypair.pubkey().to_string();
+    // Keypair wipes its secret on drop
+    drop(keypair);
 
-    println!("Successfully reconstructed private key for user: {}", req.user_id);
-    println!("Reconstructed key: {}", reconstructed_private_key);
-    println!("Used shares: {:?}", share_indices_used);
+    println!("Signed aggregate message for user: {}", req.user_id);
 
-    let response = AggregateResponse {
-        user_id: req.user_id.clone(),
-        public_key: expected_public_key,
-        private_key: reconstructed_private_key,
-        shares_used: share_indices_used,
+    Ok(HttpResponse::Ok().json(AggregateResponse {
+        signature: signature.to_string(),
+        public_key,
         success: true,
-    };
-
-    Ok(HttpResponse::Ok().json(response))
-}
\ No newline at end of file
+    }))
+}
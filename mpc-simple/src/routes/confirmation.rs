@@ -0,0 +1,159 @@
+use actix_web::rt::time::sleep;
+use serde::Serialize;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::{TransactionConfirmationStatus, UiTransactionEncoding};
+use std::time::Duration;
+
+/// Final state reported for a submitted transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfirmationStatus {
+    Confirmed,
+    /// Still not confirmed after the last poll; the transaction may yet land.
+    Pending,
+    Failed,
+}
+
+/// What a single status lookup observed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureState {
+    Confirmed,
+    Processing,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfirmConfig {
+    pub poll_interval: Duration,
+    pub max_polls: u32,
+}
+
+impl ConfirmConfig {
+    pub fn from_env() -> Self {
+        let poll_interval_ms = std::env::var("MPC_CONFIRM_POLL_INTERVAL_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(500);
+        let max_polls = std::env::var("MPC_CONFIRM_MAX_POLLS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60);
+
+        Self {
+            poll_interval: Duration::from_millis(poll_interval_ms),
+            max_polls,
+        }
+    }
+}
+
+/// Poll `check` up to `max_polls` times. Lookup errors are treated like "not seen yet".
+/// Returns the final status and the on-chain error if the transaction failed.
+pub async fn poll_confirmation<F>(config: &ConfirmConfig, mut check: F) -> (ConfirmationStatus, Option<String>)
+where
+    F: AsyncFnMut() -> Result<SignatureState, String>,
+{
+    for attempt in 1..=config.max_polls {
+        match check().await {
+            Ok(SignatureState::Confirmed) => return (ConfirmationStatus::Confirmed, None),
+            Ok(SignatureState::Failed(error)) => return (ConfirmationStatus::Failed, Some(error)),
+            Ok(SignatureState::Processing) => {}
+            Err(e) => println!("Signature status lookup failed (poll {}): {}", attempt, e),
+        }
+
+        if attempt < config.max_polls {
+            sleep(config.poll_interval).await;
+        }
+    }
+
+    (ConfirmationStatus::Pending, None)
+}
+
+/// Looked up through the client's async inner client: the blocking calls would stall the
+/// worker's executor for every poll.
+async fn signature_state(rpc_client: &RpcClient, signature: &Signature) -> Result<SignatureState, String> {
+    let statuses = rpc_client.get_inner_client()
+        .get_signature_statuses(&[*signature])
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let status = match statuses.value.into_iter().next().flatten() {
+        Some(status) => status,
+        None => return Ok(SignatureState::Processing),
+    };
+
+    if let Some(err) = status.err {
+        return Ok(SignatureState::Failed(err.to_string()));
+    }
+
+    match status.confirmation_status {
+        Some(TransactionConfirmationStatus::Confirmed) | Some(TransactionConfirmationStatus::Finalized) => {
+            Ok(SignatureState::Confirmed)
+        }
+        _ => Ok(SignatureState::Processing),
+    }
+}
+
+/// Wait for `signature` to reach confirmed commitment using the configured polling bounds.
+pub async fn confirm_signature(rpc_client: &RpcClient, signature: &Signature) -> (ConfirmationStatus, Option<String>) {
+    let config = ConfirmConfig::from_env();
+    poll_confirmation(&config, async || signature_state(rpc_client, signature).await).await
+}
+
+/// Network fee actually charged for a landed transaction, read from its status meta.
+/// `None` while the transaction is still pending or if the lookup fails.
+pub async fn fetch_fee_lamports(rpc_client: &RpcClient, signature: &Signature, status: &ConfirmationStatus) -> Option<u64> {
+    if *status == ConfirmationStatus::Pending {
+        return None;
+    }
+
+    match rpc_client.get_inner_client().get_transaction(signature, UiTransactionEncoding::Json).await {
+        Ok(transaction) => transaction.transaction.meta.map(|meta| meta.fee),
+        Err(e) => {
+            println!("Failed to fetch fee for transaction {}: {}", signature, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_polls: u32) -> ConfirmConfig {
+        ConfirmConfig {
+            poll_interval: Duration::from_millis(1),
+            max_polls,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_max_polls_exceeded_reports_pending() {
+        let mut calls = 0;
+        let (status, error) = poll_confirmation(&config(3), async || {
+            calls += 1;
+            Ok(SignatureState::Processing)
+        }).await;
+
+        assert_eq!(status, ConfirmationStatus::Pending);
+        assert_eq!(error, None);
+        assert_eq!(calls, 3);
+    }
+
+    #[actix_web::test]
+    async fn test_stops_polling_once_confirmed_or_failed() {
+        let mut calls = 0;
+        let (status, _) = poll_confirmation(&config(10), async || {
+            calls += 1;
+            if calls < 2 { Err("rpc unavailable".to_string()) } else { Ok(SignatureState::Confirmed) }
+        }).await;
+        assert_eq!(status, ConfirmationStatus::Confirmed);
+        assert_eq!(calls, 2);
+
+        let (status, error) = poll_confirmation(&config(10), async || {
+            Ok(SignatureState::Failed("InsufficientFundsForFee".to_string()))
+        }).await;
+        assert_eq!(status, ConfirmationStatus::Failed);
+        assert_eq!(error.as_deref(), Some("InsufficientFundsForFee"));
+    }
+}
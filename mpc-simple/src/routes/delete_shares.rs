@@ -0,0 +1,32 @@
+use actix_web::{web, HttpResponse, Result};
+use serde_json::json;
+
+use crate::{
+    database::DatabaseManager,
+    models::{DeleteSharesRequest, DeleteSharesResponse},
+};
+
+/// Purge the user's key shares from all three shard databases, e.g. when the account is
+/// deleted. Succeeds with `shares_deleted: 0` if the user had none.
+pub async fn delete_shares(
+    db: web::Data<DatabaseManager>,
+    req: web::Json<DeleteSharesRequest>,
+) -> Result<HttpResponse> {
+    println!("Deleting key shares for user: {}", req.user_id);
+
+    match db.delete_user_shares(&req.user_id).await {
+        Ok(shares_deleted) => {
+            println!("Deleted {} key shares for user {}", shares_deleted, req.user_id);
+            Ok(HttpResponse::Ok().json(DeleteSharesResponse {
+                user_id: req.user_id.clone(),
+                shares_deleted,
+            }))
+        }
+        Err(e) => {
+            println!("Failed to delete key shares for user {}: {}", req.user_id, e);
+            Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to delete key shares"
+            })))
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use actix_web::{web, HttpResponse, Result};
+use serde::Serialize;
+use solana_sdk::signer::Signer;
+
+use crate::{database::DatabaseManager, models::KeyShare, share_crypto::ShareKey, routes::{reconstruct_from_shares, KeyReconstructionError}};
+
+#[derive(Serialize)]
+pub struct DerivePubkeyResponse {
+    pub success: bool,
+    pub user_id: String,
+    pub public_key: Option<String>,
+    pub registered_public_key: Option<String>,
+    pub matches_registered: bool,
+    pub error: Option<String>,
+}
+
+/// The address the shares reconstruct to, alongside the one recorded with them.
+/// The keypair is dropped before returning so no secret material leaves this function.
+fn derive_public_key(shares: Vec<KeyShare>, key: &ShareKey) -> std::result::Result<(String, String), KeyReconstructionError> {
+    let registered = shares.first().map(|s| s.public_key.clone()).unwrap_or_default();
+    let keypair = reconstruct_from_shares(shares, key)?;
+    let derived = keypair.pubkey().to_string();
+    drop(keypair);
+
+    Ok((derived, registered))
+}
+
+/// Recovery check: reconstruct the user's key and report only the derived public key.
+pub async fn derive_pubkey(
+    db: web::Data<DatabaseManager>,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let user_id = path.into_inner();
+    println!("Deriving public key for user: {}", user_id);
+
+    let failed = |error: String| DerivePubkeyResponse {
+        success: false,
+        user_id: user_id.clone(),
+        public_key: None,
+        registered_public_key: None,
+        matches_registered: false,
+        error: Some(error),
+    };
+
+    let shares = match db.get_all_user_shares(&user_id).await {
+        Ok(shares) => shares,
+        Err(e) => {
+            println!("Failed to fetch key shares for user {}: {}", user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(failed("Failed to fetch key shares from databases".to_string())));
+        }
+    };
+
+    let derived = ShareKey::from_env()
+        .map_err(|e| KeyReconstructionError::ShareDecryption(e.to_string()))
+        .and_then(|key| derive_public_key(shares, &key));
+
+    match derived {
+        Ok((public_key, registered_public_key)) => {
+            let matches_registered = public_key == registered_public_key;
+            if !matches_registered {
+                println!("Derived key for user {} does not match the registered key", user_id);
+            }
+            Ok(HttpResponse::Ok().json(DerivePubkeyResponse {
+                success: true,
+                user_id: user_id.clone(),
+                public_key: Some(public_key),
+                registered_public_key: Some(registered_public_key),
+                matches_registered,
+                error: None,
+            }))
+        }
+        Err(e) => {
+            println!("Failed to reconstruct key for user {}: {}", user_id, e);
+            let response = failed(e.to_string());
+            Ok(match e {
+                KeyReconstructionError::NoShares => HttpResponse::NotFound().json(response),
+                KeyReconstructionError::InsufficientShares { .. } | KeyReconstructionError::InconsistentShares => {
+                    HttpResponse::BadRequest().json(response)
+                }
+                _ => HttpResponse::InternalServerError().json(response),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::{split_into_shares, ShareScheme};
+    use solana_sdk::signature::Keypair;
+
+    #[test]
+    fn test_derive_public_key_matches_registered() {
+        let key = ShareKey::from_hex(&"42".repeat(32)).unwrap();
+        let keypair = Keypair::new_from_array([7u8; 32]);
+        let expected = keypair.pubkey().to_string();
+
+        let (derived, registered) = derive_public_key(split_into_shares(&keypair, "user-1", &key, ShareScheme::default()).unwrap(), &key).unwrap();
+        assert_eq!(derived, expected);
+        assert_eq!(registered, expected);
+    }
+
+    #[test]
+    fn test_derive_public_key_reports_mismatch() {
+        let key = ShareKey::from_hex(&"42".repeat(32)).unwrap();
+        let other = Keypair::new_from_array([9u8; 32]).pubkey().to_string();
+        let mut shares = split_into_shares(&Keypair::new_from_array([7u8; 32]), "user-1", &key, ShareScheme::default()).unwrap();
+        for share in &mut shares {
+            share.public_key = other.clone();
+        }
+
+        let (derived, registered) = derive_public_key(shares, &key).unwrap();
+        assert_ne!(derived, registered);
+    }
+}
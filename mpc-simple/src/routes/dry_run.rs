@@ -0,0 +1,66 @@
+use serde::Serialize;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::transaction::Transaction;
+
+/// Result of a signing request made with `dry_run`: the transaction was built, signed and
+/// simulated, but never broadcast.
+#[derive(Debug, Serialize)]
+pub struct DryRunResponse {
+    pub success: bool,
+    pub dry_run: bool,
+    /// The signature the transaction would have landed under.
+    pub transaction_signature: Option<String>,
+    pub error: Option<String>,
+    pub units_consumed: Option<u64>,
+    pub logs: Vec<String>,
+}
+
+fn dry_run_response(
+    signature: Option<String>,
+    error: Option<String>,
+    units_consumed: Option<u64>,
+    logs: Vec<String>,
+) -> DryRunResponse {
+    DryRunResponse {
+        success: error.is_none(),
+        dry_run: true,
+        transaction_signature: signature,
+        error,
+        units_consumed,
+        logs,
+    }
+}
+
+/// Simulate an already-signed transaction against the cluster.
+pub fn simulate_signed_transaction(rpc_client: &RpcClient, transaction: &Transaction) -> DryRunResponse {
+    let signature = transaction.signatures.first().map(|s| s.to_string());
+
+    match rpc_client.simulate_transaction(transaction) {
+        Ok(response) => {
+            let result = response.value;
+            dry_run_response(
+                signature,
+                result.err.map(|e| format!("Simulation failed: {:?}", e)),
+                result.units_consumed,
+                result.logs.unwrap_or_default(),
+            )
+        }
+        Err(e) => dry_run_response(signature, Some(format!("Failed to simulate transaction: {}", e)), None, vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dry_run_success_follows_simulation_error() {
+        let ok = dry_run_response(Some("sig".to_string()), None, Some(450), vec!["Program log: ok".to_string()]);
+        assert!(ok.success);
+        assert!(ok.dry_run);
+
+        let failed = dry_run_response(Some("sig".to_string()), Some("Simulation failed: InsufficientFundsForFee".to_string()), None, vec![]);
+        assert!(!failed.success);
+        assert_eq!(failed.transaction_signature.as_deref(), Some("sig"));
+    }
+}
@@ -0,0 +1,29 @@
+use actix_web::{
+    error::{InternalError, JsonPayloadError},
+    web, HttpRequest, HttpResponse,
+};
+use common::serde_error::field_from_message;
+use serde_json::json;
+
+/// JSON bodies that fail to extract get the `{"error": ...}` envelope the handlers use instead
+/// of actix's plain-text 400, with the offending field when serde names one.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(|err, _req: &HttpRequest| {
+        let (mut builder, error, detail) = match &err {
+            JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+                (HttpResponse::PayloadTooLarge(), "Request body is too large", err.to_string())
+            }
+            JsonPayloadError::ContentType => {
+                (HttpResponse::UnsupportedMediaType(), "Content-Type must be application/json", err.to_string())
+            }
+            JsonPayloadError::Deserialize(e) => (HttpResponse::BadRequest(), "Invalid request body", e.to_string()),
+            _ => (HttpResponse::BadRequest(), "Invalid request body", err.to_string()),
+        };
+        let response = builder.json(json!({
+            "error": error,
+            "field": field_from_message(&detail),
+            "details": detail,
+        }));
+        InternalError::from_response(err, response).into()
+    })
+}
@@ -1,20 +1,103 @@
 use actix_web::{web, HttpResponse, Result};
+use common::shard::share_location;
 use serde_json::json;
 use uuid::Uuid;
 use solana_sdk::{
-    bs58, signature::Keypair, signer:: Signer
+    signature::Keypair, signer:: Signer
 };
+use zeroize::Zeroize;
     
 use crate::{
-    models::{GenerateRequest, GenerateResponse},
+    models::{GenerateRequest, GenerateResponse, KeyShare, ShareStorage},
     database::DatabaseManager,
+    share_crypto::{encrypt_share, ShareCryptoError, ShareKey},
+    shamir,
 };
 
+const DEFAULT_THRESHOLD: u8 = 2;
+const DEFAULT_TOTAL_SHARES: u8 = 3;
+const MAX_TOTAL_SHARES: u8 = 5;
+
+/// A `threshold`-of-`total_shares` split.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShareScheme {
+    pub threshold: u8,
+    pub total_shares: u8,
+}
+
+impl Default for ShareScheme {
+    fn default() -> Self {
+        Self { threshold: DEFAULT_THRESHOLD, total_shares: DEFAULT_TOTAL_SHARES }
+    }
+}
+
+impl ShareScheme {
+    /// The scheme a generate request asks for, 2-of-3 unless overridden. Requires
+    /// `1 <= threshold <= total_shares <= 5`.
+    pub fn from_request(req: &GenerateRequest) -> Result<Self, String> {
+        let threshold = req.threshold.unwrap_or(DEFAULT_THRESHOLD);
+        let total_shares = req.total_shares.unwrap_or(DEFAULT_TOTAL_SHARES);
+
+        if total_shares > MAX_TOTAL_SHARES {
+            return Err(format!("total_shares must be at most {}", MAX_TOTAL_SHARES));
+        }
+        if threshold < 1 || threshold > total_shares {
+            return Err(format!("threshold must be between 1 and total_shares ({})", total_shares));
+        }
+        Ok(Self { threshold, total_shares })
+    }
+}
+
+/// Shamir-split the 64-byte keypair into `scheme.total_shares` shares, any `scheme.threshold`
+/// of which rebuild it, and encrypt each (hex-encoded) for its shard.
+pub fn split_into_shares(keypair: &Keypair, user_id: &str, key: &ShareKey, scheme: ShareScheme) -> Result<Vec<KeyShare>, ShareCryptoError> {
+    let mut keypair_bytes = keypair.to_bytes();
+    let points = shamir::split(&keypair_bytes, scheme.threshold, scheme.total_shares);
+    keypair_bytes.zeroize();
+    let points = points.map_err(|e| ShareCryptoError::Malformed(e.to_string()))?;
+    let public_key = keypair.pubkey().to_string();
+
+    points.iter().map(|point| {
+        let share_index = point.x as i32;
+        let mut part = hex::encode(&point.y);
+        let encrypted_share = encrypt_share(key, user_id, share_index, &part);
+        part.zeroize();
+
+        Ok(KeyShare {
+            id: Uuid::new_v4(),
+            user_id: user_id.to_string(),
+            public_key: public_key.clone(),
+            encrypted_share: encrypted_share?,
+            share_index,
+            threshold: scheme.threshold as i32,
+            total_shares: scheme.total_shares as i32,
+            created_at: chrono::Utc::now(),
+        })
+    }).collect()
+}
+
+/// One not-yet-stored entry per share, at the database `share_location` assigns it.
+pub fn storage_plan(shares: &[KeyShare]) -> Vec<ShareStorage> {
+    shares.iter().map(|share| ShareStorage {
+        share_index: share.share_index,
+        database_index: share_location(share.share_index).0,
+        stored: false,
+    }).collect()
+}
+
 pub async fn generate(
     db: web::Data<DatabaseManager>,
     req: web::Json<GenerateRequest>,
 ) -> Result<HttpResponse> {
-    println!("Generating threshold keypair for user: {}", req.user_id);
+    let scheme = match ShareScheme::from_request(&req) {
+        Ok(scheme) => scheme,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e
+            })));
+        }
+    };
+    println!("Generating {}-of-{} threshold keypair for user: {}", scheme.threshold, scheme.total_shares, req.user_id);
     
     // Check if user already has shares
     match db.user_has_shares(&req.user_id).await {
@@ -33,65 +116,51 @@ pub async fn generate(
         }
     }
 
-    let keypair = Keypair::new();
-    let pubkey = keypair.pubkey();
-    let private_key_bytes = bs58::encode(keypair.to_bytes()).into_string();
-
-    let secret_key = &private_key_bytes[..32]; // First 32 bytes are the secret key
-    let public_key = pubkey.to_string();
+    let share_key = match ShareKey::from_env() {
+        Ok(key) => key,
+        Err(e) => {
+            println!("Cannot encrypt key shares for user {}: {}", req.user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Key share encryption is not configured"
+            })));
+        }
+    };
 
-    let shares = vec![
-        crate::models::KeyShare {
-            id: Uuid::new_v4(),
-            user_id: req.user_id.clone(),
-            public_key: public_key.clone(),
-            encrypted_share: secret_key.chars().take(10).collect::<String>(),
-            share_index: 1,
-            threshold: 2,
-            total_shares: 3,
-            created_at: chrono::Utc::now(),
-        },
-        crate::models::KeyShare {
-            id: Uuid::new_v4(),
-            user_id: req.user_id.clone(),
-            public_key: public_key.clone(),
-            encrypted_share: secret_key.chars().skip(10).take(10).collect::<String>(),
-            share_index: 2,
-            threshold: 2,
-            total_shares: 3,
-            created_at: chrono::Utc::now(),
-        },
-        crate::models::KeyShare {
-            id: Uuid::new_v4(),
-            user_id: req.user_id.clone(),
-            public_key: public_key.clone(),
-            encrypted_share: secret_key.chars().skip(20).take(12).collect::<String>(),
-            share_index: 3,
-            threshold: 2,
-            total_shares: 3,
-            created_at: chrono::Utc::now(),
-        },
-    ];
+    let keypair = Keypair::new();
+    let public_key = keypair.pubkey().to_string();
+    let shares = match split_into_shares(&keypair, &req.user_id, &share_key, scheme) {
+        Ok(shares) => shares,
+        Err(e) => {
+            println!("Failed to encrypt key shares for user {}: {}", req.user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to encrypt key shares"
+            })));
+        }
+    };
+    drop(keypair);
+    drop(share_key);
 
     let public_key_str = public_key.clone();
     println!("Generated public key: {} for user: {}", public_key_str, req.user_id);
 
     // Store shares in different databases
+    let mut storage = storage_plan(&shares);
     let mut storage_success = true;
     
-    for (_i, key_share) in shares.iter().enumerate() {        
-        // Store in the corresponding database (share_index 1->db0, 2->db1, 3->db2)
-        let db_index = (key_share.share_index - 1) as usize;
+    for (key_share, entry) in shares.iter().zip(storage.iter_mut()) {        
+        // Shares go round-robin over the databases (1->db0, 2->db1, 3->db2, 4->db0, ...)
+        let (db_index, slot) = share_location(key_share.share_index);
         
-        if let Err(e) = db.store_key_share(&key_share, db_index).await {
+        if let Err(e) = db.store_key_share(key_share, db_index).await {
             println!("Failed to store share {} for user {}: {}", 
                        key_share.share_index, req.user_id, e);
             storage_success = false;
             break;
         }
+        entry.stored = true;
         
-        println!("Stored share {} for user {} in database {}", 
-                  key_share.share_index, req.user_id, db_index + 1);
+        println!("Stored share {} for user {} in database {} (slot {})", 
+                  key_share.share_index, req.user_id, db_index + 1, slot);
     }
     
     if !storage_success {
@@ -100,8 +169,10 @@ pub async fn generate(
             println!("Failed to cleanup shares for user {}: {}", req.user_id, e);
         }
         
+        // `shares` shows how far storage got before the cleanup
         return Ok(HttpResponse::InternalServerError().json(json!({
-            "error": "Failed to store key shares"
+            "error": "Failed to store key shares",
+            "shares": storage
         })));
     }
     
@@ -109,8 +180,116 @@ pub async fn generate(
         user_id: req.user_id.clone(),
         public_key: public_key_str,
         shares_created: true,
+        threshold: scheme.threshold as i32,
+        total_shares: scheme.total_shares as i32,
+        shares: storage,
     };
 
     println!("Successfully generated and stored key shares for user: {}", req.user_id);
     Ok(HttpResponse::Ok().json(response))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::reconstruct_from_shares;
+
+    #[test]
+    fn test_split_shares_decrypt_and_reconstruct_to_the_same_keypair() {
+        let key = ShareKey::from_hex(&"5a".repeat(32)).unwrap();
+        let keypair = Keypair::new();
+
+        let shares = split_into_shares(&keypair, "user-1", &key, ShareScheme::default()).unwrap();
+        assert_eq!(shares.len(), 3);
+        assert!(shares.iter().all(|s| s.threshold == 2 && s.public_key == keypair.pubkey().to_string()));
+
+        // Any two of the three shards are enough
+        let mut shares = shares;
+        shares.remove(1);
+        let rebuilt = reconstruct_from_shares(shares, &key).unwrap();
+        assert_eq!(rebuilt.pubkey(), keypair.pubkey());
+    }
+
+    fn request(threshold: Option<u8>, total_shares: Option<u8>) -> GenerateRequest {
+        GenerateRequest { user_id: "user-1".to_string(), threshold, total_shares }
+    }
+
+    #[test]
+    fn test_three_of_five_split_rebuilds_from_any_three() {
+        let key = ShareKey::from_hex(&"5a".repeat(32)).unwrap();
+        let keypair = Keypair::new();
+        let scheme = ShareScheme::from_request(&request(Some(3), Some(5))).unwrap();
+
+        let shares = split_into_shares(&keypair, "user-1", &key, scheme).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|s| s.threshold == 3 && s.total_shares == 5));
+
+        // Shares 1, 4 and 5: two of them share the first database
+        let picked: Vec<KeyShare> = shares.into_iter().filter(|s| [1, 4, 5].contains(&s.share_index)).collect();
+        let rebuilt = reconstruct_from_shares(picked, &key).unwrap();
+        assert_eq!(rebuilt.pubkey(), keypair.pubkey());
+    }
+
+    #[test]
+    fn test_share_scheme_defaults_and_rejects_invalid_parameters() {
+        assert_eq!(ShareScheme::from_request(&request(None, None)).unwrap(), ShareScheme::default());
+        assert!(ShareScheme::from_request(&request(Some(1), Some(1))).is_ok());
+
+        assert!(ShareScheme::from_request(&request(Some(0), Some(3))).is_err());
+        assert!(ShareScheme::from_request(&request(Some(4), Some(3))).is_err());
+        assert!(ShareScheme::from_request(&request(Some(3), Some(6))).is_err());
+        assert!(ShareScheme::from_request(&request(None, Some(1))).is_err());
+    }
+
+    #[test]
+    fn test_storage_plan_lists_every_share_at_its_database() {
+        let key = ShareKey::from_hex(&"5a".repeat(32)).unwrap();
+        let scheme = ShareScheme { threshold: 3, total_shares: 5 };
+        let shares = split_into_shares(&Keypair::new(), "user-1", &key, scheme).unwrap();
+
+        let plan = storage_plan(&shares);
+        let locations: Vec<(i32, usize)> = plan.iter().map(|s| (s.share_index, s.database_index)).collect();
+        assert_eq!(locations, vec![(1, 0), (2, 1), (3, 2), (4, 0), (5, 1)]);
+        assert!(plan.iter().all(|s| !s.stored));
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires MPC1_DATABASE_URL, MPC2_DATABASE_URL, MPC3_DATABASE_URL and SHARE_ENCRYPTION_KEY"]
+    async fn test_generate_response_enumerates_every_stored_share() {
+        let db = web::Data::new(DatabaseManager::new().await.unwrap());
+        let user_id = format!("generate-response-{}", Uuid::new_v4());
+        let req = GenerateRequest { user_id: user_id.clone(), threshold: Some(3), total_shares: Some(5) };
+
+        let response = generate(db.clone(), web::Json(req)).await.unwrap();
+        let status = response.status();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        db.delete_user_shares(&user_id).await.unwrap();
+
+        assert_eq!(status, 200);
+        let response: GenerateResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!((response.threshold, response.total_shares), (3, 5));
+        let indices: Vec<i32> = response.shares.iter().map(|s| s.share_index).collect();
+        assert_eq!(indices, vec![1, 2, 3, 4, 5]);
+        assert!(response.shares.iter().all(|s| s.stored));
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires MPC1_DATABASE_URL, MPC2_DATABASE_URL and MPC3_DATABASE_URL"]
+    async fn test_generated_shares_round_trip_through_the_shards() {
+        let key = ShareKey::from_hex(&"5a".repeat(32)).unwrap();
+        let db = DatabaseManager::new().await.unwrap();
+        let user_id = format!("share-round-trip-{}", Uuid::new_v4());
+        let keypair = Keypair::new();
+
+        for share in split_into_shares(&keypair, &user_id, &key, ShareScheme::default()).unwrap() {
+            db.store_key_share(&share, share_location(share.share_index).0).await.unwrap();
+        }
+
+        let fetched = db.get_all_user_shares(&user_id).await.unwrap();
+        assert_eq!(fetched.len(), 3);
+        let rebuilt = reconstruct_from_shares(fetched, &key).unwrap();
+        db.delete_user_shares(&user_id).await.unwrap();
+
+        assert_eq!(rebuilt.pubkey(), keypair.pubkey());
+    }
+}
@@ -1,16 +1,22 @@
 use actix_web::{web, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
 use solana_sdk::{
+    pubkey::Pubkey,
+    signature::Signature,
+    signer::Signer,
     transaction::Transaction
 };
 
-use crate::{database::DatabaseManager, routes::{create_rpc_client, parse_private_key}};
+use crate::{database::DatabaseManager, share_crypto::ShareKey, routes::{create_rpc_client, reconstruct_from_shares, verify_public_key, dry_run::simulate_signed_transaction, confirmation::{confirm_signature, fetch_fee_lamports, ConfirmationStatus}}};
 
 #[derive(Deserialize)]
 pub struct SwapRequest {
     pub user_id: String,
     pub user_public_key: String,
     pub swap_transaction: serde_json::Value, 
+    /// Build, sign and simulate, but don't broadcast.
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 #[derive(Serialize)]
@@ -18,9 +24,33 @@ pub struct SwapResponse {
     pub success: bool,
     pub transaction_signature: Option<String>,
     pub error: Option<String>,
+    pub confirmation_status: Option<ConfirmationStatus>,
+    /// Network fee charged on-chain, once the transaction has landed.
+    pub fee_lamports: Option<u64>,
     // pub swap_details: Option<SwapDetails>,
 }
 
+/// Required signers other than the user, e.g. ephemeral accounts Jupiter signs for.
+fn co_signers(transaction: &Transaction, user: &Pubkey) -> Vec<Pubkey> {
+    let num_signers = transaction.message.header.num_required_signatures as usize;
+    transaction.message.account_keys.iter()
+        .take(num_signers)
+        .filter(|key| *key != user)
+        .copied()
+        .collect()
+}
+
+/// Required signers whose signature slot is still empty.
+fn missing_signers(transaction: &Transaction) -> Vec<Pubkey> {
+    let num_signers = transaction.message.header.num_required_signatures as usize;
+    transaction.message.account_keys.iter()
+        .take(num_signers)
+        .enumerate()
+        .filter(|(i, _)| transaction.signatures.get(*i).is_none_or(|sig| *sig == Signature::default()))
+        .map(|(_, key)| *key)
+        .collect()
+}
+
 pub async fn jupiter_swap(
     db: web::Data<DatabaseManager>,
     req: web::Json<SwapRequest>,
@@ -35,7 +65,9 @@ pub async fn jupiter_swap(
             return Ok(HttpResponse::InternalServerError().json(SwapResponse{
                 success: false,
                 transaction_signature: None,
-                error: Some("Faileed to fetch user shares".to_string())
+                error: Some("Faileed to fetch user shares".to_string()),
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
@@ -45,7 +77,9 @@ pub async fn jupiter_swap(
         return Ok(HttpResponse::InternalServerError().json(SwapResponse{
             success: false,
             transaction_signature: None,
-            error: Some("no key share found".to_string())
+            error: Some("no key share found".to_string()),
+            confirmation_status: None,
+            fee_lamports: None,
         }));
     }
 
@@ -58,7 +92,9 @@ pub async fn jupiter_swap(
         return Ok(HttpResponse::InternalServerError().json(SwapResponse{
             success: false,
             transaction_signature: None,
-            error: Some("insufficient key shares".to_string())
+            error: Some("insufficient key shares".to_string()),
+            confirmation_status: None,
+            fee_lamports: None,
         }))
     }
 
@@ -68,37 +104,43 @@ pub async fn jupiter_swap(
             success: false,
             transaction_signature: None,
             error: Some("Public key verification failed".to_string()),
+            confirmation_status: None,
+            fee_lamports: None,
         }));
     }
 
-    // Step 2: reconstruct private key from MPC
-    let mut sorted_shares = shares;
-    sorted_shares.sort_by_key(|s| s.share_index);
-
-    // Use only the required number of shares for threshold signature
-    let required_shares: Vec<_> = sorted_shares.iter().take(thresold as usize).collect();
-    
-    println!("Reconstructing private key from {} shares", required_shares.len());
-    
-    // TODO: Implement proper MPC reconstruction here
-    // For now, using simplified concatenation (THIS NEEDS TO BE REPLACED WITH ACTUAL MPC)
-    let mut reconstructed_private_key = String::new();
-    for share in &required_shares {
-        reconstructed_private_key.push_str(&share.encrypted_share);
-    }
+    // Steps 2 and 3: decrypt the shares and rebuild the keypair
+    let share_key = match ShareKey::from_env() {
+        Ok(key) => key,
+        Err(e) => {
+            println!("Cannot decrypt key shares for user {}: {}", req.user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(SwapResponse {
+                success: false,
+                transaction_signature: None,
+                error: Some("Key share decryption is not configured".to_string()),
+                confirmation_status: None,
+                fee_lamports: None,
+            }));
+        }
+    };
 
-    // Step 3: Parse private key
-    let keypair = match parse_private_key(&reconstructed_private_key) {
+    println!("Reconstructing private key from {} shares", thresold);
+    let keypair = match reconstruct_from_shares(shares, &share_key)
+        .and_then(|keypair| verify_public_key(&keypair, &exp_public_key).map(|_| keypair))
+    {
         Ok(keypair) => keypair,
         Err(e) => {
-            println!("Failed to parse private key for user {}: {}", req.user_id, e);
+            println!("Failed to reconstruct private key for user {}: {}", req.user_id, e);
             return Ok(HttpResponse::InternalServerError().json(SwapResponse {
                 success: false,
                 transaction_signature: None,
                 error: Some("Failed to reconstruct private key".to_string()),
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
+    drop(share_key);
 
     // Step 4: Parse the swap transaction from Jupiter
     let swap_transaction_b64 = match req.swap_transaction.as_str() {
@@ -109,6 +151,8 @@ pub async fn jupiter_swap(
                 success: false,
                 transaction_signature: None,
                 error: Some("Invalid transaction format".to_string()),
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
@@ -122,6 +166,8 @@ pub async fn jupiter_swap(
                 success: false,
                 transaction_signature: None,
                 error: Some("Failed to decode transaction".to_string()),
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
@@ -135,28 +181,39 @@ pub async fn jupiter_swap(
                 success: false,
                 transaction_signature: None,
                 error: Some("Failed to deserialize transaction".to_string()),
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
 
     // Step 5: Get recent blockhash and sign transaction
     let rpc_client = create_rpc_client();
-    let recent_blockhash = match rpc_client.get_latest_blockhash() {
-        Ok(blockhash) => blockhash,
-        Err(e) => {
-            return Ok(HttpResponse::InternalServerError().json(SwapResponse{
-                success: false,
-                transaction_signature: None,
-                error: Some("failed to get recent bloakhash".to_string())
-            }));
+    let co_signers = co_signers(&transaction, &keypair.pubkey());
+
+    // Co-signer signatures Jupiter attached cover the message as built, so a refreshed
+    // blockhash would invalidate them; keep Jupiter's blockhash in that case.
+    let signing_blockhash = if co_signers.is_empty() {
+        match rpc_client.get_latest_blockhash() {
+            Ok(blockhash) => blockhash,
+            Err(e) => {
+                println!("Failed to get recent blockhash: {}", e);
+                return Ok(HttpResponse::InternalServerError().json(SwapResponse{
+                    success: false,
+                    transaction_signature: None,
+                    error: Some("failed to get recent bloakhash".to_string()),
+                    confirmation_status: None,
+                    fee_lamports: None,
+                }));
+            }
         }
+    } else {
+        println!("Swap transaction for user {} has {} co-signer(s), preserving their signatures", req.user_id, co_signers.len());
+        transaction.message.recent_blockhash
     };
 
-    // Update blockhash and sign transaction
-    transaction.message.recent_blockhash = recent_blockhash;
-    
-    // Sign the transaction
-    match transaction.try_sign(&[&keypair], recent_blockhash) {
+    // Only the user's signature slot is filled; signatures already present are left as-is
+    match transaction.try_partial_sign(&[&keypair], signing_blockhash) {
         Ok(_) => println!("Transaction signed successfully"),
         Err(e) => {
             println!("Failed to sign transaction: {}", e);
@@ -164,15 +221,40 @@ pub async fn jupiter_swap(
                 success: false,
                 transaction_signature: None,
                 error: Some("Failed to sign transaction".to_string()),
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     }
 
+    let missing = missing_signers(&transaction);
+    if !missing.is_empty() {
+        drop(keypair);
+
+        let missing: Vec<String> = missing.iter().map(|pubkey| pubkey.to_string()).collect();
+        println!("Swap transaction for user {} is missing signatures from: {}", req.user_id, missing.join(", "));
+        return Ok(HttpResponse::BadRequest().json(SwapResponse {
+            success: false,
+            transaction_signature: None,
+            error: Some(format!("Transaction is missing required signatures from: {}", missing.join(", "))),
+            confirmation_status: None,
+            fee_lamports: None,
+        }));
+    }
+
+    if req.dry_run {
+        drop(keypair);
+
+        let result = simulate_signed_transaction(&rpc_client, &transaction);
+        println!("Dry run swap for user {}: success={}", req.user_id, result.success);
+        return Ok(HttpResponse::Ok().json(result));
+    }
+
     // Step 6: Send the transaction to Solana network
     println!("Broadcasting transaction to Solana network...");
-    let signature = match rpc_client.send_and_confirm_transaction_with_spinner(&transaction) {
+    let signature = match rpc_client.send_transaction(&transaction) {
         Ok(sig) => {
-            println!("Transaction successful for user {}: {}", req.user_id, sig);
+            println!("Transaction submitted for user {}: {}", req.user_id, sig);
             sig
         }
         Err(e) => {
@@ -181,19 +263,77 @@ pub async fn jupiter_swap(
                 success: false,
                 transaction_signature: None,
                 error: Some(format!("Failed to send transaction: {}", e)),
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
 
     // clear the private key from memory for security
     drop(keypair);
-    drop(reconstructed_private_key);
 
-    println!("Jupiter swap completed successfully for user: {}", req.user_id);
+    // Wait (bounded) for confirmation; a pending swap may still land, so it is not a failure
+    let (confirmation_status, chain_error) = confirm_signature(&rpc_client, &signature).await;
+    println!("Jupiter swap for user {} is {:?}", req.user_id, confirmation_status);
+    let fee_lamports = fetch_fee_lamports(&rpc_client, &signature, &confirmation_status).await;
     
     Ok(HttpResponse::Ok().json(SwapResponse {
-        success: true,
+        success: confirmation_status != ConfirmationStatus::Failed,
         transaction_signature: Some(signature.to_string()),
-        error: None,
+        error: chain_error.map(|e| format!("Transaction failed: {}", e)),
+        confirmation_status: Some(confirmation_status),
+        fee_lamports,
     }))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        hash::Hash,
+        instruction::{AccountMeta, Instruction},
+        message::Message,
+        signature::Keypair,
+    };
+
+    fn two_signer_transaction(user: &Keypair, ephemeral: &Keypair) -> Transaction {
+        let instruction = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![
+                AccountMeta::new(user.pubkey(), true),
+                AccountMeta::new(ephemeral.pubkey(), true),
+            ],
+            data: vec![],
+        };
+        let mut transaction = Transaction::new_unsigned(Message::new(&[instruction], Some(&user.pubkey())));
+        transaction.message.recent_blockhash = Hash::new_unique();
+        transaction
+    }
+
+    #[test]
+    fn test_user_signature_preserves_co_signer() {
+        let user = Keypair::new();
+        let ephemeral = Keypair::new();
+        let mut transaction = two_signer_transaction(&user, &ephemeral);
+        let blockhash = transaction.message.recent_blockhash;
+
+        // Jupiter's side signs first
+        transaction.try_partial_sign(&[&ephemeral], blockhash).unwrap();
+        assert_eq!(co_signers(&transaction, &user.pubkey()), vec![ephemeral.pubkey()]);
+        assert_eq!(missing_signers(&transaction), vec![user.pubkey()]);
+
+        transaction.try_partial_sign(&[&user], blockhash).unwrap();
+        assert!(missing_signers(&transaction).is_empty());
+        assert!(transaction.verify().is_ok());
+    }
+
+    #[test]
+    fn test_missing_co_signer_is_reported() {
+        let user = Keypair::new();
+        let ephemeral = Keypair::new();
+        let mut transaction = two_signer_transaction(&user, &ephemeral);
+        let blockhash = transaction.message.recent_blockhash;
+
+        transaction.try_partial_sign(&[&user], blockhash).unwrap();
+        assert_eq!(missing_signers(&transaction), vec![ephemeral.pubkey()]);
+    }
+}
@@ -2,8 +2,27 @@ pub mod generate;
 pub mod aggregate_keys;
 pub mod send_sol;
 pub mod jupiter_swap;
+pub mod sign_batch;
+pub mod confirmation;
+pub mod derive_pubkey;
+pub mod request_id;
+pub mod dry_run;
+pub mod public_key;
+pub mod admin_config;
+pub mod extractor_errors;
+pub mod delete_shares;
+pub mod rotate_keys;
+pub mod shard_consistency;
+pub mod shutdown;
 
 pub use generate::*;
 pub use aggregate_keys::*;
 pub use send_sol::*;
-pub use jupiter_swap::*;
\ No newline at end of file
+pub use jupiter_swap::*;
+pub use sign_batch::*;
+pub use derive_pubkey::*;
+pub use public_key::*;
+pub use admin_config::*;
+pub use delete_shares::*;
+pub use rotate_keys::*;
+pub use shard_consistency::*;
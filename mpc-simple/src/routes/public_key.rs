@@ -0,0 +1,95 @@
+use actix_web::{web, HttpResponse, Result};
+use common::shard::SHARD_COUNT;
+use serde::Serialize;
+
+use crate::database::DatabaseManager;
+
+#[derive(Serialize)]
+pub struct PublicKeyResponse {
+    pub success: bool,
+    pub user_id: String,
+    pub public_key: Option<String>,
+    pub consistent: bool,
+    pub shards_found: usize,
+    pub error: Option<String>,
+}
+
+/// The key from the first share record, and whether every other record agrees with it.
+fn first_and_consistent(public_keys: &[String]) -> Option<(String, bool)> {
+    let first = public_keys.first()?;
+    Some((first.clone(), public_keys.iter().all(|key| key == first)))
+}
+
+/// Read the public key recorded with the user's shares, without reconstructing the key.
+/// The first available shard's record is returned; `consistent` reports whether every
+/// shard was read and every one that holds a share records the same key.
+pub async fn get_public_key(
+    db: web::Data<DatabaseManager>,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let user_id = path.into_inner();
+    println!("Looking up public key for user: {}", user_id);
+
+    let mut public_keys = Vec::new();
+    let mut unreadable = Vec::new();
+    for shard in 0..SHARD_COUNT {
+        match db.get_key_share(&user_id, shard).await {
+            Ok(Some(share)) => public_keys.push(share.public_key),
+            Ok(None) => {}
+            Err(e) => {
+                println!("Failed to read share {} for user {}: {}", shard + 1, user_id, e);
+                unreadable.push((shard + 1).to_string());
+            }
+        }
+    }
+    // An unread shard can't be said to agree
+    let read_error = (!unreadable.is_empty()).then(|| format!("Failed to read shard(s) {}", unreadable.join(", ")));
+
+    match first_and_consistent(&public_keys) {
+        Some((public_key, agree)) => {
+            if !agree {
+                println!("Shards disagree on the public key for user {}", user_id);
+            }
+            Ok(HttpResponse::Ok().json(PublicKeyResponse {
+                success: true,
+                user_id,
+                public_key: Some(public_key),
+                consistent: agree && read_error.is_none(),
+                shards_found: public_keys.len(),
+                error: read_error,
+            }))
+        }
+        // Not knowing whether the user has shares is not the same as having none
+        None if read_error.is_some() => Ok(HttpResponse::InternalServerError().json(PublicKeyResponse {
+            success: false,
+            user_id,
+            public_key: None,
+            consistent: false,
+            shards_found: 0,
+            error: read_error,
+        })),
+        None => Ok(HttpResponse::NotFound().json(PublicKeyResponse {
+            success: false,
+            user_id,
+            public_key: None,
+            consistent: false,
+            shards_found: 0,
+            error: Some("No key shares found for user".to_string()),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_and_consistent() {
+        let key = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".to_string();
+        let other = "So11111111111111111111111111111111111111112".to_string();
+
+        assert_eq!(first_and_consistent(&[]), None);
+        assert_eq!(first_and_consistent(&[key.clone(), key.clone()]), Some((key.clone(), true)));
+        assert_eq!(first_and_consistent(&[key.clone(), other]), Some((key, false)));
+    }
+}
@@ -0,0 +1,34 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    Error, HttpMessage,
+};
+
+/// Correlation id sent by the backend; logged here so a swap or send can be traced end to end.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Middleware: log each request under the backend's request id (or a fresh one for direct calls).
+pub async fn log_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let method = req.method().clone();
+    let path = req.path().to_string();
+    println!("[{}] {} {}", id, method, path);
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let res = next.call(req).await?;
+    println!("[{}] {} {} -> {}", id, method, path, res.status());
+    Ok(res)
+}
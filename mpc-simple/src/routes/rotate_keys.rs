@@ -0,0 +1,143 @@
+use actix_web::{web, HttpResponse, Result};
+use serde_json::json;
+use solana_sdk::{signature::Keypair, signer::Signer};
+
+use crate::{
+    database::{is_complete, DatabaseManager},
+    models::{RotateKeysRequest, RotateKeysResponse},
+    share_crypto::ShareKey,
+};
+use super::generate::{split_into_shares, storage_plan, ShareScheme};
+
+/// Replace the user's threshold key with a fresh one under the same scheme, e.g. when their
+/// shares are suspected compromised. The old key is unrecoverable afterwards, so anything
+/// held at the old address must be moved out first. If storing the new shares fails before
+/// any shard commits, the old shares are left as they were; a failure partway through the
+/// commits is reported with the shards that took the new key.
+pub async fn rotate_keys(
+    db: web::Data<DatabaseManager>,
+    req: web::Json<RotateKeysRequest>,
+) -> Result<HttpResponse> {
+    println!("Rotating threshold keypair for user: {}", req.user_id);
+
+    let existing = match db.get_all_user_shares(&req.user_id).await {
+        Ok(shares) => shares,
+        Err(e) => {
+            println!("Database error reading shares for user {}: {}", req.user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Database error"
+            })));
+        }
+    };
+    if existing.is_empty() {
+        return Ok(HttpResponse::NotFound().json(json!({
+            "error": "No key shares found for user"
+        })));
+    }
+    if !is_complete(&existing) {
+        println!("Refusing to rotate incomplete shares for user {}", req.user_id);
+        return Ok(HttpResponse::Conflict().json(json!({
+            "error": "User's key shares are incomplete; check shard consistency first"
+        })));
+    }
+
+    let previous_public_key = existing[0].public_key.clone();
+    let scheme = ShareScheme {
+        threshold: existing[0].threshold as u8,
+        total_shares: existing[0].total_shares as u8,
+    };
+
+    let share_key = match ShareKey::from_env() {
+        Ok(key) => key,
+        Err(e) => {
+            println!("Cannot encrypt key shares for user {}: {}", req.user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Key share encryption is not configured"
+            })));
+        }
+    };
+
+    let keypair = Keypair::new();
+    let public_key = keypair.pubkey().to_string();
+    let shares = match split_into_shares(&keypair, &req.user_id, &share_key, scheme) {
+        Ok(shares) => shares,
+        Err(e) => {
+            println!("Failed to encrypt key shares for user {}: {}", req.user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to encrypt key shares"
+            })));
+        }
+    };
+    drop(keypair);
+    drop(share_key);
+
+    if let Err(e) = db.replace_user_shares(&req.user_id, &shares).await {
+        if e.committed_shards.is_empty() {
+            println!("Failed to store rotated shares for user {}, keeping {}: {}", req.user_id, previous_public_key, e);
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Failed to store rotated key shares; the previous key is unchanged"
+            })));
+        }
+
+        // Some shards hold shares of the new key and the rest of the old one
+        println!(
+            "CRITICAL: Rotation for user {} partly stored: shards {:?} hold {}, the rest still hold {}: {}",
+            req.user_id, e.committed_shards, public_key, previous_public_key, e
+        );
+        return Ok(HttpResponse::InternalServerError().json(json!({
+            "error": "Rotated key shares were only partly stored; the user's shares need repair",
+            "public_key": public_key,
+            "previous_public_key": previous_public_key,
+            "committed_shards": e.committed_shards
+        })));
+    }
+
+    let mut storage = storage_plan(&shares);
+    storage.iter_mut().for_each(|entry| entry.stored = true);
+
+    println!("Rotated key for user {}: {} -> {}", req.user_id, previous_public_key, public_key);
+    Ok(HttpResponse::Ok().json(RotateKeysResponse {
+        user_id: req.user_id.clone(),
+        public_key,
+        previous_public_key,
+        threshold: scheme.threshold as i32,
+        total_shares: scheme.total_shares as i32,
+        shares: storage,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::shard::share_location;
+    use uuid::Uuid;
+
+    #[actix_web::test]
+    #[ignore = "requires MPC1_DATABASE_URL, MPC2_DATABASE_URL, MPC3_DATABASE_URL and SHARE_ENCRYPTION_KEY"]
+    async fn test_rotation_replaces_the_key_and_its_shares() {
+        let db = web::Data::new(DatabaseManager::new().await.unwrap());
+        let key = ShareKey::from_env().unwrap();
+        let user_id = format!("rotate-keys-{}", Uuid::new_v4());
+
+        let original = Keypair::new();
+        for share in split_into_shares(&original, &user_id, &key, ShareScheme::default()).unwrap() {
+            db.store_key_share(&share, share_location(share.share_index).0).await.unwrap();
+        }
+
+        let req = RotateKeysRequest { user_id: user_id.clone() };
+        let response = rotate_keys(db.clone(), web::Json(req)).await.unwrap();
+        let status = response.status();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let shares = db.get_all_user_shares(&user_id).await.unwrap();
+        db.delete_user_shares(&user_id).await.unwrap();
+
+        assert_eq!(status, 200);
+        let response: RotateKeysResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response.previous_public_key, original.pubkey().to_string());
+        assert_ne!(response.public_key, response.previous_public_key);
+
+        // Every old share was overwritten by one for the new key
+        assert_eq!(shares.len(), 3);
+        assert!(shares.iter().all(|s| s.public_key == response.public_key));
+    }
+}
@@ -2,6 +2,7 @@ use actix_web::{web, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
+    hash::Hash,
     instruction::{AccountMeta, Instruction},
     message::Message,
     pubkey::Pubkey,
@@ -10,8 +11,14 @@ use solana_sdk::{
     transaction::Transaction,
 };
 use std::str::FromStr;
+use zeroize::Zeroize;
 
 use crate::database::DatabaseManager;
+use crate::models::KeyShare;
+use crate::share_crypto::{decrypt_share, ShareKey};
+use crate::shamir;
+use crate::routes::dry_run::simulate_signed_transaction;
+use crate::routes::confirmation::{confirm_signature, fetch_fee_lamports, ConfirmationStatus};
 
 // System program ID constant
 const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111112";
@@ -21,6 +28,9 @@ pub struct SendSolRequest {
     pub user_id: String,
     pub to_address: String,
     pub amount_lamports: u64,
+    /// Build, sign and simulate, but don't broadcast.
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -31,6 +41,9 @@ pub struct SendSolResponse {
     pub from_address: String,
     pub to_address: String,
     pub amount_lamports: u64,
+    pub confirmation_status: Option<ConfirmationStatus>,
+    /// Network fee charged on-chain, once the transaction has landed.
+    pub fee_lamports: Option<u64>,
 }
 
 pub async fn send_sol(
@@ -51,6 +64,8 @@ pub async fn send_sol(
                 from_address: "unknown".to_string(),
                 to_address: req.to_address.clone(),
                 amount_lamports: req.amount_lamports,
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
@@ -65,6 +80,8 @@ pub async fn send_sol(
             from_address: "unknown".to_string(),
             to_address: req.to_address.clone(),
             amount_lamports: req.amount_lamports,
+            confirmation_status: None,
+            fee_lamports: None,
         }));
     }
 
@@ -83,35 +100,52 @@ pub async fn send_sol(
             from_address: expected_public_key,
             to_address: req.to_address.clone(),
             amount_lamports: req.amount_lamports,
+            confirmation_status: None,
+            fee_lamports: None,
         }));
     }
 
-    // Step 2: Reconstruct the private key (simplified - in production use proper secret sharing)
-    let mut sorted_shares = shares;
-    sorted_shares.sort_by_key(|s| s.share_index);
-
-    // For now, concatenating the shares - in production, use Shamir's Secret Sharing
-    let mut reconstructed_private_key = String::new();
-    for share in sorted_shares.iter().take(threshold as usize) {
-        reconstructed_private_key.push_str(&share.encrypted_share);
-        println!("Using share {} for user {}", share.share_index, req.user_id);
-    }
+    // Steps 2 and 3: Decrypt the shares and rebuild the keypair
+    let share_key = match ShareKey::from_env() {
+        Ok(key) => key,
+        Err(e) => {
+            println!("Cannot decrypt key shares for user {}: {}", req.user_id, e);
+            return Ok(HttpResponse::InternalServerError().json(SendSolResponse {
+                success: false,
+                transaction_signature: None,
+                error: Some("Key share decryption is not configured".to_string()),
+                from_address: expected_public_key,
+                to_address: req.to_address.clone(),
+                amount_lamports: req.amount_lamports,
+                confirmation_status: None,
+                fee_lamports: None,
+            }));
+        }
+    };
 
-    // Step 3: Parse the private key and create Keypair
-    let keypair = match parse_private_key(&reconstructed_private_key) {
+    let keypair = match reconstruct_from_shares(shares, &share_key)
+        .and_then(|keypair| verify_public_key(&keypair, &expected_public_key).map(|_| keypair))
+    {
         Ok(kp) => kp,
         Err(e) => {
-            println!("Failed to parse private key for user {}: {}", req.user_id, e);
+            println!("Failed to reconstruct private key for user {}: {}", req.user_id, e);
+            let error = match e {
+                KeyReconstructionError::PublicKeyMismatch { .. } => e.to_string(),
+                _ => "Failed to reconstruct private key".to_string(),
+            };
             return Ok(HttpResponse::InternalServerError().json(SendSolResponse {
                 success: false,
                 transaction_signature: None,
-                error: Some("Failed to parse private key".to_string()),
+                error: Some(error),
                 from_address: expected_public_key,
                 to_address: req.to_address.clone(),
                 amount_lamports: req.amount_lamports,
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
+    drop(share_key);
 
     // Step 4: Validate the to_address
     let to_pubkey = match Pubkey::from_str(&req.to_address) {
@@ -125,17 +159,15 @@ pub async fn send_sol(
                 from_address: keypair.pubkey().to_string(),
                 to_address: req.to_address.clone(),
                 amount_lamports: req.amount_lamports,
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
 
-    // Step 5: Create the SOL transfer transaction
     let from_pubkey = keypair.pubkey();
-    
-    // Create transfer instruction manually
-    let transfer_instruction = create_transfer_instruction(&from_pubkey, &to_pubkey, req.amount_lamports);
 
-    // Step 6: Get recent blockhash from Solana network
+    // Step 5: Get recent blockhash from Solana network
     let rpc_client = create_rpc_client();
     let recent_blockhash = match rpc_client.get_latest_blockhash() {
         Ok(blockhash) => blockhash,
@@ -148,17 +180,25 @@ pub async fn send_sol(
                 from_address: from_pubkey.to_string(),
                 to_address: req.to_address.clone(),
                 amount_lamports: req.amount_lamports,
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
 
-    // Step 7: Create and sign the transaction
-    let message = Message::new(&[transfer_instruction], Some(&from_pubkey));
-    let mut transaction = Transaction::new_unsigned(message);
-    transaction.sign(&[&keypair], recent_blockhash);
+    // Steps 6 and 7: Create and sign the SOL transfer transaction
+    let transaction = signed_transfer(&keypair, &to_pubkey, req.amount_lamports, recent_blockhash);
+
+    if req.dry_run {
+        drop(keypair);
+
+        let result = simulate_signed_transaction(&rpc_client, &transaction);
+        println!("Dry run transfer of {} lamports for user {}: success={}", req.amount_lamports, req.user_id, result.success);
+        return Ok(HttpResponse::Ok().json(result));
+    }
 
     // Step 8: Send the transaction to Solana network
-    let signature = match rpc_client.send_and_confirm_transaction_with_spinner(&transaction) {
+    let signature = match rpc_client.send_transaction(&transaction) {
         Ok(sig) => sig,
         Err(e) => {
             println!("Failed to send transaction for user {}: {}", req.user_id, e);
@@ -169,28 +209,48 @@ pub async fn send_sol(
                 from_address: from_pubkey.to_string(),
                 to_address: req.to_address.clone(),
                 amount_lamports: req.amount_lamports,
+                confirmation_status: None,
+                fee_lamports: None,
             }));
         }
     };
 
-    println!("Successfully sent {} lamports from {} to {} for user {}. Signature: {}", 
-             req.amount_lamports, from_pubkey, to_pubkey, req.user_id, signature);
-
     // Clear the private key from memory for security
     drop(keypair);
-    drop(reconstructed_private_key);
 
-    // Step 9: Return success response
+    // Step 9: Wait (bounded) for confirmation
+    let (confirmation_status, chain_error) = confirm_signature(&rpc_client, &signature).await;
+    println!("Transfer of {} lamports from {} to {} for user {} is {:?}. Signature: {}", 
+             req.amount_lamports, from_pubkey, to_pubkey, req.user_id, confirmation_status, signature);
+
+    let fee_lamports = fetch_fee_lamports(&rpc_client, &signature, &confirmation_status).await;
+
+    // A pending transaction may still land, so it is not reported as a failure
+    let failed = confirmation_status == ConfirmationStatus::Failed;
+
+    // Step 10: Return response with the final status
     Ok(HttpResponse::Ok().json(SendSolResponse {
-        success: true,
+        success: !failed,
         transaction_signature: Some(signature.to_string()),
-        error: None,
+        error: chain_error.map(|e| format!("Transaction failed: {}", e)),
         from_address: from_pubkey.to_string(),
         to_address: req.to_address.clone(),
         amount_lamports: req.amount_lamports,
+        confirmation_status: Some(confirmation_status),
+        fee_lamports,
     }))
 }
 
+/// A system transfer of `lamports` from `keypair` to `to`, signed against `recent_blockhash`.
+pub fn signed_transfer(keypair: &Keypair, to: &Pubkey, lamports: u64, recent_blockhash: Hash) -> Transaction {
+    let from_pubkey = keypair.pubkey();
+    let transfer_instruction = create_transfer_instruction(&from_pubkey, to, lamports);
+    let message = Message::new(&[transfer_instruction], Some(&from_pubkey));
+    let mut transaction = Transaction::new_unsigned(message);
+    transaction.sign(&[keypair], recent_blockhash);
+    transaction
+}
+
 fn create_transfer_instruction(from: &Pubkey, to: &Pubkey, lamports: u64) -> Instruction {
     // System program transfer instruction
     let system_program_id = Pubkey::from_str(SYSTEM_PROGRAM_ID).unwrap();
@@ -212,42 +272,147 @@ fn encode_transfer_instruction(lamports: u64) -> Vec<u8> {
     data
 }
 
-pub fn parse_private_key(private_key_str: &str) -> Result<Keypair, Box<dyn std::error::Error>> {
-    // Try different formats for private key parsing
-    
-    // First, try as base58 string (common format)
-    if let Ok(_) = bs58::decode(private_key_str).into_vec() {
-        // Try the from_base58_string method that exists
-        return Ok(Keypair::from_base58_string(private_key_str));
-    }
-    
-    // Try as hex string for 32-byte private key
-    if let Ok(bytes) = hex::decode(private_key_str) {
-        if bytes.len() == 32 {
-            let mut private_key_bytes = [0u8; 32];
-            private_key_bytes.copy_from_slice(&bytes);
-            return Ok(Keypair::new_from_array(private_key_bytes));
-        }
-    }
-    
-    // Try as JSON array format [byte1, byte2, ...] for 32-byte private key
-    if private_key_str.starts_with('[') && private_key_str.ends_with(']') {
-        if let Ok(bytes_vec) = serde_json::from_str::<Vec<u8>>(private_key_str) {
-            if bytes_vec.len() == 32 {
-                let mut private_key_bytes = [0u8; 32];
-                private_key_bytes.copy_from_slice(&bytes_vec);
-                return Ok(Keypair::new_from_array(private_key_bytes));
-            }
-        }
-    }
-    
-    Err("Unable to parse private key in any recognized format".into())
-}
-
 pub fn create_rpc_client() -> RpcClient {
     // Use devnet for testing, mainnet for production
     let rpc_url = std::env::var("SOLANA_RPC_URL")
         .unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
     
     RpcClient::new(rpc_url)
-}
\ No newline at end of file
+}
+
+#[derive(Debug)]
+pub enum KeyReconstructionError {
+    SharesUnavailable(String),
+    NoShares,
+    InsufficientShares { found: usize, needed: usize },
+    InconsistentShares,
+    ShareDecryption(String),
+    InvalidKey(String),
+    PublicKeyMismatch { expected: String, derived: String },
+}
+
+impl std::fmt::Display for KeyReconstructionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyReconstructionError::SharesUnavailable(msg) => write!(f, "Failed to fetch key shares: {}", msg),
+            KeyReconstructionError::NoShares => write!(f, "No key shares found for user"),
+            KeyReconstructionError::InsufficientShares { found, needed } => {
+                write!(f, "Insufficient shares: found {}, need {}", found, needed)
+            }
+            KeyReconstructionError::InconsistentShares => write!(f, "Inconsistent public keys across shares"),
+            KeyReconstructionError::ShareDecryption(msg) => write!(f, "Failed to decrypt key share: {}", msg),
+            KeyReconstructionError::InvalidKey(msg) => write!(f, "Failed to parse private key: {}", msg),
+            KeyReconstructionError::PublicKeyMismatch { expected, derived } => {
+                write!(f, "Reconstructed public key {} does not match the registered {}", derived, expected)
+            }
+        }
+    }
+}
+
+/// Fetch the user's shares, rebuild the keypair and wipe the intermediate key material.
+/// The keypair is only returned if it matches the public key recorded with the shares.
+pub async fn reconstruct_keypair(db: &DatabaseManager, user_id: &str) -> std::result::Result<Keypair, KeyReconstructionError> {
+    let shares = db.get_all_user_shares(user_id).await
+        .map_err(|e| KeyReconstructionError::SharesUnavailable(e.to_string()))?;
+    let key = ShareKey::from_env()
+        .map_err(|e| KeyReconstructionError::ShareDecryption(e.to_string()))?;
+    let expected = shares.first().map(|s| s.public_key.clone()).unwrap_or_default();
+
+    let keypair = reconstruct_from_shares(shares, &key)?;
+    verify_public_key(&keypair, &expected)?;
+    Ok(keypair)
+}
+
+/// Refuse a reconstructed key that doesn't belong to the address recorded with the shares,
+/// e.g. because too few or mismatched shares were combined.
+pub fn verify_public_key(keypair: &Keypair, expected: &str) -> std::result::Result<(), KeyReconstructionError> {
+    let derived = keypair.pubkey().to_string();
+    if derived != expected {
+        return Err(KeyReconstructionError::PublicKeyMismatch { expected: expected.to_string(), derived });
+    }
+    Ok(())
+}
+
+/// Rebuild the keypair from already-fetched shares, wiping the intermediate key material.
+/// Each share decrypts to a hex-encoded Shamir point (x = share index) of the 64-byte
+/// keypair; `threshold` of them are interpolated back to the keypair bytes.
+pub fn reconstruct_from_shares(shares: Vec<KeyShare>, key: &ShareKey) -> std::result::Result<Keypair, KeyReconstructionError> {
+    let first_share = shares.first().ok_or(KeyReconstructionError::NoShares)?;
+    let threshold = first_share.threshold as usize;
+    if shares.iter().any(|s| s.public_key != first_share.public_key) {
+        return Err(KeyReconstructionError::InconsistentShares);
+    }
+    if shares.len() < threshold {
+        return Err(KeyReconstructionError::InsufficientShares { found: shares.len(), needed: threshold });
+    }
+
+    let mut sorted_shares = shares;
+    sorted_shares.sort_by_key(|s| s.share_index);
+
+    let mut points = Vec::with_capacity(threshold);
+    for share in sorted_shares.iter().take(threshold) {
+        let x = u8::try_from(share.share_index)
+            .map_err(|_| KeyReconstructionError::InvalidKey(format!("share index {} out of range", share.share_index)))?;
+        let mut part = decrypt_share(key, share)
+            .map_err(|e| KeyReconstructionError::ShareDecryption(format!("share {}: {}", share.share_index, e)))?;
+        let y = hex::decode(&part);
+        part.zeroize();
+        let y = y.map_err(|e| KeyReconstructionError::InvalidKey(format!("share {}: {}", share.share_index, e)))?;
+        points.push(shamir::Share { x, y });
+    }
+
+    let mut keypair_bytes = shamir::combine(&points)
+        .map_err(|e| KeyReconstructionError::InvalidKey(e.to_string()))?;
+    drop(points);
+
+    let keypair = keypair_from_bytes(&keypair_bytes);
+    keypair_bytes.zeroize();
+
+    keypair
+}
+
+/// The keypair from its 64-byte form: the 32-byte secret seed followed by the public key.
+fn keypair_from_bytes(bytes: &[u8]) -> std::result::Result<Keypair, KeyReconstructionError> {
+    if bytes.len() != 64 {
+        return Err(KeyReconstructionError::InvalidKey(format!("expected 64 keypair bytes, got {}", bytes.len())));
+    }
+
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&bytes[..32]);
+    let keypair = Keypair::new_from_array(seed);
+    seed.zeroize();
+
+    Ok(keypair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::{split_into_shares, ShareScheme};
+
+    #[test]
+    fn test_generated_shares_reconstruct_and_sign_a_transfer() {
+        let key = ShareKey::from_hex(&"3c".repeat(32)).unwrap();
+        let wallet = Keypair::new();
+        let recipient = Keypair::new().pubkey();
+        let shares = split_into_shares(&wallet, "user-1", &key, ShareScheme::default()).unwrap();
+        let registered = shares[0].public_key.clone();
+
+        let keypair = reconstruct_from_shares(shares, &key).unwrap();
+        verify_public_key(&keypair, &registered).unwrap();
+
+        let transaction = signed_transfer(&keypair, &recipient, 1_000_000, Hash::new_from_array([7u8; 32]));
+
+        assert!(transaction.verify().is_ok());
+        assert_eq!(transaction.message.account_keys[0], wallet.pubkey());
+    }
+
+    #[test]
+    fn test_verify_public_key_rejects_another_address() {
+        let keypair = Keypair::new();
+        let other = Keypair::new().pubkey().to_string();
+
+        assert!(verify_public_key(&keypair, &keypair.pubkey().to_string()).is_ok());
+        assert!(matches!(verify_public_key(&keypair, &other), Err(KeyReconstructionError::PublicKeyMismatch { .. })));
+    }
+}
@@ -0,0 +1,173 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::database::{is_complete, DatabaseManager};
+use crate::models::KeyShare;
+use super::admin_config::is_admin;
+
+const DEFAULT_SAMPLE_SIZE: i64 = 100;
+const MAX_SAMPLE_SIZE: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct ShardConsistencyQuery {
+    pub user_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// One share whose metadata disagrees with the first share found for the user.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ShardMismatch {
+    pub share_index: i32,
+    pub field: &'static str,
+    pub expected: String,
+    pub found: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserConsistency {
+    pub user_id: String,
+    pub shards_found: usize,
+    pub consistent: bool,
+    pub mismatches: Vec<ShardMismatch>,
+}
+
+#[derive(Serialize)]
+pub struct ShardConsistencyReport {
+    pub users_checked: usize,
+    pub consistent: bool,
+    /// Every user with a missing or disagreeing share; with `?user_id=`, that user regardless.
+    pub users: Vec<UserConsistency>,
+}
+
+/// Compare `public_key`, `threshold` and `total_shares` of each share against the first one.
+/// A user missing a share is inconsistent even if the shares it has agree.
+fn check_user_shares(user_id: &str, shares: &[KeyShare]) -> UserConsistency {
+    let mut mismatches = Vec::new();
+    if let Some(first) = shares.first() {
+        for share in &shares[1..] {
+            let fields = [
+                ("public_key", first.public_key.clone(), share.public_key.clone()),
+                ("threshold", first.threshold.to_string(), share.threshold.to_string()),
+                ("total_shares", first.total_shares.to_string(), share.total_shares.to_string()),
+            ];
+            for (field, expected, found) in fields {
+                if expected != found {
+                    mismatches.push(ShardMismatch { share_index: share.share_index, field, expected, found });
+                }
+            }
+        }
+    }
+
+    UserConsistency {
+        user_id: user_id.to_string(),
+        shards_found: shares.len(),
+        consistent: mismatches.is_empty() && is_complete(shares),
+        mismatches,
+    }
+}
+
+/// Check that every shard agrees on each user's share metadata, for one user
+/// (`?user_id=`) or a sample of up to `?limit=` users (default 100).
+pub async fn shard_consistency(
+    http_req: HttpRequest,
+    db: web::Data<DatabaseManager>,
+    query: web::Query<ShardConsistencyQuery>,
+) -> Result<HttpResponse> {
+    if !is_admin(&http_req) {
+        return Ok(HttpResponse::Unauthorized().json(json!({
+            "error": "Admin key required"
+        })));
+    }
+
+    let user_ids = match &query.user_id {
+        Some(user_id) => vec![user_id.clone()],
+        None => {
+            let limit = query.limit.unwrap_or(DEFAULT_SAMPLE_SIZE).clamp(1, MAX_SAMPLE_SIZE);
+            match db.sample_user_ids(limit).await {
+                Ok(user_ids) => user_ids,
+                Err(e) => {
+                    println!("Failed to sample users for shard consistency: {}", e);
+                    return Ok(HttpResponse::InternalServerError().json(json!({
+                        "error": "Failed to list users"
+                    })));
+                }
+            }
+        }
+    };
+
+    let mut users = Vec::new();
+    for user_id in &user_ids {
+        let shares = match db.get_all_user_shares(user_id).await {
+            Ok(shares) => shares,
+            Err(e) => {
+                println!("Failed to read shares for user {}: {}", user_id, e);
+                return Ok(HttpResponse::InternalServerError().json(json!({
+                    "error": "Failed to read key shares",
+                    "user_id": user_id
+                })));
+            }
+        };
+
+        if shares.is_empty() && query.user_id.is_some() {
+            return Ok(HttpResponse::NotFound().json(json!({
+                "error": "No key shares found for user"
+            })));
+        }
+
+        let result = check_user_shares(user_id, &shares);
+        if !result.consistent {
+            println!("Shard metadata for user {} is inconsistent: {:?}", user_id, result.mismatches);
+        }
+        if !result.consistent || query.user_id.is_some() {
+            users.push(result);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ShardConsistencyReport {
+        users_checked: user_ids.len(),
+        consistent: users.iter().all(|user| user.consistent),
+        users,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn share(share_index: i32, public_key: &str, threshold: i32) -> KeyShare {
+        KeyShare {
+            id: uuid::Uuid::new_v4(),
+            user_id: "user-1".to_string(),
+            public_key: public_key.to_string(),
+            encrypted_share: "share".to_string(),
+            share_index,
+            threshold,
+            total_shares: 3,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_agreeing_shards_are_consistent() {
+        let shares = [share(1, "key", 2), share(2, "key", 2), share(3, "key", 2)];
+        let result = check_user_shares("user-1", &shares);
+        assert!(result.consistent);
+        assert!(result.mismatches.is_empty());
+    }
+
+    #[test]
+    fn test_diverged_and_missing_shards_are_reported() {
+        let shares = [share(1, "key", 2), share(2, "other", 2), share(3, "key", 3)];
+        let result = check_user_shares("user-1", &shares);
+        assert!(!result.consistent);
+        assert_eq!(result.mismatches, vec![
+            ShardMismatch { share_index: 2, field: "public_key", expected: "key".to_string(), found: "other".to_string() },
+            ShardMismatch { share_index: 3, field: "threshold", expected: "2".to_string(), found: "3".to_string() },
+        ]);
+
+        let missing = check_user_shares("user-1", &shares[..2]);
+        assert_eq!(missing.shards_found, 2);
+        assert!(!missing.consistent);
+    }
+}
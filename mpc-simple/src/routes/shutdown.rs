@@ -0,0 +1,150 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServerHandle, ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+
+/// Signing routes that must not be cut off mid-way; refused with 503 once shutdown starts.
+const DRAINED_PATHS: [&str; 4] = ["/api/generate", "/api/rotate-keys", "/api/send-sol", "/api/jupiter-swap"];
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long in-flight signing requests get to finish after Ctrl-C (`SHUTDOWN_DRAIN_TIMEOUT_SECS`).
+pub fn drain_timeout_from_env() -> Duration {
+    let secs = std::env::var("SHUTDOWN_DRAIN_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+#[derive(Default)]
+pub struct ShutdownState {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+/// Counts one signing request as in flight until dropped.
+pub struct InFlightGuard(web::Data<ShutdownState>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ShutdownState {
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Stop admitting signing requests; returns how many were already running.
+    pub fn begin_draining(&self) -> usize {
+        self.draining.store(true, Ordering::SeqCst);
+        self.in_flight()
+    }
+
+    /// Wait for in-flight signing requests to finish, up to `timeout`. Returns how many were
+    /// still running when it gave up.
+    pub async fn drain(&self, timeout: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.in_flight() > 0 && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+        self.in_flight()
+    }
+}
+
+fn track(state: &web::Data<ShutdownState>) -> InFlightGuard {
+    state.in_flight.fetch_add(1, Ordering::SeqCst);
+    InFlightGuard(state.clone())
+}
+
+/// Middleware: refuse new signing requests with 503 while shutting down, and count the
+/// ones admitted so shutdown can wait for them.
+pub async fn reject_while_draining<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let state = match req.app_data::<web::Data<ShutdownState>>() {
+        Some(state) if DRAINED_PATHS.contains(&req.path()) => state.clone(),
+        _ => return next.call(req).await.map(ServiceResponse::map_into_left_body),
+    };
+
+    if state.is_draining() {
+        let response = HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Server is shutting down"
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    let _guard = track(&state);
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// On Ctrl-C, stop admitting signing requests, give the in-flight ones up to `drain_timeout`
+/// to finish, then stop the server.
+pub async fn shutdown_on_ctrl_c(server: ServerHandle, state: web::Data<ShutdownState>, drain_timeout: Duration) {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        println!("❌ Failed to listen for Ctrl-C: {}", e);
+        return;
+    }
+
+    let in_flight = state.begin_draining();
+    println!("🛑 Shutting down, draining {} in-flight signing request(s) (timeout {:?})", in_flight, drain_timeout);
+    let remaining = state.drain(drain_timeout).await;
+    if remaining > 0 {
+        println!("⚠️ Drain timed out with {} signing request(s) still running", remaining);
+    }
+    println!("✅ Drained {} signing request(s)", in_flight.saturating_sub(remaining));
+
+    server.stop(true).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test, App};
+
+    #[actix_web::test]
+    async fn test_signing_routes_are_refused_once_draining() {
+        let state = web::Data::new(ShutdownState::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .wrap(from_fn(reject_while_draining))
+                .route("/api/generate", web::post().to(HttpResponse::Ok))
+                .route("/api/health", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::post().uri("/api/generate").to_request()).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(state.in_flight(), 0);
+
+        state.begin_draining();
+        let res = test::call_service(&app, test::TestRequest::post().uri("/api/generate").to_request()).await;
+        assert_eq!(res.status(), 503);
+        let res = test::call_service(&app, test::TestRequest::get().uri("/api/health").to_request()).await;
+        assert_eq!(res.status(), 200);
+    }
+
+    #[actix_web::test]
+    async fn test_drain_waits_for_in_flight_requests() {
+        let state = web::Data::new(ShutdownState::default());
+        let guard = track(&state);
+        assert_eq!(state.begin_draining(), 1);
+        assert_eq!(state.drain(Duration::from_millis(100)).await, 1);
+
+        drop(guard);
+        assert_eq!(state.drain(Duration::from_millis(100)).await, 0);
+    }
+}
@@ -0,0 +1,165 @@
+use actix_web::{web, HttpResponse, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer, transaction::Transaction};
+
+use crate::{database::DatabaseManager, routes::{reconstruct_keypair, KeyReconstructionError}};
+
+const MAX_BATCH_SIZE: usize = 16;
+
+#[derive(Deserialize)]
+pub struct SignBatchRequest {
+    pub user_id: String,
+    /// Base64-encoded, bincode-serialized legacy transactions, each with its blockhash set.
+    pub transactions: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct SignedTransactionResult {
+    pub index: usize,
+    pub success: bool,
+    pub signature: Option<String>,
+    pub signed_transaction: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SignBatchResponse {
+    pub success: bool,
+    pub public_key: Option<String>,
+    pub results: Vec<SignedTransactionResult>,
+    pub error: Option<String>,
+}
+
+impl SignBatchResponse {
+    fn failed(error: String) -> Self {
+        Self {
+            success: false,
+            public_key: None,
+            results: vec![],
+            error: Some(error),
+        }
+    }
+}
+
+/// Decode and sign one transaction. The user's key must be one of the required signers;
+/// other signers' slots are left untouched so co-signed transactions can be completed later.
+fn sign_transaction(encoded: &str, keypair: &Keypair) -> std::result::Result<(String, String), String> {
+    let bytes = STANDARD.decode(encoded).map_err(|_| "Transaction is not valid base64".to_string())?;
+    let mut transaction: Transaction = bincode::deserialize(&bytes)
+        .map_err(|_| "Failed to deserialize transaction".to_string())?;
+
+    let signer = keypair.pubkey();
+    let required_signers: &[Pubkey] = &transaction.message.account_keys
+        [..transaction.message.header.num_required_signatures as usize];
+    let position = required_signers.iter().position(|key| *key == signer)
+        .ok_or_else(|| "Transaction does not require the user's signature".to_string())?;
+
+    let blockhash = transaction.message.recent_blockhash;
+    transaction.try_partial_sign(&[keypair], blockhash)
+        .map_err(|e| format!("Failed to sign transaction: {}", e))?;
+
+    let signed = bincode::serialize(&transaction)
+        .map_err(|_| "Failed to serialize signed transaction".to_string())?;
+
+    Ok((transaction.signatures[position].to_string(), STANDARD.encode(signed)))
+}
+
+pub async fn sign_batch(
+    db: web::Data<DatabaseManager>,
+    req: web::Json<SignBatchRequest>,
+) -> Result<HttpResponse> {
+    println!("Processing batch of {} transactions for user: {}", req.transactions.len(), req.user_id);
+
+    if req.transactions.is_empty() || req.transactions.len() > MAX_BATCH_SIZE {
+        return Ok(HttpResponse::BadRequest().json(SignBatchResponse::failed(
+            format!("Batch must contain between 1 and {} transactions", MAX_BATCH_SIZE),
+        )));
+    }
+
+    // Reconstruct the key once for the whole batch
+    let keypair = match reconstruct_keypair(&db, &req.user_id).await {
+        Ok(keypair) => keypair,
+        Err(e) => {
+            println!("Failed to reconstruct key for user {}: {}", req.user_id, e);
+            let response = SignBatchResponse::failed(e.to_string());
+            return Ok(match e {
+                KeyReconstructionError::NoShares => HttpResponse::NotFound().json(response),
+                KeyReconstructionError::InsufficientShares { .. } | KeyReconstructionError::InconsistentShares => {
+                    HttpResponse::BadRequest().json(response)
+                }
+                _ => HttpResponse::InternalServerError().json(response),
+            });
+        }
+    };
+
+    let results: Vec<SignedTransactionResult> = req.transactions.iter().enumerate().map(|(index, encoded)| {
+        match sign_transaction(encoded, &keypair) {
+            Ok((signature, signed_transaction)) => SignedTransactionResult {
+                index,
+                success: true,
+                signature: Some(signature),
+                signed_transaction: Some(signed_transaction),
+                error: None,
+            },
+            Err(error) => {
+                println!("Failed to sign transaction {} for user {}: {}", index, req.user_id, error);
+                SignedTransactionResult {
+                    index,
+                    success: false,
+                    signature: None,
+                    signed_transaction: None,
+                    error: Some(error),
+                }
+            }
+        }
+    }).collect();
+
+    let public_key = keypair.pubkey().to_string();
+    // Keypair wipes its secret on drop
+    drop(keypair);
+
+    let all_signed = results.iter().all(|r| r.success);
+    println!("Signed {}/{} transactions for user {}",
+             results.iter().filter(|r| r.success).count(), results.len(), req.user_id);
+
+    Ok(HttpResponse::Ok().json(SignBatchResponse {
+        success: all_signed,
+        public_key: Some(public_key),
+        results,
+        error: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{hash::Hash, instruction::Instruction, message::Message};
+
+    fn encoded_transaction(payer: &Pubkey) -> String {
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[1, 2, 3], vec![]);
+        let mut message = Message::new(&[instruction], Some(payer));
+        message.recent_blockhash = Hash::new_unique();
+        STANDARD.encode(bincode::serialize(&Transaction::new_unsigned(message)).unwrap())
+    }
+
+    #[test]
+    fn test_sign_transaction_for_required_signer() {
+        let keypair = Keypair::new();
+        let (signature, signed) = sign_transaction(&encoded_transaction(&keypair.pubkey()), &keypair).unwrap();
+
+        let transaction: Transaction = bincode::deserialize(&STANDARD.decode(signed).unwrap()).unwrap();
+        assert_eq!(transaction.signatures[0].to_string(), signature);
+        assert!(transaction.verify().is_ok());
+    }
+
+    #[test]
+    fn test_sign_transaction_rejects_foreign_and_malformed() {
+        let keypair = Keypair::new();
+        let other = Keypair::new();
+
+        assert!(sign_transaction(&encoded_transaction(&other.pubkey()), &keypair).is_err());
+        assert!(sign_transaction("not base64!", &keypair).is_err());
+        assert!(sign_transaction(&STANDARD.encode([1u8, 2, 3]), &keypair).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use common::gf256;
+use zeroize::Zeroize;
+
+/// One point of a Shamir split: the polynomials for every secret byte evaluated at `x`.
+pub struct Share {
+    pub x: u8,
+    pub y: Vec<u8>,
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.y.zeroize();
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ShamirError {
+    InvalidThreshold { threshold: u8, total: u8 },
+    NoShares,
+    MismatchedLengths,
+    InvalidIndex(u8),
+}
+
+impl std::fmt::Display for ShamirError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShamirError::InvalidThreshold { threshold, total } => {
+                write!(f, "Threshold {} is not between 1 and the {} shares", threshold, total)
+            }
+            ShamirError::NoShares => write!(f, "No shares to combine"),
+            ShamirError::MismatchedLengths => write!(f, "Shares have different lengths"),
+            ShamirError::InvalidIndex(x) => write!(f, "Share index {} is zero or repeated", x),
+        }
+    }
+}
+
+/// Split `secret` into `total` shares at x = 1..=total, any `threshold` of which recover it.
+pub fn split(secret: &[u8], threshold: u8, total: u8) -> Result<Vec<Share>, ShamirError> {
+    if threshold == 0 || threshold > total {
+        return Err(ShamirError::InvalidThreshold { threshold, total });
+    }
+
+    // Per secret byte, a polynomial with the byte as its constant term and random
+    // coefficients of x^1..x^(threshold-1)
+    let width = threshold as usize;
+    let mut coefficients = vec![0u8; secret.len() * width];
+    OsRng.fill_bytes(&mut coefficients);
+    for (polynomial, &byte) in coefficients.chunks_mut(width).zip(secret) {
+        polynomial[0] = byte;
+    }
+
+    let shares = (1..=total).map(|x| {
+        let y = coefficients.chunks(width)
+            .map(|polynomial| gf256::eval_polynomial(polynomial, x))
+            .collect();
+        Share { x, y }
+    }).collect();
+
+    coefficients.zeroize();
+    Ok(shares)
+}
+
+/// Recover the secret by Lagrange interpolation at x = 0. Given fewer shares than the split's
+/// threshold this returns garbage rather than an error, so callers must check the result.
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>, ShamirError> {
+    let len = shares.first().ok_or(ShamirError::NoShares)?.y.len();
+    if shares.iter().any(|s| s.y.len() != len) {
+        return Err(ShamirError::MismatchedLengths);
+    }
+    for (i, share) in shares.iter().enumerate() {
+        if share.x == 0 || shares[..i].iter().any(|other| other.x == share.x) {
+            return Err(ShamirError::InvalidIndex(share.x));
+        }
+    }
+
+    let xs: Vec<u8> = shares.iter().map(|share| share.x).collect();
+    let weights = gf256::lagrange_weights_at_zero(&xs);
+
+    Ok((0..len).map(|i| {
+        shares.iter().zip(&weights).fold(0, |acc, (share, &weight)| acc ^ gf256::mul(share.y[i], weight))
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_any_two_of_three_shares_recover_the_secret() {
+        let secret: Vec<u8> = (0..64).collect();
+        let shares = split(&secret, 2, 3).unwrap();
+        assert!(shares.iter().all(|s| s.y != secret));
+
+        for (a, b) in [(0, 1), (0, 2), (1, 2)] {
+            let pair = [
+                Share { x: shares[a].x, y: shares[a].y.clone() },
+                Share { x: shares[b].x, y: shares[b].y.clone() },
+            ];
+            assert_eq!(combine(&pair).unwrap(), secret);
+        }
+    }
+
+    #[test]
+    fn test_combine_rejects_repeated_index() {
+        let shares = [Share { x: 1, y: vec![1, 2] }, Share { x: 1, y: vec![3, 4] }];
+        assert_eq!(combine(&shares), Err(ShamirError::InvalidIndex(1)));
+        assert_eq!(split(b"secret", 4, 3).err(), Some(ShamirError::InvalidThreshold { threshold: 4, total: 3 }));
+    }
+}
@@ -0,0 +1,150 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use zeroize::Zeroize;
+
+use crate::models::KeyShare;
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, PartialEq)]
+pub enum ShareCryptoError {
+    MissingKey,
+    InvalidKey(String),
+    Malformed(String),
+    DecryptionFailed,
+}
+
+impl std::fmt::Display for ShareCryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShareCryptoError::MissingKey => write!(f, "SHARE_ENCRYPTION_KEY is not set"),
+            ShareCryptoError::InvalidKey(msg) => write!(f, "Invalid SHARE_ENCRYPTION_KEY: {}", msg),
+            ShareCryptoError::Malformed(msg) => write!(f, "Malformed key share: {}", msg),
+            ShareCryptoError::DecryptionFailed => write!(f, "Key share failed to decrypt"),
+        }
+    }
+}
+
+/// The AES-256-GCM key shares are encrypted with, from `SHARE_ENCRYPTION_KEY` (64 hex characters).
+pub struct ShareKey(Key<Aes256Gcm>);
+
+impl ShareKey {
+    pub fn from_env() -> Result<Self, ShareCryptoError> {
+        let mut hex_key = std::env::var("SHARE_ENCRYPTION_KEY").map_err(|_| ShareCryptoError::MissingKey)?;
+        let key = Self::from_hex(hex_key.trim());
+        hex_key.zeroize();
+        key
+    }
+
+    pub fn from_hex(hex_key: &str) -> Result<Self, ShareCryptoError> {
+        let mut bytes = hex::decode(hex_key).map_err(|e| ShareCryptoError::InvalidKey(e.to_string()))?;
+        if bytes.len() != 32 {
+            let len = bytes.len();
+            bytes.zeroize();
+            return Err(ShareCryptoError::InvalidKey(format!("expected 32 bytes, got {}", len)));
+        }
+        let key = *Key::<Aes256Gcm>::from_slice(&bytes);
+        bytes.zeroize();
+        Ok(Self(key))
+    }
+}
+
+impl Drop for ShareKey {
+    fn drop(&mut self) {
+        self.0.as_mut_slice().zeroize();
+    }
+}
+
+/// Binds a ciphertext to its owner and slot, so a share copied onto another user or index won't decrypt.
+fn associated_data(user_id: &str, share_index: i32) -> String {
+    format!("{}:{}", user_id, share_index)
+}
+
+/// Encrypt one share with a fresh random nonce. The result is base64 of the 12-byte nonce
+/// followed by the ciphertext, which is what `KeyShare::encrypted_share` stores.
+pub fn encrypt_share(key: &ShareKey, user_id: &str, share_index: i32, plaintext: &str) -> Result<String, ShareCryptoError> {
+    let cipher = Aes256Gcm::new(&key.0);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let aad = associated_data(user_id, share_index);
+
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext.as_bytes(), aad: aad.as_bytes() })
+        .map_err(|_| ShareCryptoError::Malformed("encryption failed".to_string()))?;
+
+    let mut stored = nonce.to_vec();
+    stored.extend_from_slice(&ciphertext);
+    Ok(STANDARD.encode(stored))
+}
+
+/// Decrypt a stored share back to its plaintext part of the key. Callers should zeroize the result.
+pub fn decrypt_share(key: &ShareKey, share: &KeyShare) -> Result<String, ShareCryptoError> {
+    let stored = STANDARD
+        .decode(&share.encrypted_share)
+        .map_err(|e| ShareCryptoError::Malformed(e.to_string()))?;
+    if stored.len() <= NONCE_LEN {
+        return Err(ShareCryptoError::Malformed("too short to hold a nonce and ciphertext".to_string()));
+    }
+
+    let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+    let aad = associated_data(&share.user_id, share.share_index);
+    let mut plaintext = Aes256Gcm::new(&key.0)
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: aad.as_bytes() })
+        .map_err(|_| ShareCryptoError::DecryptionFailed)?;
+
+    let decoded = String::from_utf8(plaintext.clone()).map_err(|_| ShareCryptoError::Malformed("share is not UTF-8".to_string()));
+    plaintext.zeroize();
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_KEY_HEX: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn share(user_id: &str, share_index: i32, encrypted_share: String) -> KeyShare {
+        KeyShare {
+            id: uuid::Uuid::new_v4(),
+            user_id: user_id.to_string(),
+            public_key: "pubkey".to_string(),
+            encrypted_share,
+            share_index,
+            threshold: 3,
+            total_shares: 3,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_share_round_trips() {
+        let key = ShareKey::from_hex(TEST_KEY_HEX).unwrap();
+        let encrypted = encrypt_share(&key, "user-1", 2, "deadbeef").unwrap();
+        assert_ne!(encrypted, "deadbeef");
+
+        assert_eq!(decrypt_share(&key, &share("user-1", 2, encrypted)).unwrap(), "deadbeef");
+    }
+
+    #[test]
+    fn test_each_encryption_uses_a_fresh_nonce() {
+        let key = ShareKey::from_hex(TEST_KEY_HEX).unwrap();
+        assert_ne!(encrypt_share(&key, "user-1", 1, "abc").unwrap(), encrypt_share(&key, "user-1", 1, "abc").unwrap());
+    }
+
+    #[test]
+    fn test_share_moved_to_another_slot_fails_to_decrypt() {
+        let key = ShareKey::from_hex(TEST_KEY_HEX).unwrap();
+        let encrypted = encrypt_share(&key, "user-1", 1, "deadbeef").unwrap();
+
+        assert_eq!(decrypt_share(&key, &share("user-1", 2, encrypted.clone())), Err(ShareCryptoError::DecryptionFailed));
+        assert_eq!(decrypt_share(&key, &share("user-2", 1, encrypted)), Err(ShareCryptoError::DecryptionFailed));
+    }
+
+    #[test]
+    fn test_share_key_requires_32_bytes() {
+        assert!(matches!(ShareKey::from_hex("abcd"), Err(ShareCryptoError::InvalidKey(_))));
+        assert!(matches!(ShareKey::from_hex("not hex"), Err(ShareCryptoError::InvalidKey(_))));
+    }
+}
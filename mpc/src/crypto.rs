@@ -5,7 +5,9 @@ use solana_sdk::{
     signer::Signer,
     pubkey::Pubkey,
 };
+use rand::{rngs::OsRng, RngCore};
 use sha2::{Digest, Sha256};
+use common::gf256;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,55 +58,79 @@ impl MPCCrypto {
         Ok((public_key, encrypted_shares))
     }
     
-    /// Simple Shamir's Secret Sharing implementation
-    /// In production, use a proper cryptographic library
+    /// Shamir's Secret Sharing over GF(256). Each byte of the secret is the constant term of
+    /// its own random polynomial of degree `threshold - 1`; share `i` holds every polynomial
+    /// evaluated at x = i. Any `threshold` shares recover the secret, fewer reveal nothing.
     fn shamir_secret_share(
         secret: &[u8],
         threshold: u16,
         total_shares: u16,
     ) -> Result<HashMap<u16, Vec<u8>>> {
+        if threshold == 0 {
+            return Err(anyhow::anyhow!("Threshold must be at least 1"));
+        }
         if threshold > total_shares {
             return Err(anyhow::anyhow!("Threshold cannot be greater than total shares"));
         }
+        if total_shares > 255 {
+            return Err(anyhow::anyhow!("At most 255 shares are supported over GF(256)"));
+        }
         
-        let mut shares = HashMap::new();
-        
-        // For simplicity, we'll use a basic polynomial approach
-        // In production, use a proper implementation like the `sharks` crate
+        let mut shares: HashMap<u16, Vec<u8>> = (1..=total_shares)
+            .map(|i| (i, Vec::with_capacity(secret.len())))
+            .collect();
         
-        for i in 1..=total_shares {
-            // Generate a share by hashing secret with share index
-            let mut hasher = Sha256::new();
-            hasher.update(secret);
-            hasher.update(&i.to_le_bytes());
-            hasher.update(&threshold.to_le_bytes());
-            let share = hasher.finalize().to_vec();
-            shares.insert(i, share);
+        let mut coefficients = vec![0u8; threshold as usize];
+        for &byte in secret {
+            coefficients[0] = byte;
+            OsRng.fill_bytes(&mut coefficients[1..]);
+            
+            for (&index, share) in shares.iter_mut() {
+                share.push(gf256::eval_polynomial(&coefficients, index as u8));
+            }
         }
+        coefficients.fill(0);
         
         Ok(shares)
     }
     
-    /// Reconstruct secret from shares
+    /// Reconstruct the secret by Lagrange interpolation at x = 0, using `threshold` of the
+    /// given shares (lowest indices first).
     pub fn reconstruct_secret(
         shares: &HashMap<u16, Vec<u8>>,
         threshold: u16,
     ) -> Result<Vec<u8>> {
+        if threshold == 0 {
+            return Err(anyhow::anyhow!("Threshold must be at least 1"));
+        }
         if shares.len() < threshold as usize {
             return Err(anyhow::anyhow!("Not enough shares to reconstruct secret"));
         }
         
-        // For this simplified implementation, we'll use the first share as the base
-        // In production, use proper Lagrange interpolation
-        let _first_share = shares.values().next().unwrap();
+        let mut indices: Vec<u16> = shares.keys().copied().collect();
+        indices.sort_unstable();
+        indices.truncate(threshold as usize);
         
-        // Derive the original secret (this is a simplified approach)
-        let mut hasher = Sha256::new();
-        for (_, share) in shares.iter().take(threshold as usize) {
-            hasher.update(share);
+        if indices.iter().any(|&i| i == 0 || i > 255) {
+            return Err(anyhow::anyhow!("Share indices must be between 1 and 255"));
+        }
+        let secret_len = shares[&indices[0]].len();
+        if indices.iter().any(|i| shares[i].len() != secret_len) {
+            return Err(anyhow::anyhow!("Shares have inconsistent lengths"));
         }
         
-        Ok(hasher.finalize().to_vec())
+        let xs: Vec<u8> = indices.iter().map(|&i| i as u8).collect();
+        let weights = gf256::lagrange_weights_at_zero(&xs);
+        
+        let secret = (0..secret_len)
+            .map(|byte| {
+                indices.iter().zip(&weights).fold(0u8, |acc, (index, &weight)| {
+                    acc ^ gf256::mul(shares[index][byte], weight)
+                })
+            })
+            .collect();
+        
+        Ok(secret)
     }
     
     /// Simple encryption (NOT secure for production)
@@ -187,20 +213,52 @@ mod tests {
         assert!(!public_key.to_string().is_empty());
     }
     
+    fn subset(shares: &HashMap<u16, Vec<u8>>, indices: &[u16]) -> HashMap<u16, Vec<u8>> {
+        indices.iter().map(|i| (*i, shares[i].clone())).collect()
+    }
+    
     #[test]
     fn test_secret_sharing_and_reconstruction() {
-        let secret = b"this is a test secret key!!!!!!";
+        let secret = b"this is a test secret key!!!!!!!";
         let shares = MPCCrypto::shamir_secret_share(secret, 2, 3).unwrap();
         
-        // Take 2 shares for reconstruction
-        let mut subset: HashMap<u16, Vec<u8>> = HashMap::new();
-        for (&index, share) in shares.iter().take(2) {
-            subset.insert(index, share.clone());
+        for indices in [[1, 2], [1, 3], [2, 3]] {
+            let reconstructed = MPCCrypto::reconstruct_secret(&subset(&shares, &indices), 2).unwrap();
+            assert_eq!(reconstructed, secret.to_vec(), "subset {:?}", indices);
         }
         
-        let reconstructed = MPCCrypto::reconstruct_secret(&subset, 2).unwrap();
-        // Note: In this simplified implementation, the reconstructed secret 
-        // won't be identical to the original, but the test verifies the process works
-        assert_eq!(reconstructed.len(), 32);
+        let all = MPCCrypto::reconstruct_secret(&shares, 2).unwrap();
+        assert_eq!(all, secret.to_vec());
+    }
+    
+    #[test]
+    fn test_reconstruction_from_larger_threshold_subsets() {
+        let secret: Vec<u8> = (0..32).collect();
+        let shares = MPCCrypto::shamir_secret_share(&secret, 3, 5).unwrap();
+        
+        for indices in [[1, 2, 3], [1, 4, 5], [2, 3, 5], [3, 4, 5]] {
+            let reconstructed = MPCCrypto::reconstruct_secret(&subset(&shares, &indices), 3).unwrap();
+            assert_eq!(reconstructed, secret, "subset {:?}", indices);
+        }
+        
+        assert!(MPCCrypto::reconstruct_secret(&subset(&shares, &[1, 2]), 3).is_err());
+        // Two shares interpolate to the wrong polynomial
+        let wrong = MPCCrypto::reconstruct_secret(&subset(&shares, &[1, 2]), 2).unwrap();
+        assert_ne!(wrong, secret);
+    }
+    
+    #[test]
+    fn test_threshold_keypair_round_trips_through_signing() {
+        let (public_key, encrypted_shares) = MPCCrypto::generate_threshold_keypair(2, 3).unwrap();
+        let shares: HashMap<u16, Vec<u8>> = encrypted_shares
+            .iter()
+            .map(|(&index, share)| (index, MPCCrypto::simple_decrypt(share, index)))
+            .collect();
+        
+        let message = b"round trip";
+        for indices in [[1, 2], [2, 3], [1, 3]] {
+            let signature = MPCCrypto::threshold_sign(message, &subset(&shares, &indices), 2).unwrap();
+            assert!(MPCCrypto::verify_signature(message, &signature, &public_key), "subset {:?}", indices);
+        }
     }
 }
@@ -1,8 +1,40 @@
-use sqlx::{PgPool, Row};
+use sqlx::{postgres::PgPoolOptions, PgPool, Row};
 use anyhow::Result;
 use std::env;
+use std::time::Duration;
+use common::shard::{share_location, ShardPoolConfig, SHARD_COUNT};
+use serde::Serialize;
 use crate::models::{KeyShare, MPCSession};
 
+/// How long a health check waits on one database before reporting it down.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseHealth {
+    pub name: String,
+    pub connected: bool,
+}
+
+async fn ping(pool: &PgPool) -> bool {
+    matches!(
+        tokio::time::timeout(HEALTH_CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(pool)).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Open the pool for one shard database with its `ShardPoolConfig` tuning.
+async fn connect_shard(config: &ShardPoolConfig, database_url: &str) -> Result<PgPool> {
+    let pool = PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections.min(config.max_connections))
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+        .idle_timeout(Duration::from_secs(config.idle_timeout_secs))
+        .max_lifetime(Duration::from_secs(config.max_lifetime_secs))
+        .connect(database_url)
+        .await?;
+    Ok(pool)
+}
+
 #[derive(Clone)]
 pub struct DatabaseManager {
     pub mpc1_pool: PgPool,
@@ -19,9 +51,9 @@ impl DatabaseManager {
         let mpc3_url = env::var("MPC3_DATABASE_URL")
             .expect("MPC3_DATABASE_URL must be set");
 
-        let mpc1_pool = PgPool::connect(&mpc1_url).await?;
-        let mpc2_pool = PgPool::connect(&mpc2_url).await?;
-        let mpc3_pool = PgPool::connect(&mpc3_url).await?;
+        let mpc1_pool = connect_shard(&ShardPoolConfig::from_env(1), &mpc1_url).await?;
+        let mpc2_pool = connect_shard(&ShardPoolConfig::from_env(2), &mpc2_url).await?;
+        let mpc3_pool = connect_shard(&ShardPoolConfig::from_env(3), &mpc3_url).await?;
 
         // Initialize tables for all databases
         Self::initialize_tables(&mpc1_pool).await?;
@@ -57,6 +89,7 @@ impl DatabaseManager {
                 session_id TEXT UNIQUE NOT NULL,
                 user_id TEXT NOT NULL,
                 participants TEXT[] NOT NULL,
+                threshold INTEGER NOT NULL DEFAULT 1,
                 current_step INTEGER DEFAULT 1,
                 commitments JSONB DEFAULT '{}',
                 signature_shares JSONB DEFAULT '{}',
@@ -68,6 +101,8 @@ impl DatabaseManager {
 
             CREATE INDEX IF NOT EXISTS idx_mpc_sessions_session_id ON mpc_sessions(session_id);
             CREATE INDEX IF NOT EXISTS idx_mpc_sessions_user_id ON mpc_sessions(user_id);
+
+            ALTER TABLE mpc_sessions ADD COLUMN IF NOT EXISTS threshold INTEGER NOT NULL DEFAULT 1;
         "#;
 
         sqlx::query(query).execute(pool).await?;
@@ -117,12 +152,13 @@ impl DatabaseManager {
         Ok(())
     }
 
+    /// Share `share_index` (1-based), read from the database `share_location` puts it in.
     pub async fn get_key_share(
         &self,
         user_id: &str,
-        database_index: usize,
+        share_index: i32,
     ) -> Result<Option<KeyShare>> {
-        let pool = self.get_pool_by_index(database_index);
+        let pool = self.get_pool_by_index(share_location(share_index).0);
         
         let query = r#"
             SELECT id, user_id, public_key, encrypted_share, share_index, threshold, total_shares, created_at
@@ -132,20 +168,27 @@ impl DatabaseManager {
 
         let result = sqlx::query_as::<_, KeyShare>(query)
             .bind(user_id)
-            .bind((database_index + 1) as i32) // share_index is 1-based
+            .bind(share_index)
             .fetch_optional(pool)
             .await?;
 
         Ok(result)
     }
 
+    /// Every share the user has on every database, in share index order per database.
     pub async fn get_all_user_shares(&self, user_id: &str) -> Result<Vec<KeyShare>> {
         let mut all_shares = Vec::new();
 
-        for i in 0..3 {
-            if let Some(share) = self.get_key_share(user_id, i).await? {
-                all_shares.push(share);
-            }
+        for i in 0..SHARD_COUNT {
+            let pool = self.get_pool_by_index(i);
+            let query = r#"
+                SELECT id, user_id, public_key, encrypted_share, share_index, threshold, total_shares, created_at
+                FROM key_shares
+                WHERE user_id = $1
+                ORDER BY share_index
+            "#;
+            let shares = sqlx::query_as::<_, KeyShare>(query).bind(user_id).fetch_all(pool).await?;
+            all_shares.extend(shares);
         }
 
         Ok(all_shares)
@@ -157,8 +200,8 @@ impl DatabaseManager {
         
         let query = r#"
             INSERT INTO mpc_sessions (session_id, user_id, participants, current_step, 
-                                    commitments, signature_shares, final_signature, message_to_sign)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                                    commitments, signature_shares, final_signature, message_to_sign, threshold)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
         "#;
 
         sqlx::query(query)
@@ -170,6 +213,7 @@ impl DatabaseManager {
             .bind(serde_json::to_value(&session.signature_shares).unwrap())
             .bind(&session.final_signature)
             .bind(&session.message_to_sign)
+            .bind(session.threshold)
             .execute(pool)
             .await?;
 
@@ -180,7 +224,7 @@ impl DatabaseManager {
         let pool = &self.mpc1_pool;
         
         let query = r#"
-            SELECT id, session_id, user_id, participants, current_step, 
+            SELECT id, session_id, user_id, participants, threshold, current_step, 
                    commitments, signature_shares, final_signature, message_to_sign,
                    created_at, updated_at
             FROM mpc_sessions 
@@ -198,6 +242,7 @@ impl DatabaseManager {
                 session_id: row.try_get("session_id")?,
                 user_id: row.try_get("user_id")?,
                 participants: row.try_get("participants")?,
+                threshold: row.try_get("threshold")?,
                 current_step: row.try_get("current_step")?,
                 commitments: serde_json::from_value(row.try_get("commitments")?).unwrap_or_default(),
                 signature_shares: serde_json::from_value(row.try_get("signature_shares")?).unwrap_or_default(),
@@ -211,6 +256,27 @@ impl DatabaseManager {
         }
     }
 
+    /// Bind the session to `message` unless it is already bound to a different one, in a
+    /// single conditional UPDATE so concurrent step 2 requests can't each sign their own.
+    /// Returns false when the session is signing something else.
+    pub async fn pin_session_message(&self, session_id: &str, message: &str) -> Result<bool> {
+        let pool = &self.mpc1_pool;
+
+        let query = r#"
+            UPDATE mpc_sessions
+            SET message_to_sign = $2, updated_at = NOW()
+            WHERE session_id = $1 AND (message_to_sign IS NULL OR message_to_sign = $2)
+        "#;
+
+        let result = sqlx::query(query)
+            .bind(session_id)
+            .bind(message)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
     pub async fn update_mpc_session(&self, session: &MPCSession) -> Result<()> {
         let pool = &self.mpc1_pool;
         
@@ -234,7 +300,7 @@ impl DatabaseManager {
     }
 
     pub async fn delete_user_shares(&self, user_id: &str) -> Result<()> {
-        for i in 0..3 {
+        for i in 0..SHARD_COUNT {
             let pool = self.get_pool_by_index(i);
             let query = "DELETE FROM key_shares WHERE user_id = $1";
             sqlx::query(query).bind(user_id).execute(pool).await?;
@@ -242,8 +308,23 @@ impl DatabaseManager {
         Ok(())
     }
 
+    /// Run `SELECT 1` against every shard concurrently.
+    pub async fn health_check(&self) -> Vec<DatabaseHealth> {
+        let (mpc1, mpc2, mpc3) = tokio::join!(
+            ping(&self.mpc1_pool),
+            ping(&self.mpc2_pool),
+            ping(&self.mpc3_pool),
+        );
+
+        [("mpc1", mpc1), ("mpc2", mpc2), ("mpc3", mpc3)]
+            .into_iter()
+            .map(|(name, connected)| DatabaseHealth { name: name.to_string(), connected })
+            .collect()
+    }
+
     pub async fn user_has_shares(&self, user_id: &str) -> Result<bool> {
         let shares = self.get_all_user_shares(user_id).await?;
-        Ok(shares.len() == 3) // Should have shares in all 3 databases
+        // Complete once every share of the user's scheme is stored
+        Ok(shares.first().is_some_and(|first| shares.len() == first.total_shares as usize))
     }
 }
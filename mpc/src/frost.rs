@@ -0,0 +1,297 @@
+//! Two-round FROST-style threshold Ed25519 signing over Shamir shares of the signing scalar.
+//!
+//! Round 1: each signer derives a hiding and a binding nonce and publishes their commitments.
+//! Round 2: each signer returns `z_i = d_i + e_i * rho_i + lambda_i * s_i * c`, and the sum of the
+//! `z_i` together with the group commitment `R` is an ordinary Ed25519 signature.
+
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use curve25519_dalek::{
+    constants::ED25519_BASEPOINT_TABLE,
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use rand::RngCore;
+use sha2::{Digest, Sha512};
+
+const NONCE_DOMAIN: &[u8] = b"clippr-frost-nonce";
+const BINDING_DOMAIN: &[u8] = b"clippr-frost-binding";
+
+fn hash_to_scalar(parts: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut wide = [0u8; 64];
+    wide.copy_from_slice(&hasher.finalize());
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+fn decode_point(bytes: &[u8]) -> Option<EdwardsPoint> {
+    let bytes: [u8; 32] = bytes.try_into().ok()?;
+    CompressedEdwardsY(bytes).decompress()
+}
+
+pub fn random_scalar() -> Scalar {
+    let mut wide = [0u8; 64];
+    rand::rngs::OsRng.fill_bytes(&mut wide);
+    Scalar::from_bytes_mod_order_wide(&wide)
+}
+
+/// Compressed public key `A = s * B` for a signing scalar.
+pub fn public_key(secret: &Scalar) -> [u8; 32] {
+    (secret * &ED25519_BASEPOINT_TABLE).compress().to_bytes()
+}
+
+/// Decode a base58 (Solana address) public key, rejecting anything that is not a curve point.
+pub fn decode_public_key(public_key: &str) -> Result<[u8; 32], String> {
+    let bytes = bs58::decode(public_key).into_vec().map_err(|_| "Public key is not valid base58".to_string())?;
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| "Public key must be 32 bytes".to_string())?;
+    if decode_point(&bytes).is_none() {
+        return Err("Public key is not an Ed25519 point".to_string());
+    }
+    Ok(bytes)
+}
+
+/// Split `secret` into `total` Shamir shares, any `threshold` of which can sign. Share `i`
+/// is the polynomial evaluated at `x = i`, so indices start at 1.
+pub fn split_secret(secret: &Scalar, threshold: u16, total: u16) -> Vec<(u16, Scalar)> {
+    let coefficients: Vec<Scalar> = std::iter::once(*secret)
+        .chain((1..threshold).map(|_| random_scalar()))
+        .collect();
+
+    (1..=total)
+        .map(|index| {
+            let x = Scalar::from(index as u64);
+            let value = coefficients.iter().rev().fold(Scalar::zero(), |acc, coefficient| acc * x + coefficient);
+            (index, value)
+        })
+        .collect()
+}
+
+/// Shares are stored as hex of the 32-byte little-endian scalar.
+pub fn encode_share(share: &Scalar) -> String {
+    hex::encode(share.as_bytes())
+}
+
+pub fn decode_share(share: &str) -> Result<Scalar, String> {
+    let bytes = hex::decode(share).map_err(|_| "Key share is not valid hex".to_string())?;
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| "Key share must be 32 bytes".to_string())?;
+    Scalar::from_canonical_bytes(bytes).ok_or_else(|| "Key share is not a canonical scalar".to_string())
+}
+
+/// Signature shares travel as base64 of the 32-byte scalar.
+pub fn encode_signature_share(share: &Scalar) -> String {
+    STANDARD.encode(share.as_bytes())
+}
+
+pub fn decode_signature_share(share: &str) -> Result<Scalar, String> {
+    let bytes = STANDARD.decode(share).map_err(|_| "Signature share is not valid base64".to_string())?;
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| "Signature share must be 32 bytes".to_string())?;
+    Scalar::from_canonical_bytes(bytes).ok_or_else(|| "Signature share is not a canonical scalar".to_string())
+}
+
+/// Lagrange coefficient at `x = 0` for `index` within the signing set.
+pub fn lagrange_coefficient(index: u16, signers: impl IntoIterator<Item = u16>) -> Scalar {
+    let x_i = Scalar::from(index as u64);
+    let mut numerator = Scalar::one();
+    let mut denominator = Scalar::one();
+    for signer in signers.into_iter().filter(|signer| *signer != index) {
+        let x_j = Scalar::from(signer as u64);
+        numerator *= x_j;
+        denominator *= x_j - x_i;
+    }
+    numerator * denominator.invert()
+}
+
+pub struct SigningNonces {
+    hiding: Scalar,
+    binding: Scalar,
+}
+
+/// Commitments `(D_i, E_i)` to a signer's nonces, published in round 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceCommitment {
+    pub hiding: EdwardsPoint,
+    pub binding: EdwardsPoint,
+}
+
+impl SigningNonces {
+    /// Nonces are derived from the share, the session and fresh randomness from the signer, so
+    /// round 2 can recompute them instead of storing secret nonces between requests. Reusing
+    /// the randomness for a different message would leak the share; callers must pin one
+    /// message per session.
+    pub fn derive(share: &Scalar, session_id: &str, randomness: &[u8]) -> Self {
+        let hiding = hash_to_scalar(&[NONCE_DOMAIN, b"hiding", share.as_bytes(), session_id.as_bytes(), randomness]);
+        let binding = hash_to_scalar(&[NONCE_DOMAIN, b"binding", share.as_bytes(), session_id.as_bytes(), randomness]);
+        Self { hiding, binding }
+    }
+
+    pub fn commitment(&self) -> NonceCommitment {
+        NonceCommitment {
+            hiding: &self.hiding * &ED25519_BASEPOINT_TABLE,
+            binding: &self.binding * &ED25519_BASEPOINT_TABLE,
+        }
+    }
+}
+
+impl NonceCommitment {
+    /// Base64 of the two compressed points, hiding first.
+    pub fn to_base64(&self) -> String {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(self.hiding.compress().as_bytes());
+        bytes[32..].copy_from_slice(self.binding.compress().as_bytes());
+        STANDARD.encode(bytes)
+    }
+
+    pub fn from_base64(encoded: &str) -> Result<Self, String> {
+        let bytes = STANDARD.decode(encoded).map_err(|_| "Commitment is not valid base64".to_string())?;
+        if bytes.len() != 64 {
+            return Err(format!("Commitment must be 64 bytes, got {}", bytes.len()));
+        }
+        match (decode_point(&bytes[..32]), decode_point(&bytes[32..])) {
+            (Some(hiding), Some(binding)) => Ok(Self { hiding, binding }),
+            _ => Err("Commitment is not a pair of Ed25519 points".to_string()),
+        }
+    }
+}
+
+/// `rho_i`, binding each signer's nonces to the message and the whole commitment list.
+fn binding_factor(index: u16, message: &[u8], commitments: &BTreeMap<u16, NonceCommitment>) -> Scalar {
+    let mut encoded = Vec::with_capacity(commitments.len() * 66);
+    for (signer, commitment) in commitments {
+        encoded.extend_from_slice(&signer.to_le_bytes());
+        encoded.extend_from_slice(commitment.hiding.compress().as_bytes());
+        encoded.extend_from_slice(commitment.binding.compress().as_bytes());
+    }
+    let message_hash = Sha512::digest(message);
+    hash_to_scalar(&[BINDING_DOMAIN, &index.to_le_bytes(), &message_hash, &encoded])
+}
+
+/// `R = sum(D_i + rho_i * E_i)`, the nonce point of the final signature.
+fn group_commitment(message: &[u8], commitments: &BTreeMap<u16, NonceCommitment>) -> EdwardsPoint {
+    commitments.iter().fold(EdwardsPoint::identity(), |acc, (index, commitment)| {
+        acc + commitment.hiding + commitment.binding * binding_factor(*index, message, commitments)
+    })
+}
+
+/// The Ed25519 challenge `c = H(R || A || M)`.
+fn challenge(group_commitment: &EdwardsPoint, public_key: &[u8; 32], message: &[u8]) -> Scalar {
+    hash_to_scalar(&[group_commitment.compress().as_bytes(), public_key, message])
+}
+
+/// Round 2: this signer's share `z_i` of the signature over `message`. `commitments` must
+/// hold every signer's round-1 commitment, including this one's.
+pub fn sign_share(
+    index: u16,
+    share: &Scalar,
+    nonces: &SigningNonces,
+    message: &[u8],
+    public_key: &[u8; 32],
+    commitments: &BTreeMap<u16, NonceCommitment>,
+) -> Scalar {
+    let rho = binding_factor(index, message, commitments);
+    let c = challenge(&group_commitment(message, commitments), public_key, message);
+    let lambda = lagrange_coefficient(index, commitments.keys().copied());
+    nonces.hiding + nonces.binding * rho + lambda * share * c
+}
+
+/// Combine the signature shares of every committed signer into a 64-byte Ed25519 signature,
+/// checking it verifies before returning it.
+pub fn aggregate(
+    message: &[u8],
+    public_key: &[u8; 32],
+    commitments: &BTreeMap<u16, NonceCommitment>,
+    shares: &BTreeMap<u16, Scalar>,
+) -> Result<[u8; 64], String> {
+    if shares.keys().ne(commitments.keys()) {
+        return Err("Every committed participant must submit exactly one signature share".to_string());
+    }
+
+    let r = group_commitment(message, commitments);
+    let z = shares.values().fold(Scalar::zero(), |acc, share| acc + share);
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(r.compress().as_bytes());
+    signature[32..].copy_from_slice(z.as_bytes());
+
+    if !verify(public_key, message, &signature) {
+        return Err("Aggregated signature does not verify against the public key".to_string());
+    }
+    Ok(signature)
+}
+
+pub fn verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    use ed25519_dalek::Verifier;
+
+    let Ok(public_key) = ed25519_dalek::PublicKey::from_bytes(public_key) else {
+        return false;
+    };
+    let Ok(signature) = ed25519_dalek::Signature::try_from(&signature[..]) else {
+        return false;
+    };
+    public_key.verify(message, &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(signers: &[(u16, Scalar)], public_key: &[u8; 32], message: &[u8]) -> (BTreeMap<u16, NonceCommitment>, BTreeMap<u16, Scalar>) {
+        let nonces: Vec<(u16, SigningNonces)> = signers.iter()
+            .map(|(index, share)| (*index, SigningNonces::derive(share, "session-1", &[*index as u8; 32])))
+            .collect();
+        let commitments: BTreeMap<u16, NonceCommitment> = nonces.iter()
+            .map(|(index, nonces)| (*index, nonces.commitment()))
+            .collect();
+        let shares = signers.iter().zip(&nonces)
+            .map(|((index, share), (_, nonces))| (*index, sign_share(*index, share, nonces, message, public_key, &commitments)))
+            .collect();
+        (commitments, shares)
+    }
+
+    #[test]
+    fn test_any_two_of_three_shares_produce_a_valid_signature() {
+        let secret = random_scalar();
+        let public_key = public_key(&secret);
+        let shares = split_secret(&secret, 2, 3);
+        let message = b"transfer 1 SOL";
+
+        for signers in [[shares[0], shares[1]], [shares[0], shares[2]], [shares[1], shares[2]]] {
+            let (commitments, signature_shares) = sign(&signers, &public_key, message);
+            let signature = aggregate(message, &public_key, &commitments, &signature_shares).unwrap();
+
+            let key = ed25519_dalek::PublicKey::from_bytes(&public_key).unwrap();
+            let signature = ed25519_dalek::Signature::try_from(&signature[..]).unwrap();
+            assert!(ed25519_dalek::Verifier::verify(&key, message, &signature).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_tampered_or_missing_shares_are_rejected() {
+        let secret = random_scalar();
+        let public_key = public_key(&secret);
+        let shares = split_secret(&secret, 2, 3);
+        let message = b"transfer 1 SOL";
+
+        let (commitments, mut signature_shares) = sign(&shares[..2], &public_key, message);
+        *signature_shares.get_mut(&1).unwrap() += Scalar::one();
+        assert!(aggregate(message, &public_key, &commitments, &signature_shares).unwrap_err().contains("does not verify"));
+
+        signature_shares.remove(&1);
+        assert!(aggregate(message, &public_key, &commitments, &signature_shares).is_err());
+    }
+
+    #[test]
+    fn test_share_and_commitment_encoding_round_trip() {
+        let share = random_scalar();
+        assert_eq!(decode_share(&encode_share(&share)).unwrap(), share);
+        assert!(decode_share("encrypted_share_1").is_err());
+
+        let commitment = SigningNonces::derive(&share, "session-1", &[7; 32]).commitment();
+        assert_eq!(NonceCommitment::from_base64(&commitment.to_base64()).unwrap(), commitment);
+        assert!(NonceCommitment::from_base64(&STANDARD.encode([0u8; 8])).is_err());
+    }
+}
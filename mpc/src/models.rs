@@ -7,7 +7,7 @@ pub struct KeyShare {
     pub user_id: String,
     pub public_key: String,
     pub encrypted_share: String, // encrypted private key share
-    pub share_index: i32, // which share this is (1, 2, or 3)
+    pub share_index: i32, // which share this is (1-based, up to total_shares)
     pub threshold: i32, // threshold for reconstruction
     pub total_shares: i32, // total number of shares
     pub created_at: chrono::DateTime<chrono::Utc>,
@@ -16,6 +16,12 @@ pub struct KeyShare {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GenerateRequest {
     pub user_id: String,
+    /// Shares needed to sign (default 2)
+    #[serde(default)]
+    pub threshold: Option<u16>,
+    /// Shares created (default 3, at most 5)
+    #[serde(default)]
+    pub total_shares: Option<u16>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,6 +29,17 @@ pub struct GenerateResponse {
     pub user_id: String,
     pub public_key: String,
     pub shares_created: bool,
+    pub threshold: i32,
+    pub total_shares: i32,
+    pub shares: Vec<ShareStorage>,
+}
+
+/// Where one generated share goes and whether it was written there.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareStorage {
+    pub share_index: i32,
+    pub database_index: usize,
+    pub stored: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,9 +48,12 @@ pub struct AggregateRequest {
     pub message: String, // message to sign
 }
 
+/// Shared by `mpc` and `mpc-simple`. The reconstructed key never leaves the service;
+/// callers get the signature and the public key to verify it against.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AggregateResponse {
     pub signature: String,
+    pub public_key: String,
     pub success: bool,
 }
 
@@ -78,15 +98,20 @@ pub struct ThresholdSignRequest {
 pub struct AggSendStep1Request {
     pub user_id: String,
     pub session_id: String,
-    pub participant_id: String,
+    pub participant_id: String, // share index the participant signs with: "1", "2" or "3"
     pub nonce: String, // Base64 encoded nonce
+    // Only used by the request that creates the session
+    #[serde(default)]
+    pub participants: Vec<String>, // expected participants; defaults to just the caller
+    #[serde(default)]
+    pub threshold: Option<i32>, // submissions needed to advance; defaults to all participants
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AggSendStep1Response {
     pub session_id: String,
     pub participant_id: String,
-    pub commitment: String, // Base64 encoded commitment
+    pub commitment: String, // Base64 encoded hiding and binding nonce commitments
     pub success: bool,
     pub message: String,
 }
@@ -97,8 +122,8 @@ pub struct AggSendStep2Request {
     pub user_id: String,
     pub session_id: String,
     pub participant_id: String,
-    pub message_to_sign: String, // The actual message/transaction to sign
-    pub commitments: Vec<CommitmentData>, // Commitments from other participants
+    pub message_to_sign: String, // Base64 encoded message/transaction bytes to sign
+    pub commitments: Vec<CommitmentData>, // Commitments from other participants, checked against the session
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -134,8 +159,8 @@ pub struct SignatureShareData {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AggregateSignaturesBroadcastResponse {
     pub session_id: String,
-    pub final_signature: String, // Base64 encoded final aggregated signature
-    pub public_key: String, // Public key for verification
+    pub final_signature: String, // Base64 encoded 64-byte Ed25519 signature
+    pub public_key: String, // Base58 public key the signature verifies against
     pub success: bool,
     pub message: String,
 }
@@ -147,11 +172,29 @@ pub struct MPCSession {
     pub session_id: String,
     pub user_id: String,
     pub participants: Vec<String>, // JSON array of participant IDs
+    pub threshold: i32, // participants needed to advance each step
     pub current_step: i32, // 1 = commitment, 2 = signature shares, 3 = aggregation
-    pub commitments: serde_json::Value, // JSON object of commitments
+    pub commitments: serde_json::Value, // JSON object of {commitment, nonce} per participant
     pub signature_shares: serde_json::Value, // JSON object of signature shares
     pub final_signature: Option<String>,
     pub message_to_sign: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
+
+// Session status for coordinators polling progress
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionStatusResponse {
+    pub session_id: String,
+    pub user_id: String,
+    pub current_step: i32,
+    pub participants: Vec<String>,
+    pub threshold: i32,
+    pub committed_participants: Vec<String>,
+    pub participants_with_shares: Vec<String>,
+    pub has_final_signature: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub expired: bool,
+}
@@ -26,6 +26,23 @@ pub async fn aggregate_keys(
         }
     };
     
+    let public_key = match shares.first() {
+        Some(share) => share.public_key.clone(),
+        None => {
+            log::warn!("No key shares found for user {}", req.user_id);
+            return Ok(HttpResponse::NotFound().json(json!({
+                "error": "No key shares found for user"
+            })));
+        }
+    };
+
+    if shares.iter().any(|share| share.public_key != public_key) {
+        log::error!("Mismatched public keys in shares for user {}", req.user_id);
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Inconsistent public keys across shares"
+        })));
+    }
+
     if shares.len() < 2 {
         log::warn!("Insufficient shares for user {}: found {}", req.user_id, shares.len());
         return Ok(HttpResponse::BadRequest().json(json!({
@@ -76,6 +93,7 @@ pub async fn aggregate_keys(
     
     let response = AggregateResponse {
         signature: signature_str,
+        public_key,
         success: true,
     };
     
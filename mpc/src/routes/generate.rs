@@ -1,20 +1,56 @@
 
 use actix_web::{web, HttpResponse, Result};
+use common::shard::share_location;
 use serde_json::json;
 use uuid::Uuid;
 
 use crate::{
-    models::{GenerateRequest, GenerateResponse, KeyShare},
+    models::{GenerateRequest, GenerateResponse, KeyShare, ShareStorage},
     database::DatabaseManager,
-    // Temporarily disable crypto module
-    // crypto::MPCCrypto,
+    frost,
 };
 
+const DEFAULT_THRESHOLD: u16 = 2;
+const DEFAULT_TOTAL_SHARES: u16 = 3;
+pub const MAX_TOTAL_SHARES: u16 = 5;
+
+/// The `(threshold, total_shares)` a generate request asks for, 2-of-3 unless overridden.
+/// Requires `1 <= threshold <= total_shares <= 5`.
+fn share_scheme(req: &GenerateRequest) -> std::result::Result<(u16, u16), String> {
+    let threshold = req.threshold.unwrap_or(DEFAULT_THRESHOLD);
+    let total_shares = req.total_shares.unwrap_or(DEFAULT_TOTAL_SHARES);
+
+    if total_shares > MAX_TOTAL_SHARES {
+        return Err(format!("total_shares must be at most {}", MAX_TOTAL_SHARES));
+    }
+    if threshold < 1 || threshold > total_shares {
+        return Err(format!("threshold must be between 1 and total_shares ({})", total_shares));
+    }
+    Ok((threshold, total_shares))
+}
+
+/// One not-yet-stored entry per share, at the database `share_location` assigns it.
+fn storage_plan(shares: &[KeyShare]) -> Vec<ShareStorage> {
+    shares.iter().map(|share| ShareStorage {
+        share_index: share.share_index,
+        database_index: share_location(share.share_index).0,
+        stored: false,
+    }).collect()
+}
+
 pub async fn generate(
     db: web::Data<DatabaseManager>,
     req: web::Json<GenerateRequest>,
 ) -> Result<HttpResponse> {
-    log::info!("Generating threshold keypair for user: {}", req.user_id);
+    let (threshold, total_shares) = match share_scheme(&req) {
+        Ok(scheme) => scheme,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e
+            })));
+        }
+    };
+    log::info!("Generating {}-of-{} threshold keypair for user: {}", threshold, total_shares, req.user_id);
     
     // Check if user already has shares
     match db.user_has_shares(&req.user_id).await {
@@ -33,71 +69,44 @@ pub async fn generate(
         }
     }
     
-    // Generate threshold keypair using MPC crypto (placeholder)
-    // TODO: Re-enable once crypto module is fixed
-    // let (public_key, shares) = match MPCCrypto::generate_threshold_keypair(2, 3) {
-    //     Ok((pk, shares)) => (pk, shares),
-    //     Err(e) => {
-    //         return Ok(HttpResponse::InternalServerError().json(json!({
-    //             "error": format!("Failed to generate keypair: {}", e)
-    //         })));
-    //     }
-    // };
-
-    // Placeholder implementation for now
-    let public_key = format!("pk_placeholder_{}", req.user_id);
-    let shares = vec![
-        crate::models::KeyShare {
-            id: Uuid::new_v4(),
-            user_id: req.user_id.clone(),
-            public_key: public_key.clone(),
-            encrypted_share: "encrypted_share_1".to_string(),
-            share_index: 1,
-            threshold: 2,
-            total_shares: 3,
-            created_at: chrono::Utc::now(),
-        },
-        crate::models::KeyShare {
+    // Split a fresh signing scalar into threshold-of-total shares; the scalar itself is never stored
+    let secret = frost::random_scalar();
+    let public_key = bs58::encode(frost::public_key(&secret)).into_string();
+    let shares: Vec<KeyShare> = frost::split_secret(&secret, threshold, total_shares)
+        .into_iter()
+        .map(|(index, share)| KeyShare {
             id: Uuid::new_v4(),
             user_id: req.user_id.clone(),
             public_key: public_key.clone(),
-            encrypted_share: "encrypted_share_2".to_string(),
-            share_index: 2,
-            threshold: 2,
-            total_shares: 3,
+            encrypted_share: frost::encode_share(&share),
+            share_index: index as i32,
+            threshold: threshold as i32,
+            total_shares: total_shares as i32,
             created_at: chrono::Utc::now(),
-        },
-        crate::models::KeyShare {
-            id: Uuid::new_v4(),
-            user_id: req.user_id.clone(),
-            public_key: public_key.clone(),
-            encrypted_share: "encrypted_share_3".to_string(),
-            share_index: 3,
-            threshold: 2,
-            total_shares: 3,
-            created_at: chrono::Utc::now(),
-        },
-    ];
+        })
+        .collect();
 
     let public_key_str = public_key.clone();
     log::info!("Generated public key: {} for user: {}", public_key_str, req.user_id);
     
     // Store shares in different databases
+    let mut storage = storage_plan(&shares);
     let mut storage_success = true;
     
-    for (i, key_share) in shares.iter().enumerate() {        
-        // Store in the corresponding database (share_index 1->db0, 2->db1, 3->db2)
-        let db_index = (key_share.share_index - 1) as usize;
+    for (key_share, entry) in shares.iter().zip(storage.iter_mut()) {        
+        // Shares go round-robin over the databases (1->db0, 2->db1, 3->db2, 4->db0, ...)
+        let (db_index, slot) = share_location(key_share.share_index);
         
-        if let Err(e) = db.store_key_share(&key_share, db_index).await {
+        if let Err(e) = db.store_key_share(key_share, db_index).await {
             log::error!("Failed to store share {} for user {}: {}", 
                        key_share.share_index, req.user_id, e);
             storage_success = false;
             break;
         }
+        entry.stored = true;
         
-        log::info!("Stored share {} for user {} in database {}", 
-                  key_share.share_index, req.user_id, db_index + 1);
+        log::info!("Stored share {} for user {} in database {} (slot {})", 
+                  key_share.share_index, req.user_id, db_index + 1, slot);
     }
     
     if !storage_success {
@@ -106,8 +115,10 @@ pub async fn generate(
             log::error!("Failed to cleanup shares for user {}: {}", req.user_id, e);
         }
         
+        // `shares` shows how far storage got before the cleanup
         return Ok(HttpResponse::InternalServerError().json(json!({
-            "error": "Failed to store key shares"
+            "error": "Failed to store key shares",
+            "shares": storage
         })));
     }
     
@@ -115,8 +126,62 @@ pub async fn generate(
         user_id: req.user_id.clone(),
         public_key: public_key_str,
         shares_created: true,
+        threshold: threshold as i32,
+        total_shares: total_shares as i32,
+        shares: storage,
     };
     
     log::info!("Successfully generated and stored key shares for user: {}", req.user_id);
     Ok(HttpResponse::Ok().json(response))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(threshold: Option<u16>, total_shares: Option<u16>) -> GenerateRequest {
+        GenerateRequest { user_id: "user-1".to_string(), threshold, total_shares }
+    }
+
+    #[test]
+    fn test_share_scheme_defaults_and_rejects_invalid_parameters() {
+        assert_eq!(share_scheme(&request(None, None)), Ok((2, 3)));
+        assert_eq!(share_scheme(&request(Some(3), Some(5))), Ok((3, 5)));
+
+        assert!(share_scheme(&request(Some(0), Some(3))).is_err());
+        assert!(share_scheme(&request(Some(4), Some(3))).is_err());
+        assert!(share_scheme(&request(Some(2), Some(6))).is_err());
+    }
+
+    #[test]
+    fn test_storage_plan_lists_every_share_at_its_database() {
+        let shares: Vec<KeyShare> = frost::split_secret(&frost::random_scalar(), 3, 5)
+            .into_iter()
+            .map(|(index, share)| KeyShare {
+                id: Uuid::new_v4(),
+                user_id: "user-1".to_string(),
+                public_key: "key".to_string(),
+                encrypted_share: frost::encode_share(&share),
+                share_index: index as i32,
+                threshold: 3,
+                total_shares: 5,
+                created_at: chrono::Utc::now(),
+            })
+            .collect();
+
+        let plan = storage_plan(&shares);
+        let locations: Vec<(i32, usize)> = plan.iter().map(|s| (s.share_index, s.database_index)).collect();
+        assert_eq!(locations, vec![(1, 0), (2, 1), (3, 2), (4, 0), (5, 1)]);
+        assert!(plan.iter().all(|s| !s.stored));
+    }
+
+    #[test]
+    fn test_three_of_five_shares_land_in_distinct_slots() {
+        let shares = frost::split_secret(&frost::random_scalar(), 3, 5);
+        assert_eq!(shares.len(), 5);
+
+        let locations: std::collections::HashSet<_> = shares.iter().map(|(index, _)| share_location(*index as i32)).collect();
+        assert_eq!(locations.len(), 5);
+        assert!(locations.iter().all(|(db_index, _)| *db_index < 3));
+    }
+}
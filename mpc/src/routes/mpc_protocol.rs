@@ -1,38 +1,236 @@
 use actix_web::{web, HttpResponse, Result};
 use serde_json::json;
 use uuid::Uuid;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use curve25519_dalek::scalar::Scalar;
 
 use crate::database::DatabaseManager;
+use crate::frost::{self, NonceCommitment, SigningNonces};
+use crate::routes::generate::MAX_TOTAL_SHARES;
 use crate::models::{
-    MPCSession, AggSendStep1Request, AggSendStep1Response,
+    MPCSession, KeyShare, AggSendStep1Request, AggSendStep1Response,
     AggSendStep2Request, AggSendStep2Response,
     AggregateSignaturesBroadcastRequest, AggregateSignaturesBroadcastResponse,
-    SignatureShareData
+    SessionStatusResponse
 };
 
+/// Step 1 nonces are 32 random bytes, base64 encoded.
+pub const NONCE_LEN: usize = 32;
+
+fn decode_nonce(nonce: &str) -> std::result::Result<Vec<u8>, String> {
+    let bytes = STANDARD.decode(nonce).map_err(|_| "Nonce is not valid base64".to_string())?;
+    if bytes.len() != NONCE_LEN {
+        return Err(format!("Nonce must be {} bytes, got {}", NONCE_LEN, bytes.len()));
+    }
+    Ok(bytes)
+}
+
+/// Resolve the participant set and threshold for a new session. Without an explicit list
+/// the session is single-party; without a threshold every participant must submit.
+fn session_params(
+    participant_id: &str,
+    declared: &[String],
+    threshold: Option<i32>,
+) -> std::result::Result<(Vec<String>, i32), String> {
+    let mut participants: Vec<String> = Vec::new();
+    for participant in declared {
+        if !participants.contains(participant) {
+            participants.push(participant.clone());
+        }
+    }
+    if participants.is_empty() {
+        participants.push(participant_id.to_string());
+    }
+    if !participants.iter().any(|p| p == participant_id) {
+        return Err(format!("Participant {} is not in the declared participant list", participant_id));
+    }
+
+    let threshold = threshold.unwrap_or(participants.len() as i32);
+    if threshold < 1 || threshold as usize > participants.len() {
+        return Err(format!("Threshold must be between 1 and {}", participants.len()));
+    }
+
+    Ok((participants, threshold))
+}
+
+/// True once at least `threshold` of the declared participants have an entry in `submissions`.
+fn threshold_reached(session: &MPCSession, submissions: &serde_json::Value) -> bool {
+    let submitted = session.participants.iter()
+        .filter(|p| submissions.get(p.as_str()).is_some())
+        .count();
+    submitted >= session.threshold as usize
+}
+
+/// How long a multi-step session stays usable after it was created.
+pub const SESSION_TTL_SECS: i64 = 600;
+
+fn participant_ids(value: &serde_json::Value) -> Vec<String> {
+    let mut ids: Vec<String> = value.as_object()
+        .map(|map| map.keys().cloned().collect())
+        .unwrap_or_default();
+    ids.sort();
+    ids
+}
+
+fn session_status(session: &MPCSession, now: chrono::DateTime<chrono::Utc>) -> SessionStatusResponse {
+    let expires_at = session.created_at + chrono::Duration::seconds(SESSION_TTL_SECS);
+
+    SessionStatusResponse {
+        session_id: session.session_id.clone(),
+        user_id: session.user_id.clone(),
+        current_step: session.current_step,
+        participants: session.participants.clone(),
+        threshold: session.threshold,
+        committed_participants: participant_ids(&session.commitments),
+        participants_with_shares: participant_ids(&session.signature_shares),
+        has_final_signature: session.final_signature.is_some(),
+        created_at: session.created_at,
+        updated_at: session.updated_at,
+        expires_at,
+        expired: now >= expires_at,
+    }
+}
+
+pub async fn get_session_status(
+    path: web::Path<String>,
+    db: web::Data<DatabaseManager>,
+) -> Result<HttpResponse> {
+    let session_id = path.into_inner();
+
+    match db.get_mpc_session(&session_id).await {
+        Ok(Some(session)) => Ok(HttpResponse::Ok().json(session_status(&session, chrono::Utc::now()))),
+        Ok(None) => Ok(HttpResponse::NotFound().json(json!({
+            "error": "Session not found"
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "error": format!("Database error: {}", e)
+        }))),
+    }
+}
+
+/// Participants are named by the share they sign with: "1" up to "5".
+fn share_index(participant_id: &str) -> std::result::Result<u16, String> {
+    match participant_id.parse::<u16>() {
+        Ok(index) if (1..=MAX_TOTAL_SHARES).contains(&index) => Ok(index),
+        _ => Err(format!("Participant {} must be a share index between 1 and {}", participant_id, MAX_TOTAL_SHARES)),
+    }
+}
+
+/// The message is signed as raw bytes, sent base64 encoded (e.g. a serialized transaction message).
+fn decode_message(message: &str) -> std::result::Result<Vec<u8>, String> {
+    STANDARD.decode(message).map_err(|_| "message_to_sign must be base64".to_string())
+}
+
+/// The nonces are bound to the session, so signing a second message with them would leak the
+/// share: once a message is pinned, step 2 only accepts that one.
+fn signs_another_message(session: &MPCSession, message: &str) -> bool {
+    session.message_to_sign.as_deref().is_some_and(|pinned| pinned != message)
+}
+
+/// Round-1 commitments recorded on the session, keyed by share index.
+fn session_commitments(session: &MPCSession) -> std::result::Result<BTreeMap<u16, NonceCommitment>, String> {
+    let mut commitments = BTreeMap::new();
+    for participant in participant_ids(&session.commitments) {
+        let encoded = session.commitments[participant.as_str()]["commitment"]
+            .as_str()
+            .ok_or_else(|| format!("Commitment for participant {} is missing", participant))?;
+        commitments.insert(share_index(&participant)?, NonceCommitment::from_base64(encoded)?);
+    }
+    Ok(commitments)
+}
+
+/// The participant's decoded share of the user's key, or the response to send instead.
+async fn load_share(
+    db: &DatabaseManager,
+    user_id: &str,
+    index: u16,
+) -> std::result::Result<(KeyShare, Scalar), HttpResponse> {
+    let key_share = match db.get_key_share(user_id, index as i32).await {
+        Ok(Some(key_share)) => key_share,
+        Ok(None) => {
+            return Err(HttpResponse::NotFound().json(json!({
+                "error": format!("No key share {} found for user", index)
+            })));
+        }
+        Err(e) => {
+            return Err(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Database error: {}", e)
+            })));
+        }
+    };
+
+    match frost::decode_share(&key_share.encrypted_share) {
+        Ok(share) => Ok((key_share, share)),
+        Err(e) => {
+            println!("Unusable key share {} for user {}: {}", index, user_id, e);
+            Err(HttpResponse::BadRequest().json(json!({
+                "error": "Key share cannot be used for signing; regenerate the user's keys"
+            })))
+        }
+    }
+}
+
 pub async fn agg_send_step1(
     data: web::Json<AggSendStep1Request>,
     db: web::Data<DatabaseManager>,
 ) -> Result<HttpResponse> {
     println!("Starting MPC Step 1 - Commitment Phase");
+
+    // Reject malformed nonces before they are recorded on the session
+    let randomness = match decode_nonce(&data.nonce) {
+        Ok(randomness) => randomness,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e
+            })));
+        }
+    };
+
+    let index = match share_index(&data.participant_id) {
+        Ok(index) => index,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e
+            })));
+        }
+    };
+
+    let share = match load_share(&db, &data.user_id, index).await {
+        Ok((_, share)) => share,
+        Err(response) => return Ok(response),
+    };
     
     // Create or get existing session
     let mut session = match db.get_mpc_session(&data.session_id).await {
         Ok(Some(session)) => session,
         Ok(None) => {
-            // Create new session
-            let participants = vec![data.participant_id.clone()]; // For now, single participant
+            // Create new session with the declared participant set
+            let (participants, threshold) = match session_params(&data.participant_id, &data.participants, data.threshold) {
+                Ok(params) => params,
+                Err(e) => {
+                    return Ok(HttpResponse::BadRequest().json(json!({
+                        "error": e
+                    })));
+                }
+            };
+            if let Some(Err(e)) = participants.iter().map(|p| share_index(p)).find(|index| index.is_err()) {
+                return Ok(HttpResponse::BadRequest().json(json!({
+                    "error": e
+                })));
+            }
             let session = MPCSession {
                 id: Uuid::new_v4(),
                 session_id: data.session_id.clone(),
                 user_id: data.user_id.clone(),
                 participants,
+                threshold,
                 current_step: 1,
                 commitments: serde_json::json!({}),
                 signature_shares: serde_json::json!({}),
                 final_signature: None,
-                message_to_sign: Some(data.nonce.clone()),
+                // Pinned by the first step 2 request
+                message_to_sign: None,
                 created_at: chrono::Utc::now(),
                 updated_at: chrono::Utc::now(),
             };
@@ -50,6 +248,12 @@ pub async fn agg_send_step1(
         }
     };
 
+    if session.user_id != data.user_id {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Session belongs to a different user"
+        })));
+    }
+
     // Validate step
     if session.current_step != 1 {
         return Ok(HttpResponse::BadRequest().json(json!({
@@ -57,24 +261,26 @@ pub async fn agg_send_step1(
         })));
     }
 
-    // Generate commitment from nonce
-    let commitment = format!("commitment_{}", data.nonce);
+    if !session.participants.contains(&data.participant_id) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Participant {} is not part of this session", data.participant_id)
+        })));
+    }
+
+    // Commit to nonces derived from the share, the session and the participant's randomness;
+    // the randomness is kept so step 2 can derive the same nonces again
+    let commitment = SigningNonces::derive(&share, &session.session_id, &randomness).commitment().to_base64();
 
     // Store commitment for this participant
     if let serde_json::Value::Object(ref mut commitments) = session.commitments {
-        commitments.insert(data.participant_id.clone(), serde_json::Value::String(commitment.clone()));
+        commitments.insert(data.participant_id.clone(), json!({
+            "commitment": commitment,
+            "nonce": data.nonce
+        }));
     }
 
-    // Check if all participants have submitted commitments
-    let participants_committed: Vec<String> = if let serde_json::Value::Object(ref commitments) = session.commitments {
-        commitments.keys().cloned().collect()
-    } else {
-        vec![]
-    };
-
-    let all_committed = session.participants.len() == participants_committed.len();
-
-    if all_committed {
+    // Advance once enough of the declared participants have committed
+    if threshold_reached(&session, &session.commitments) {
         // Advance to step 2
         session.current_step = 2;
         session.updated_at = chrono::Utc::now();
@@ -118,6 +324,12 @@ pub async fn agg_send_step2(
         }
     };
 
+    if session.user_id != data.user_id {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Session belongs to a different user"
+        })));
+    }
+
     // Validate step
     if session.current_step != 2 {
         return Ok(HttpResponse::BadRequest().json(json!({
@@ -125,24 +337,105 @@ pub async fn agg_send_step2(
         })));
     }
 
-    // Generate signature share from the message
-    let signature_share = format!("sig_share_{}", data.message_to_sign);
+    if !session.participants.contains(&data.participant_id) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("Participant {} is not part of this session", data.participant_id)
+        })));
+    }
 
-    // Store signature share for this participant
-    if let serde_json::Value::Object(ref mut shares) = session.signature_shares {
-        shares.insert(data.participant_id.clone(), serde_json::Value::String(signature_share.clone()));
+    if signs_another_message(&session, &data.message_to_sign) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Session is already signing a different message"
+        })));
+    }
+
+    let (index, message) = match share_index(&data.participant_id).and_then(|index| Ok((index, decode_message(&data.message_to_sign)?))) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e
+            })));
+        }
+    };
+
+    let commitments = match session_commitments(&session) {
+        Ok(commitments) => commitments,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Invalid session commitments: {}", e)
+            })));
+        }
+    };
+
+    let nonce = match session.commitments[data.participant_id.as_str()]["nonce"].as_str() {
+        Some(nonce) => nonce.to_string(),
+        None => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": format!("Participant {} did not commit in step 1", data.participant_id)
+            })));
+        }
+    };
+
+    // Commitments relayed by the coordinator must be the ones the session recorded
+    for provided in &data.commitments {
+        let recorded = session.commitments[provided.participant_id.as_str()]["commitment"].as_str();
+        if recorded != Some(provided.commitment.as_str()) {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": format!("Commitment for participant {} does not match the session", provided.participant_id)
+            })));
+        }
     }
 
-    // Check if all participants have submitted signature shares
-    let participants_with_shares: Vec<String> = if let serde_json::Value::Object(ref shares) = session.signature_shares {
-        shares.keys().cloned().collect()
-    } else {
-        vec![]
+    let (key_share, share) = match load_share(&db, &session.user_id, index).await {
+        Ok(loaded) => loaded,
+        Err(response) => return Ok(response),
+    };
+
+    if commitments.len() < key_share.threshold as usize {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": format!("At least {} participants must commit before signing", key_share.threshold)
+        })));
+    }
+
+    let public_key = match frost::decode_public_key(&key_share.public_key) {
+        Ok(public_key) => public_key,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e
+            })));
+        }
     };
 
-    let all_shares_received = session.participants.len() == participants_with_shares.len();
+    // The check above read a snapshot; pin the message in the database before signing, so
+    // two concurrent requests for different messages can't both get a share
+    match db.pin_session_message(&session.session_id, &data.message_to_sign).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": "Session is already signing a different message"
+            })));
+        }
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Database error: {}", e)
+            })));
+        }
+    }
+
+    let randomness = decode_nonce(&nonce).map_err(actix_web::error::ErrorInternalServerError)?;
+    let nonces = SigningNonces::derive(&share, &session.session_id, &randomness);
+    let signature_share = frost::encode_signature_share(
+        &frost::sign_share(index, &share, &nonces, &message, &public_key, &commitments)
+    );
 
-    if all_shares_received {
+    // Store signature share for this participant
+    session.message_to_sign = Some(data.message_to_sign.clone());
+    if let serde_json::Value::Object(ref mut shares) = session.signature_shares {
+        shares.insert(data.participant_id.clone(), serde_json::Value::String(signature_share.clone()));
+    }
+
+    // Ready for aggregation once every committed participant has submitted a share
+    if participant_ids(&session.signature_shares) == participant_ids(&session.commitments) {
         // Ready for aggregation
         session.current_step = 3;
         session.updated_at = chrono::Utc::now();
@@ -186,6 +479,12 @@ pub async fn aggregate_signatures_broadcast(
         }
     };
 
+    if session.user_id != data.user_id {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "Session belongs to a different user"
+        })));
+    }
+
     // Validate step
     if session.current_step != 3 {
         return Ok(HttpResponse::BadRequest().json(json!({
@@ -200,14 +499,69 @@ pub async fn aggregate_signatures_broadcast(
         })));
     }
 
-    // Convert signature shares to HashMap for aggregation
-    let mut shares_map = HashMap::new();
-    for share_data in &data.signature_shares {
-        shares_map.insert(share_data.participant_id.clone(), share_data.signature_share.clone());
+    if session.message_to_sign.as_deref() != Some(data.message_to_sign.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "error": "message_to_sign does not match the message signed in step 2"
+        })));
     }
 
-    // Perform signature aggregation
-    let aggregated_signature = simulate_signature_aggregation(&shares_map, &data.message_to_sign);
+    // Collect the signature shares by share index for aggregation
+    let parsed = decode_message(&data.message_to_sign).and_then(|message| {
+        let mut shares = BTreeMap::new();
+        for share_data in &data.signature_shares {
+            shares.insert(
+                share_index(&share_data.participant_id)?,
+                frost::decode_signature_share(&share_data.signature_share)?,
+            );
+        }
+        Ok((message, shares))
+    });
+    let (message, shares) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e
+            })));
+        }
+    };
+
+    let commitments = match session_commitments(&session) {
+        Ok(commitments) => commitments,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Invalid session commitments: {}", e)
+            })));
+        }
+    };
+
+    let public_key = match db.get_all_user_shares(&session.user_id).await {
+        Ok(key_shares) => match key_shares.first() {
+            Some(key_share) => key_share.public_key.clone(),
+            None => {
+                return Ok(HttpResponse::NotFound().json(json!({
+                    "error": "No key shares found for user"
+                })));
+            }
+        },
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": format!("Database error: {}", e)
+            })));
+        }
+    };
+
+    // Perform signature aggregation; the result is checked against the public key
+    let aggregated_signature = match frost::decode_public_key(&public_key)
+        .and_then(|public_key_bytes| frost::aggregate(&message, &public_key_bytes, &commitments, &shares))
+    {
+        Ok(signature) => STANDARD.encode(signature),
+        Err(e) => {
+            println!("Aggregation failed for session {}: {}", session.session_id, e);
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": e
+            })));
+        }
+    };
 
     // Store final signature
     session.final_signature = Some(aggregated_signature.clone());
@@ -218,9 +572,6 @@ pub async fn aggregate_signatures_broadcast(
         actix_web::error::ErrorInternalServerError(format!("Failed to update session: {}", e))
     })?;
 
-    // Generate a dummy public key for now
-    let public_key = "dummy_public_key_placeholder".to_string();
-
     let response = AggregateSignaturesBroadcastResponse {
         session_id: session.session_id.clone(),
         final_signature: aggregated_signature.clone(),
@@ -233,23 +584,203 @@ pub async fn aggregate_signatures_broadcast(
     Ok(HttpResponse::Ok().json(response))
 }
 
-// Simulate signature aggregation for demonstration
-fn simulate_signature_aggregation(signature_shares: &HashMap<String, String>, message: &str) -> String {
-    use sha2::{Sha256, Digest};
-    
-    // Combine all signature shares with the message
-    let mut hasher = Sha256::new();
-    hasher.update(message.as_bytes());
-    
-    // Add each signature share to the hash
-    let mut sorted_shares: Vec<_> = signature_shares.iter().collect();
-    sorted_shares.sort_by_key(|(k, _)| *k);
-    
-    for (participant, share) in sorted_shares {
-        hasher.update(participant.as_bytes());
-        hasher.update(share.as_bytes());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_nonce_rejects_malformed_input() {
+        assert!(decode_nonce(&STANDARD.encode([1u8; NONCE_LEN])).is_ok());
+        assert!(decode_nonce("not base64!!").unwrap_err().contains("base64"));
+        assert!(decode_nonce(&STANDARD.encode([1u8; 8])).unwrap_err().contains("32 bytes"));
+    }
+
+    #[test]
+    fn test_session_params_defaults_and_validation() {
+        assert_eq!(session_params("p1", &[], None).unwrap(), (vec!["p1".to_string()], 1));
+
+        let declared = vec!["p1".to_string(), "p2".to_string(), "p3".to_string()];
+        assert_eq!(session_params("p2", &declared, Some(2)).unwrap().1, 2);
+        assert_eq!(session_params("p2", &declared, None).unwrap().1, 3);
+        assert!(session_params("p4", &declared, Some(2)).is_err());
+        assert!(session_params("p1", &declared, Some(4)).is_err());
+        assert!(session_params("p1", &declared, Some(0)).is_err());
+    }
+
+    #[test]
+    fn test_session_status_reports_progress() {
+        let created_at = chrono::Utc::now();
+        let session = MPCSession {
+            id: Uuid::new_v4(),
+            session_id: "session-1".to_string(),
+            user_id: "user-1".to_string(),
+            participants: vec!["p1".to_string(), "p2".to_string()],
+            threshold: 2,
+            current_step: 2,
+            commitments: json!({"p2": "c2", "p1": "c1"}),
+            signature_shares: json!({"p1": "s1"}),
+            final_signature: None,
+            message_to_sign: None,
+            created_at,
+            updated_at: created_at,
+        };
+
+        assert!(threshold_reached(&session, &session.commitments));
+        assert!(!threshold_reached(&session, &session.signature_shares));
+
+        let status = session_status(&session, created_at);
+        assert_eq!(status.committed_participants, vec!["p1", "p2"]);
+        assert_eq!(status.participants_with_shares, vec!["p1"]);
+        assert!(!status.has_final_signature);
+        assert!(!status.expired);
+
+        let later = created_at + chrono::Duration::seconds(SESSION_TTL_SECS);
+        assert!(session_status(&session, later).expired);
+    }
+
+    #[test]
+    fn test_second_message_is_rejected_once_pinned() {
+        let created_at = chrono::Utc::now();
+        let mut session = MPCSession {
+            id: Uuid::new_v4(),
+            session_id: "session-1".to_string(),
+            user_id: "user-1".to_string(),
+            participants: vec!["1".to_string(), "2".to_string()],
+            threshold: 2,
+            current_step: 2,
+            commitments: json!({"1": "c1", "2": "c2"}),
+            signature_shares: json!({}),
+            final_signature: None,
+            message_to_sign: None,
+            created_at,
+            updated_at: created_at,
+        };
+        let first = STANDARD.encode(b"transfer 1 SOL");
+        let second = STANDARD.encode(b"transfer 100 SOL");
+
+        assert!(!signs_another_message(&session, &first));
+        session.message_to_sign = Some(first.clone());
+        // The other participant may sign the same message, but not a different one
+        assert!(!signs_another_message(&session, &first));
+        assert!(signs_another_message(&session, &second));
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires MPC1_DATABASE_URL, MPC2_DATABASE_URL and MPC3_DATABASE_URL"]
+    async fn test_concurrent_pins_agree_on_one_message() {
+        let db = DatabaseManager::new().await.unwrap();
+        let created_at = chrono::Utc::now();
+        let session = MPCSession {
+            id: Uuid::new_v4(),
+            session_id: Uuid::new_v4().to_string(),
+            user_id: Uuid::new_v4().to_string(),
+            participants: vec!["1".to_string(), "2".to_string()],
+            threshold: 2,
+            current_step: 2,
+            commitments: json!({}),
+            signature_shares: json!({}),
+            final_signature: None,
+            message_to_sign: None,
+            created_at,
+            updated_at: created_at,
+        };
+        db.create_mpc_session(&session).await.unwrap();
+
+        let (first, second) = tokio::join!(
+            db.pin_session_message(&session.session_id, "message-a"),
+            db.pin_session_message(&session.session_id, "message-b"),
+        );
+        assert_eq!([first.unwrap(), second.unwrap()].iter().filter(|pinned| **pinned).count(), 1);
+
+        let pinned = db.get_mpc_session(&session.session_id).await.unwrap().unwrap().message_to_sign.unwrap();
+        assert!(db.pin_session_message(&session.session_id, &pinned).await.unwrap());
+    }
+
+    #[test]
+    fn test_share_index_accepts_only_share_numbers() {
+        assert_eq!(share_index("2"), Ok(2));
+        assert!(share_index("0").is_err());
+        assert!(share_index("6").is_err());
+        assert!(share_index("p1").is_err());
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires MPC1_DATABASE_URL, MPC2_DATABASE_URL and MPC3_DATABASE_URL"]
+    async fn test_three_steps_produce_a_verifying_signature() {
+        use actix_web::{test, App};
+
+        let db = web::Data::new(DatabaseManager::new().await.unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(db.clone())
+                .route("/generate", web::post().to(crate::routes::generate))
+                .route("/agg-send-step1", web::post().to(agg_send_step1))
+                .route("/agg-send-step2", web::post().to(agg_send_step2))
+                .route("/aggregate-signatures-broadcast", web::post().to(aggregate_signatures_broadcast)),
+        )
+        .await;
+
+        let user_id = Uuid::new_v4().to_string();
+        let session_id = Uuid::new_v4().to_string();
+        let message = b"transfer 1 SOL";
+        let message_to_sign = STANDARD.encode(message);
+        let signers = ["1", "3"];
+
+        let generated: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::post().uri("/generate").set_json(json!({ "user_id": user_id })).to_request(),
+        )
+        .await;
+
+        let mut commitments = Vec::new();
+        for participant in signers {
+            let response: AggSendStep1Response = test::call_and_read_body_json(
+                &app,
+                test::TestRequest::post().uri("/agg-send-step1").set_json(json!({
+                    "user_id": user_id,
+                    "session_id": session_id,
+                    "participant_id": participant,
+                    "nonce": STANDARD.encode([participant.as_bytes()[0]; NONCE_LEN]),
+                    "participants": signers,
+                    "threshold": 2
+                })).to_request(),
+            )
+            .await;
+            commitments.push(json!({ "participant_id": participant, "commitment": response.commitment }));
+        }
+
+        let mut signature_shares = Vec::new();
+        for participant in signers {
+            let response: AggSendStep2Response = test::call_and_read_body_json(
+                &app,
+                test::TestRequest::post().uri("/agg-send-step2").set_json(json!({
+                    "user_id": user_id,
+                    "session_id": session_id,
+                    "participant_id": participant,
+                    "message_to_sign": message_to_sign,
+                    "commitments": commitments
+                })).to_request(),
+            )
+            .await;
+            signature_shares.push(json!({ "participant_id": participant, "signature_share": response.signature_share }));
+        }
+
+        let response: AggregateSignaturesBroadcastResponse = test::call_and_read_body_json(
+            &app,
+            test::TestRequest::post().uri("/aggregate-signatures-broadcast").set_json(json!({
+                "user_id": user_id,
+                "session_id": session_id,
+                "message_to_sign": message_to_sign,
+                "signature_shares": signature_shares
+            })).to_request(),
+        )
+        .await;
+
+        assert_eq!(generated["public_key"], response.public_key);
+        let public_key = ed25519_dalek::PublicKey::from_bytes(&bs58::decode(&response.public_key).into_vec().unwrap()).unwrap();
+        let signature = ed25519_dalek::Signature::try_from(&STANDARD.decode(&response.final_signature).unwrap()[..]).unwrap();
+        assert!(ed25519_dalek::Verifier::verify(&public_key, message, &signature).is_ok());
+
+        db.delete_user_shares(&user_id).await.unwrap();
     }
-    
-    let result = hasher.finalize();
-    hex::encode(result)
 }
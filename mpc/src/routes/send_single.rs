@@ -4,6 +4,7 @@ use serde_json::json;
 use crate::{
     models::{KeyShareRequest, KeyShareResponse},
     database::DatabaseManager,
+    routes::generate::MAX_TOTAL_SHARES,
 };
 
 pub async fn send_single(
@@ -13,15 +14,13 @@ pub async fn send_single(
     log::info!("Checking single key share for user: {}, share_index: {}", 
               req.user_id, req.share_index);
     
-    if req.share_index < 1 || req.share_index > 3 {
+    if req.share_index < 1 || req.share_index > MAX_TOTAL_SHARES as i32 {
         return Ok(HttpResponse::BadRequest().json(json!({
-            "error": "Invalid share index. Must be 1, 2, or 3"
+            "error": format!("Invalid share index. Must be between 1 and {}", MAX_TOTAL_SHARES)
         })));
     }
     
-    let db_index = (req.share_index - 1) as usize;
-    
-    match db.get_key_share(&req.user_id, db_index).await {
+    match db.get_key_share(&req.user_id, req.share_index).await {
         Ok(Some(share)) => {
             log::info!("Found share {} for user {}", req.share_index, req.user_id);
             let response = KeyShareResponse {
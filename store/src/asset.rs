@@ -1,4 +1,4 @@
-use crate::{error::UserError, Store};
+use crate::{error::UserError, pubkey::SolanaPubkey, Store};
 use uuid::Uuid;
 use chrono::Utc;
 use sqlx::Row;
@@ -16,9 +16,22 @@ pub struct Asset {
     pub updated_at: chrono::DateTime<Utc>,
 }
 
+fn asset_from_row(row: &sqlx::postgres::PgRow) -> Result<Asset, UserError> {
+    Ok(Asset {
+        id: row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        mint_address: row.try_get("mint_address").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        decimals: row.try_get("decimals").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        name: row.try_get("name").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        symbol: row.try_get("symbol").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        logo_url: row.try_get("logo_url").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        created_at: row.try_get("created_at").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        updated_at: row.try_get("updated_at").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateAssetRequest {
-    pub mint_address: String,
+    pub mint_address: SolanaPubkey,
     pub decimals: i32,
     pub name: String,
     pub symbol: String,
@@ -33,6 +46,43 @@ pub struct UpdateAssetRequest {
     pub logo_url: Option<String>,
 }
 
+/// Columns the asset list may be sorted by. Anything else is rejected before it reaches SQL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetSort {
+    Symbol,
+    Name,
+    CreatedAt,
+}
+
+impl AssetSort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "symbol" => Some(AssetSort::Symbol),
+            "name" => Some(AssetSort::Name),
+            "created_at" => Some(AssetSort::CreatedAt),
+            _ => None,
+        }
+    }
+
+    fn column(&self) -> &'static str {
+        match self {
+            AssetSort::Symbol => "symbol",
+            AssetSort::Name => "name",
+            AssetSort::CreatedAt => "created_at",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ListAssetsParams {
+    pub limit: i64,
+    pub offset: i64,
+    pub sort: AssetSort,
+    pub descending: bool,
+    pub search: Option<String>,
+    pub symbol: Option<String>,
+}
+
 impl Store {
     pub async fn create_asset(&self, request: CreateAssetRequest) -> Result<Asset, UserError> {
         let asset_id = Uuid::new_v4().to_string();
@@ -69,7 +119,7 @@ impl Store {
 
         let asset = Asset {
             id: asset_id,
-            mint_address: request.mint_address,
+            mint_address: request.mint_address.into_inner(),
             decimals: request.decimals,
             name: request.name,
             symbol: request.symbol,
@@ -78,9 +128,57 @@ impl Store {
             updated_at: now,
         };
 
+        self.warn_on_symbol_collision(&asset).await;
         Ok(asset)
     }
 
+    /// Insert the asset unless its mint is already known. Returns the stored asset and
+    /// whether this call created it.
+    pub async fn get_or_create_asset(&self, request: CreateAssetRequest) -> Result<(Asset, bool), UserError> {
+        let asset_id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+
+        // ON CONFLICT keeps concurrent callers from racing on the unique mint
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO assets (id, mint_address, decimals, name, symbol, logo_url, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (mint_address) DO NOTHING
+            "#
+        )
+        .bind(&asset_id)
+        .bind(&request.mint_address)
+        .bind(request.decimals)
+        .bind(&request.name)
+        .bind(&request.symbol)
+        .bind(&request.logo_url)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        if inserted.rows_affected() == 1 {
+            let asset = Asset {
+                id: asset_id,
+                mint_address: request.mint_address.into_inner(),
+                decimals: request.decimals,
+                name: request.name,
+                symbol: request.symbol,
+                logo_url: request.logo_url,
+                created_at: now,
+                updated_at: now,
+            };
+            self.warn_on_symbol_collision(&asset).await;
+            return Ok((asset, true));
+        }
+
+        let existing = self.get_asset_by_mint(request.mint_address.as_str()).await?
+            .ok_or(UserError::AssetNotFound)?;
+
+        Ok((existing, false))
+    }
+
     pub async fn get_asset_by_id(&self, asset_id: &str) -> Result<Option<Asset>, UserError> {
         let row = sqlx::query(
             r#"
@@ -94,21 +192,7 @@ impl Store {
         .await
         .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-        if let Some(row) = row {
-            let asset = Asset {
-                id: row.try_get("id").unwrap_or_default(),
-                mint_address: row.try_get("mint_address").unwrap_or_default(),
-                decimals: row.try_get("decimals").unwrap_or(0),
-                name: row.try_get("name").unwrap_or_default(),
-                symbol: row.try_get("symbol").unwrap_or_default(),
-                logo_url: row.try_get("logo_url").unwrap_or(None),
-                created_at: row.try_get("created_at").unwrap_or_default(),
-                updated_at: row.try_get("updated_at").unwrap_or_default(),
-            };
-            Ok(Some(asset))
-        } else {
-            Ok(None)
-        }
+        row.as_ref().map(asset_from_row).transpose()
     }
 
     pub async fn get_asset_by_mint(&self, mint_address: &str) -> Result<Option<Asset>, UserError> {
@@ -124,20 +208,44 @@ impl Store {
         .await
         .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-        if let Some(row) = row {
-            let asset = Asset {
-                id: row.try_get("id").unwrap_or_default(),
-                mint_address: row.try_get("mint_address").unwrap_or_default(),
-                decimals: row.try_get("decimals").unwrap_or(0),
-                name: row.try_get("name").unwrap_or_default(),
-                symbol: row.try_get("symbol").unwrap_or_default(),
-                logo_url: row.try_get("logo_url").unwrap_or(None),
-                created_at: row.try_get("created_at").unwrap_or_default(),
-                updated_at: row.try_get("updated_at").unwrap_or_default(),
-            };
-            Ok(Some(asset))
-        } else {
-            Ok(None)
+        row.as_ref().map(asset_from_row).transpose()
+    }
+
+    /// Every asset using `symbol`, oldest first. Symbols are not unique across mints (and
+    /// placeholder symbols can collide), so clients use this to tell tokens apart by mint.
+    pub async fn get_assets_by_symbol(&self, symbol: &str) -> Result<Vec<Asset>, UserError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, mint_address, decimals, name, symbol, logo_url, created_at, updated_at
+            FROM assets
+            WHERE symbol = $1
+            ORDER BY created_at, id
+            "#
+        )
+        .bind(symbol)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(asset_from_row).collect()
+    }
+
+    /// Mints other than `mint_address` that already use `symbol`.
+    pub async fn symbol_collisions(&self, symbol: &str, mint_address: &str) -> Result<Vec<String>, UserError> {
+        let assets = self.get_assets_by_symbol(symbol).await?;
+        Ok(colliding_mints(&assets, mint_address))
+    }
+
+    /// Creating an asset never fails on a shared symbol, since real tokens do share them; the
+    /// collision is logged so a confusing catalog entry can be spotted and renamed.
+    async fn warn_on_symbol_collision(&self, asset: &Asset) {
+        match self.symbol_collisions(&asset.symbol, &asset.mint_address).await {
+            Ok(mints) if !mints.is_empty() => println!(
+                "Asset {} ({}) shares symbol {} with mints: {}",
+                asset.id, asset.mint_address, asset.symbol, mints.join(", ")
+            ),
+            Ok(_) => {}
+            Err(e) => println!("Failed to check symbol collisions for {}: {:?}", asset.symbol, e),
         }
     }
 
@@ -153,20 +261,70 @@ impl Store {
         .await
         .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-        let assets = rows.into_iter().map(|row| {
-            Asset {
-                id: row.try_get("id").unwrap_or_default(),
-                mint_address: row.try_get("mint_address").unwrap_or_default(),
-                decimals: row.try_get("decimals").unwrap_or(0),
-                name: row.try_get("name").unwrap_or_default(),
-                symbol: row.try_get("symbol").unwrap_or_default(),
-                logo_url: row.try_get("logo_url").unwrap_or(None),
-                created_at: row.try_get("created_at").unwrap_or_default(),
-                updated_at: row.try_get("updated_at").unwrap_or_default(),
-            }
-        }).collect();
+        rows.iter().map(asset_from_row).collect()
+    }
 
-        Ok(assets)
+    /// Newest assets first, `limit` at a time from `offset`, plus the total number of matches.
+    /// `symbol_filter` is a case-insensitive substring match on the symbol.
+    pub async fn list_assets_paged(&self, limit: i64, offset: i64, symbol_filter: Option<&str>) -> Result<(Vec<Asset>, i64), UserError> {
+        self.list_assets_paginated(ListAssetsParams {
+            limit,
+            offset,
+            sort: AssetSort::CreatedAt,
+            descending: true,
+            search: None,
+            symbol: symbol_filter.map(str::to_string),
+        }).await
+    }
+
+    /// One page of assets plus the total number of matches. `search` is a case-insensitive
+    /// substring match on symbol, name and mint address; `symbol` matches the symbol alone.
+    pub async fn list_assets_paginated(&self, params: ListAssetsParams) -> Result<(Vec<Asset>, i64), UserError> {
+        let pattern = like_pattern(params.search);
+        let symbol_pattern = like_pattern(params.symbol);
+
+        let total: i64 = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS total
+            FROM assets
+            WHERE ($1::TEXT IS NULL OR symbol ILIKE $1 OR name ILIKE $1 OR mint_address ILIKE $1)
+              AND ($2::TEXT IS NULL OR symbol ILIKE $2)
+            "#
+        )
+        .bind(&pattern)
+        .bind(&symbol_pattern)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?
+        .try_get("total")
+        .unwrap_or(0);
+
+        // The sort column comes from the AssetSort allow-list, never from user input
+        let query = format!(
+            r#"
+            SELECT id, mint_address, decimals, name, symbol, logo_url, created_at, updated_at
+            FROM assets
+            WHERE ($1::TEXT IS NULL OR symbol ILIKE $1 OR name ILIKE $1 OR mint_address ILIKE $1)
+              AND ($2::TEXT IS NULL OR symbol ILIKE $2)
+            ORDER BY {} {}, id
+            LIMIT $3 OFFSET $4
+            "#,
+            params.sort.column(),
+            if params.descending { "DESC" } else { "ASC" },
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(&pattern)
+            .bind(&symbol_pattern)
+            .bind(params.limit)
+            .bind(params.offset.max(0))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let assets = rows.iter().map(asset_from_row).collect::<Result<Vec<_>, _>>()?;
+
+        Ok((assets, total))
     }
 
     pub async fn update_asset(&self, request: UpdateAssetRequest) -> Result<Asset, UserError> {
@@ -223,4 +381,109 @@ impl Store {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// A `%value%` ILIKE pattern, or `None` when there is nothing to filter on.
+fn like_pattern(value: Option<String>) -> Option<String> {
+    value
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| format!("%{}%", escape_like(s.trim())))
+}
+
+fn colliding_mints(assets: &[Asset], mint_address: &str) -> Vec<String> {
+    assets.iter()
+        .filter(|asset| asset.mint_address != mint_address)
+        .map(|asset| asset.mint_address.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use solana_sdk::{signature::Keypair, signer::Signer};
+
+    #[test]
+    fn test_asset_sort_allow_list() {
+        assert_eq!(AssetSort::parse("symbol"), Some(AssetSort::Symbol));
+        assert_eq!(AssetSort::parse("name"), Some(AssetSort::Name));
+        assert_eq!(AssetSort::parse("created_at"), Some(AssetSort::CreatedAt));
+        assert_eq!(AssetSort::parse("id; DROP TABLE assets"), None);
+        assert_eq!(AssetSort::parse("mint_address"), None);
+    }
+
+    #[test]
+    fn test_escape_like() {
+        assert_eq!(escape_like("usd"), "usd");
+        assert_eq!(escape_like("50%_off"), "50\\%\\_off");
+    }
+
+    #[test]
+    fn test_like_pattern_skips_blank_filters() {
+        assert_eq!(like_pattern(Some(" usd ".to_string())), Some("%usd%".to_string()));
+        assert_eq!(like_pattern(Some("  ".to_string())), None);
+        assert_eq!(like_pattern(None), None);
+    }
+
+    fn asset(mint_address: &str, symbol: &str) -> Asset {
+        Asset {
+            id: Uuid::new_v4().to_string(),
+            mint_address: mint_address.to_string(),
+            decimals: 6,
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            logo_url: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_colliding_mints_excludes_own_mint() {
+        let assets = vec![asset("mint-a", "USDC"), asset("mint-b", "USDC")];
+
+        assert_eq!(colliding_mints(&assets, "mint-a"), vec!["mint-b".to_string()]);
+        assert!(colliding_mints(&assets[..1], "mint-a").is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_list_assets_paged_filters_symbol_and_counts_matches() {
+        let store = test_store().await;
+
+        // A random tag keeps the filter from matching assets other tests left behind
+        let tag = Uuid::new_v4().simple().to_string()[..8].to_uppercase();
+        let mut ids = Vec::new();
+        for symbol in [format!("{}A", tag), format!("{}B", tag), format!("x{}c", tag.to_lowercase())] {
+            let mint_address = Keypair::new().pubkey().to_string();
+            let asset = store.create_asset(CreateAssetRequest {
+                mint_address: mint_address.parse().unwrap(),
+                decimals: 6,
+                name: symbol.clone(),
+                symbol,
+                logo_url: None,
+            }).await.unwrap();
+            ids.push(asset.id);
+        }
+
+        let (page, total) = store.list_assets_paged(2, 0, Some(&tag.to_lowercase())).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(page.len(), 2);
+
+        let (rest, total) = store.list_assets_paged(2, 2, Some(&tag)).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(rest.len(), 1);
+
+        let (exact, total) = store.list_assets_paged(50, 0, Some(&format!("{}b", tag))).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(exact[0].id, ids[1]);
+
+        for id in &ids {
+            store.delete_asset(id).await.unwrap();
+        }
+    }
+}
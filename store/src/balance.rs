@@ -1,7 +1,9 @@
 use crate::{error::UserError, Store};
+use crate::ledger::{insert_ledger_entry, LedgerEntry, LedgerReason, NewLedgerEntry};
+use crate::user::user_exists;
 use uuid::Uuid;
 use chrono::Utc;
-use sqlx::Row;
+use sqlx::{PgConnection, Row};
 use serde::{Deserialize, Serialize};
 use rust_decimal::Decimal;
 
@@ -52,25 +54,194 @@ pub struct TransferRequest {
     pub amount: Decimal,
 }
 
+/// Default cap on distinct nonzero balances a user can hold; `MAX_BALANCES_PER_USER` overrides it.
+pub const DEFAULT_MAX_BALANCES_PER_USER: i64 = 100;
+
+pub fn max_balances_per_user() -> i64 {
+    std::env::var("MAX_BALANCES_PER_USER")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_BALANCES_PER_USER)
+}
+
+/// Whether a user already holding `nonzero_balances` may open another one.
+fn balance_limit_reached(nonzero_balances: i64, max_balances: i64) -> bool {
+    nonzero_balances >= max_balances
+}
+
+fn reject_negative(amount: Decimal) -> Result<(), UserError> {
+    if amount.is_sign_negative() && !amount.is_zero() {
+        return Err(UserError::InvalidInput("Balance amount cannot be negative".to_string()));
+    }
+    Ok(())
+}
+
+/// `balance + amount`, or `BalanceOverflow` past `Decimal::MAX`.
+fn add_to_balance(balance: Decimal, amount: Decimal) -> Result<Decimal, UserError> {
+    balance.checked_add(amount).ok_or(UserError::BalanceOverflow)
+}
+
+fn balance_from_row(row: &sqlx::postgres::PgRow) -> Result<Balance, UserError> {
+    Ok(Balance {
+        id: row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        amount: row.try_get("amount").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        created_at: row.try_get("created_at").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        updated_at: row.try_get("updated_at").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        user_id: row.try_get("user_id").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+        asset_id: row.try_get("asset_id").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+    })
+}
+
+/// Ok when the user already has a balance row for `asset_id` or is below the cap on
+/// distinct nonzero balances; otherwise `UserError::TooManyBalances`. Takes a connection so
+/// writers can check inside the transaction that adds the row.
+async fn check_balance_limit_on(conn: &mut PgConnection, user_id: &str, asset_id: &str) -> Result<(), UserError> {
+    let row = sqlx::query(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE amount <> 0) AS nonzero_count,
+            BOOL_OR(asset_id = $2) AS has_asset
+        FROM balances
+        WHERE user_id = $1
+        "#
+    )
+    .bind(user_id)
+    .bind(asset_id)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+    let has_asset: Option<bool> = row.try_get("has_asset").unwrap_or(None);
+    if has_asset == Some(true) {
+        return Ok(());
+    }
+
+    let nonzero_count: i64 = row.try_get("nonzero_count").unwrap_or(0);
+    let max_balances = max_balances_per_user();
+    if balance_limit_reached(nonzero_count, max_balances) {
+        return Err(UserError::TooManyBalances(max_balances));
+    }
+    Ok(())
+}
+
+/// The conditional debit behind `try_debit_balance_keeping`, on a caller's transaction.
+pub(crate) async fn debit_balance_on(
+    conn: &mut PgConnection,
+    user_id: &str,
+    asset_id: &str,
+    amount: Decimal,
+    reserve: Decimal,
+    reason: LedgerReason,
+    reference: Option<&str>,
+) -> Result<(Balance, LedgerEntry), UserError> {
+    if amount <= Decimal::ZERO {
+        return Err(UserError::InvalidInput("Debit amount must be positive".to_string()));
+    }
+
+    let row = sqlx::query(
+        r#"
+        UPDATE balances SET amount = amount - $3, updated_at = $4
+        WHERE user_id = $1 AND asset_id = $2 AND amount >= $3 AND amount - $3 >= $5
+        RETURNING id, amount, created_at, updated_at, user_id, asset_id
+        "#
+    )
+    .bind(user_id)
+    .bind(asset_id)
+    .bind(amount)
+    .bind(Utc::now())
+    .bind(reserve)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| UserError::DatabaseError(e.to_string()))?
+    .ok_or(UserError::InsufficientBalance)?;
+    let balance = balance_from_row(&row)?;
+
+    let entry = insert_ledger_entry(&mut *conn, NewLedgerEntry {
+        user_id: user_id.to_string(),
+        asset_id: asset_id.to_string(),
+        amount: -amount,
+        balance_after: balance.amount,
+        reason,
+        reference: reference.map(str::to_string),
+    }).await?;
+
+    Ok((balance, entry))
+}
+
+/// `Store::credit_balance` on the given connection, so a credit can share a transaction
+/// with whatever it settles.
+pub(crate) async fn credit_balance_on(
+    conn: &mut PgConnection,
+    user_id: &str,
+    asset_id: &str,
+    amount: Decimal,
+    reason: LedgerReason,
+    reference: Option<&str>,
+) -> Result<Balance, UserError> {
+    let row = sqlx::query(
+        r#"
+        INSERT INTO balances (id, amount, created_at, updated_at, user_id, asset_id)
+        VALUES ($1, $2, $3, $3, $4, $5)
+        ON CONFLICT (user_id, asset_id)
+        DO UPDATE SET amount = balances.amount + EXCLUDED.amount, updated_at = EXCLUDED.updated_at
+        RETURNING id, amount, created_at, updated_at, user_id, asset_id
+        "#
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(amount)
+    .bind(Utc::now())
+    .bind(user_id)
+    .bind(asset_id)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+    let balance = balance_from_row(&row)?;
+
+    insert_ledger_entry(conn, NewLedgerEntry {
+        user_id: user_id.to_string(),
+        asset_id: asset_id.to_string(),
+        amount,
+        balance_after: balance.amount,
+        reason,
+        reference: reference.map(str::to_string),
+    }).await?;
+
+    Ok(balance)
+}
+
 impl Store {
+    /// Ok when the user already has a balance row for `asset_id` or is below the cap on
+    /// distinct nonzero balances; otherwise `UserError::TooManyBalances`.
+    pub async fn check_balance_limit(&self, user_id: &str, asset_id: &str) -> Result<(), UserError> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        check_balance_limit_on(&mut conn, user_id, asset_id).await
+    }
+
     pub async fn create_or_update_balance(&self, request: CreateBalanceRequest) -> Result<Balance, UserError> {
+        reject_negative(request.amount)?;
+
         let now = Utc::now();
+        let mut tx = self.pool.begin().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
         // Check if balance already exists for this user and asset
         let existing = sqlx::query(
-            "SELECT id, amount FROM balances WHERE user_id = $1 AND asset_id = $2"
+            "SELECT id, amount, created_at FROM balances WHERE user_id = $1 AND asset_id = $2 FOR UPDATE"
         )
         .bind(&request.user_id)
         .bind(&request.asset_id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *tx)
         .await
         .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-        if let Some(row) = existing {
+        let balance = if let Some(row) = existing {
             // Update existing balance
             let existing_id: String = row.try_get("id").unwrap_or_default();
             let existing_amount: Decimal = row.try_get("amount").unwrap_or(Decimal::ZERO);
-            let new_amount = existing_amount + request.amount;
+            let created_at: chrono::DateTime<Utc> = row.try_get("created_at")
+                .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            let new_amount = add_to_balance(existing_amount, request.amount)?;
 
             sqlx::query(
                 "UPDATE balances SET amount = $1, updated_at = $2 WHERE id = $3"
@@ -78,19 +249,21 @@ impl Store {
             .bind(new_amount)
             .bind(now)
             .bind(&existing_id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-            Ok(Balance {
+            Balance {
                 id: existing_id,
                 amount: new_amount,
-                created_at: now, // Will be overwritten by actual created_at from DB if needed
+                created_at,
                 updated_at: now,
                 user_id: request.user_id,
                 asset_id: request.asset_id,
-            })
+            }
         } else {
+            check_balance_limit_on(&mut *tx, &request.user_id, &request.asset_id).await?;
+
             // Create new balance
             let balance_id = Uuid::new_v4().to_string();
 
@@ -106,19 +279,33 @@ impl Store {
             .bind(now)
             .bind(&request.user_id)
             .bind(&request.asset_id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-            Ok(Balance {
+            Balance {
                 id: balance_id,
                 amount: request.amount,
                 created_at: now,
                 updated_at: now,
                 user_id: request.user_id,
                 asset_id: request.asset_id,
-            })
-        }
+            }
+        };
+
+        insert_ledger_entry(&mut *tx, NewLedgerEntry {
+            user_id: balance.user_id.clone(),
+            asset_id: balance.asset_id.clone(),
+            amount: request.amount,
+            balance_after: balance.amount,
+            reason: LedgerReason::Deposit,
+            reference: None,
+        }).await?;
+
+        tx.commit().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(balance)
     }
 
     pub async fn get_user_balances(&self, user_id: &str) -> Result<Vec<BalanceWithDetails>, UserError> {
@@ -188,60 +375,231 @@ impl Store {
     }
 
     pub async fn update_balance(&self, request: UpdateBalanceRequest) -> Result<Balance, UserError> {
+        self.set_balance(request, None).await
+    }
+
+    /// Set the balance to what the chain holds. The correction and its `Reconciliation`
+    /// ledger entry are written in one transaction.
+    pub async fn reconcile_balance(&self, request: UpdateBalanceRequest) -> Result<Balance, UserError> {
+        self.set_balance(request, Some(LedgerReason::Reconciliation)).await
+    }
+
+    /// Overwrite the balance, creating the row if needed, and record the change under
+    /// `reason`, or as a deposit or withdrawal by its sign when there is none.
+    async fn set_balance(&self, request: UpdateBalanceRequest, reason: Option<LedgerReason>) -> Result<Balance, UserError> {
+        reject_negative(request.amount)?;
+
         let now = Utc::now();
+        let mut tx = self.pool.begin().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        // Lock the row and take the amount being replaced, so the ledger delta is exact
+        let existing = sqlx::query(
+            "SELECT id, amount, created_at FROM balances WHERE user_id = $1 AND asset_id = $2 FOR UPDATE"
+        )
+        .bind(&request.user_id)
+        .bind(&request.asset_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let (balance, previous) = if let Some(row) = existing {
+            let id: String = row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            let previous: Decimal = row.try_get("amount").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            let created_at: chrono::DateTime<Utc> = row.try_get("created_at")
+                .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-        // Check if balance exists
-        let existing = self.get_balance(&request.user_id, &request.asset_id).await?;
-        
-        if let Some(balance) = existing {
             sqlx::query(
                 "UPDATE balances SET amount = $1, updated_at = $2 WHERE id = $3"
             )
             .bind(request.amount)
             .bind(now)
-            .bind(&balance.id)
-            .execute(&self.pool)
+            .bind(&id)
+            .execute(&mut *tx)
             .await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-            Ok(Balance {
-                id: balance.id,
+            (Balance {
+                id,
                 amount: request.amount,
-                created_at: balance.created_at,
+                created_at,
                 updated_at: now,
                 user_id: request.user_id,
                 asset_id: request.asset_id,
-            })
+            }, previous)
         } else {
-            // Create new balance if it doesn't exist
-            self.create_or_update_balance(CreateBalanceRequest {
+            check_balance_limit_on(&mut *tx, &request.user_id, &request.asset_id).await?;
+
+            let id = Uuid::new_v4().to_string();
+            sqlx::query(
+                r#"
+                INSERT INTO balances (id, amount, created_at, updated_at, user_id, asset_id)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#
+            )
+            .bind(&id)
+            .bind(request.amount)
+            .bind(now)
+            .bind(now)
+            .bind(&request.user_id)
+            .bind(&request.asset_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+            (Balance {
+                id,
+                amount: request.amount,
+                created_at: now,
+                updated_at: now,
                 user_id: request.user_id,
                 asset_id: request.asset_id,
-                amount: request.amount,
-            }).await
+            }, Decimal::ZERO)
+        };
+
+        let delta = balance.amount - previous;
+        if !delta.is_zero() {
+            insert_ledger_entry(&mut *tx, NewLedgerEntry {
+                user_id: balance.user_id.clone(),
+                asset_id: balance.asset_id.clone(),
+                amount: delta,
+                balance_after: balance.amount,
+                reason: reason.unwrap_or_else(|| LedgerReason::for_delta(delta)),
+                reference: None,
+            }).await?;
         }
+
+        tx.commit().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(balance)
+    }
+
+    /// Subtract `amount` in a single conditional UPDATE, so concurrent debits can't both pass
+    /// a stale balance check. Fails with `InsufficientBalance` if the row is missing or short.
+    /// Returns the ledger entry too, so a debit taken before its transaction was signed can
+    /// have the signature attached with `set_ledger_reference`.
+    pub async fn try_debit_balance(
+        &self,
+        user_id: &str,
+        asset_id: &str,
+        amount: Decimal,
+        reason: LedgerReason,
+        reference: Option<&str>,
+    ) -> Result<(Balance, LedgerEntry), UserError> {
+        self.try_debit_balance_keeping(user_id, asset_id, amount, Decimal::ZERO, reason, reference).await
+    }
+
+    /// Like `try_debit_balance`, but also fails with `InsufficientBalance` if less than
+    /// `reserve` would be left. The check is part of the UPDATE, so two debits that each
+    /// respect the reserve can't together spend into it.
+    pub async fn try_debit_balance_keeping(
+        &self,
+        user_id: &str,
+        asset_id: &str,
+        amount: Decimal,
+        reserve: Decimal,
+        reason: LedgerReason,
+        reference: Option<&str>,
+    ) -> Result<(Balance, LedgerEntry), UserError> {
+        if amount <= Decimal::ZERO {
+            return Err(UserError::InvalidInput("Debit amount must be positive".to_string()));
+        }
+
+        let mut tx = self.pool.begin().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let debited = debit_balance_on(&mut *tx, user_id, asset_id, amount, reserve, reason, reference).await?;
+
+        tx.commit().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(debited)
+    }
+
+    /// Add `amount` atomically, creating the row if needed. Used to refund a debit, where
+    /// writing back a previously read balance would undo concurrent changes.
+    pub async fn credit_balance(
+        &self,
+        user_id: &str,
+        asset_id: &str,
+        amount: Decimal,
+        reason: LedgerReason,
+        reference: Option<&str>,
+    ) -> Result<Balance, UserError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let balance = credit_balance_on(&mut *tx, user_id, asset_id, amount, reason, reference).await?;
+
+        tx.commit().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(balance)
     }
 
     pub async fn transfer_balance(&self, request: TransferRequest) -> Result<(Balance, Balance), UserError> {
+        if request.amount <= Decimal::ZERO {
+            return Err(UserError::InvalidInput("Transfer amount must be positive".to_string()));
+        }
+        if request.from_user_id == request.to_user_id {
+            return Err(UserError::InvalidInput("Cannot transfer to the same user".to_string()));
+        }
+
         let mut tx = self.pool.begin().await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
+        // There's no foreign key stopping a balance row for a user that doesn't exist
+        for user_id in [&request.from_user_id, &request.to_user_id] {
+            if !user_exists(&mut *tx, user_id).await? {
+                return Err(UserError::UnknownUser(user_id.clone()));
+            }
+        }
+
         // Clone the values we'll need later
         let from_user_id = request.from_user_id.clone();
         let to_user_id = request.to_user_id.clone();
         let asset_id = request.asset_id.clone();
         let amount = request.amount;
 
-        // Get sender balance
-        let sender_balance = self.get_balance(&request.from_user_id, &request.asset_id).await?
-            .ok_or(UserError::InsufficientBalance)?;
+        // Lock both rows in id order, so concurrent transfers in opposite directions can't
+        // deadlock and neither can act on a balance read before the other committed
+        let rows = sqlx::query(
+            r#"
+            SELECT id, amount, created_at, user_id
+            FROM balances
+            WHERE asset_id = $1 AND user_id = ANY($2)
+            ORDER BY id
+            FOR UPDATE
+            "#
+        )
+        .bind(&asset_id)
+        .bind(vec![from_user_id.clone(), to_user_id.clone()])
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let mut sender_row = None;
+        let mut receiver_row = None;
+        for row in rows {
+            let id: String = row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            let row_amount: Decimal = row.try_get("amount").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            let created_at: chrono::DateTime<Utc> = row.try_get("created_at").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            let row_user_id: String = row.try_get("user_id").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            if row_user_id == from_user_id {
+                sender_row = Some((id, row_amount, created_at));
+            } else {
+                receiver_row = Some((id, row_amount, created_at));
+            }
+        }
 
-        if sender_balance.amount < request.amount {
+        let (sender_id, sender_amount, sender_created_at) = sender_row.ok_or(UserError::InsufficientBalance)?;
+        if sender_amount < amount {
             return Err(UserError::InsufficientBalance);
         }
 
         let now = Utc::now();
-        let new_sender_amount = sender_balance.amount - request.amount;
+        let new_sender_amount = sender_amount - amount;
 
         // Update sender balance
         sqlx::query(
@@ -249,72 +607,78 @@ impl Store {
         )
         .bind(new_sender_amount)
         .bind(now)
-        .bind(&sender_balance.id)
+        .bind(&sender_id)
         .execute(&mut *tx)
         .await
         .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-        // Get or create receiver balance
-        let receiver_balance = self.get_balance(&request.to_user_id, &request.asset_id).await?;
-        
-        let updated_receiver = if let Some(balance) = receiver_balance {
-            let new_receiver_amount = balance.amount + request.amount;
-            
+        let updated_receiver = if let Some((receiver_id, receiver_amount, created_at)) = receiver_row {
+            let new_receiver_amount = add_to_balance(receiver_amount, amount)?;
+
             sqlx::query(
                 "UPDATE balances SET amount = $1, updated_at = $2 WHERE id = $3"
             )
             .bind(new_receiver_amount)
             .bind(now)
-            .bind(&balance.id)
+            .bind(&receiver_id)
             .execute(&mut *tx)
             .await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
             Balance {
-                id: balance.id,
+                id: receiver_id,
                 amount: new_receiver_amount,
-                created_at: balance.created_at,
+                created_at,
                 updated_at: now,
                 user_id: to_user_id.clone(),
                 asset_id: asset_id.clone(),
             }
         } else {
-            // Create new balance for receiver
-            let receiver_id = Uuid::new_v4().to_string();
-            
-            sqlx::query(
+            check_balance_limit_on(&mut *tx, &to_user_id, &asset_id).await?;
+
+            // A concurrent credit may create the row after the lock above; add to it then
+            let row = sqlx::query(
                 r#"
                 INSERT INTO balances (id, amount, created_at, updated_at, user_id, asset_id)
-                VALUES ($1, $2, $3, $4, $5, $6)
+                VALUES ($1, $2, $3, $3, $4, $5)
+                ON CONFLICT (user_id, asset_id)
+                DO UPDATE SET amount = balances.amount + EXCLUDED.amount, updated_at = EXCLUDED.updated_at
+                RETURNING id, amount, created_at, updated_at, user_id, asset_id
                 "#
             )
-            .bind(&receiver_id)
+            .bind(Uuid::new_v4().to_string())
             .bind(amount)
             .bind(now)
-            .bind(now)
             .bind(&to_user_id)
             .bind(&asset_id)
-            .execute(&mut *tx)
+            .fetch_one(&mut *tx)
             .await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-            Balance {
-                id: receiver_id,
-                amount,
-                created_at: now,
-                updated_at: now,
-                user_id: to_user_id,
-                asset_id: asset_id.clone(),
-            }
+            balance_from_row(&row)?
         };
 
+        for (entry_user_id, delta, balance_after) in [
+            (&from_user_id, -amount, new_sender_amount),
+            (&updated_receiver.user_id, amount, updated_receiver.amount),
+        ] {
+            insert_ledger_entry(&mut *tx, NewLedgerEntry {
+                user_id: entry_user_id.clone(),
+                asset_id: asset_id.clone(),
+                amount: delta,
+                balance_after,
+                reason: LedgerReason::Transfer,
+                reference: None,
+            }).await?;
+        }
+
         tx.commit().await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
         let updated_sender = Balance {
-            id: sender_balance.id,
+            id: sender_id,
             amount: new_sender_amount,
-            created_at: sender_balance.created_at,
+            created_at: sender_created_at,
             updated_at: now,
             user_id: from_user_id,
             asset_id,
@@ -322,4 +686,319 @@ impl Store {
 
         Ok((updated_sender, updated_receiver))
     }
-}
\ No newline at end of file
+
+    /// Delete the user's zero-amount balance rows. History is kept in `balance_ledger`, which
+    /// does not reference balance rows, so nothing is lost.
+    pub async fn prune_zero_balances(&self, user_id: &str) -> Result<usize, UserError> {
+        let result = sqlx::query("DELETE FROM balances WHERE user_id = $1 AND amount = 0")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(result.rows_affected() as usize)
+    }
+
+    /// Same as `prune_zero_balances` across every user, for the background cleanup job.
+    pub async fn prune_all_zero_balances(&self) -> Result<usize, UserError> {
+        let result = sqlx::query("DELETE FROM balances WHERE amount = 0")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(result.rows_affected() as usize)
+    }
+
+    /// Sum of every user's balance per asset: what the operator owes and must hold on chain.
+    pub async fn total_balance_by_asset(&self) -> Result<Vec<(String, Decimal)>, UserError> {
+        let rows = sqlx::query("SELECT asset_id, SUM(amount) AS total FROM balances GROUP BY asset_id ORDER BY asset_id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(|row| {
+            let asset_id: String = row.try_get("asset_id").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            let total: Decimal = row.try_get("total").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            Ok((asset_id, total))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn test_balance_limit_boundary() {
+        assert!(!balance_limit_reached(0, 3));
+        assert!(!balance_limit_reached(2, 3));
+        assert!(balance_limit_reached(3, 3));
+        assert!(balance_limit_reached(4, 3));
+    }
+
+    #[test]
+    fn test_balance_addition_overflow() {
+        let near_max = Decimal::MAX - Decimal::ONE;
+        assert_eq!(add_to_balance(near_max, Decimal::ONE).unwrap(), Decimal::MAX);
+        assert!(matches!(add_to_balance(near_max, Decimal::TWO), Err(UserError::BalanceOverflow)));
+    }
+
+    #[tokio::test]
+    async fn test_negative_and_zero_amounts_are_rejected() {
+        // Validation runs before any query, so the pool never has to connect
+        let pool = sqlx::postgres::PgPoolOptions::new().connect_lazy("postgresql://localhost/unused").unwrap();
+        let store = Store::new(pool).unwrap();
+
+        let create = store.create_or_update_balance(CreateBalanceRequest {
+            user_id: "user".to_string(),
+            asset_id: "asset".to_string(),
+            amount: Decimal::NEGATIVE_ONE,
+        }).await;
+        assert!(matches!(create, Err(UserError::InvalidInput(_))));
+
+        let update = store.update_balance(UpdateBalanceRequest {
+            user_id: "user".to_string(),
+            asset_id: "asset".to_string(),
+            amount: Decimal::new(-5, 1),
+        }).await;
+        assert!(matches!(update, Err(UserError::InvalidInput(_))));
+
+        for amount in [Decimal::ZERO, Decimal::NEGATIVE_ONE] {
+            let transfer = store.transfer_balance(TransferRequest {
+                from_user_id: "sender".to_string(),
+                to_user_id: "receiver".to_string(),
+                asset_id: "asset".to_string(),
+                amount,
+            }).await;
+            assert!(matches!(transfer, Err(UserError::InvalidInput(_))));
+
+            let debit = store.try_debit_balance("user", "asset", amount, LedgerReason::Withdrawal, None).await;
+            assert!(matches!(debit, Err(UserError::InvalidInput(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_self_transfer_is_rejected() {
+        let pool = sqlx::postgres::PgPoolOptions::new().connect_lazy("postgresql://localhost/unused").unwrap();
+        let store = Store::new(pool).unwrap();
+
+        let transfer = store.transfer_balance(TransferRequest {
+            from_user_id: "user".to_string(),
+            to_user_id: "user".to_string(),
+            asset_id: "asset".to_string(),
+            amount: Decimal::ONE,
+        }).await;
+        assert!(matches!(transfer, Err(UserError::InvalidInput(msg)) if msg.contains("same user")));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_transfer_to_missing_user_is_rejected() {
+        let (store, user_id) = store_with_user().await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        store.create_or_update_balance(CreateBalanceRequest {
+            user_id: user_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(10, 0),
+        }).await.unwrap();
+
+        let missing = Uuid::new_v4().to_string();
+        let transfer = store.transfer_balance(TransferRequest {
+            from_user_id: user_id.clone(),
+            to_user_id: missing.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::ONE,
+        }).await;
+        assert!(matches!(transfer, Err(UserError::UnknownUser(id)) if id == missing));
+
+        // Nothing was debited and no row was created for the missing user
+        assert_eq!(store.get_balance(&user_id, &asset_id).await.unwrap().unwrap().amount, Decimal::new(10, 0));
+        assert!(store.get_balance(&missing, &asset_id).await.unwrap().is_none());
+
+        delete_test_user(&store, &user_id).await;
+        delete_test_asset(&store, &asset_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_update_keeps_created_at() {
+        let (store, user_id) = store_with_user().await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+
+        let deposit = || CreateBalanceRequest {
+            user_id: user_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::ONE,
+        };
+        let created = store.create_or_update_balance(deposit()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let updated = store.create_or_update_balance(deposit()).await.unwrap();
+
+        assert_eq!(updated.id, created.id);
+        assert_eq!(updated.amount, Decimal::TWO);
+        // Postgres keeps microseconds, the first call returns the in-memory timestamp
+        assert_eq!(updated.created_at.timestamp_micros(), created.created_at.timestamp_micros());
+        assert!(updated.updated_at > created.updated_at);
+
+        delete_test_user(&store, &user_id).await;
+        delete_test_asset(&store, &asset_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_concurrent_debits_cannot_overspend() {
+        let (store, user_id) = store_with_user().await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        store.create_or_update_balance(CreateBalanceRequest {
+            user_id: user_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(10, 0),
+        }).await.unwrap();
+
+        // Each debit fits the balance on its own, but not both together
+        let (first, second) = tokio::join!(
+            store.try_debit_balance(&user_id, &asset_id, Decimal::new(7, 0), LedgerReason::Withdrawal, None),
+            store.try_debit_balance(&user_id, &asset_id, Decimal::new(7, 0), LedgerReason::Withdrawal, None),
+        );
+        assert_eq!([first.is_ok(), second.is_ok()].iter().filter(|ok| **ok).count(), 1);
+        let failed = if first.is_err() { first } else { second };
+        assert!(matches!(failed, Err(UserError::InsufficientBalance)));
+
+        let balance = store.get_balance(&user_id, &asset_id).await.unwrap().unwrap();
+        assert_eq!(balance.amount, Decimal::new(3, 0));
+
+        let refunded = store.credit_balance(&user_id, &asset_id, Decimal::new(7, 0), LedgerReason::Deposit, None).await.unwrap();
+        assert_eq!(refunded.amount, Decimal::new(10, 0));
+
+        delete_test_user(&store, &user_id).await;
+        delete_test_asset(&store, &asset_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_concurrent_debits_cannot_spend_the_reserve() {
+        let (store, user_id) = store_with_user().await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        store.create_or_update_balance(CreateBalanceRequest {
+            user_id: user_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(10, 0),
+        }).await.unwrap();
+
+        // Each debit leaves the reserve on its own, but not both together
+        let reserve = Decimal::new(3, 0);
+        let (first, second) = tokio::join!(
+            store.try_debit_balance_keeping(&user_id, &asset_id, Decimal::new(4, 0), reserve, LedgerReason::Withdrawal, None),
+            store.try_debit_balance_keeping(&user_id, &asset_id, Decimal::new(4, 0), reserve, LedgerReason::Withdrawal, None),
+        );
+        assert_eq!([first.is_ok(), second.is_ok()].iter().filter(|ok| **ok).count(), 1);
+
+        let balance = store.get_balance(&user_id, &asset_id).await.unwrap().unwrap();
+        assert_eq!(balance.amount, Decimal::new(6, 0));
+
+        delete_test_user(&store, &user_id).await;
+        delete_test_asset(&store, &asset_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_debit_records_its_transaction_reference() {
+        let (store, user_id) = store_with_user().await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        store.create_or_update_balance(CreateBalanceRequest {
+            user_id: user_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(10, 0),
+        }).await.unwrap();
+
+        // Debited before signing, then tied to the transaction; the fee carries it from the start
+        let (_, debit) = store.try_debit_balance(&user_id, &asset_id, Decimal::new(4, 0), LedgerReason::Withdrawal, None).await.unwrap();
+        assert_eq!(debit.reference, None);
+        store.set_ledger_reference(&debit.id, "sig-1").await.unwrap();
+        store.try_debit_balance(&user_id, &asset_id, Decimal::ONE, LedgerReason::Fee, Some("sig-1")).await.unwrap();
+
+        let history = store.get_balance_history(&user_id, &asset_id, None, None).await.unwrap();
+        let referenced: Vec<_> = history.iter()
+            .filter(|entry| entry.reference.as_deref() == Some("sig-1"))
+            .map(|entry| entry.reason)
+            .collect();
+        assert_eq!(referenced.len(), 2);
+        assert!(referenced.contains(&LedgerReason::Withdrawal));
+        assert!(referenced.contains(&LedgerReason::Fee));
+
+        delete_test_user(&store, &user_id).await;
+        delete_test_asset(&store, &asset_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_reconcile_records_the_correction() {
+        let (store, user_id) = store_with_user().await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        store.create_or_update_balance(CreateBalanceRequest {
+            user_id: user_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(10, 0),
+        }).await.unwrap();
+
+        let reconciled = store.reconcile_balance(UpdateBalanceRequest {
+            user_id: user_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(7, 0),
+        }).await.unwrap();
+        assert_eq!(reconciled.amount, Decimal::new(7, 0));
+
+        let history = store.get_balance_history(&user_id, &asset_id, None, None).await.unwrap();
+        assert_eq!(history[0].reason, LedgerReason::Reconciliation);
+        assert_eq!(history[0].amount, Decimal::new(-3, 0));
+        assert_eq!(history[0].balance_after, Decimal::new(7, 0));
+
+        delete_test_user(&store, &user_id).await;
+        delete_test_asset(&store, &asset_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_transfer_writes_opposite_history_rows() {
+        let (store, sender_id) = store_with_user().await;
+        let receiver_id = Uuid::new_v4().to_string();
+        insert_user(&store, &receiver_id).await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        store.create_or_update_balance(CreateBalanceRequest {
+            user_id: sender_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(10, 0),
+        }).await.unwrap();
+
+        store.transfer_balance(TransferRequest {
+            from_user_id: sender_id.clone(),
+            to_user_id: receiver_id.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(4, 0),
+        }).await.unwrap();
+
+        let sent = store.get_balance_history(&sender_id, &asset_id, None, None).await.unwrap();
+        let received = store.get_balance_history(&receiver_id, &asset_id, None, None).await.unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].reason, LedgerReason::Transfer);
+        assert_eq!(sent[0].amount, Decimal::new(-4, 0));
+        assert_eq!(sent[0].balance_after, Decimal::new(6, 0));
+        assert_eq!(sent[1].reason, LedgerReason::Deposit);
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].reason, LedgerReason::Transfer);
+        assert_eq!(received[0].amount, -sent[0].amount);
+
+        for user_id in [&sender_id, &receiver_id] {
+            delete_test_user(&store, user_id).await;
+        }
+        delete_test_asset(&store, &asset_id).await;
+    }
+}
@@ -0,0 +1,144 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::error::UserError;
+
+const NONCE_LEN: usize = 12;
+const ENCRYPTED_FIELD: &str = "encrypted";
+
+/// AES-256-GCM cipher for JSON columns. Sealed values are stored as
+/// `{"encrypted": "<base64 nonce || ciphertext>"}` so they still fit the JSONB columns.
+#[derive(Clone)]
+pub struct JsonCipher {
+    cipher: Aes256Gcm,
+}
+
+impl JsonCipher {
+    pub fn new(key: &[u8]) -> Result<Self, UserError> {
+        let cipher = Aes256Gcm::new_from_slice(key)
+            .map_err(|_| UserError::InvalidInput("encryption key must be 32 bytes".to_string()))?;
+        Ok(Self { cipher })
+    }
+
+    /// Cipher for quote data when `ENCRYPT_QUOTE_DATA=true`, keyed by the base64
+    /// `QUOTE_ENCRYPTION_KEY`. Encryption on without a usable key is a configuration error.
+    pub fn quote_cipher_from_env() -> Result<Option<Self>, UserError> {
+        Self::quote_cipher_from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn quote_cipher_from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Option<Self>, UserError> {
+        let enabled = lookup("ENCRYPT_QUOTE_DATA")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(None);
+        }
+
+        let key = lookup("QUOTE_ENCRYPTION_KEY")
+            .and_then(|k| STANDARD.decode(k.trim()).ok())
+            .ok_or_else(|| UserError::Configuration(
+                "ENCRYPT_QUOTE_DATA is set but QUOTE_ENCRYPTION_KEY is not valid base64".to_string()
+            ))?;
+
+        Self::new(&key)
+            .map(Some)
+            .map_err(|_| UserError::Configuration("QUOTE_ENCRYPTION_KEY must decode to 32 bytes".to_string()))
+    }
+
+    pub fn seal(&self, value: &serde_json::Value) -> Result<serde_json::Value, UserError> {
+        let plaintext = serde_json::to_vec(value)
+            .map_err(|e| UserError::InvalidInput(e.to_string()))?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| UserError::InvalidInput("failed to encrypt value".to_string()))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(serde_json::json!({ ENCRYPTED_FIELD: STANDARD.encode(sealed) }))
+    }
+
+    /// Decrypt a sealed value. Values that were never sealed are returned unchanged, so rows
+    /// written before encryption was enabled stay readable.
+    pub fn open(&self, value: serde_json::Value) -> Result<serde_json::Value, UserError> {
+        let encoded = match sealed_payload(&value) {
+            Some(encoded) => encoded,
+            None => return Ok(value),
+        };
+
+        let sealed = STANDARD.decode(encoded)
+            .map_err(|_| UserError::InvalidInput("encrypted value is not valid base64".to_string()))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(UserError::InvalidInput("encrypted value is truncated".to_string()));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| UserError::InvalidInput("failed to decrypt value".to_string()))?;
+
+        serde_json::from_slice(&plaintext).map_err(|e| UserError::InvalidInput(e.to_string()))
+    }
+}
+
+fn sealed_payload(value: &serde_json::Value) -> Option<&str> {
+    let object = value.as_object()?;
+    if object.len() != 1 {
+        return None;
+    }
+    object.get(ENCRYPTED_FIELD)?.as_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open_round_trip() {
+        let cipher = JsonCipher::new(&[7u8; 32]).unwrap();
+        let route_plan = serde_json::json!([
+            { "swapInfo": { "ammKey": "abc", "label": "Whirlpool" }, "percent": 100 }
+        ]);
+
+        let sealed = cipher.seal(&route_plan).unwrap();
+        assert!(sealed.get("encrypted").is_some());
+        assert!(!sealed.to_string().contains("Whirlpool"));
+
+        assert_eq!(cipher.open(sealed).unwrap(), route_plan);
+    }
+
+    #[test]
+    fn test_open_passes_plaintext_through() {
+        let cipher = JsonCipher::new(&[7u8; 32]).unwrap();
+        let plain = serde_json::json!({ "amount": "10", "feeBps": 5 });
+
+        assert_eq!(cipher.open(plain.clone()).unwrap(), plain);
+    }
+
+    #[test]
+    fn test_open_rejects_wrong_key() {
+        let sealed = JsonCipher::new(&[7u8; 32]).unwrap().seal(&serde_json::json!([1, 2])).unwrap();
+
+        assert!(JsonCipher::new(&[8u8; 32]).unwrap().open(sealed).is_err());
+    }
+
+    #[test]
+    fn test_unusable_quote_key_is_a_configuration_error() {
+        let lookup = |key: Option<String>| move |name: &str| match name {
+            "ENCRYPT_QUOTE_DATA" => Some("true".to_string()),
+            "QUOTE_ENCRYPTION_KEY" => key.clone(),
+            _ => None,
+        };
+
+        assert!(matches!(JsonCipher::quote_cipher_from_lookup(lookup(None)), Err(UserError::Configuration(_))));
+        assert!(matches!(JsonCipher::quote_cipher_from_lookup(lookup(Some("not base64!".to_string()))), Err(UserError::Configuration(_))));
+        assert!(matches!(JsonCipher::quote_cipher_from_lookup(lookup(Some(STANDARD.encode([7u8; 16])))), Err(UserError::Configuration(_))));
+
+        assert!(JsonCipher::quote_cipher_from_lookup(lookup(Some(STANDARD.encode([7u8; 32])))).unwrap().is_some());
+        assert!(JsonCipher::quote_cipher_from_lookup(|_: &str| None).unwrap().is_none());
+    }
+}
@@ -3,15 +3,21 @@
 pub enum UserError {
     UserExists,
     UserNotFound,
+    // A specific user named in the request, e.g. a transfer recipient
+    UnknownUser(String),
     InvalidCredentials,
     InvalidInput(String),
     DatabaseError(String),
+    InvalidToken,
+    Configuration(String),
     // Asset-related errors
     AssetNotFound,
     AssetAlreadyExists,
     // Balance-related errors
     InsufficientBalance,
     BalanceNotFound,
+    TooManyBalances(i64),
+    BalanceOverflow,
     // Quote-related errors
     QuoteNotFound,
     InvalidQuote,
@@ -22,17 +28,124 @@ impl std::fmt::Display for UserError {
         match self {
             UserError::UserExists => write!(f, "User already exists"),
             UserError::UserNotFound => write!(f, "User not found"),
+            UserError::UnknownUser(user_id) => write!(f, "User {} not found", user_id),
             UserError::InvalidCredentials => write!(f, "Invalid credentials"),
             UserError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
             UserError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+            UserError::InvalidToken => write!(f, "Invalid or expired token"),
+            UserError::Configuration(msg) => write!(f, "Configuration error: {}", msg),
             UserError::AssetNotFound => write!(f, "Asset not found"),
             UserError::AssetAlreadyExists => write!(f, "Asset already exists"),
             UserError::InsufficientBalance => write!(f, "Insufficient balance"),
             UserError::BalanceNotFound => write!(f, "Balance not found"),
+            UserError::TooManyBalances(max) => write!(f, "Balance limit reached: at most {} nonzero balances per user", max),
+            UserError::BalanceOverflow => write!(f, "Balance would overflow"),
             UserError::QuoteNotFound => write!(f, "Quote not found"),
             UserError::InvalidQuote => write!(f, "Invalid quote data"),
         }
     }
 }
 
-impl std::error::Error for UserError {}
\ No newline at end of file
+impl std::error::Error for UserError {}
+
+impl UserError {
+    /// Stable machine-readable identifier sent as `code` in error responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            UserError::UserExists => "user_exists",
+            UserError::UserNotFound | UserError::UnknownUser(_) => "user_not_found",
+            UserError::InvalidCredentials => "invalid_credentials",
+            UserError::InvalidInput(_) => "invalid_input",
+            UserError::DatabaseError(_) => "database_error",
+            UserError::InvalidToken => "invalid_token",
+            UserError::Configuration(_) => "configuration_error",
+            UserError::AssetNotFound => "asset_not_found",
+            UserError::AssetAlreadyExists => "asset_already_exists",
+            UserError::InsufficientBalance => "insufficient_balance",
+            UserError::BalanceNotFound => "balance_not_found",
+            UserError::TooManyBalances(_) => "too_many_balances",
+            UserError::BalanceOverflow => "balance_overflow",
+            UserError::QuoteNotFound => "quote_not_found",
+            UserError::InvalidQuote => "invalid_quote",
+        }
+    }
+
+    /// Whether the error is the server's fault rather than the request's; its details are
+    /// logged instead of returned.
+    pub fn is_internal(&self) -> bool {
+        matches!(self, UserError::DatabaseError(_) | UserError::Configuration(_))
+    }
+}
+
+#[cfg(feature = "actix")]
+impl actix_web::ResponseError for UserError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        use actix_web::http::StatusCode;
+
+        match self {
+            UserError::UserNotFound
+            | UserError::UnknownUser(_)
+            | UserError::AssetNotFound
+            | UserError::BalanceNotFound
+            | UserError::QuoteNotFound => StatusCode::NOT_FOUND,
+            UserError::UserExists | UserError::AssetAlreadyExists => StatusCode::CONFLICT,
+            UserError::InvalidCredentials | UserError::InvalidToken => StatusCode::UNAUTHORIZED,
+            UserError::InvalidInput(_)
+            | UserError::InsufficientBalance
+            | UserError::TooManyBalances(_)
+            | UserError::BalanceOverflow
+            | UserError::InvalidQuote => StatusCode::BAD_REQUEST,
+            UserError::DatabaseError(_) | UserError::Configuration(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// `{"code": ..., "message": ...}`.
+    fn error_response(&self) -> actix_web::HttpResponse {
+        let message = if self.is_internal() {
+            eprintln!("Internal error: {}", self);
+            "Internal server error".to_string()
+        } else {
+            self.to_string()
+        };
+
+        actix_web::HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "code": self.code(),
+            "message": message
+        }))
+    }
+}
+
+#[cfg(all(test, feature = "actix"))]
+mod tests {
+    use super::*;
+    use actix_web::{body::to_bytes, ResponseError};
+
+    async fn body(error: UserError) -> (u16, serde_json::Value) {
+        let response = error.error_response();
+        let status = response.status().as_u16();
+        let bytes = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_user_not_found_is_404() {
+        let (status, body) = body(UserError::UserNotFound).await;
+        assert_eq!(status, 404);
+        assert_eq!(body["code"], "user_not_found");
+        assert_eq!(body["message"], "User not found");
+    }
+
+    #[tokio::test]
+    async fn test_insufficient_balance_is_400() {
+        let (status, body) = body(UserError::InsufficientBalance).await;
+        assert_eq!(status, 400);
+        assert_eq!(body["code"], "insufficient_balance");
+    }
+
+    #[tokio::test]
+    async fn test_database_error_details_are_not_returned() {
+        let (status, body) = body(UserError::DatabaseError("connection refused at 10.0.0.5".to_string())).await;
+        assert_eq!(status, 500);
+        assert_eq!(body["message"], "Internal server error");
+    }
+}
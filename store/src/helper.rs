@@ -1,13 +1,115 @@
 use chrono::Utc;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
 // use solana_sdk::{signature::Keypair, signer::Signer};
 
 use crate::{error::UserError};
 
+/// Token lifetime when `JWT_TTL_SECS` is not set. Kept short; clients renew with a refresh token.
+const DEFAULT_JWT_TTL_SECS: i64 = 15 * 60;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    iat: i64,
+    exp: i64,
+}
+
+fn jwt_secret() -> Result<String, UserError> {
+    match std::env::var("JWT_SECRET") {
+        Ok(secret) if !secret.is_empty() => Ok(secret),
+        _ => Err(UserError::Configuration("JWT_SECRET is not set".to_string())),
+    }
+}
+
+/// Seconds an access token from `generate_token` stays valid.
+pub fn access_token_ttl_secs() -> i64 {
+    std::env::var("JWT_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|ttl| *ttl > 0)
+        .unwrap_or(DEFAULT_JWT_TTL_SECS)
+}
+
+/// An HS256 JWT for `user_id`, signed with `JWT_SECRET` and valid for `JWT_TTL_SECS`.
 pub fn generate_token(user_id: &str) -> Result<String, UserError> {
-    // Generate a simple token with timestamp (in production, use JWT)
-    let timestamp = Utc::now().timestamp();
-    let token = format!("token-{}-{}", user_id, timestamp);
-    Ok(token)
+    encode_token(user_id, &jwt_secret()?, Utc::now().timestamp(), access_token_ttl_secs())
+}
+
+/// Verify a token from `generate_token` (signature and expiry) and return its user id.
+pub fn validate_token(token: &str) -> Result<String, UserError> {
+    decode_token(token, &jwt_secret()?)
+}
+
+fn encode_token(user_id: &str, secret: &str, issued_at: i64, ttl_secs: i64) -> Result<String, UserError> {
+    let claims = Claims {
+        sub: user_id.to_string(),
+        iat: issued_at,
+        exp: issued_at + ttl_secs,
+    };
+
+    encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret.as_bytes()))
+        .map_err(|e| UserError::Configuration(format!("Failed to sign token: {}", e)))
+}
+
+fn decode_token(token: &str, secret: &str) -> Result<String, UserError> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = 0;
+
+    let data = decode::<Claims>(token, &DecodingKey::from_secret(secret.as_bytes()), &validation)
+        .map_err(|_| UserError::InvalidToken)?;
+
+    if data.claims.sub.is_empty() {
+        return Err(UserError::InvalidToken);
+    }
+    Ok(data.claims.sub)
+}
+
+/// Render `amount` at the asset's native precision (e.g. 6 places for USDC, 9 for SOL).
+/// Extra places are truncated so we never display more than the user holds.
+pub fn format_amount(amount: Decimal, decimals: i32) -> String {
+    to_native_precision(amount, decimals).to_string()
+}
+
+/// The raw integer amount in the asset's base units (lamports for SOL).
+pub fn to_base_units(amount: Decimal, decimals: i32) -> String {
+    to_native_precision(amount, decimals).mantissa().to_string()
+}
+
+fn to_native_precision(amount: Decimal, decimals: i32) -> Decimal {
+    let scale = decimals.clamp(0, 28) as u32;
+    let mut value = amount.round_dp_with_strategy(scale, RoundingStrategy::ToZero);
+    value.rescale(scale);
+    value
+}
+
+/// Highest `decimals` we accept for an asset; SPL mints never go past this.
+pub const MAX_ASSET_DECIMALS: i32 = 18;
+
+/// A mint address is a base58-encoded 32-byte public key.
+pub fn is_valid_mint_address(mint_address: &str) -> bool {
+    bs58::decode(mint_address)
+        .into_vec()
+        .map(|bytes| bytes.len() == 32)
+        .unwrap_or(false)
+}
+
+/// A base58 transaction signature (64 bytes).
+pub fn is_valid_signature(signature: &str) -> bool {
+    bs58::decode(signature)
+        .into_vec()
+        .map(|bytes| bytes.len() == 64)
+        .unwrap_or(false)
+}
+
+pub fn validate_asset_decimals(decimals: i32) -> Result<(), UserError> {
+    if !(0..=MAX_ASSET_DECIMALS).contains(&decimals) {
+        return Err(UserError::InvalidInput(format!(
+            "decimals must be between 0 and {}", MAX_ASSET_DECIMALS
+        )));
+    }
+    Ok(())
 }
 
 // pub fn generate_keypair() ->  Result<KeypairData, UserError> {
@@ -20,3 +122,72 @@ pub fn generate_token(user_id: &str) -> Result<String, UserError> {
 //         secret,
 //     })
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const SECRET: &str = "test-secret";
+
+    #[test]
+    fn test_valid_token_round_trips_user_id() {
+        let user_id = "7f9c2b1e-4d3a-4b8e-9c1d-2a3b4c5d6e7f";
+        let token = encode_token(user_id, SECRET, Utc::now().timestamp(), 3600).unwrap();
+
+        assert_eq!(decode_token(&token, SECRET).unwrap(), user_id);
+        assert!(matches!(decode_token("not-a-token", SECRET), Err(UserError::InvalidToken)));
+    }
+
+    #[test]
+    fn test_expired_token_is_rejected() {
+        let issued_at = Utc::now().timestamp() - 7200;
+        let token = encode_token("user-1", SECRET, issued_at, 3600).unwrap();
+
+        assert!(matches!(decode_token(&token, SECRET), Err(UserError::InvalidToken)));
+    }
+
+    #[test]
+    fn test_tampered_signature_is_rejected() {
+        let token = encode_token("user-1", SECRET, Utc::now().timestamp(), 3600).unwrap();
+
+        // Signed with a different secret
+        assert!(matches!(decode_token(&token, "other-secret"), Err(UserError::InvalidToken)));
+
+        // Signature bytes altered
+        let (unsigned, signature) = token.rsplit_once('.').unwrap();
+        let flipped = if signature.starts_with('A') { "B" } else { "A" };
+        let tampered = format!("{}.{}{}", unsigned, flipped, &signature[1..]);
+        assert!(matches!(decode_token(&tampered, SECRET), Err(UserError::InvalidToken)));
+    }
+
+    #[test]
+    fn test_format_amount_uses_asset_precision() {
+        let amount = Decimal::from_str("1.123456789012345678").unwrap();
+        assert_eq!(format_amount(amount, 6), "1.123456");
+        assert_eq!(format_amount(amount, 9), "1.123456789");
+        assert_eq!(format_amount(Decimal::from(2), 6), "2.000000");
+        assert_eq!(format_amount(Decimal::from(5), 0), "5");
+    }
+
+    #[test]
+    fn test_to_base_units() {
+        let amount = Decimal::from_str("1.5").unwrap();
+        assert_eq!(to_base_units(amount, 9), "1500000000");
+        assert_eq!(to_base_units(amount, 6), "1500000");
+        assert_eq!(to_base_units(Decimal::from_str("0.0000001").unwrap(), 6), "0");
+    }
+
+    #[test]
+    fn test_mint_and_decimals_validation() {
+        assert!(is_valid_mint_address("So11111111111111111111111111111111111111112"));
+        assert!(is_valid_mint_address("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"));
+        assert!(!is_valid_mint_address("not-a-mint"));
+        assert!(!is_valid_mint_address("1111"));
+
+        assert!(validate_asset_decimals(0).is_ok());
+        assert!(validate_asset_decimals(9).is_ok());
+        assert!(validate_asset_decimals(-1).is_err());
+        assert!(validate_asset_decimals(19).is_err());
+    }
+}
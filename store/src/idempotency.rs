@@ -0,0 +1,171 @@
+use crate::{error::UserError, Store};
+use chrono::{Duration, Utc};
+use sqlx::Row;
+
+/// How long a key is remembered. A retry after this runs the operation again.
+pub const IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
+
+/// What a request carrying an idempotency key should do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdempotencyClaim {
+    /// The key is new and now reserved for this request, which should run the operation.
+    Claimed,
+    /// Another request holding the key has not finished yet.
+    InProgress,
+    /// The key was already used for this operation; replay its response.
+    Completed { status_code: u16, response: serde_json::Value },
+    /// The key was already used for a different operation.
+    OperationMismatch,
+}
+
+/// Classify an existing key row for a request making `operation`.
+fn existing_claim(
+    operation: &str,
+    stored_operation: &str,
+    status_code: Option<i32>,
+    response: Option<serde_json::Value>,
+) -> IdempotencyClaim {
+    if operation != stored_operation {
+        return IdempotencyClaim::OperationMismatch;
+    }
+
+    match (status_code.and_then(|code| u16::try_from(code).ok()), response) {
+        (Some(status_code), Some(response)) => IdempotencyClaim::Completed { status_code, response },
+        _ => IdempotencyClaim::InProgress,
+    }
+}
+
+impl Store {
+    /// Reserve `key` for the user, or report how it was used before. The insert and lookup
+    /// share a transaction and the insert relies on the primary key, so of two concurrent
+    /// requests with the same key exactly one gets `Claimed`.
+    pub async fn claim_idempotency_key(&self, user_id: &str, key: &str, operation: &str) -> Result<IdempotencyClaim, UserError> {
+        let cutoff = Utc::now() - Duration::hours(IDEMPOTENCY_KEY_TTL_HOURS);
+        let mut tx = self.pool.begin().await.map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        sqlx::query("DELETE FROM idempotency_keys WHERE created_at < $1")
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (user_id, key, operation, created_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (user_id, key) DO NOTHING
+            "#
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(operation)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let claim = if inserted.rows_affected() == 1 {
+            IdempotencyClaim::Claimed
+        } else {
+            let row = sqlx::query(
+                "SELECT operation, response_status, response_json FROM idempotency_keys WHERE user_id = $1 AND key = $2"
+            )
+            .bind(user_id)
+            .bind(key)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+            existing_claim(
+                operation,
+                &row.try_get::<String, _>("operation").unwrap_or_default(),
+                row.try_get("response_status").unwrap_or(None),
+                row.try_get("response_json").unwrap_or(None),
+            )
+        };
+
+        tx.commit().await.map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        Ok(claim)
+    }
+
+    /// Record the response for a claimed key so retries replay it.
+    pub async fn complete_idempotency_key(
+        &self,
+        user_id: &str,
+        key: &str,
+        status_code: u16,
+        response: &serde_json::Value,
+    ) -> Result<(), UserError> {
+        sqlx::query(
+            "UPDATE idempotency_keys SET response_status = $3, response_json = $4 WHERE user_id = $1 AND key = $2"
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(status_code as i32)
+        .bind(response)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Forget a claimed key whose request did nothing, so a retry can run it.
+    pub async fn release_idempotency_key(&self, user_id: &str, key: &str) -> Result<(), UserError> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2 AND response_json IS NULL")
+            .bind(user_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_existing_claim() {
+        let response = serde_json::json!({ "success": true });
+
+        assert_eq!(
+            existing_claim("swap", "swap", Some(200), Some(response.clone())),
+            IdempotencyClaim::Completed { status_code: 200, response: response.clone() }
+        );
+        assert_eq!(existing_claim("swap", "swap", None, None), IdempotencyClaim::InProgress);
+        assert_eq!(existing_claim("send_sol", "swap", Some(200), Some(response)), IdempotencyClaim::OperationMismatch);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_concurrent_claims_of_one_key_admit_one_request() {
+        let store = test_store().await;
+        let user_id = Uuid::new_v4().to_string();
+        let key = Uuid::new_v4().to_string();
+
+        let (first, second) = tokio::join!(
+            store.claim_idempotency_key(&user_id, &key, "swap"),
+            store.claim_idempotency_key(&user_id, &key, "swap"),
+        );
+        let claims = [first.unwrap(), second.unwrap()];
+        assert_eq!(claims.iter().filter(|c| **c == IdempotencyClaim::Claimed).count(), 1);
+        assert!(claims.contains(&IdempotencyClaim::InProgress));
+
+        let response = serde_json::json!({ "success": true });
+        store.complete_idempotency_key(&user_id, &key, 200, &response).await.unwrap();
+        assert_eq!(
+            store.claim_idempotency_key(&user_id, &key, "swap").await.unwrap(),
+            IdempotencyClaim::Completed { status_code: 200, response }
+        );
+
+        sqlx::query("DELETE FROM idempotency_keys WHERE user_id = $1")
+            .bind(&user_id)
+            .execute(&store.pool)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{error::UserError, Store};
+use uuid::Uuid;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, Row};
+
+/// Default and maximum number of entries returned by one history query.
+pub const DEFAULT_HISTORY_LIMIT: i64 = 50;
+pub const MAX_HISTORY_LIMIT: i64 = 500;
+
+/// Why a balance changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerReason {
+    Deposit,
+    Withdrawal,
+    Swap,
+    Transfer,
+    /// A transaction's network fee, charged separately from what it moved.
+    Fee,
+    /// A correction to match the on-chain balance.
+    Reconciliation,
+}
+
+impl LedgerReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LedgerReason::Deposit => "deposit",
+            LedgerReason::Withdrawal => "withdrawal",
+            LedgerReason::Swap => "swap",
+            LedgerReason::Transfer => "transfer",
+            LedgerReason::Fee => "fee",
+            LedgerReason::Reconciliation => "reconciliation",
+        }
+    }
+
+    /// Deposit for an increase and withdrawal for a decrease, for changes with no more
+    /// specific cause such as manual adjustments.
+    pub fn for_delta(delta: Decimal) -> Self {
+        if delta.is_sign_negative() {
+            LedgerReason::Withdrawal
+        } else {
+            LedgerReason::Deposit
+        }
+    }
+}
+
+impl std::str::FromStr for LedgerReason {
+    type Err = UserError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(LedgerReason::Deposit),
+            "withdrawal" => Ok(LedgerReason::Withdrawal),
+            "swap" => Ok(LedgerReason::Swap),
+            "transfer" => Ok(LedgerReason::Transfer),
+            "fee" => Ok(LedgerReason::Fee),
+            "reconciliation" => Ok(LedgerReason::Reconciliation),
+            other => Err(UserError::DatabaseError(format!("Unknown ledger reason: {}", other))),
+        }
+    }
+}
+
+/// One signed change to a user's balance of an asset. `balance_after` is the stored balance
+/// once the change was applied, so the ledger can be replayed or audited on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub id: String,
+    pub user_id: String,
+    pub asset_id: String,
+    pub amount: Decimal,
+    pub balance_after: Decimal,
+    pub reason: LedgerReason,
+    pub reference: Option<String>,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewLedgerEntry {
+    pub user_id: String,
+    pub asset_id: String,
+    pub amount: Decimal,
+    pub balance_after: Decimal,
+    pub reason: LedgerReason,
+    pub reference: Option<String>,
+}
+
+/// Insert on the given connection, so balance changes can write their entry in the same
+/// transaction. `entry_type` predates `reason` and is kept in step with it.
+pub(crate) async fn insert_ledger_entry(conn: &mut PgConnection, entry: NewLedgerEntry) -> Result<LedgerEntry, UserError> {
+    let entry_id = Uuid::new_v4().to_string();
+    let created_at = Utc::now();
+
+    sqlx::query(
+        r#"
+        INSERT INTO balance_ledger (id, user_id, asset_id, amount, balance_after, entry_type, reason, reference, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $6, $7, $8)
+        "#
+    )
+    .bind(&entry_id)
+    .bind(&entry.user_id)
+    .bind(&entry.asset_id)
+    .bind(entry.amount)
+    .bind(entry.balance_after)
+    .bind(entry.reason.as_str())
+    .bind(&entry.reference)
+    .bind(created_at)
+    .execute(conn)
+    .await
+    .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+    Ok(LedgerEntry {
+        id: entry_id,
+        user_id: entry.user_id,
+        asset_id: entry.asset_id,
+        amount: entry.amount,
+        balance_after: entry.balance_after,
+        reason: entry.reason,
+        reference: entry.reference,
+        created_at,
+    })
+}
+
+fn history_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT)
+}
+
+impl Store {
+    pub async fn record_ledger_entry(&self, entry: NewLedgerEntry) -> Result<LedgerEntry, UserError> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        insert_ledger_entry(&mut conn, entry).await
+    }
+
+    /// Record the transaction behind an entry written before it was signed, e.g. the debit
+    /// taken ahead of a send. An entry that already has a reference is left alone.
+    pub async fn set_ledger_reference(&self, entry_id: &str, reference: &str) -> Result<(), UserError> {
+        sqlx::query("UPDATE balance_ledger SET reference = $2 WHERE id = $1 AND reference IS NULL")
+            .bind(entry_id)
+            .bind(reference)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// A user's changes to one asset, newest first.
+    pub async fn get_balance_history(
+        &self,
+        user_id: &str,
+        asset_id: &str,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> Result<Vec<LedgerEntry>, UserError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, user_id, asset_id, amount, balance_after, reason, reference, created_at
+            FROM balance_ledger
+            WHERE user_id = $1 AND asset_id = $2
+            ORDER BY created_at DESC, id
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(user_id)
+        .bind(asset_id)
+        .bind(history_limit(limit))
+        .bind(offset.unwrap_or(0).max(0))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(|row| {
+            let reason: String = row.try_get("reason").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            Ok(LedgerEntry {
+                id: row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                user_id: row.try_get("user_id").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                asset_id: row.try_get("asset_id").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                amount: row.try_get("amount").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                balance_after: row.try_get("balance_after").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                reason: reason.parse()?,
+                reference: row.try_get("reference").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                created_at: row.try_get("created_at").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+            })
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reason_round_trip() {
+        for reason in [
+            LedgerReason::Deposit,
+            LedgerReason::Withdrawal,
+            LedgerReason::Swap,
+            LedgerReason::Transfer,
+            LedgerReason::Fee,
+            LedgerReason::Reconciliation,
+        ] {
+            assert_eq!(reason.as_str().parse::<LedgerReason>().unwrap(), reason);
+        }
+        assert!("refund".parse::<LedgerReason>().is_err());
+    }
+
+    #[test]
+    fn test_reason_for_delta() {
+        assert_eq!(LedgerReason::for_delta(Decimal::ONE), LedgerReason::Deposit);
+        assert_eq!(LedgerReason::for_delta(Decimal::NEGATIVE_ONE), LedgerReason::Withdrawal);
+    }
+
+    #[test]
+    fn test_history_limit_is_clamped() {
+        assert_eq!(history_limit(None), DEFAULT_HISTORY_LIMIT);
+        assert_eq!(history_limit(Some(10_000)), MAX_HISTORY_LIMIT);
+        assert_eq!(history_limit(Some(0)), 1);
+    }
+}
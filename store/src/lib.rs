@@ -4,37 +4,105 @@ pub mod error;
 pub mod quote;
 pub mod asset;
 pub mod balance;
+pub mod pending_operation;
+pub mod ledger;
+pub mod encryption;
+pub mod settings;
+pub mod pubkey;
+pub mod login_attempt;
+pub mod idempotency;
+pub mod validation;
+pub mod refresh_token;
+#[cfg(test)]
+mod test_support;
 
 use sqlx::{postgres::PgPoolOptions, PgPool};
 
+use error::UserError;
+
 #[derive(Clone)]
 pub struct Store {
     pub pool: PgPool,
+    // Set when ENCRYPT_QUOTE_DATA is on; quote route plans and fees are then encrypted at rest
+    quote_cipher: Option<encryption::JsonCipher>,
 }
 
 impl Store {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    /// Fails with `UserError::Configuration` if quote encryption is on without a usable key.
+    pub fn new(pool: PgPool) -> Result<Self, UserError> {
+        Ok(Self {
+            pool,
+            quote_cipher: encryption::JsonCipher::quote_cipher_from_env()?,
+        })
     }
 
-    pub async fn connect(database_url: &str) -> Result<Self, sqlx::Error> {
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .connect(database_url)
-            .await?;
-
-        Ok(Self::new(pool))
+    pub async fn connect(database_url: &str) -> Result<Self, UserError> {
+        Self::connect_with_options(database_url, 5).await
     }
 
     pub async fn connect_with_options(
         database_url: &str,
         max_connections: u32,
-    ) -> Result<Self, sqlx::Error> {
+    ) -> Result<Self, UserError> {
         let pool = PgPoolOptions::new()
             .max_connections(max_connections)
             .connect(database_url)
-            .await?;
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Self::new(pool)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+    use balance::{CreateBalanceRequest, TransferRequest};
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_concurrent_transfers_keep_balances_consistent() {
+        let (store, first) = store_with_user().await;
+        let second = Uuid::new_v4().to_string();
+        insert_user(&store, &second).await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        for user_id in [&first, &second] {
+            store.create_or_update_balance(CreateBalanceRequest {
+                user_id: user_id.clone(),
+                asset_id: asset_id.clone(),
+                amount: Decimal::new(10, 0),
+            }).await.unwrap();
+        }
+
+        let transfer = |from: &String, to: &String, amount: i64| TransferRequest {
+            from_user_id: from.clone(),
+            to_user_id: to.clone(),
+            asset_id: asset_id.clone(),
+            amount: Decimal::new(amount, 0),
+        };
 
-        Ok(Self::new(pool))
+        // Opposite directions at once: with stale reads one update would overwrite the other
+        let (a, b, c, d) = tokio::join!(
+            store.transfer_balance(transfer(&first, &second, 3)),
+            store.transfer_balance(transfer(&second, &first, 5)),
+            store.transfer_balance(transfer(&first, &second, 2)),
+            store.transfer_balance(transfer(&second, &first, 1)),
+        );
+        for result in [a, b, c, d] {
+            result.unwrap();
+        }
+
+        let first_balance = store.get_balance(&first, &asset_id).await.unwrap().unwrap();
+        let second_balance = store.get_balance(&second, &asset_id).await.unwrap().unwrap();
+        assert_eq!(first_balance.amount, Decimal::new(11, 0));
+        assert_eq!(second_balance.amount, Decimal::new(9, 0));
+
+        for user_id in [&first, &second] {
+            delete_test_user(&store, user_id).await;
+        }
+        delete_test_asset(&store, &asset_id).await;
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,149 @@
+use crate::{error::UserError, Store};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+
+/// Default and maximum number of attempts returned by one query.
+pub const DEFAULT_LOGIN_ATTEMPT_LIMIT: i64 = 100;
+pub const MAX_LOGIN_ATTEMPT_LIMIT: i64 = 500;
+
+/// One sign-in attempt. `ip_address` is a salted SHA-256 hash when `LOGIN_ATTEMPTS_HASH_IP`
+/// is on, so addresses can still be grouped without being stored in the clear.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginAttempt {
+    pub id: String,
+    pub email: String,
+    pub ip_address: Option<String>,
+    pub success: bool,
+    pub failure_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LoginAttemptFilter {
+    pub email: Option<String>,
+    /// Raw address; hashed the same way as stored values before matching.
+    pub ip_address: Option<String>,
+    pub success: Option<bool>,
+    pub since: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl LoginAttemptFilter {
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LOGIN_ATTEMPT_LIMIT).clamp(1, MAX_LOGIN_ATTEMPT_LIMIT)
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// The value stored for `ip_address`: a hash when `LOGIN_ATTEMPTS_HASH_IP` is set, otherwise
+/// the address as given.
+fn stored_ip(ip_address: &str) -> String {
+    let hash_enabled = std::env::var("LOGIN_ATTEMPTS_HASH_IP")
+        .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+        .unwrap_or(false);
+    if !hash_enabled {
+        return ip_address.to_string();
+    }
+
+    let salt = std::env::var("LOGIN_ATTEMPTS_IP_SALT").unwrap_or_default();
+    hash_ip(ip_address, &salt)
+}
+
+fn hash_ip(ip_address: &str, salt: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(salt.as_bytes())
+        .chain_update(ip_address.as_bytes())
+        .finalize();
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Store {
+    pub async fn record_login_attempt(
+        &self,
+        email: &str,
+        ip_address: Option<&str>,
+        success: bool,
+        failure_reason: Option<&str>,
+    ) -> Result<(), UserError> {
+        sqlx::query(
+            r#"
+            INSERT INTO login_attempts (id, email, ip_address, success, failure_reason, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(normalize_email(email))
+        .bind(ip_address.map(stored_ip))
+        .bind(success)
+        .bind(failure_reason)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Most recent login attempts matching the filter, newest first.
+    pub async fn get_recent_login_attempts(&self, filter: LoginAttemptFilter) -> Result<Vec<LoginAttempt>, UserError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, email, ip_address, success, failure_reason, created_at
+            FROM login_attempts
+            WHERE ($1::TEXT IS NULL OR email = $1)
+              AND ($2::TEXT IS NULL OR ip_address = $2)
+              AND ($3::BOOLEAN IS NULL OR success = $3)
+              AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+            ORDER BY created_at DESC
+            LIMIT $5
+            "#
+        )
+        .bind(filter.email.as_deref().map(normalize_email))
+        .bind(filter.ip_address.as_deref().map(stored_ip))
+        .bind(filter.success)
+        .bind(filter.since)
+        .bind(filter.limit())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(|row| {
+            Ok(LoginAttempt {
+                id: row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                email: row.try_get("email").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                ip_address: row.try_get("ip_address").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                success: row.try_get("success").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                failure_reason: row.try_get("failure_reason").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+                created_at: row.try_get("created_at").map_err(|e| UserError::DatabaseError(e.to_string()))?,
+            })
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_ip_is_salted_and_stable() {
+        let hashed = hash_ip("203.0.113.7", "pepper");
+
+        assert_eq!(hashed.len(), 64);
+        assert_eq!(hashed, hash_ip("203.0.113.7", "pepper"));
+        assert_ne!(hashed, hash_ip("203.0.113.7", "other"));
+        assert_ne!(hashed, hash_ip("203.0.113.8", "pepper"));
+    }
+
+    #[test]
+    fn test_filter_limit_is_clamped() {
+        assert_eq!(LoginAttemptFilter::default().limit(), DEFAULT_LOGIN_ATTEMPT_LIMIT);
+        assert_eq!(LoginAttemptFilter { limit: Some(10_000), ..Default::default() }.limit(), MAX_LOGIN_ATTEMPT_LIMIT);
+        assert_eq!(LoginAttemptFilter { limit: Some(0), ..Default::default() }.limit(), 1);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::{
+    balance::{credit_balance_on, debit_balance_on, Balance},
+    error::UserError,
+    ledger::{LedgerEntry, LedgerReason},
+    Store,
+};
+use uuid::Uuid;
+use chrono::{Duration, Utc};
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, Row};
+use serde::{Deserialize, Serialize};
+
+/// How long a confirmation challenge stays valid before it has to be requested again.
+pub const PENDING_OPERATION_TTL_SECS: i64 = 300;
+
+/// Status of a value-moving operation whose debit was kept because its on-chain outcome is
+/// unknown, e.g. the MPC call timed out after the transaction may have been broadcast.
+pub const UNRESOLVED_STATUS: &str = "unresolved";
+
+/// For unresolved operations, `expires_at` is when a transaction signed for it can no longer
+/// land: its blockhash has expired by then, so a signature the chain still doesn't know never
+/// landed.
+pub const UNRESOLVED_LANDING_WINDOW_SECS: i64 = 180;
+
+/// A balance credit applied in the same transaction that settles an unresolved operation.
+#[derive(Debug)]
+pub struct SettlementCredit<'a> {
+    pub asset_id: &'a str,
+    pub amount: Decimal,
+    pub reason: LedgerReason,
+    pub reference: Option<&'a str>,
+}
+
+/// A confirmation to consume in the same transaction as the debit it authorizes.
+#[derive(Debug)]
+pub struct ConfirmationClaim<'a> {
+    pub operation_id: &'a str,
+    pub operation_type: &'a str,
+    pub payload: &'a serde_json::Value,
+}
+
+const PENDING_OPERATION_COLUMNS: &str = "id, user_id, operation_type, payload, status, expires_at, created_at";
+
+fn pending_operation_from_row(row: &sqlx::postgres::PgRow) -> PendingOperation {
+    PendingOperation {
+        id: row.try_get("id").unwrap_or_default(),
+        user_id: row.try_get("user_id").unwrap_or_default(),
+        operation_type: row.try_get("operation_type").unwrap_or_default(),
+        payload: row.try_get("payload").unwrap_or(serde_json::json!({})),
+        status: row.try_get("status").unwrap_or_default(),
+        expires_at: row.try_get("expires_at").unwrap_or_else(|_| Utc::now()),
+        created_at: row.try_get("created_at").unwrap_or_else(|_| Utc::now()),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingOperation {
+    pub id: String,
+    pub user_id: String,
+    pub operation_type: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub expires_at: chrono::DateTime<Utc>,
+    pub created_at: chrono::DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePendingOperationRequest {
+    pub user_id: String,
+    pub operation_type: String,
+    pub payload: serde_json::Value,
+}
+
+/// Mark a pending operation as confirmed on the given connection; see
+/// `Store::consume_pending_operation`.
+async fn consume_pending_operation_on(
+    conn: &mut PgConnection,
+    claim: &ConfirmationClaim<'_>,
+    user_id: &str,
+) -> Result<Option<PendingOperation>, UserError> {
+    let row = sqlx::query(
+        r#"
+        UPDATE pending_operations
+        SET status = 'confirmed'
+        WHERE id = $1 AND user_id = $2 AND operation_type = $3 AND payload = $4
+          AND status = 'pending' AND expires_at > NOW()
+        RETURNING id, user_id, operation_type, payload, status, expires_at, created_at
+        "#
+    )
+    .bind(claim.operation_id)
+    .bind(user_id)
+    .bind(claim.operation_type)
+    .bind(claim.payload)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+    Ok(row.as_ref().map(pending_operation_from_row))
+}
+
+impl Store {
+    pub async fn create_pending_operation(&self, request: CreatePendingOperationRequest) -> Result<PendingOperation, UserError> {
+        self.insert_pending_operation(request, "pending", PENDING_OPERATION_TTL_SECS).await
+    }
+
+    /// Record an operation whose debit stands until its transaction's fate is known. It stays
+    /// listed by `list_unresolved_operations` until `settle_unresolved_operation` closes it.
+    pub async fn record_unresolved_operation(&self, request: CreatePendingOperationRequest) -> Result<PendingOperation, UserError> {
+        self.insert_pending_operation(request, UNRESOLVED_STATUS, UNRESOLVED_LANDING_WINDOW_SECS).await
+    }
+
+    async fn insert_pending_operation(
+        &self,
+        request: CreatePendingOperationRequest,
+        status: &str,
+        ttl_secs: i64,
+    ) -> Result<PendingOperation, UserError> {
+        let operation_id = Uuid::new_v4().to_string();
+        let created_at = Utc::now();
+        let expires_at = created_at + Duration::seconds(ttl_secs);
+
+        sqlx::query(
+            r#"
+            INSERT INTO pending_operations (id, user_id, operation_type, payload, status, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#
+        )
+        .bind(&operation_id)
+        .bind(&request.user_id)
+        .bind(&request.operation_type)
+        .bind(&request.payload)
+        .bind(status)
+        .bind(&expires_at)
+        .bind(&created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(PendingOperation {
+            id: operation_id,
+            user_id: request.user_id,
+            operation_type: request.operation_type,
+            payload: request.payload,
+            status: status.to_string(),
+            expires_at,
+            created_at,
+        })
+    }
+
+    /// Atomically mark a pending operation as confirmed and return it. Returns `None` when the
+    /// id is unknown, belongs to another user, was already used, has expired, or was issued
+    /// for a different `payload`; a mismatch leaves the operation pending.
+    pub async fn consume_pending_operation(
+        &self,
+        operation_id: &str,
+        user_id: &str,
+        operation_type: &str,
+        payload: &serde_json::Value,
+    ) -> Result<Option<PendingOperation>, UserError> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        let claim = ConfirmationClaim { operation_id, operation_type, payload };
+        consume_pending_operation_on(&mut *conn, &claim, user_id).await
+    }
+
+    /// `try_debit_balance` authorized by a confirmation, consumed in the same transaction: a
+    /// debit that fails (e.g. `InsufficientBalance`) leaves the confirmation pending, and a
+    /// confirmation that doesn't match debits nothing and returns `None`.
+    pub async fn try_debit_balance_confirmed(
+        &self,
+        claim: ConfirmationClaim<'_>,
+        user_id: &str,
+        asset_id: &str,
+        amount: Decimal,
+        reason: LedgerReason,
+    ) -> Result<Option<(Balance, LedgerEntry)>, UserError> {
+        if amount <= Decimal::ZERO {
+            return Err(UserError::InvalidInput("Debit amount must be positive".to_string()));
+        }
+
+        let mut tx = self.pool.begin().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        if consume_pending_operation_on(&mut *tx, &claim, user_id).await?.is_none() {
+            return Ok(None);
+        }
+        let debited = debit_balance_on(&mut *tx, user_id, asset_id, amount, Decimal::ZERO, reason, None).await?;
+
+        tx.commit().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(Some(debited))
+    }
+
+    pub async fn get_pending_operation(&self, operation_id: &str) -> Result<Option<PendingOperation>, UserError> {
+        let row = sqlx::query(&format!("SELECT {} FROM pending_operations WHERE id = $1", PENDING_OPERATION_COLUMNS))
+            .bind(operation_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(row.as_ref().map(pending_operation_from_row))
+    }
+
+    /// Unresolved operations across all users, oldest first.
+    pub async fn list_unresolved_operations(&self) -> Result<Vec<PendingOperation>, UserError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM pending_operations WHERE status = $1 ORDER BY created_at",
+            PENDING_OPERATION_COLUMNS
+        ))
+        .bind(UNRESOLVED_STATUS)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().map(pending_operation_from_row).collect())
+    }
+
+    /// Close an unresolved operation with `outcome` as its status and apply `credit` (a refund,
+    /// or what a landed swap owes) in the same transaction, so the credit happens exactly once.
+    /// Returns `None` if the operation is unknown or was already settled; nothing is credited then.
+    pub async fn settle_unresolved_operation(
+        &self,
+        operation_id: &str,
+        outcome: &str,
+        credit: Option<SettlementCredit<'_>>,
+    ) -> Result<Option<PendingOperation>, UserError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let row = sqlx::query(&format!(
+            "UPDATE pending_operations SET status = $2 WHERE id = $1 AND status = $3 RETURNING {}",
+            PENDING_OPERATION_COLUMNS
+        ))
+        .bind(operation_id)
+        .bind(outcome)
+        .bind(UNRESOLVED_STATUS)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let operation = match row {
+            Some(row) => pending_operation_from_row(&row),
+            None => return Ok(None),
+        };
+
+        if let Some(credit) = credit {
+            credit_balance_on(&mut *tx, &operation.user_id, credit.asset_id, credit.amount, credit.reason, credit.reference).await?;
+        }
+
+        tx.commit().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(Some(operation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_mismatched_payload_does_not_consume() {
+        let (store, user_id) = store_with_user().await;
+        let payload = serde_json::json!({ "quote_id": "quote-1", "amount": "5" });
+        let pending = store.create_pending_operation(CreatePendingOperationRequest {
+            user_id: user_id.clone(),
+            operation_type: "swap".to_string(),
+            payload: payload.clone(),
+        }).await.unwrap();
+
+        let other = serde_json::json!({ "quote_id": "quote-2", "amount": "5" });
+        assert!(store.consume_pending_operation(&pending.id, &user_id, "swap", &other).await.unwrap().is_none());
+
+        // Still pending, so the payload it was issued for can use it, once
+        let consumed = store.consume_pending_operation(&pending.id, &user_id, "swap", &payload).await.unwrap();
+        assert_eq!(consumed.unwrap().status, "confirmed");
+        assert!(store.consume_pending_operation(&pending.id, &user_id, "swap", &payload).await.unwrap().is_none());
+
+        delete_test_user(&store, &user_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_expired_operation_is_not_consumed() {
+        let (store, user_id) = store_with_user().await;
+        let payload = serde_json::json!({ "quote_id": "quote-1" });
+        let pending = store.create_pending_operation(CreatePendingOperationRequest {
+            user_id: user_id.clone(),
+            operation_type: "swap".to_string(),
+            payload: payload.clone(),
+        }).await.unwrap();
+        sqlx::query("UPDATE pending_operations SET expires_at = NOW() - INTERVAL '1 second' WHERE id = $1")
+            .bind(&pending.id)
+            .execute(&store.pool)
+            .await
+            .unwrap();
+
+        assert!(store.consume_pending_operation(&pending.id, &user_id, "swap", &payload).await.unwrap().is_none());
+
+        delete_test_user(&store, &user_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_failed_debit_leaves_confirmation_usable() {
+        let (store, user_id) = store_with_user().await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        let payload = serde_json::json!({ "in_amount": "5" });
+        let pending = store.create_pending_operation(CreatePendingOperationRequest {
+            user_id: user_id.clone(),
+            operation_type: "swap".to_string(),
+            payload: payload.clone(),
+        }).await.unwrap();
+        let claim = || ConfirmationClaim { operation_id: &pending.id, operation_type: "swap", payload: &payload };
+
+        // Nothing to debit yet: the confirmation stays pending
+        let short = store.try_debit_balance_confirmed(claim(), &user_id, &asset_id, Decimal::new(5, 0), LedgerReason::Swap).await;
+        assert!(matches!(short, Err(UserError::InsufficientBalance)));
+        assert_eq!(store.get_pending_operation(&pending.id).await.unwrap().unwrap().status, "pending");
+
+        store.credit_balance(&user_id, &asset_id, Decimal::new(5, 0), LedgerReason::Deposit, None).await.unwrap();
+        let debited = store.try_debit_balance_confirmed(claim(), &user_id, &asset_id, Decimal::new(5, 0), LedgerReason::Swap).await.unwrap();
+        assert_eq!(debited.unwrap().0.amount, Decimal::ZERO);
+        assert_eq!(store.get_pending_operation(&pending.id).await.unwrap().unwrap().status, "confirmed");
+        assert!(store.try_debit_balance_confirmed(claim(), &user_id, &asset_id, Decimal::ONE, LedgerReason::Swap).await.unwrap().is_none());
+
+        delete_test_user(&store, &user_id).await;
+        delete_test_asset(&store, &asset_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_settling_credits_exactly_once() {
+        let (store, user_id) = store_with_user().await;
+        let asset_id = Uuid::new_v4().to_string();
+        insert_asset(&store, &asset_id).await;
+        let unresolved = store.record_unresolved_operation(CreatePendingOperationRequest {
+            user_id: user_id.clone(),
+            operation_type: "send_sol".to_string(),
+            payload: serde_json::json!({ "reason": "withdrawal", "request_id": "req-1" }),
+        }).await.unwrap();
+        assert!(store.list_unresolved_operations().await.unwrap().iter().any(|op| op.id == unresolved.id));
+
+        // As the settle handler does: the refund is booked under the reason the send recorded
+        // (a send_sol debit is a withdrawal) and references the signature it settled on
+        let refund = || SettlementCredit {
+            asset_id: &asset_id,
+            amount: Decimal::new(3, 0),
+            reason: LedgerReason::Withdrawal,
+            reference: Some("sig-1"),
+        };
+        let settled = store.settle_unresolved_operation(&unresolved.id, "failed", Some(refund())).await.unwrap();
+        assert_eq!(settled.unwrap().status, "failed");
+        assert!(store.settle_unresolved_operation(&unresolved.id, "failed", Some(refund())).await.unwrap().is_none());
+
+        assert_eq!(store.get_balance(&user_id, &asset_id).await.unwrap().unwrap().amount, Decimal::new(3, 0));
+        let history = store.get_balance_history(&user_id, &asset_id, None, None).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].reason, LedgerReason::Withdrawal);
+        assert_eq!(history[0].reference.as_deref(), Some("sig-1"));
+
+        delete_test_user(&store, &user_id).await;
+        delete_test_asset(&store, &asset_id).await;
+    }
+}
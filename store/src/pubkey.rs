@@ -0,0 +1,151 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::error::UserError;
+
+pub const SPL_TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+pub const TOKEN_METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+/// A base58-encoded 32-byte Solana public key (wallets, mints, recipients). Parsing and
+/// deserializing validate the value, so anything holding one is known to be well formed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(try_from = "String", into = "String")]
+#[sqlx(transparent)]
+pub struct SolanaPubkey(String);
+
+impl SolanaPubkey {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+
+    /// The associated token account holding `mint` tokens for this owner. `token_program` is
+    /// the program that owns the mint, SPL Token or Token-2022; each derives its own address.
+    pub fn associated_token_address(&self, mint: &SolanaPubkey, token_program: &SolanaPubkey) -> SolanaPubkey {
+        let owner = self.to_sdk();
+        let mint = mint.to_sdk();
+        let token_program = token_program.to_sdk();
+        let associated_program = Pubkey::from_str_const(ASSOCIATED_TOKEN_PROGRAM_ID);
+
+        let (address, _) = Pubkey::find_program_address(
+            &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
+            &associated_program,
+        );
+        SolanaPubkey(address.to_string())
+    }
+
+    /// The Metaplex metadata account holding the name and symbol of this mint.
+    pub fn metadata_address(&self) -> SolanaPubkey {
+        let mint = self.to_sdk();
+        let metadata_program = Pubkey::from_str_const(TOKEN_METADATA_PROGRAM_ID);
+
+        let (address, _) = Pubkey::find_program_address(
+            &[b"metadata", metadata_program.as_ref(), mint.as_ref()],
+            &metadata_program,
+        );
+        SolanaPubkey(address.to_string())
+    }
+
+    fn to_sdk(&self) -> Pubkey {
+        // Validated on construction, so this cannot fail
+        Pubkey::from_str(&self.0).unwrap_or_default()
+    }
+}
+
+impl FromStr for SolanaPubkey {
+    type Err = UserError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let bytes = bs58::decode(value)
+            .into_vec()
+            .map_err(|_| UserError::InvalidInput(format!("Invalid public key {}: not valid base58", value)))?;
+        if bytes.len() != 32 {
+            return Err(UserError::InvalidInput(format!(
+                "Invalid public key {}: expected 32 bytes, got {}", value, bytes.len()
+            )));
+        }
+        Ok(Self(value.to_string()))
+    }
+}
+
+impl TryFrom<String> for SolanaPubkey {
+    type Error = UserError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<SolanaPubkey> for String {
+    fn from(pubkey: SolanaPubkey) -> Self {
+        pubkey.0
+    }
+}
+
+impl AsRef<str> for SolanaPubkey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SolanaPubkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_validates_base58_and_length() {
+        assert!("So11111111111111111111111111111111111111112".parse::<SolanaPubkey>().is_ok());
+        assert!("not-a-pubkey".parse::<SolanaPubkey>().is_err());
+        // A user id must not pass as a pubkey
+        assert!("7f9c2b1e-4d3a-4b8e-9c1d-2a3b4c5d6e7f".parse::<SolanaPubkey>().is_err());
+        assert!("1111".parse::<SolanaPubkey>().is_err());
+    }
+
+    #[test]
+    fn test_associated_token_address_matches_known_account() {
+        let owner: SolanaPubkey = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin".parse().unwrap();
+        let usdc: SolanaPubkey = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".parse().unwrap();
+        let wsol: SolanaPubkey = "So11111111111111111111111111111111111111112".parse().unwrap();
+        let spl_token: SolanaPubkey = SPL_TOKEN_PROGRAM_ID.parse().unwrap();
+
+        let usdc_account = owner.associated_token_address(&usdc, &spl_token);
+        assert_eq!(usdc_account.as_str(), "F4YA4H7HeXLCvjLRKdh56FgE4cyHpPqLP1VCM6fEqEmX");
+        assert_ne!(usdc_account, owner.associated_token_address(&wsol, &spl_token));
+
+        // A mint under another token program (Token-2022) has its accounts at other addresses
+        let other_program: SolanaPubkey = "11111111111111111111111111111111".parse().unwrap();
+        assert_ne!(usdc_account, owner.associated_token_address(&usdc, &other_program));
+    }
+
+    #[test]
+    fn test_metadata_address_is_per_mint() {
+        let usdc: SolanaPubkey = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".parse().unwrap();
+        let wsol: SolanaPubkey = "So11111111111111111111111111111111111111112".parse().unwrap();
+
+        assert_eq!(usdc.metadata_address(), usdc.metadata_address());
+        assert_ne!(usdc.metadata_address(), wsol.metadata_address());
+        assert_ne!(usdc.metadata_address(), usdc);
+    }
+
+    #[test]
+    fn test_deserialize_rejects_invalid_pubkey() {
+        let valid: SolanaPubkey = serde_json::from_str("\"EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v\"").unwrap();
+        assert_eq!(serde_json::to_string(&valid).unwrap(), "\"EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v\"");
+
+        let err = serde_json::from_str::<SolanaPubkey>("\"user-1\"").unwrap_err();
+        assert!(err.to_string().contains("Invalid public key"));
+    }
+}
@@ -36,6 +36,17 @@ pub struct GetQuoteRequest {
     pub quote_id: Option<String>,
 }
 
+/// Default age after which a quote can no longer be swapped; `MAX_QUOTE_AGE_SECS` overrides it.
+pub const DEFAULT_MAX_QUOTE_AGE_SECS: i64 = 30;
+
+pub fn max_quote_age_secs() -> i64 {
+    std::env::var("MAX_QUOTE_AGE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_MAX_QUOTE_AGE_SECS)
+}
+
 impl Store {
     pub async fn save_quote(&self, request: SaveQuoteRequest) -> Result<QuoteData, UserError> {
         // Parse the quote response
@@ -44,6 +55,14 @@ impl Store {
         let quote_id = Uuid::new_v4().to_string();
         let created_at = Utc::now();
 
+        let route_plan = quote.get("routePlan").cloned().unwrap_or(serde_json::json!([]));
+        let platform_fee = quote.get("platformFee").cloned();
+        let stored_route_plan = self.seal_quote_value(&route_plan)?;
+        let stored_platform_fee = match &platform_fee {
+            Some(fee) => Some(self.seal_quote_value(fee)?),
+            None => None,
+        };
+
         // Deactivate all previous quotes for this user
         sqlx::query("UPDATE quotes SET is_active = false WHERE user_id = $1")
             .bind(&request.user_id)
@@ -70,9 +89,9 @@ impl Store {
         .bind(quote.get("otherAmountThreshold").and_then(|v| v.as_str()).unwrap_or(""))
         .bind(quote.get("swapMode").and_then(|v| v.as_str()).unwrap_or("ExactIn"))
         .bind(quote.get("slippageBps").and_then(|v| v.as_i64()).unwrap_or(50) as i32)
-        .bind(quote.get("platformFee"))
+        .bind(&stored_platform_fee)
         .bind(quote.get("priceImpactPct").and_then(|v| v.as_str()).unwrap_or("0"))
-        .bind(quote.get("routePlan").unwrap_or(&serde_json::json!([])))
+        .bind(&stored_route_plan)
         .bind(quote.get("contextSlot").and_then(|v| v.as_i64()))
         .bind(quote.get("timeTaken").and_then(|v| v.as_f64()))
         .bind(&created_at)
@@ -92,9 +111,9 @@ impl Store {
             other_amount_threshold: quote.get("otherAmountThreshold").and_then(|v| v.as_str()).unwrap_or("").to_string(),
             swap_mode: quote.get("swapMode").and_then(|v| v.as_str()).unwrap_or("ExactIn").to_string(),
             slippage_bps: quote.get("slippageBps").and_then(|v| v.as_i64()).unwrap_or(50) as i32,
-            platform_fee: quote.get("platformFee").cloned(),
+            platform_fee,
             price_impact_pct: quote.get("priceImpactPct").and_then(|v| v.as_str()).unwrap_or("0").to_string(),
-            route_plan: quote.get("routePlan").cloned().unwrap_or(serde_json::json!([])),
+            route_plan,
             context_slot: quote.get("contextSlot").and_then(|v| v.as_i64()),
             time_taken: quote.get("timeTaken").and_then(|v| v.as_f64()),
             created_at,
@@ -104,19 +123,24 @@ impl Store {
         Ok(saved_quote)
     }
 
-    pub async fn get_active_quote(&self, user_id: &str) -> Result<Option<serde_json::Value>, UserError> {
+    /// The user's active quote, if it is younger than `MAX_QUOTE_AGE_SECS`. A stale quote
+    /// would execute at an out-of-date price, so it is treated as missing: `QuoteNotFound`.
+    pub async fn get_active_quote(&self, user_id: &str) -> Result<serde_json::Value, UserError> {
+        let cutoff = Utc::now() - chrono::Duration::seconds(max_quote_age_secs());
+
         let row = sqlx::query(
             r#"
             SELECT input_mint, output_mint, in_amount, out_amount, other_amount_threshold,
                    swap_mode, slippage_bps, platform_fee, price_impact_pct, route_plan,
                    context_slot, time_taken
             FROM quotes 
-            WHERE user_id = $1 AND is_active = true 
+            WHERE user_id = $1 AND is_active = true AND created_at >= $2
             ORDER BY created_at DESC 
             LIMIT 1
             "#
         )
         .bind(user_id)
+        .bind(cutoff)
         .fetch_optional(&self.pool)
         .await
         .map_err(|e| UserError::DatabaseError(e.to_string()))?;
@@ -130,16 +154,16 @@ impl Store {
                 "otherAmountThreshold": row.try_get::<String, _>("other_amount_threshold").unwrap_or_default(),
                 "swapMode": row.try_get::<String, _>("swap_mode").unwrap_or_default(),
                 "slippageBps": row.try_get::<i32, _>("slippage_bps").unwrap_or(50),
-                "platformFee": row.try_get::<Option<serde_json::Value>, _>("platform_fee").unwrap_or(None),
+                "platformFee": self.open_quote_value(row.try_get::<Option<serde_json::Value>, _>("platform_fee").unwrap_or(None))?,
                 "priceImpactPct": row.try_get::<String, _>("price_impact_pct").unwrap_or_default(),
-                "routePlan": row.try_get::<serde_json::Value, _>("route_plan").unwrap_or(serde_json::json!([])),
+                "routePlan": self.open_quote_value(row.try_get::<serde_json::Value, _>("route_plan").ok())?.unwrap_or(serde_json::json!([])),
                 "contextSlot": row.try_get::<Option<i64>, _>("context_slot").unwrap_or(None),
                 "timeTaken": row.try_get::<Option<f64>, _>("time_taken").unwrap_or(None)
             });
 
-            Ok(Some(quote_response))
+            Ok(quote_response)
         } else {
-            Ok(None)
+            Err(UserError::QuoteNotFound)
         }
     }
 
@@ -168,9 +192,9 @@ impl Store {
                 "otherAmountThreshold": row.try_get::<String, _>("other_amount_threshold").unwrap_or_default(),
                 "swapMode": row.try_get::<String, _>("swap_mode").unwrap_or_default(),
                 "slippageBps": row.try_get::<i32, _>("slippage_bps").unwrap_or(50),
-                "platformFee": row.try_get::<Option<serde_json::Value>, _>("platform_fee").unwrap_or(None),
+                "platformFee": self.open_quote_value(row.try_get::<Option<serde_json::Value>, _>("platform_fee").unwrap_or(None))?,
                 "priceImpactPct": row.try_get::<String, _>("price_impact_pct").unwrap_or_default(),
-                "routePlan": row.try_get::<serde_json::Value, _>("route_plan").unwrap_or(serde_json::json!([])),
+                "routePlan": self.open_quote_value(row.try_get::<serde_json::Value, _>("route_plan").ok())?.unwrap_or(serde_json::json!([])),
                 "contextSlot": row.try_get::<Option<i64>, _>("context_slot").unwrap_or(None),
                 "timeTaken": row.try_get::<Option<f64>, _>("time_taken").unwrap_or(None)
             });
@@ -180,4 +204,66 @@ impl Store {
             Ok(None)
         }
     }
-}
\ No newline at end of file
+
+    /// Delete inactive quotes created more than `age` ago. Active quotes are always kept.
+    pub async fn delete_quotes_older_than(&self, age: chrono::Duration) -> Result<u64, UserError> {
+        let cutoff = Utc::now() - age;
+
+        let result = sqlx::query("DELETE FROM quotes WHERE is_active = false AND created_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Encrypt a quote column when quote encryption is enabled; otherwise store it as-is.
+    fn seal_quote_value(&self, value: &serde_json::Value) -> Result<serde_json::Value, UserError> {
+        match &self.quote_cipher {
+            Some(cipher) => cipher.seal(value),
+            None => Ok(value.clone()),
+        }
+    }
+
+    fn open_quote_value(&self, value: Option<serde_json::Value>) -> Result<Option<serde_json::Value>, UserError> {
+        match (&self.quote_cipher, value) {
+            (Some(cipher), Some(value)) => cipher.open(value).map(Some),
+            (_, value) => Ok(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    /// Needs a database with the schema applied: `TEST_DATABASE_URL=... cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_backdated_quote_is_not_returned() {
+        let (store, user_id) = store_with_user().await;
+
+        let saved = store.save_quote(SaveQuoteRequest {
+            user_id: user_id.clone(),
+            quote_response: serde_json::json!({
+                "inputMint": "So11111111111111111111111111111111111111112",
+                "outputMint": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                "inAmount": "1000000",
+                "outAmount": "150000",
+            }),
+        }).await.unwrap();
+        assert!(store.get_active_quote(&user_id).await.is_ok());
+
+        sqlx::query("UPDATE quotes SET created_at = $1 WHERE id = $2")
+            .bind(Utc::now() - chrono::Duration::seconds(DEFAULT_MAX_QUOTE_AGE_SECS + 1))
+            .bind(&saved.id)
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        assert!(matches!(store.get_active_quote(&user_id).await, Err(UserError::QuoteNotFound)));
+
+        delete_test_user(&store, &user_id).await;
+    }
+}
@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, Row};
+use uuid::Uuid;
+
+use crate::{error::UserError, helper::{access_token_ttl_secs, generate_token}, Store};
+
+/// Refresh token lifetime when `REFRESH_TOKEN_TTL_SECS` is not set.
+const DEFAULT_REFRESH_TOKEN_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// What sign-in and refresh hand back: a short-lived access JWT and the refresh token that
+/// replaces it once it expires.
+#[derive(Debug, Serialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Seconds until `access_token` expires.
+    pub expires_in: i64,
+}
+
+/// A refresh token presented again after it was already rotated, i.e. leaked. Its whole
+/// family has been revoked by the time this is returned.
+#[derive(Debug)]
+pub struct TokenReuse {
+    pub user_id: String,
+    pub family_id: String,
+    /// Tokens of the family that were still live and are now revoked.
+    pub revoked: u64,
+}
+
+/// What a refresh ends in when it doesn't fail outright.
+#[derive(Debug)]
+pub enum RefreshOutcome {
+    Rotated(TokenPair),
+    Reused(TokenReuse),
+}
+
+fn refresh_token_ttl_secs() -> i64 {
+    std::env::var("REFRESH_TOKEN_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|ttl| *ttl > 0)
+        .unwrap_or(DEFAULT_REFRESH_TOKEN_TTL_SECS)
+}
+
+/// Only a hash is stored, so a leaked table can't be replayed.
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn new_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+async fn insert_refresh_token(conn: &mut PgConnection, user_id: &str, family_id: &str) -> Result<String, UserError> {
+    let token = new_token();
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"
+        INSERT INTO refresh_tokens (id, family_id, user_id, token_hash, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(family_id)
+    .bind(user_id)
+    .bind(hash_token(&token))
+    .bind(now)
+    .bind(now + chrono::Duration::seconds(refresh_token_ttl_secs()))
+    .execute(conn)
+    .await
+    .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+    Ok(token)
+}
+
+async fn revoke_family(conn: &mut PgConnection, family_id: &str) -> Result<u64, UserError> {
+    let result = sqlx::query("UPDATE refresh_tokens SET revoked_at = NOW() WHERE family_id = $1 AND revoked_at IS NULL")
+        .bind(family_id)
+        .execute(conn)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+    Ok(result.rows_affected())
+}
+
+/// Revoke every refresh token the user holds, e.g. when the account is deactivated.
+pub(crate) async fn revoke_user_tokens(conn: &mut PgConnection, user_id: &str) -> Result<u64, UserError> {
+    let result = sqlx::query("UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(conn)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+    Ok(result.rows_affected())
+}
+
+impl Store {
+    /// Start a new token family for `user_id`, e.g. at sign-in.
+    pub async fn issue_refresh_token(&self, user_id: &str) -> Result<String, UserError> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        insert_refresh_token(&mut conn, user_id, &Uuid::new_v4().to_string()).await
+    }
+
+    /// Exchange a refresh token for a new access and refresh token. Each refresh token works
+    /// once; presenting one that was already rotated means it leaked, so its whole family is
+    /// revoked and `Reused` is returned for the caller to report. Unknown, expired and revoked
+    /// tokens, and tokens of deactivated users, are `InvalidToken`.
+    pub async fn rotate_refresh_token(&self, token: &str) -> Result<RefreshOutcome, UserError> {
+        let (user_id, refresh_token) = match self.rotate(token).await? {
+            Ok(rotated) => rotated,
+            Err(reuse) => return Ok(RefreshOutcome::Reused(reuse)),
+        };
+
+        Ok(RefreshOutcome::Rotated(TokenPair {
+            access_token: generate_token(&user_id)?,
+            refresh_token,
+            expires_in: access_token_ttl_secs(),
+        }))
+    }
+
+    /// The refresh half of `rotate_refresh_token`: returns the user id and the new token, or
+    /// the reuse that revoked the token's family.
+    async fn rotate(&self, token: &str) -> Result<Result<(String, String), TokenReuse>, UserError> {
+        let mut tx = self.pool.begin().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT t.id, t.family_id, t.user_id, t.expires_at, t.rotated_at, t.revoked_at, u.deactivated_at
+            FROM refresh_tokens t
+            JOIN users u ON u.id = t.user_id
+            WHERE t.token_hash = $1
+            FOR UPDATE OF t
+            "#
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?
+        .ok_or(UserError::InvalidToken)?;
+
+        let id: String = row.get("id");
+        let family_id: String = row.get("family_id");
+        let user_id: String = row.get("user_id");
+        let expires_at: DateTime<Utc> = row.get("expires_at");
+        let rotated_at: Option<DateTime<Utc>> = row.get("rotated_at");
+        let revoked_at: Option<DateTime<Utc>> = row.get("revoked_at");
+        let deactivated_at: Option<DateTime<Utc>> = row.get("deactivated_at");
+
+        if rotated_at.is_some() && revoked_at.is_none() {
+            let revoked = revoke_family(&mut *tx, &family_id).await?;
+            tx.commit().await.map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            return Ok(Err(TokenReuse { user_id, family_id, revoked }));
+        }
+        if revoked_at.is_some() || deactivated_at.is_some() || expires_at <= Utc::now() {
+            return Err(UserError::InvalidToken);
+        }
+
+        sqlx::query("UPDATE refresh_tokens SET rotated_at = NOW() WHERE id = $1")
+            .bind(&id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        let refresh_token = insert_refresh_token(&mut *tx, &user_id, &family_id).await?;
+
+        tx.commit().await.map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        Ok(Ok((user_id, refresh_token)))
+    }
+
+    /// Sign out: revoke the token's whole family. Returns false for an unknown token.
+    pub async fn revoke_refresh_token(&self, token: &str) -> Result<bool, UserError> {
+        let mut conn = self.pool.acquire().await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let family_id: Option<String> = sqlx::query_scalar("SELECT family_id FROM refresh_tokens WHERE token_hash = $1")
+            .bind(hash_token(token))
+            .fetch_optional(&mut *conn)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        match family_id {
+            Some(family_id) => {
+                revoke_family(&mut conn, &family_id).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_rotation_replaces_the_token() {
+        let (store, user_id) = store_with_user().await;
+        let first = store.issue_refresh_token(&user_id).await.unwrap();
+
+        let (rotated_user, second) = store.rotate(&first).await.unwrap().unwrap();
+        assert_eq!(rotated_user, user_id);
+        assert_ne!(second, first);
+
+        // The new token rotates in turn
+        let (_, third) = store.rotate(&second).await.unwrap().unwrap();
+        assert_ne!(third, second);
+
+        delete_test_user(&store, &user_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_reusing_a_rotated_token_revokes_the_family() {
+        let (store, user_id) = store_with_user().await;
+        let first = store.issue_refresh_token(&user_id).await.unwrap();
+        let (_, second) = store.rotate(&first).await.unwrap().unwrap();
+
+        // Someone replays the old token: it fails and takes the live one down with it
+        let reuse = store.rotate(&first).await.unwrap().unwrap_err();
+        assert_eq!(reuse.user_id, user_id);
+        assert_eq!(reuse.revoked, 1);
+        assert!(matches!(store.rotate(&second).await, Err(UserError::InvalidToken)));
+
+        // Other sign-ins are separate families and unaffected
+        let other = store.issue_refresh_token(&user_id).await.unwrap();
+        assert!(matches!(store.rotate(&other).await, Ok(Ok(_))));
+
+        assert!(matches!(store.rotate("unknown-token").await, Err(UserError::InvalidToken)));
+        delete_test_user(&store, &user_id).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_deactivation_ends_refresh() {
+        let (store, user_id) = store_with_user().await;
+        let token = store.issue_refresh_token(&user_id).await.unwrap();
+
+        store.deactivate_user(&user_id).await.unwrap();
+        assert!(matches!(store.rotate(&token).await, Err(UserError::InvalidToken)));
+
+        // A token issued around deactivation is refused by the join on the user as well
+        let late = store.issue_refresh_token(&user_id).await.unwrap();
+        assert!(matches!(store.rotate(&late).await, Err(UserError::InvalidToken)));
+
+        delete_test_user(&store, &user_id).await;
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{error::UserError, Store};
+use chrono::Utc;
+use sqlx::Row;
+use serde::{Deserialize, Serialize};
+use rust_decimal::Decimal;
+
+pub const DEFAULT_SLIPPAGE_BPS: i32 = 50;
+pub const MAX_SLIPPAGE_BPS: i32 = 10_000;
+
+/// Per-user preferences. `None` means "use the service-wide default" (usually an env var).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UserSettings {
+    pub user_id: String,
+    pub default_slippage_bps: i32,
+    pub min_sol_reserve: Option<Decimal>,
+    pub require_confirmation_over: Option<Decimal>,
+    pub allowlist_enabled: bool,
+    pub updated_at: Option<chrono::DateTime<Utc>>,
+}
+
+impl UserSettings {
+    pub fn defaults(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            default_slippage_bps: DEFAULT_SLIPPAGE_BPS,
+            min_sol_reserve: None,
+            require_confirmation_over: None,
+            allowlist_enabled: false,
+            updated_at: None,
+        }
+    }
+
+    /// Overlay the fields present in `update` onto these settings.
+    pub fn apply(&mut self, update: UpdateSettingsRequest) -> Result<(), UserError> {
+        if let Some(slippage) = update.default_slippage_bps {
+            if !(0..=MAX_SLIPPAGE_BPS).contains(&slippage) {
+                return Err(UserError::InvalidInput(format!("default_slippage_bps must be between 0 and {}", MAX_SLIPPAGE_BPS)));
+            }
+            self.default_slippage_bps = slippage;
+        }
+        if let Some(reserve) = update.min_sol_reserve {
+            if reserve.is_sign_negative() {
+                return Err(UserError::InvalidInput("min_sol_reserve cannot be negative".to_string()));
+            }
+            self.min_sol_reserve = Some(reserve);
+        }
+        if let Some(threshold) = update.require_confirmation_over {
+            if threshold.is_sign_negative() {
+                return Err(UserError::InvalidInput("require_confirmation_over cannot be negative".to_string()));
+            }
+            self.require_confirmation_over = Some(threshold);
+        }
+        if let Some(enabled) = update.allowlist_enabled {
+            self.allowlist_enabled = enabled;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateSettingsRequest {
+    pub default_slippage_bps: Option<i32>,
+    pub min_sol_reserve: Option<Decimal>,
+    pub require_confirmation_over: Option<Decimal>,
+    pub allowlist_enabled: Option<bool>,
+}
+
+impl Store {
+    /// Settings for `user_id`, falling back to defaults when the user never saved any.
+    pub async fn get_settings(&self, user_id: &str) -> Result<UserSettings, UserError> {
+        let row = sqlx::query(
+            r#"
+            SELECT user_id, default_slippage_bps, min_sol_reserve, require_confirmation_over, allowlist_enabled, updated_at
+            FROM user_settings
+            WHERE user_id = $1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        if let Some(row) = row {
+            Ok(UserSettings {
+                user_id: row.try_get("user_id").unwrap_or_default(),
+                default_slippage_bps: row.try_get("default_slippage_bps").unwrap_or(DEFAULT_SLIPPAGE_BPS),
+                min_sol_reserve: row.try_get("min_sol_reserve").unwrap_or(None),
+                require_confirmation_over: row.try_get("require_confirmation_over").unwrap_or(None),
+                allowlist_enabled: row.try_get("allowlist_enabled").unwrap_or(false),
+                updated_at: row.try_get("updated_at").unwrap_or(None),
+            })
+        } else {
+            Ok(UserSettings::defaults(user_id))
+        }
+    }
+
+    pub async fn update_settings(&self, user_id: &str, update: UpdateSettingsRequest) -> Result<UserSettings, UserError> {
+        let mut settings = self.get_settings(user_id).await?;
+        settings.apply(update)?;
+        let updated_at = Utc::now();
+
+        sqlx::query(
+            r#"
+            INSERT INTO user_settings (user_id, default_slippage_bps, min_sol_reserve, require_confirmation_over, allowlist_enabled, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id) DO UPDATE SET
+                default_slippage_bps = EXCLUDED.default_slippage_bps,
+                min_sol_reserve = EXCLUDED.min_sol_reserve,
+                require_confirmation_over = EXCLUDED.require_confirmation_over,
+                allowlist_enabled = EXCLUDED.allowlist_enabled,
+                updated_at = EXCLUDED.updated_at
+            "#
+        )
+        .bind(user_id)
+        .bind(settings.default_slippage_bps)
+        .bind(settings.min_sol_reserve)
+        .bind(settings.require_confirmation_over)
+        .bind(settings.allowlist_enabled)
+        .bind(updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        settings.updated_at = Some(updated_at);
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_only_overwrites_provided_fields() {
+        let mut settings = UserSettings::defaults("user-1");
+        settings.apply(UpdateSettingsRequest {
+            min_sol_reserve: Some(Decimal::new(5, 2)),
+            ..Default::default()
+        }).unwrap();
+
+        assert_eq!(settings.default_slippage_bps, DEFAULT_SLIPPAGE_BPS);
+        assert_eq!(settings.min_sol_reserve, Some(Decimal::new(5, 2)));
+        assert!(!settings.allowlist_enabled);
+    }
+
+    #[test]
+    fn test_apply_rejects_out_of_range_values() {
+        let mut settings = UserSettings::defaults("user-1");
+
+        assert!(settings.apply(UpdateSettingsRequest { default_slippage_bps: Some(10_001), ..Default::default() }).is_err());
+        assert!(settings.apply(UpdateSettingsRequest { min_sol_reserve: Some(Decimal::NEGATIVE_ONE), ..Default::default() }).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+use uuid::Uuid;
+
+use crate::Store;
+
+/// Store on `TEST_DATABASE_URL`, which needs the schema applied:
+/// `TEST_DATABASE_URL=... cargo test -- --ignored`
+pub(crate) async fn test_store() -> Store {
+    let database_url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+    Store::connect(&database_url).await.unwrap()
+}
+
+/// The email `insert_user` gives a user.
+pub(crate) fn test_email(user_id: &str) -> String {
+    format!("{}@store-test.local", user_id)
+}
+
+/// Insert a bare user row: no public key, no balances.
+pub(crate) async fn insert_user(store: &Store, user_id: &str) {
+    sqlx::query("INSERT INTO users (id, email, password_hash, created_at) VALUES ($1, $2, 'x', NOW())")
+        .bind(user_id)
+        .bind(test_email(user_id))
+        .execute(&store.pool)
+        .await
+        .unwrap();
+}
+
+/// Insert a 9-decimal test asset whose mint address is its id.
+pub(crate) async fn insert_asset(store: &Store, asset_id: &str) {
+    sqlx::query("INSERT INTO assets (id, mint_address, decimals, name, symbol) VALUES ($1, $2, 9, 'Test', 'TST')")
+        .bind(asset_id)
+        .bind(asset_id)
+        .execute(&store.pool)
+        .await
+        .unwrap();
+}
+
+/// A test store and a fresh user in it.
+pub(crate) async fn store_with_user() -> (Store, String) {
+    let store = test_store().await;
+    let user_id = Uuid::new_v4().to_string();
+    insert_user(&store, &user_id).await;
+    (store, user_id)
+}
+
+/// Remove a user inserted by a test; their balances, ledger and tokens go with them.
+pub(crate) async fn delete_test_user(store: &Store, user_id: &str) {
+    sqlx::query("DELETE FROM users WHERE id = $1").bind(user_id).execute(&store.pool).await.unwrap();
+}
+
+/// Remove an asset inserted by `insert_asset`, once no balance refers to it.
+pub(crate) async fn delete_test_asset(store: &Store, asset_id: &str) {
+    sqlx::query("DELETE FROM assets WHERE id = $1").bind(asset_id).execute(&store.pool).await.unwrap();
+}
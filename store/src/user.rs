@@ -1,7 +1,15 @@
-use crate::{error::UserError, helper::generate_token, Store};
+use crate::{
+    error::UserError,
+    helper::{access_token_ttl_secs, generate_token},
+    pubkey::SolanaPubkey,
+    refresh_token::{revoke_user_tokens, TokenPair},
+    validation::{normalize_email, validate_email, validate_password_strength},
+    Store,
+};
 use uuid::Uuid;
 use chrono::Utc;
-use sqlx::Row;
+use rust_decimal::Decimal;
+use sqlx::{postgres::PgRow, PgConnection, Row};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,7 +19,7 @@ pub struct User {
     pub password: String,
     pub created_at: chrono::DateTime<Utc>,
     pub updated_at: chrono::DateTime<Utc>,
-    pub public_key: Option<String>,
+    pub public_key: Option<SolanaPubkey>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,7 +28,7 @@ pub struct UserResponse {
     pub email: String,
     pub created_at: chrono::DateTime<Utc>,
     pub updated_at: chrono::DateTime<Utc>,
-    pub public_key: Option<String>,
+    pub public_key: Option<SolanaPubkey>,
 }
 
 #[derive(Debug)]
@@ -50,13 +58,72 @@ pub struct GenerateRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GenerateResponse {
     pub user_id: String,
-    pub public_key: String,
+    pub public_key: SolanaPubkey,
     pub shares_created: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicKeyResponse {
+    pub user_id: String,
+    pub public_key: Option<SolanaPubkey>,
+    pub consistent: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteSharesRequest {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteSharesResponse {
+    pub user_id: String,
+    pub shares_deleted: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateKeysRequest {
+    pub user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateKeysResponse {
+    pub user_id: String,
+    pub public_key: SolanaPubkey,
+    pub previous_public_key: SolanaPubkey,
+}
+
+/// What `delete_user` removed.
+#[derive(Debug, Serialize)]
+pub struct DeletedUser {
+    pub user_id: String,
+    pub public_key: Option<String>,
+    pub balances_deleted: u64,
+    pub quotes_deleted: u64,
+}
+
+fn user_response(row: &PgRow) -> Result<UserResponse, UserError> {
+    let column_error = |e: sqlx::Error| UserError::DatabaseError(e.to_string());
+    Ok(UserResponse {
+        id: row.try_get("id").map_err(column_error)?,
+        email: row.try_get("email").map_err(column_error)?,
+        created_at: row.try_get("created_at").map_err(column_error)?,
+        updated_at: row.try_get("updated_at").map_err(column_error)?,
+        public_key: row.try_get("public_key").map_err(column_error)?,
+    })
+}
+
+/// Whether a user row exists, run on `conn` so it can share a caller's transaction.
+pub(crate) async fn user_exists(conn: &mut PgConnection, user_id: &str) -> Result<bool, UserError> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)")
+        .bind(user_id)
+        .fetch_one(conn)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))
+}
+
 impl Store {
     // function to call MPC-Simple service to generate keypair
-    async fn generate_keypair_via_mpc(&self, user_id: &str) -> Result<String, UserError> {
+    async fn generate_keypair_via_mpc(&self, user_id: &str) -> Result<SolanaPubkey, UserError> {
         let client = reqwest::Client::new();
         let mpc_service_url = std::env::var("MPC_SIMPLE_URL")
             .unwrap_or_else(|_| "http://127.0.0.1:8081".to_string());
@@ -84,17 +151,114 @@ impl Store {
         }
     }
 
-    pub async fn create_user(&self, request: CreateUserRequest) -> Result<UserResponse, UserError> {
-        if !request.email.contains('@') {
-            return Err(UserError::InvalidInput("Invalid email format".to_string()));
+    /// Ask the MPC service for the public key recorded with the user's shares. `None` means
+    /// the service holds no shares for this user.
+    pub async fn fetch_public_key_via_mpc(&self, user_id: &str) -> Result<Option<SolanaPubkey>, UserError> {
+        let client = reqwest::Client::new();
+        let mpc_service_url = std::env::var("MPC_SIMPLE_URL")
+            .unwrap_or_else(|_| "http://127.0.0.1:8081".to_string());
+
+        let response = client
+            .get(&format!("{}/api/public-key/{}", mpc_service_url, user_id))
+            .send()
+            .await
+            .map_err(|e| UserError::DatabaseError(format!("Failed to call MPC service: {}", e)))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(UserError::DatabaseError(format!("MPC service returned error: {}", response.status())));
         }
 
-        if request.password.len() < 6 {
-            return Err(UserError::InvalidInput("Password must be at least 6 characters".to_string()));
+        let public_key_response: PublicKeyResponse = response
+            .json()
+            .await
+            .map_err(|e| UserError::DatabaseError(format!("Failed to parse MPC response: {}", e)))?;
+
+        // Shards that disagree need a human to look at them, not a backfill
+        if !public_key_response.consistent {
+            return Err(UserError::DatabaseError("MPC shards disagree on the user's public key".to_string()));
+        }
+
+        Ok(public_key_response.public_key)
+    }
+
+    /// Have the MPC service purge the user's key shares from all three shard databases.
+    /// Returns how many shares it removed.
+    pub async fn delete_shares_via_mpc(&self, user_id: &str) -> Result<u64, UserError> {
+        let client = reqwest::Client::new();
+        let mpc_service_url = std::env::var("MPC_SIMPLE_URL")
+            .unwrap_or_else(|_| "http://127.0.0.1:8081".to_string());
+
+        let response = client
+            .post(&format!("{}/api/delete-shares", mpc_service_url))
+            .json(&DeleteSharesRequest { user_id: user_id.to_string() })
+            .send()
+            .await
+            .map_err(|e| UserError::DatabaseError(format!("Failed to call MPC service: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(UserError::DatabaseError(format!("MPC service returned error: {}", response.status())));
+        }
+
+        let delete_response: DeleteSharesResponse = response
+            .json()
+            .await
+            .map_err(|e| UserError::DatabaseError(format!("Failed to parse MPC response: {}", e)))?;
+
+        Ok(delete_response.shares_deleted)
+    }
+
+    /// Have the MPC service replace the user's key shares with ones for a fresh key.
+    /// Returns the new public key; on error the service keeps the old shares.
+    pub async fn rotate_keypair_via_mpc(&self, user_id: &str) -> Result<SolanaPubkey, UserError> {
+        let client = reqwest::Client::new();
+        let mpc_service_url = std::env::var("MPC_SIMPLE_URL")
+            .unwrap_or_else(|_| "http://127.0.0.1:8081".to_string());
+
+        let response = client
+            .post(&format!("{}/api/rotate-keys", mpc_service_url))
+            .json(&RotateKeysRequest { user_id: user_id.to_string() })
+            .send()
+            .await
+            .map_err(|e| UserError::DatabaseError(format!("Failed to call MPC service: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(UserError::DatabaseError(format!("MPC service returned error: {}", response.status())));
+        }
+
+        let rotate_response: RotateKeysResponse = response
+            .json()
+            .await
+            .map_err(|e| UserError::DatabaseError(format!("Failed to parse MPC response: {}", e)))?;
+
+        Ok(rotate_response.public_key)
+    }
+
+    /// Record a rotated public key, replacing whatever the user had.
+    pub async fn set_public_key(&self, user_id: &str, public_key: &SolanaPubkey) -> Result<(), UserError> {
+        let result = sqlx::query("UPDATE users SET public_key = $2, updated_at = NOW() WHERE id = $1")
+            .bind(user_id)
+            .bind(public_key)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserError::UserNotFound);
         }
+        Ok(())
+    }
+
+    pub async fn create_user(&self, request: CreateUserRequest) -> Result<UserResponse, UserError> {
+        let email = normalize_email(&request.email);
+        validate_email(&email)?;
+        validate_password_strength(&request.password)?;
 
-        let existing_user = sqlx::query("SELECT id FROM users WHERE email = $1")
-            .bind(&request.email)
+        // LOWER() also catches mixed-case rows stored before emails were normalized
+        let existing_user = sqlx::query("SELECT id FROM users WHERE LOWER(email) = $1")
+            .bind(&email)
             .fetch_optional(&self.pool)
             .await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
@@ -116,7 +280,7 @@ impl Store {
         // Insert user into database
         sqlx::query("INSERT INTO users (id, email, password_hash, created_at, update_at, publicKey) VALUES ($1, $2, $3, $4, $5, $6)")
             .bind(&user_id)
-            .bind(&request.email)
+            .bind(&email)
             .bind(&password_hash)
             .bind(&created_at)
             .bind(&created_at)
@@ -127,7 +291,7 @@ impl Store {
 
         let user = UserResponse {
             id: user_id,
-            email: request.email,
+            email,
             created_at,
             updated_at: created_at,
             public_key: Some(public_key),
@@ -136,15 +300,36 @@ impl Store {
         Ok(user)
     }
 
-    pub async fn authenticate_user(&self, email: &str, password: &str) -> Result<String, UserError> {
+    /// Check the credentials and start a new session: an access token plus a refresh token
+    /// heading a fresh token family. Every attempt is recorded in `login_attempts`; a failure
+    /// to record it is logged but never fails the sign-in itself.
+    pub async fn authenticate_user(&self, email: &str, password: &str, ip_address: Option<&str>) -> Result<TokenPair, UserError> {
+        let result = self.verify_credentials(email, password).await;
+
+        let failure_reason = result.as_ref().err().map(|e| e.to_string());
+        if let Err(e) = self.record_login_attempt(email, ip_address, result.is_ok(), failure_reason.as_deref()).await {
+            eprintln!("Failed to record login attempt: {}", e);
+        }
+
+        let user_id = result?;
+        Ok(TokenPair {
+            access_token: generate_token(&user_id)?,
+            refresh_token: self.issue_refresh_token(&user_id).await?,
+            expires_in: access_token_ttl_secs(),
+        })
+    }
+
+    /// The user id behind a valid email and password.
+    async fn verify_credentials(&self, email: &str, password: &str) -> Result<String, UserError> {
         // validate input
         if email.is_empty() || password.is_empty() {
             return Err(UserError::InvalidInput("Email and password cannot be empty".to_string()));
         }
 
         // Fetch user by email
-        let user = sqlx::query("SELECT id, password_hash FROM users WHERE email = $1")
-            .bind(email)
+        // Deactivated users can no longer sign in
+        let user = sqlx::query("SELECT id, password_hash FROM users WHERE LOWER(email) = $1 AND deactivated_at IS NULL")
+            .bind(normalize_email(email))
             .fetch_optional(&self.pool)
             .await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
@@ -158,9 +343,7 @@ impl Store {
                 .map_err(|e| UserError::DatabaseError(format!("Password verification failed: {}", e)))?;
 
             if is_valid {
-                // Generate token
-                let token = generate_token(&user_id)?;
-                Ok(token)
+                Ok(user_id)
             } else {
                 Err(UserError::InvalidCredentials)
             }
@@ -169,21 +352,10 @@ impl Store {
         }
     }
 
-    // pub fn validate_token(&self, token: &str) -> Result<String, UserError> {
-    //     // Simple token validation (in production, use proper JWT validation)
-    //     if token.starts_with("token-") {
-    //         let parts: Vec<&str> = token.split('-').collect();
-    //         if parts.len() >= 2 {
-    //             // Extract user_id from token
-    //             let user_id = parts[1].to_string();
-    //             Ok(user_id)
-    //         } else {
-    //             Err(UserError::InvalidInput("Invalid token format".to_string()))
-    //         }
-    //     } else {
-    //         Err(UserError::InvalidInput("Invalid token".to_string()))
-    //     }
-    // }
+    /// The user id behind a token issued at sign-in.
+    pub fn validate_token(&self, token: &str) -> Result<String, UserError> {
+        crate::helper::validate_token(token)
+    }
 
     pub async fn get_user_by_id(&self, user_id: &str) -> Result<UserResponse, UserError> {
         let user = sqlx::query("SELECT id, email, created_at, updated_at, public_key FROM users WHERE id = $1")
@@ -192,45 +364,210 @@ impl Store {
             .await
             .map_err(|e| UserError::DatabaseError(e.to_string()))?;
 
-        if let Some(row) = user {
+        match user {
+            Some(row) => user_response(&row),
+            None => Err(UserError::UserNotFound),
+        }
+    }
+
+    /// Look a user up by email, ignoring case.
+    pub async fn get_user_by_email(&self, email: &str) -> Result<UserResponse, UserError> {
+        let user = sqlx::query("SELECT id, email, created_at, updated_at, public_key FROM users WHERE LOWER(email) = $1")
+            .bind(normalize_email(email))
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        match user {
+            Some(row) => user_response(&row),
+            None => Err(UserError::UserNotFound),
+        }
+    }
+
+    /// Deactivate the user, revoke their refresh tokens and flag their keys for removal from
+    /// the indexer. Returns the public keys that should stop being monitored.
+    pub async fn deactivate_user(&self, user_id: &str) -> Result<Vec<String>, UserError> {
+        let mut tx = self.pool.begin().await.map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let row = sqlx::query(
+            r#"
+            UPDATE users
+            SET deactivated_at = COALESCE(deactivated_at, NOW()), indexer_removal_pending = TRUE, updated_at = NOW()
+            WHERE id = $1
+            RETURNING public_key
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| UserError::DatabaseError(e.to_string()))?
+        .ok_or(UserError::UserNotFound)?;
+        let public_key: Option<String> = row.try_get("public_key").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        revoke_user_tokens(&mut *tx, user_id).await?;
+
+        tx.commit().await.map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        Ok(public_key.into_iter().collect())
+    }
+
+    /// Deactivated users whose keys the indexer has not yet confirmed removing.
+    pub async fn users_pending_key_removal(&self) -> Result<Vec<(String, Vec<String>)>, UserError> {
+        let rows = sqlx::query("SELECT id, public_key FROM users WHERE indexer_removal_pending = TRUE")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        rows.iter().map(|row| {
             let id: String = row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string()))?;
-            let email: String = row.try_get("email").map_err(|e| UserError::DatabaseError(e.to_string()))?;
-            let created_at: chrono::DateTime<Utc> = row.try_get("created_at").map_err(|e| UserError::DatabaseError(e.to_string()))?;
-            let updated_at: chrono::DateTime<Utc> = row.try_get("updated_at").map_err(|e| UserError::DatabaseError(e.to_string()))?;
             let public_key: Option<String> = row.try_get("public_key").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            Ok((id, public_key.into_iter().collect()))
+        }).collect()
+    }
 
-            Ok(UserResponse {
-                id,
-                email,
-                created_at,
-                updated_at,
-                public_key,
-            })
-        } else {
-            Err(UserError::UserNotFound)
+    pub async fn mark_keys_removed(&self, user_id: &str) -> Result<(), UserError> {
+        sqlx::query("UPDATE users SET indexer_removal_pending = FALSE WHERE id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Users with no public key recorded, e.g. created before the column existed.
+    pub async fn users_missing_public_key(&self) -> Result<Vec<String>, UserError> {
+        let rows = sqlx::query("SELECT id FROM users WHERE public_key IS NULL ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        rows.iter()
+            .map(|row| row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string())))
+            .collect()
+    }
+
+    /// Every wallet the store has recorded, deactivated users included since their funds are
+    /// still held.
+    pub async fn wallet_public_keys(&self) -> Result<Vec<String>, UserError> {
+        let rows = sqlx::query("SELECT public_key FROM users WHERE public_key IS NOT NULL ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        rows.iter()
+            .map(|row| row.try_get("public_key").map_err(|e| UserError::DatabaseError(e.to_string())))
+            .collect()
+    }
+
+    /// Record the user's public key if none is set yet. Returns false if the user already
+    /// had one (or doesn't exist), so a backfill never overwrites an existing key.
+    pub async fn set_missing_public_key(&self, user_id: &str, public_key: &SolanaPubkey) -> Result<bool, UserError> {
+        let result = sqlx::query("UPDATE users SET public_key = $2, updated_at = NOW() WHERE id = $1 AND public_key IS NULL")
+            .bind(user_id)
+            .bind(public_key)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete the user with their balances and quotes in one transaction. Ledger entries,
+    /// pending operations and settings go with the user row through `ON DELETE CASCADE`.
+    /// Refused while any balance is non-zero, since the funds would be lost with the user.
+    pub async fn delete_user(&self, user_id: &str) -> Result<DeletedUser, UserError> {
+        let mut tx = self.pool.begin().await.map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        // Locked so a credit can't land between this check and the delete
+        let held = sqlx::query("SELECT amount FROM balances WHERE user_id = $1 FOR UPDATE")
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+        for row in &held {
+            let amount: Decimal = row.try_get("amount").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+            if !amount.is_zero() {
+                return Err(UserError::InvalidInput("User still holds non-zero balances".to_string()));
+            }
         }
+
+        let quotes_deleted = sqlx::query("DELETE FROM quotes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?
+            .rows_affected();
+
+        sqlx::query("DELETE FROM idempotency_keys WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        let balances_deleted = sqlx::query("DELETE FROM balances WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?
+            .rows_affected();
+
+        // Dropping the transaction without committing rolls the deletes above back
+        let row = sqlx::query("DELETE FROM users WHERE id = $1 RETURNING public_key")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| UserError::DatabaseError(e.to_string()))?
+            .ok_or(UserError::UserNotFound)?;
+        let public_key: Option<String> = row.try_get("public_key").map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        tx.commit().await.map_err(|e| UserError::DatabaseError(e.to_string()))?;
+
+        Ok(DeletedUser {
+            user_id: user_id.to_string(),
+            public_key,
+            balances_deleted,
+            quotes_deleted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    /// Needs a database with the schema applied: `TEST_DATABASE_URL=... cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_deleted_user_is_not_found() {
+        let (store, user_id) = store_with_user().await;
+        assert!(store.get_user_by_id(&user_id).await.is_ok());
+
+        let deleted = store.delete_user(&user_id).await.unwrap();
+        assert_eq!(deleted.user_id, user_id);
+        assert_eq!(deleted.balances_deleted, 0);
+
+        assert!(matches!(store.get_user_by_id(&user_id).await, Err(UserError::UserNotFound)));
+        assert!(matches!(store.delete_user(&user_id).await, Err(UserError::UserNotFound)));
     }
 
-    // pub async fn get_user_by_email(&self, email: &str) -> Result<User, UserError> {
-    //     let user = sqlx::query("SELECT id, email, created_at FROM users WHERE email = $1")
-    //         .bind(email)
-    //         .fetch_optional(&self.pool)
-    //         .await
-    //         .map_err(|e| UserError::DatabaseError(e.to_string()))?;
-
-    //     if let Some(row) = user {
-    //         let id: String = row.try_get("id").map_err(|e| UserError::DatabaseError(e.to_string()))?;
-    //         let email: String = row.try_get("email").map_err(|e| UserError::DatabaseError(e.to_string()))?;
-    //         let created_at: chrono::DateTime<Utc> = row.try_get("created_at").map_err(|e| UserError::DatabaseError(e.to_string()))?;
-
-    //         Ok(User {
-    //             id,
-    //             email,
-    //             created_at: created_at.to_rfc3339(),
-    //         })
-    //     } else {
-    //         Err(UserError::UserNotFound)
-    //     }
-    // }
+    #[tokio::test]
+    #[ignore = "requires TEST_DATABASE_URL"]
+    async fn test_email_lookup_and_duplicates_ignore_case() {
+        let (store, user_id) = store_with_user().await;
+        let email = test_email(&user_id);
+
+        let found = store.get_user_by_email(&email.to_uppercase()).await.unwrap();
+        assert_eq!(found.id, user_id);
+        assert!(matches!(store.get_user_by_email("nobody@store-test.local").await, Err(UserError::UserNotFound)));
 
+        // Rejected before the MPC service is ever called
+        let duplicate = store.create_user(CreateUserRequest {
+            email: email.to_uppercase(),
+            password: "hunter42abc".to_string(),
+        }).await;
+        assert!(matches!(duplicate, Err(UserError::UserExists)));
+
+        delete_test_user(&store, &user_id).await;
+    }
 }
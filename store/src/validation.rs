@@ -0,0 +1,137 @@
+use crate::error::UserError;
+
+const MAX_EMAIL_LEN: usize = 254;
+const MAX_LOCAL_PART_LEN: usize = 64;
+const MAX_DOMAIN_LABEL_LEN: usize = 63;
+const MIN_PASSWORD_LEN: usize = 8;
+// Besides ASCII letters and digits, the characters RFC 5322 allows unquoted in a local part
+const LOCAL_PART_SYMBOLS: &str = "!#$%&'*+/=?^_`{|}~-.";
+
+fn invalid(message: &str) -> UserError {
+    UserError::InvalidInput(message.to_string())
+}
+
+/// Accept `local@domain` addresses with an unquoted local part and a dotted domain ending in
+/// an alphabetic TLD. Quoted local parts and IP-literal domains are rejected.
+pub fn validate_email(email: &str) -> Result<(), UserError> {
+    if email.is_empty() {
+        return Err(invalid("Email is required"));
+    }
+    if email.len() > MAX_EMAIL_LEN {
+        return Err(invalid("Email must be at most 254 characters"));
+    }
+
+    let (local, domain) = match email.split_once('@') {
+        Some((local, domain)) if !domain.contains('@') => (local, domain),
+        _ => return Err(invalid("Email must contain exactly one '@'")),
+    };
+
+    if local.is_empty() || local.len() > MAX_LOCAL_PART_LEN {
+        return Err(invalid("Email must have 1 to 64 characters before the '@'"));
+    }
+    if !local.chars().all(|c| c.is_ascii_alphanumeric() || LOCAL_PART_SYMBOLS.contains(c)) {
+        return Err(invalid("Email contains a character that is not allowed before the '@'"));
+    }
+    if local.starts_with('.') || local.ends_with('.') || local.contains("..") {
+        return Err(invalid("Email cannot start or end with a dot or contain consecutive dots before the '@'"));
+    }
+
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2 {
+        return Err(invalid("Email domain must include a top-level domain, like example.com"));
+    }
+    for label in &labels {
+        let valid = !label.is_empty()
+            && label.len() <= MAX_DOMAIN_LABEL_LEN
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !label.starts_with('-')
+            && !label.ends_with('-');
+        if !valid {
+            return Err(invalid("Email domain is not a valid host name"));
+        }
+    }
+
+    let tld = labels[labels.len() - 1];
+    if tld.len() < 2 || !tld.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(invalid("Email top-level domain must be at least 2 letters"));
+    }
+
+    Ok(())
+}
+
+/// The form emails are stored and compared in: trimmed and lowercased, so `A@x.com` and
+/// `a@x.com` are the same account.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Require at least 8 characters including a letter and a digit.
+pub fn validate_password_strength(password: &str) -> Result<(), UserError> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(invalid("Password must be at least 8 characters"));
+    }
+    if !password.chars().any(|c| c.is_alphabetic()) {
+        return Err(invalid("Password must contain at least one letter"));
+    }
+    if !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err(invalid("Password must contain at least one digit"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(result: Result<(), UserError>) -> String {
+        match result {
+            Err(UserError::InvalidInput(message)) => message,
+            other => panic!("expected InvalidInput, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_valid_emails() {
+        for email in [
+            "alice@example.com",
+            "first.last@mail.example.co.uk",
+            "user+tag@sub-domain.example.org",
+            "o'brien@example.io",
+        ] {
+            assert!(validate_email(email).is_ok(), "{} should be valid", email);
+        }
+    }
+
+    #[test]
+    fn test_invalid_emails() {
+        assert_eq!(message(validate_email("")), "Email is required");
+        assert!(message(validate_email("alice.example.com")).contains("exactly one '@'"));
+        assert!(message(validate_email("a@b@example.com")).contains("exactly one '@'"));
+        assert!(message(validate_email("@example.com")).contains("before the '@'"));
+        assert!(message(validate_email("al ice@example.com")).contains("not allowed"));
+        assert!(message(validate_email(".alice@example.com")).contains("dot"));
+        assert!(message(validate_email("alice..b@example.com")).contains("consecutive dots"));
+        assert!(message(validate_email("alice@localhost")).contains("top-level domain"));
+        assert!(message(validate_email("alice@-example.com")).contains("host name"));
+        assert!(message(validate_email("alice@example..com")).contains("host name"));
+        assert!(message(validate_email("alice@example.c0m")).contains("top-level domain"));
+        assert!(validate_email(&format!("{}@example.com", "a".repeat(65))).is_err());
+    }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(normalize_email(" Alice@Example.COM "), "alice@example.com");
+        assert_eq!(normalize_email("bob@example.com"), "bob@example.com");
+    }
+
+    #[test]
+    fn test_password_strength() {
+        assert!(validate_password_strength("hunter42abc").is_ok());
+        assert!(validate_password_strength("pässwört9").is_ok());
+
+        assert!(message(validate_password_strength("abc123")).contains("at least 8 characters"));
+        assert!(message(validate_password_strength("12345678")).contains("one letter"));
+        assert!(message(validate_password_strength("password")).contains("one digit"));
+    }
+}